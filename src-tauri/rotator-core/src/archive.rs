@@ -0,0 +1,728 @@
+use crate::audit::snapshot;
+use crate::backup::BackupReason;
+use crate::budget::{load_budgets, Budget};
+use crate::db::{load_active_tracking, next_entry_id, next_project_position, next_task_position, DATA_TABLES};
+use crate::entries::find_overlap;
+use crate::error::RotatorError;
+use crate::history::RotationEvent;
+use crate::journal::{
+    insert_entry_record, insert_session_record, load_entry_record, load_session_record, EntryRecord, SessionRecord,
+};
+use crate::policy::{load_project_policy, TimePolicy};
+use crate::recovery::load_orphaned_sessions;
+use crate::rotator::Rotator;
+use crate::timebox::TimeboxBlock;
+use crate::totals::refresh_task_total;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// Written into every archive so a file from somewhere else is recognized.
+pub const ARCHIVE_FORMAT: &str = "rotator-archive";
+
+/// Layout version of `Archive`. Bump it when a field changes meaning or a
+/// required field is added; older archives must keep importing.
+///
+/// 2 added budgets, project time policies and the rotation and timebox
+/// history. Version 1 archives import with none of them.
+pub const ARCHIVE_VERSION: u32 = 2;
+
+/// A project row as stored, archived ones included.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProjectRecord {
+    pub id: u64,
+    pub name: String,
+    pub position: i64,
+    pub archived_at: Option<u64>,
+    pub current_task_id: Option<u64>,
+    pub rotation_weight: f64,
+    pub target_seconds: Option<u64>,
+    pub task_rotation_strategy: Option<String>,
+}
+
+/// A task row as stored, archived and done ones included.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub id: u64,
+    pub project_id: u64,
+    pub name: String,
+    pub position: i64,
+    pub time_seconds: u64,
+    pub done_at: Option<u64>,
+    pub archived_at: Option<u64>,
+    pub rotation_weight: f64,
+    pub target_seconds: Option<u64>,
+}
+
+/// A project's own time policy, overriding the global one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRecord {
+    pub project_id: u64,
+    #[serde(flatten)]
+    pub policy: TimePolicy,
+}
+
+/// Everything needed to rebuild the data on another machine. The undo
+/// journal and the audit log stay behind.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: u64,
+    pub projects: Vec<ProjectRecord>,
+    pub tasks: Vec<TaskRecord>,
+    pub time_entries: Vec<EntryRecord>,
+    pub active_tracking: Vec<SessionRecord>,
+    pub app_state: BTreeMap<String, String>,
+    #[serde(default)]
+    pub budgets: Vec<Budget>,
+    #[serde(default)]
+    pub time_policies: Vec<PolicyRecord>,
+    #[serde(default)]
+    pub rotation_events: Vec<RotationEvent>,
+    #[serde(default)]
+    pub timebox_blocks: Vec<TimeboxBlock>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Throw the current data away and take the archive's, ids and all.
+    /// Undo history goes too, as it describes the data being replaced.
+    Replace,
+    /// Add the archive to the current data. Projects and tasks are matched
+    /// by name; everything else gets new ids. Budgets and policies fill in
+    /// where the matched project or task has none; history is left out.
+    Merge,
+}
+
+/// Something from the archive that was left out of a merge.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportConflict {
+    /// The entry overlaps one already tracked on the same task.
+    TimeEntry { archive_entry_id: u64, existing_entry_id: u64 },
+    /// The task is already running here.
+    RunningSession { task_id: u64 },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub projects_added: usize,
+    pub tasks_added: usize,
+    pub entries_added: usize,
+    pub sessions_added: usize,
+    /// Entries that were already there with the same task, start and end.
+    pub duplicates: usize,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+/// Settings that point at rows by id, so they only survive a replace.
+const ID_SETTINGS: &[&str] = &["current_project_id"];
+
+const PROJECT_COLUMNS: &str =
+    "id, name, position, archived_at, current_task_id, rotation_weight, target_seconds, task_rotation_strategy";
+const TASK_COLUMNS: &str = "id, project_id, name, position, time_seconds, done_at, archived_at, rotation_weight, target_seconds";
+
+fn project_from_row(row: &Row) -> rusqlite::Result<ProjectRecord> {
+    Ok(ProjectRecord {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        position: row.get(2)?,
+        archived_at: row.get(3)?,
+        current_task_id: row.get(4)?,
+        rotation_weight: row.get(5)?,
+        target_seconds: row.get(6)?,
+        task_rotation_strategy: row.get(7)?,
+    })
+}
+
+fn task_from_row(row: &Row) -> rusqlite::Result<TaskRecord> {
+    Ok(TaskRecord {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        name: row.get(2)?,
+        position: row.get(3)?,
+        time_seconds: row.get(4)?,
+        done_at: row.get(5)?,
+        archived_at: row.get(6)?,
+        rotation_weight: row.get(7)?,
+        target_seconds: row.get(8)?,
+    })
+}
+
+/// The project with its tasks and time entries, in archive form, so the
+/// audit log keeps what a permanent delete takes away.
+pub(crate) fn project_snapshot(conn: &Connection, project_id: u64) -> Result<Value, RotatorError> {
+    let project = conn.query_row(
+        &format!("SELECT {} FROM projects WHERE id = ?", PROJECT_COLUMNS),
+        [project_id],
+        project_from_row,
+    )?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM tasks WHERE project_id = ? ORDER BY id", TASK_COLUMNS))?;
+    let tasks = stmt.query_map([project_id], task_from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let time_entries = entry_records(conn, "project_id", project_id)?;
+    Ok(json!({ "project": project, "tasks": tasks, "time_entries": time_entries }))
+}
+
+/// The task with its time entries, as [`project_snapshot`] does for projects.
+pub(crate) fn task_snapshot(conn: &Connection, task_id: u64) -> Result<Value, RotatorError> {
+    let task = conn.query_row(&format!("SELECT {} FROM tasks WHERE id = ?", TASK_COLUMNS), [task_id], task_from_row)?;
+    let time_entries = entry_records(conn, "task_id", task_id)?;
+    Ok(json!({ "task": task, "time_entries": time_entries }))
+}
+
+fn entry_records(conn: &Connection, column: &str, id: u64) -> Result<Vec<EntryRecord>, RotatorError> {
+    let mut stmt = conn.prepare(&format!("SELECT id FROM time_entries WHERE {} = ? ORDER BY id", column))?;
+    let ids = stmt.query_map([id], |row| row.get(0))?.collect::<rusqlite::Result<Vec<u64>>>()?;
+    ids.into_iter().map(|id| load_entry_record(conn, id)).collect()
+}
+
+/// Insert `project` under `id`, or a fresh id when `None`, and return it.
+fn insert_project(conn: &Connection, id: Option<u64>, project: &ProjectRecord, position: i64) -> rusqlite::Result<u64> {
+    conn.execute(
+        "INSERT INTO projects (id, name, position, archived_at, archived, rotation_weight, target_seconds, task_rotation_strategy)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            id,
+            project.name,
+            position,
+            project.archived_at,
+            project.archived_at.is_some(),
+            project.rotation_weight,
+            project.target_seconds,
+            project.task_rotation_strategy,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn insert_task(conn: &Connection, id: Option<u64>, task: &TaskRecord, project_id: u64, position: i64) -> rusqlite::Result<u64> {
+    conn.execute(
+        "INSERT INTO tasks (id, project_id, name, position, time_seconds, done_at, archived_at, archived, rotation_weight, target_seconds)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            id,
+            project_id,
+            task.name,
+            position,
+            task.time_seconds,
+            task.done_at,
+            task.archived_at,
+            task.archived_at.is_some(),
+            task.rotation_weight,
+            task.target_seconds,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn insert_budget(conn: &Connection, id: Option<u64>, budget: &Budget, project_id: u64, task_id: Option<u64>) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO budgets (id, project_id, task_id, period, limit_seconds) VALUES (?, ?, ?, ?, ?)",
+        params![id, project_id, task_id, budget.period.as_str(), budget.limit_seconds],
+    )?;
+    Ok(())
+}
+
+fn insert_policy(conn: &Connection, project_id: u64, policy: &TimePolicy) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO project_time_policies (project_id, min_session_seconds, rounding, round_to_minutes) VALUES (?, ?, ?, ?)",
+        params![project_id, policy.min_session_seconds, policy.rounding.as_str(), policy.round_to_minutes],
+    )?;
+    Ok(())
+}
+
+impl Archive {
+    pub fn from_json(json: &str) -> Result<Self, RotatorError> {
+        let archive: Archive = serde_json::from_str(json)
+            .map_err(|e| RotatorError::invalid(format!("not a Rotator archive: {}", e)))?;
+        archive.validate()?;
+        Ok(archive)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Check the archive hangs together before anything is written.
+    fn validate(&self) -> Result<(), RotatorError> {
+        if self.format != ARCHIVE_FORMAT {
+            return Err(RotatorError::invalid("not a Rotator archive"));
+        }
+        if self.version == 0 || self.version > ARCHIVE_VERSION {
+            return Err(RotatorError::invalid(format!(
+                "archive version {} is not supported by this build (up to {})",
+                self.version, ARCHIVE_VERSION,
+            )));
+        }
+
+        let mut projects = HashSet::new();
+        for project in &self.projects {
+            if !projects.insert(project.id) {
+                return Err(RotatorError::invalid(format!("project {} appears twice", project.id)));
+            }
+        }
+        let mut tasks = HashMap::new();
+        for task in &self.tasks {
+            if !projects.contains(&task.project_id) {
+                return Err(RotatorError::invalid(format!("task {} belongs to missing project {}", task.id, task.project_id)));
+            }
+            if tasks.insert(task.id, task.project_id).is_some() {
+                return Err(RotatorError::invalid(format!("task {} appears twice", task.id)));
+            }
+        }
+        let belongs = |project_id: u64, task_id: u64| tasks.get(&task_id) == Some(&project_id);
+
+        let mut entries = HashSet::new();
+        for entry in &self.time_entries {
+            if !belongs(entry.project_id, entry.task_id) {
+                return Err(RotatorError::invalid(format!("time entry {} belongs to missing task {}", entry.id, entry.task_id)));
+            }
+            if entry.end_time < entry.start_time {
+                return Err(RotatorError::invalid(format!("time entry {} ends before it starts", entry.id)));
+            }
+            if !entries.insert(entry.id) {
+                return Err(RotatorError::invalid(format!("time entry {} appears twice", entry.id)));
+            }
+        }
+        let mut running = HashSet::new();
+        for session in &self.active_tracking {
+            if !belongs(session.project_id, session.task_id) {
+                return Err(RotatorError::invalid(format!("running session belongs to missing task {}", session.task_id)));
+            }
+            if !running.insert(session.task_id) {
+                return Err(RotatorError::invalid(format!("task {} is running twice", session.task_id)));
+            }
+        }
+        for budget in &self.budgets {
+            let known = match budget.task_id {
+                Some(task_id) => belongs(budget.project_id, task_id),
+                None => projects.contains(&budget.project_id),
+            };
+            if !known {
+                return Err(RotatorError::invalid(format!("budget {} belongs to a missing project or task", budget.id)));
+            }
+        }
+        for record in &self.time_policies {
+            if !projects.contains(&record.project_id) {
+                return Err(RotatorError::invalid(format!("time policy belongs to missing project {}", record.project_id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Rotator {
+    /// Every project, task, time entry and running session, the settings,
+    /// budgets and policies, and the rotation and timebox history.
+    pub fn export_archive(&self) -> Result<Archive, RotatorError> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM projects ORDER BY id", PROJECT_COLUMNS))?;
+        let projects = stmt.query_map([], project_from_row)?.collect::<rusqlite::Result<_>>()?;
+
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM tasks ORDER BY id", TASK_COLUMNS))?;
+        let tasks = stmt.query_map([], task_from_row)?.collect::<rusqlite::Result<_>>()?;
+
+        let mut stmt = self.conn.prepare("SELECT id FROM time_entries ORDER BY id")?;
+        let entry_ids = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<Vec<u64>>>()?;
+        let time_entries = entry_ids.into_iter()
+            .map(|id| load_entry_record(&self.conn, id))
+            .collect::<Result<_, _>>()?;
+
+        let active_tracking = load_active_tracking(&self.conn)?
+            .iter()
+            .map(|session| load_session_record(&self.conn, session))
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut stmt = self.conn.prepare("SELECT key, value FROM app_state")?;
+        let app_state = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+
+        let mut stmt = self.conn.prepare("SELECT project_id FROM project_time_policies ORDER BY project_id")?;
+        let policy_ids = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<Vec<u64>>>()?;
+        let mut time_policies = Vec::new();
+        for project_id in policy_ids {
+            if let Some(policy) = load_project_policy(&self.conn, project_id)? {
+                time_policies.push(PolicyRecord { project_id, policy });
+            }
+        }
+
+        // SQLite integers are signed, so this is the end of time
+        let end = i64::MAX as u64;
+        Ok(Archive {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            exported_at: self.clock.now(),
+            projects,
+            tasks,
+            time_entries,
+            active_tracking,
+            app_state,
+            budgets: load_budgets(&self.conn)?,
+            time_policies,
+            rotation_events: self.rotation_events(0, end)?,
+            timebox_blocks: self.timebox_blocks(0, end)?,
+        })
+    }
+
+    pub fn export_archive_to(&self, path: &Path) -> Result<(), RotatorError> {
+        std::fs::write(path, self.export_archive()?.to_json())?;
+        Ok(())
+    }
+
+    pub fn import_archive_from(&mut self, path: &Path, mode: ImportMode) -> Result<ImportReport, RotatorError> {
+        let archive = Archive::from_json(&std::fs::read_to_string(path)?)?;
+        self.import_archive(&archive, mode)
+    }
+
+    /// Bring an archive in. The database is backed up first, and nothing is
+    /// written unless the whole import succeeds.
+    pub fn import_archive(&mut self, archive: &Archive, mode: ImportMode) -> Result<ImportReport, RotatorError> {
+        archive.validate()?;
+        self.create_backup(BackupReason::Import)?;
+
+        let report = match mode {
+            ImportMode::Replace => self.replace_with(archive)?,
+            ImportMode::Merge => self.merge_in(archive)?,
+        };
+
+        self.reload()?;
+        self.orphaned = load_orphaned_sessions(&self.conn, self.clock.now())?;
+        if mode == ImportMode::Replace {
+            self.pending_idle = None;
+            self.timebox = None;
+        }
+        Ok(report)
+    }
+
+    fn replace_with(&mut self, archive: &Archive) -> Result<ImportReport, RotatorError> {
+        let now = self.clock.now();
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        for table in DATA_TABLES {
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+        for project in &archive.projects {
+            insert_project(&tx, Some(project.id), project, project.position)?;
+        }
+        for task in &archive.tasks {
+            insert_task(&tx, Some(task.id), task, task.project_id, task.position)?;
+        }
+        // Set once the tasks exist
+        for project in &archive.projects {
+            tx.execute("UPDATE projects SET current_task_id = ? WHERE id = ?", params![project.current_task_id, project.id])?;
+        }
+        for entry in &archive.time_entries {
+            insert_entry_record(&tx, entry)?;
+        }
+        for session in &archive.active_tracking {
+            insert_session_record(&tx, session, now)?;
+        }
+        for (key, value) in &archive.app_state {
+            tx.execute("INSERT INTO app_state (key, value) VALUES (?, ?)", params![key, value])?;
+        }
+        for budget in &archive.budgets {
+            insert_budget(&tx, Some(budget.id), budget, budget.project_id, budget.task_id)?;
+        }
+        for record in &archive.time_policies {
+            insert_policy(&tx, record.project_id, &record.policy)?;
+        }
+        for event in &archive.rotation_events {
+            tx.execute(
+                "INSERT INTO rotation_events
+                    (id, occurred_at, from_project_id, from_task_id, to_project_id, to_task_id, trigger, tracking_followed)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    event.id,
+                    event.occurred_at,
+                    event.from_project_id,
+                    event.from_task_id,
+                    event.to_project_id,
+                    event.to_task_id,
+                    event.trigger.as_str(),
+                    event.tracking_followed,
+                ],
+            )?;
+        }
+        for block in &archive.timebox_blocks {
+            tx.execute(
+                "INSERT INTO timebox_blocks (id, kind, project_id, task_id, started_at, ended_at, completed)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+                params![block.id, block.kind.as_str(), block.project_id, block.task_id, block.started_at, block.ended_at, block.completed],
+            )?;
+        }
+
+        let report = ImportReport {
+            projects_added: archive.projects.len(),
+            tasks_added: archive.tasks.len(),
+            entries_added: archive.time_entries.len(),
+            sessions_added: archive.active_tracking.len(),
+            ..ImportReport::default()
+        };
+        audit.record(&tx, "import_archive", None, Value::Null, snapshot(&report))?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    fn merge_in(&mut self, archive: &Archive) -> Result<ImportReport, RotatorError> {
+        let now = self.clock.now();
+        let mut report = ImportReport::default();
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+
+        // Archive id -> id here
+        let mut projects: HashMap<u64, u64> = HashMap::new();
+        let mut added_projects = Vec::new();
+        for project in &archive.projects {
+            let existing: Option<u64> = tx.query_row(
+                "SELECT id FROM projects WHERE name = ? ORDER BY archived_at IS NOT NULL, id LIMIT 1",
+                [&project.name],
+                |row| row.get(0),
+            ).optional()?;
+            let id = match existing {
+                Some(id) => id,
+                None => {
+                    report.projects_added += 1;
+                    added_projects.push(project);
+                    insert_project(&tx, None, project, next_project_position(&tx)?)?
+                }
+            };
+            projects.insert(project.id, id);
+        }
+
+        let mut tasks: HashMap<u64, u64> = HashMap::new();
+        let mut sorted_tasks: Vec<&TaskRecord> = archive.tasks.iter().collect();
+        sorted_tasks.sort_by_key(|t| (t.project_id, t.position, t.id));
+        for task in sorted_tasks {
+            let project_id = projects[&task.project_id];
+            let existing: Option<u64> = tx.query_row(
+                "SELECT id FROM tasks WHERE project_id = ? AND name = ? ORDER BY archived_at IS NOT NULL, id LIMIT 1",
+                params![project_id, task.name],
+                |row| row.get(0),
+            ).optional()?;
+            let id = match existing {
+                Some(id) => id,
+                None => {
+                    report.tasks_added += 1;
+                    // Time comes from the entries merged below
+                    let fresh = TaskRecord { time_seconds: 0, ..task.clone() };
+                    insert_task(&tx, None, &fresh, project_id, next_task_position(&tx, project_id)?)?
+                }
+            };
+            tasks.insert(task.id, id);
+        }
+        for project in added_projects {
+            let current_task_id = project.current_task_id.and_then(|id| tasks.get(&id));
+            tx.execute("UPDATE projects SET current_task_id = ? WHERE id = ?", params![current_task_id, projects[&project.id]])?;
+        }
+
+        let mut touched: Vec<u64> = Vec::new();
+        for entry in &archive.time_entries {
+            let task_id = tasks[&entry.task_id];
+            let duplicate: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM time_entries WHERE task_id = ? AND start_time = ? AND end_time = ?)",
+                params![task_id, entry.start_time, entry.end_time],
+                |row| row.get(0),
+            )?;
+            if duplicate {
+                report.duplicates += 1;
+                continue;
+            }
+            if let Some(existing_entry_id) = find_overlap(&tx, task_id, entry.start_time, entry.end_time, &[])? {
+                report.conflicts.push(ImportConflict::TimeEntry { archive_entry_id: entry.id, existing_entry_id });
+                continue;
+            }
+            let id = next_entry_id(&tx)?;
+            insert_entry_record(&tx, &EntryRecord { id, project_id: projects[&entry.project_id], task_id, ..entry.clone() })?;
+            report.entries_added += 1;
+            touched.push(task_id);
+        }
+
+        for session in &archive.active_tracking {
+            let task_id = tasks[&session.task_id];
+            let running: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM active_tracking WHERE task_id = ?)",
+                [task_id],
+                |row| row.get(0),
+            )?;
+            if running {
+                report.conflicts.push(ImportConflict::RunningSession { task_id });
+                continue;
+            }
+            insert_session_record(&tx, &SessionRecord { project_id: projects[&session.project_id], task_id, ..session.clone() }, now)?;
+            report.sessions_added += 1;
+        }
+
+        // Budgets and policies already set here win
+        for budget in &archive.budgets {
+            let task_id = budget.task_id.map(|id| tasks[&id]);
+            let project_id = projects[&budget.project_id];
+            let taken: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM budgets WHERE project_id = ? AND task_id IS ? AND period = ?)",
+                params![project_id, task_id, budget.period.as_str()],
+                |row| row.get(0),
+            )?;
+            if !taken {
+                insert_budget(&tx, None, budget, project_id, task_id)?;
+            }
+        }
+        for record in &archive.time_policies {
+            if load_project_policy(&tx, projects[&record.project_id])?.is_none() {
+                insert_policy(&tx, projects[&record.project_id], &record.policy)?;
+            }
+        }
+
+        // Settings already made here win
+        for (key, value) in &archive.app_state {
+            if !ID_SETTINGS.contains(&key.as_str()) {
+                tx.execute("INSERT OR IGNORE INTO app_state (key, value) VALUES (?, ?)", params![key, value])?;
+            }
+        }
+
+        touched.sort_unstable();
+        touched.dedup();
+        for &task_id in &touched {
+            refresh_task_total(&tx, task_id)?;
+        }
+        audit.record(&tx, "import_archive", None, Value::Null, snapshot(&report))?;
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+    use crate::{BudgetPeriod, IdleSettings, RoundingMode};
+
+    // Wednesday 2024-03-13 12:00 UTC
+    const NOW: u64 = 1_710_331_200;
+
+    /// Two projects, one archived, with a done task, a paused entry and a
+    /// session still running.
+    fn populated() -> Rotator {
+        let (mut rotator, clock) = rotator_at(NOW);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let old = rotator.add_project("Old".into()).unwrap()[1].id;
+        let tasks = rotator.add_task(work, "Review".into()).unwrap();
+        let review = tasks.tasks[0].id;
+        let docs = rotator.add_task(work, "Docs".into()).unwrap().tasks[1].id;
+        rotator.add_task(old, "Legacy".into()).unwrap();
+        rotator.set_project_rotation_weight(work, 2.0, Some(3_600)).unwrap();
+        rotator.set_idle_settings(IdleSettings { threshold_seconds: 600, ..IdleSettings::default() }).unwrap();
+
+        rotator.start_tracking(work, review, false).unwrap();
+        clock.advance(300);
+        rotator.pause_tracking(None).unwrap();
+        clock.advance(60);
+        rotator.resume_tracking(None).unwrap();
+        clock.advance(600);
+        rotator.stop_tracking(None).unwrap();
+        rotator.add_time_entry_manual(work, docs, NOW - 7_200, 1_200).unwrap();
+        rotator.toggle_task_done(work, docs, true).unwrap();
+        rotator.remove_project(old).unwrap();
+        rotator.start_tracking(work, review, false).unwrap();
+        clock.advance(120);
+        rotator
+    }
+
+    #[test]
+    fn replace_round_trips_through_json() {
+        let source = populated();
+        let archive = source.export_archive().unwrap();
+        assert_eq!(archive.time_entries[0].pauses.len(), 1);
+        assert_eq!(archive.active_tracking.len(), 1);
+
+        let (mut target, _) = rotator_at(source.now());
+        target.add_project("Scratch".into()).unwrap();
+        let report = target.import_archive(&Archive::from_json(&archive.to_json()).unwrap(), ImportMode::Replace).unwrap();
+        assert_eq!((report.projects_added, report.tasks_added, report.entries_added, report.sessions_added), (2, 3, 2, 1));
+
+        assert_eq!(target.export_archive().unwrap(), archive);
+        assert_eq!(snapshot(&target.projects()), snapshot(&source.projects()));
+        assert_eq!(target.active_tracking(), source.active_tracking());
+        assert_eq!(target.idle_settings(), source.idle_settings());
+        assert_eq!(target.current_project_id(), source.current_project_id());
+    }
+
+    #[test]
+    fn replace_keeps_budgets_and_policies() {
+        let mut source = populated();
+        let work = source.projects()[0].id;
+        let review = source.projects()[0].tasks[0].id;
+        let budget = source.set_budget(work, Some(review), BudgetPeriod::Week, 7_200).unwrap();
+        let policy = TimePolicy { min_session_seconds: 60, rounding: RoundingMode::Up, round_to_minutes: 15 };
+        source.set_project_time_policy(work, Some(policy)).unwrap();
+        let archive = source.export_archive().unwrap();
+        assert_eq!(archive.budgets.len(), 1);
+
+        let (mut target, _) = rotator_at(source.now());
+        target.import_archive(&Archive::from_json(&archive.to_json()).unwrap(), ImportMode::Replace).unwrap();
+        assert_eq!(target.budgets().unwrap(), [budget]);
+        assert_eq!(target.project_time_policy(work).unwrap(), Some(policy));
+        assert_eq!(target.export_archive().unwrap(), archive);
+
+        // Version 1 archives had neither and still import
+        let mut json: serde_json::Value = serde_json::from_str(&archive.to_json()).unwrap();
+        json["version"] = 1.into();
+        for key in ["budgets", "time_policies", "rotation_events", "timebox_blocks"] {
+            json.as_object_mut().unwrap().remove(key);
+        }
+        let old = Archive::from_json(&json.to_string()).unwrap();
+        target.import_archive(&old, ImportMode::Replace).unwrap();
+        assert!(target.budgets().unwrap().is_empty());
+        assert_eq!(target.project_time_policy(work).unwrap(), None);
+    }
+
+    #[test]
+    fn merge_matches_by_name_and_skips_what_is_already_there() {
+        let source = populated();
+        let archive = source.export_archive().unwrap();
+
+        let (mut target, clock) = rotator_at(source.now());
+        target.add_project("Home".into()).unwrap();
+        let work = target.add_project("Work".into()).unwrap()[1].id;
+        let review = target.add_task(work, "Review".into()).unwrap().tasks[0].id;
+        // Overlaps the archived review entry
+        target.add_time_entry_manual(work, review, NOW + 100, 60).unwrap();
+        target.start_tracking(work, review, false).unwrap();
+        clock.advance(10);
+
+        let report = target.import_archive(&archive, ImportMode::Merge).unwrap();
+        assert_eq!((report.projects_added, report.tasks_added, report.entries_added, report.sessions_added), (1, 2, 1, 0));
+        assert_eq!(report.conflicts.len(), 2);
+        assert!(matches!(report.conflicts[0], ImportConflict::TimeEntry { .. }));
+        assert_eq!(report.conflicts[1], ImportConflict::RunningSession { task_id: review });
+
+        // Merging again finds the docs entry already there
+        let again = target.import_archive(&archive, ImportMode::Merge).unwrap();
+        assert_eq!((again.projects_added, again.tasks_added, again.entries_added, again.duplicates), (0, 0, 0, 1));
+
+        let projects = target.projects();
+        let names: Vec<&str> = projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Home", "Work"]);
+        let docs = projects[1].tasks.iter().find(|t| t.name == "Docs").unwrap();
+        assert_eq!(docs.time_seconds, 1_200);
+        // Settings only fill gaps
+        assert_eq!(target.idle_settings().threshold_seconds, 600);
+    }
+
+    #[test]
+    fn rejects_newer_or_broken_archives() {
+        let source = populated();
+        let mut archive = source.export_archive().unwrap();
+        archive.version = ARCHIVE_VERSION + 1;
+        assert!(matches!(Archive::from_json(&archive.to_json()), Err(RotatorError::Validation(_))));
+
+        archive.version = ARCHIVE_VERSION;
+        archive.tasks.remove(0);
+        let (mut target, _) = rotator_at(NOW);
+        target.add_project("Keep".into()).unwrap();
+        assert!(matches!(target.import_archive(&archive, ImportMode::Replace), Err(RotatorError::Validation(_))));
+        assert_eq!(target.projects()[0].name, "Keep");
+
+        assert!(matches!(Archive::from_json("{\"format\": \"other\"}"), Err(RotatorError::Validation(_))));
+    }
+}
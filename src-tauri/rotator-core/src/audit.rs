@@ -0,0 +1,306 @@
+use crate::error::{Entity, RotatorError};
+use crate::journal::EntryRecord;
+use crate::models::ClosedSession;
+use crate::rotator::Rotator;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where a change came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSource {
+    /// The main window.
+    Ui,
+    /// A global shortcut.
+    Hotkey,
+    FloatingPanel,
+    /// The core used directly, without the app.
+    Cli,
+    /// The app's own timers and background checks, with nobody asking.
+    System,
+}
+
+impl AuditSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditSource::Ui => "ui",
+            AuditSource::Hotkey => "hotkey",
+            AuditSource::FloatingPanel => "floating_panel",
+            AuditSource::Cli => "cli",
+            AuditSource::System => "system",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ui" => Some(AuditSource::Ui),
+            "hotkey" => Some(AuditSource::Hotkey),
+            "floating_panel" => Some(AuditSource::FloatingPanel),
+            "cli" => Some(AuditSource::Cli),
+            "system" => Some(AuditSource::System),
+            _ => None,
+        }
+    }
+}
+
+/// One row of the audit log. `before` and `after` hold whatever the command
+/// changed, `None` where there was nothing on that side.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub id: u64,
+    pub at: u64,
+    pub command: String,
+    pub source: AuditSource,
+    pub entity: Option<Entity>,
+    pub entity_id: Option<u64>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+fn record_from_row(row: &Row) -> rusqlite::Result<AuditRecord> {
+    let json = |index: usize| -> rusqlite::Result<Option<Value>> {
+        Ok(row.get::<_, Option<String>>(index)?.and_then(|text| serde_json::from_str(&text).ok()))
+    };
+    Ok(AuditRecord {
+        id: row.get(0)?,
+        at: row.get(1)?,
+        command: row.get(2)?,
+        source: AuditSource::parse(&row.get::<_, String>(3)?).unwrap_or(AuditSource::Cli),
+        entity: row.get::<_, Option<String>>(4)?.and_then(|e| Entity::parse(&e)),
+        entity_id: row.get(5)?,
+        before: json(6)?,
+        after: json(7)?,
+    })
+}
+
+/// JSON of any serializable value, for `before` and `after`.
+pub(crate) fn snapshot<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// `{ "<name>": value }`, for changes to a single field.
+pub(crate) fn field<T: Serialize>(name: &str, value: &T) -> Value {
+    let mut object = serde_json::Map::new();
+    object.insert(name.to_string(), snapshot(value));
+    Value::Object(object)
+}
+
+/// Writes audit rows through whatever connection or transaction it is given.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Auditor {
+    at: u64,
+    source: AuditSource,
+    replaying: bool,
+}
+
+impl Auditor {
+    /// Append a row to the audit log. Replays by undo and redo are logged
+    /// once as the undo or redo itself.
+    pub(crate) fn record(&self, conn: &Connection, command: &str, entity: Option<(Entity, u64)>, before: Value, after: Value) -> rusqlite::Result<()> {
+        if self.replaying {
+            return Ok(());
+        }
+        let text = |value: Value| (!value.is_null()).then(|| value.to_string());
+        conn.execute(
+            "INSERT INTO audit_log (at, command, source, entity, entity_id, before, after) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                self.at,
+                command,
+                self.source.as_str(),
+                entity.map(|(e, _)| e.as_str()),
+                entity.map(|(_, id)| id),
+                text(before),
+                text(after),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Log the time entries a stop recorded, or the task when its session
+    /// was too short to keep.
+    pub(crate) fn closed(&self, conn: &Connection, command: &str, closed: &[ClosedSession]) -> rusqlite::Result<()> {
+        for session in closed {
+            let entity = match session.entry_id {
+                Some(entry_id) => (Entity::TimeEntry, entry_id),
+                None => (Entity::Task, session.task_id),
+            };
+            self.record(conn, command, Some(entity), Value::Null, snapshot(session))?;
+        }
+        Ok(())
+    }
+
+    /// Log an edit of time entries, one row per entry it touched.
+    pub(crate) fn entries(&self, conn: &Connection, command: &str, before: &[EntryRecord], after: &[EntryRecord]) -> rusqlite::Result<()> {
+        let mut ids: Vec<u64> = before.iter().chain(after).map(|e| e.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        for id in ids {
+            let find = |records: &[EntryRecord]| records.iter().find(|e| e.id == id).map(snapshot).unwrap_or(Value::Null);
+            self.record(conn, command, Some((Entity::TimeEntry, id)), find(before), find(after))?;
+        }
+        Ok(())
+    }
+}
+
+impl Rotator {
+    /// Attribute the changes that follow to `source` until it is set again.
+    pub fn set_source(&mut self, source: AuditSource) {
+        self.source = source;
+    }
+
+    /// Who is changing what, and when, for audit rows written inside a
+    /// transaction that already borrows the connection.
+    pub(crate) fn auditor(&self) -> Auditor {
+        Auditor { at: self.clock.now(), source: self.source, replaying: self.replaying }
+    }
+
+    /// Append a row to the audit log on its own. Changes made in a
+    /// transaction log through `auditor` instead, so the row commits with them.
+    pub(crate) fn audit(&self, command: &str, entity: Option<(Entity, u64)>, before: Value, after: Value) -> rusqlite::Result<()> {
+        self.auditor().record(&self.conn, command, entity, before, after)
+    }
+
+    /// Every audit row, oldest first.
+    pub(crate) fn audit_records(&self) -> rusqlite::Result<Vec<AuditRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, at, command, source, entity, entity_id, before, after FROM audit_log ORDER BY id",
+        )?;
+        let records = stmt.query_map([], record_from_row)?;
+        records.collect()
+    }
+
+    /// Put back the rows of `records` that are newer than the last one in
+    /// the log, keeping their ids. Used after a restore, whose backup only
+    /// has the log up to the moment it was taken.
+    pub(crate) fn append_audit_records(&self, records: &[AuditRecord]) -> rusqlite::Result<()> {
+        let last: u64 = self.conn.query_row("SELECT COALESCE(MAX(id), 0) FROM audit_log", [], |row| row.get(0))?;
+        let text = |value: &Option<Value>| value.as_ref().map(Value::to_string);
+        for record in records.iter().filter(|r| r.id > last) {
+            self.conn.execute(
+                "INSERT INTO audit_log (id, at, command, source, entity, entity_id, before, after) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    record.id,
+                    record.at,
+                    record.command,
+                    record.source.as_str(),
+                    record.entity.map(Entity::as_str),
+                    record.entity_id,
+                    text(&record.before),
+                    text(&record.after),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Audit rows, newest first, optionally only those about `entity` (and
+    /// `entity_id`).
+    pub fn audit_log(&self, entity: Option<Entity>, entity_id: Option<u64>, limit: usize) -> Result<Vec<AuditRecord>, RotatorError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, at, command, source, entity, entity_id, before, after FROM audit_log
+             WHERE (?1 IS NULL OR entity = ?1) AND (?2 IS NULL OR entity_id = ?2)
+             ORDER BY id DESC LIMIT ?3",
+        )?;
+        let records = stmt.query_map(params![entity.map(Entity::as_str), entity_id, limit], record_from_row)?;
+        Ok(records.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+    use serde_json::json;
+
+    #[test]
+    fn change_is_rolled_back_when_its_audit_row_fails() {
+        let (mut rotator, _) = rotator_at(10_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.connection().execute_batch(
+            "CREATE TRIGGER reject_audit BEFORE INSERT ON audit_log
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+        ).unwrap();
+
+        assert!(matches!(rotator.add_time_entry_manual(project_id, task_id, 1_000, 600), Err(RotatorError::Db(_))));
+        assert!(matches!(rotator.remove_task(project_id, task_id), Err(RotatorError::Db(_))));
+        rotator.reload().unwrap();
+        assert!(rotator.all_time_entries().unwrap().is_empty());
+        assert_eq!(rotator.projects()[0].tasks.len(), 1);
+    }
+
+    #[test]
+    fn manual_and_edited_entries_are_flagged() {
+        let (mut rotator, clock) = rotator_at(10_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(600);
+        rotator.stop_tracking(None).unwrap();
+        let manual = rotator.add_time_entry_manual(project_id, task_id, 1_000, 600).unwrap();
+
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!((entries[0].manual, entries[0].edited_at), (true, None));
+        assert_eq!((entries[1].manual, entries[1].edited_at), (false, None));
+
+        clock.advance(60);
+        let (first, second) = rotator.split_time_entry(entries[1].id, 10_300).unwrap();
+        assert_eq!((first.manual, first.edited_at), (false, Some(10_660)));
+        assert_eq!((second.manual, second.edited_at), (false, Some(10_660)));
+        let moved = rotator.update_time_entry(manual.id, 2_000, 2_600).unwrap();
+        assert_eq!((moved.manual, moved.edited_at), (true, Some(10_660)));
+    }
+
+    #[test]
+    fn mutations_are_logged_with_their_source() {
+        let (mut rotator, clock) = rotator_at(10_000);
+        rotator.set_source(AuditSource::Ui);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.rename_task(project_id, task_id, "B".into()).unwrap();
+        rotator.set_source(AuditSource::Hotkey);
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(600);
+        let entry_id = rotator.stop_tracking(None).unwrap()[0].entry_id.unwrap();
+        rotator.set_source(AuditSource::Ui);
+        rotator.update_time_entry(entry_id, 10_100, 10_600).unwrap();
+
+        let task_log = rotator.audit_log(Some(Entity::Task), Some(task_id), 10).unwrap();
+        let commands: Vec<&str> = task_log.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, ["start_tracking", "rename_task", "add_task"]);
+        assert_eq!(task_log[0].source, AuditSource::Hotkey);
+        assert_eq!(task_log[1].before, Some(json!({ "name": "A" })));
+        assert_eq!(task_log[1].after, Some(json!({ "name": "B" })));
+
+        let entry_log = rotator.audit_log(Some(Entity::TimeEntry), Some(entry_id), 10).unwrap();
+        assert_eq!(entry_log.len(), 2);
+        assert_eq!((entry_log[0].command.as_str(), entry_log[0].source), ("update_time_entry", AuditSource::Ui));
+        assert_eq!(entry_log[0].before.as_ref().unwrap()["start_time"], 10_000);
+        assert_eq!(entry_log[0].after.as_ref().unwrap()["start_time"], 10_100);
+        assert_eq!((entry_log[1].command.as_str(), entry_log[1].source), ("stop_tracking", AuditSource::Hotkey));
+        assert_eq!(entry_log[1].at, 10_600);
+    }
+
+    #[test]
+    fn undo_is_logged_once_and_the_log_survives_reset() {
+        let (mut rotator, _) = rotator_at(10_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        rotator.rename_project(project_id, "Home".into()).unwrap();
+        rotator.undo().unwrap();
+        let commands: Vec<String> = rotator.audit_log(None, None, 10).unwrap().into_iter().map(|r| r.command).collect();
+        assert_eq!(commands, ["undo", "rename_project", "add_project"]);
+
+        rotator.reset_database().unwrap();
+        assert_eq!(rotator.audit_log(None, None, 10).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn log_is_append_only() {
+        let (mut rotator, _) = rotator_at(10_000);
+        rotator.add_project("Work".into()).unwrap();
+        assert!(rotator.connection().execute("DELETE FROM audit_log", []).is_err());
+        assert!(rotator.connection().execute("UPDATE audit_log SET source = 'cli'", []).is_err());
+        assert_eq!(rotator.audit_log(None, None, 10).unwrap().len(), 1);
+    }
+}
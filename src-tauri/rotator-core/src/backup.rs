@@ -0,0 +1,463 @@
+use crate::audit::{field, snapshot};
+use crate::budget::{civil_from_days, days_from_civil, BudgetPeriod};
+use crate::db::{load_active_tracking, load_setting, next_entry_id, retire_entry_ids, save_setting};
+use crate::error::RotatorError;
+use crate::migrations::{self, MigrationError};
+use crate::recovery::load_orphaned_sessions;
+use crate::rotator::Rotator;
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// How often `backup_if_due` takes a scheduled backup.
+pub const BACKUP_INTERVAL_SECONDS: u64 = 24 * 60 * 60;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Why a backup was taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupReason {
+    Scheduled,
+    Manual,
+    Reset,
+    DeleteProject,
+    DeleteTask,
+    Migration,
+    /// The state that was replaced by restoring another backup.
+    Restore,
+    /// The state before an archive was imported into it.
+    Import,
+}
+
+impl BackupReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackupReason::Scheduled => "scheduled",
+            BackupReason::Manual => "manual",
+            BackupReason::Reset => "reset",
+            BackupReason::DeleteProject => "delete-project",
+            BackupReason::DeleteTask => "delete-task",
+            BackupReason::Migration => "migration",
+            BackupReason::Restore => "restore",
+            BackupReason::Import => "import",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "scheduled" => Some(BackupReason::Scheduled),
+            "manual" => Some(BackupReason::Manual),
+            "reset" => Some(BackupReason::Reset),
+            "delete-project" => Some(BackupReason::DeleteProject),
+            "delete-task" => Some(BackupReason::DeleteTask),
+            "migration" => Some(BackupReason::Migration),
+            "restore" => Some(BackupReason::Restore),
+            "import" => Some(BackupReason::Import),
+            _ => None,
+        }
+    }
+}
+
+/// A backup file in the `backups/` folder next to the database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub path: String,
+    pub created_at: u64,
+    pub reason: BackupReason,
+    pub size_bytes: u64,
+}
+
+/// Which backups survive pruning. Everything from the last 24 hours is
+/// kept; older backups are thinned out to the newest one per day for the
+/// last `daily` days that have one, and the newest per week for the last
+/// `weekly` weeks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupRetention {
+    pub daily: u32,
+    pub weekly: u32,
+}
+
+impl Default for BackupRetention {
+    fn default() -> Self {
+        Self { daily: 7, weekly: 4 }
+    }
+}
+
+impl BackupRetention {
+    fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let default = Self::default();
+        let get = |key: &str, fallback: u32| -> rusqlite::Result<u32> {
+            Ok(load_setting(conn, key)?.and_then(|v| v.parse().ok()).unwrap_or(fallback))
+        };
+        Ok(Self {
+            daily: get("backup_keep_daily", default.daily)?,
+            weekly: get("backup_keep_weekly", default.weekly)?,
+        })
+    }
+
+    fn save(&self, conn: &Connection) -> rusqlite::Result<()> {
+        save_setting(conn, "backup_keep_daily", &self.daily.to_string())?;
+        save_setting(conn, "backup_keep_weekly", &self.weekly.to_string())
+    }
+
+    /// The backups of `backups` (newest first) to delete at `now`.
+    fn expired<'a>(&self, backups: &'a [BackupInfo], now: u64) -> Vec<&'a BackupInfo> {
+        let mut days: Vec<u64> = Vec::new();
+        let mut weeks: Vec<u64> = Vec::new();
+        let mut expired = Vec::new();
+        for backup in backups {
+            let day = BudgetPeriod::Day.bounds(backup.created_at).0;
+            let week = BudgetPeriod::Week.bounds(backup.created_at).0;
+            let recent = backup.created_at + DAY > now;
+            let newest_of_day = !days.contains(&day) && days.len() < self.daily as usize;
+            let newest_of_week = !weeks.contains(&week) && weeks.len() < self.weekly as usize;
+            if newest_of_day {
+                days.push(day);
+            }
+            if newest_of_week {
+                weeks.push(week);
+            }
+            if !(recent || newest_of_day || newest_of_week) {
+                expired.push(backup);
+            }
+        }
+        expired
+    }
+}
+
+/// `backups/` next to the database file, `None` for in-memory databases.
+fn backup_dir(conn: &Connection) -> Option<PathBuf> {
+    let path = Path::new(conn.path().filter(|p| !p.is_empty())?);
+    Some(path.parent()?.join("backups"))
+}
+
+/// `rotator-20240313-120000-reset.db`, in UTC. Further backups with the
+/// same reason in the same second get a sequence number after the time,
+/// `rotator-20240313-120000.1-reset.db`, so none overwrites another.
+fn file_name(created_at: u64, reason: BackupReason, sequence: u32) -> String {
+    let (year, month, day) = civil_from_days(created_at / DAY);
+    let seconds = created_at % DAY;
+    let sequence = if sequence == 0 { String::new() } else { format!(".{}", sequence) };
+    format!(
+        "rotator-{:04}{:02}{:02}-{:02}{:02}{:02}{}-{}.db",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, sequence, reason.as_str(),
+    )
+}
+
+fn parse_file_name(name: &str) -> Option<(u64, BackupReason, u32)> {
+    let rest = name.strip_prefix("rotator-")?.strip_suffix(".db")?;
+    let (date, rest) = rest.split_once('-')?;
+    let (time, reason) = rest.split_once('-')?;
+    let (time, sequence) = match time.split_once('.') {
+        Some((time, sequence)) => (time, sequence.parse().ok()?),
+        None => (time, 0),
+    };
+    if date.len() != 8 || time.len() != 6 {
+        return None;
+    }
+    let number = |s: &str| s.parse::<u64>().ok();
+    let days = days_from_civil(number(&date[..4])?, number(&date[4..6])?, number(&date[6..])?);
+    let seconds = number(&time[..2])? * 3600 + number(&time[2..4])? * 60 + number(&time[4..])?;
+    Some((days * DAY + seconds, BackupReason::parse(reason)?, sequence))
+}
+
+fn list_backups_in(dir: &Path) -> std::io::Result<Vec<BackupInfo>> {
+    let mut backups = Vec::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some((created_at, reason, sequence)) = parse_file_name(&file_name) else { continue };
+        backups.push((sequence, BackupInfo {
+            path: entry.path().to_string_lossy().into_owned(),
+            file_name,
+            created_at,
+            reason,
+            size_bytes: entry.metadata()?.len(),
+        }));
+    }
+    backups.sort_by(|(a_sequence, a), (b_sequence, b)| {
+        b.created_at.cmp(&a.created_at)
+            .then_with(|| b_sequence.cmp(a_sequence))
+            .then_with(|| b.file_name.cmp(&a.file_name))
+    });
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Copy the live database into the backup folder with SQLite's online
+/// backup API. `None` for in-memory databases, which have nowhere to go.
+pub(crate) fn backup_database(conn: &Connection, now: u64, reason: BackupReason) -> Result<Option<BackupInfo>, RotatorError> {
+    let Some(dir) = backup_dir(conn) else {
+        return Ok(None);
+    };
+    std::fs::create_dir_all(&dir)?;
+    let mut sequence = 0;
+    let (file_name, path) = loop {
+        let file_name = file_name(now, reason, sequence);
+        let path = dir.join(&file_name);
+        if !path.exists() {
+            break (file_name, path);
+        }
+        sequence += 1;
+    };
+    conn.backup(DatabaseName::Main, &path, None)?;
+    let size_bytes = std::fs::metadata(&path)?.len();
+    Ok(Some(BackupInfo { path: path.to_string_lossy().into_owned(), file_name, created_at: now, reason, size_bytes }))
+}
+
+impl Rotator {
+    pub fn backup_retention(&self) -> Result<BackupRetention, RotatorError> {
+        Ok(BackupRetention::load(&self.conn)?)
+    }
+
+    pub fn set_backup_retention(&mut self, retention: BackupRetention) -> Result<BackupRetention, RotatorError> {
+        let before = BackupRetention::load(&self.conn)?;
+        retention.save(&self.conn)?;
+        self.audit("set_backup_retention", None, snapshot(&before), snapshot(&retention))?;
+        self.prune_backups()?;
+        Ok(retention)
+    }
+
+    /// Backups of this database, newest first.
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>, RotatorError> {
+        match backup_dir(&self.conn) {
+            Some(dir) => Ok(list_backups_in(&dir)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Back the database up now and prune old backups.
+    pub fn create_backup(&mut self, reason: BackupReason) -> Result<Option<BackupInfo>, RotatorError> {
+        let backup = backup_database(&self.conn, self.clock.now(), reason)?;
+        self.prune_backups()?;
+        Ok(backup)
+    }
+
+    /// Take a scheduled backup if the newest one is older than
+    /// `BACKUP_INTERVAL_SECONDS`. Call it periodically.
+    pub fn backup_if_due(&mut self) -> Result<Option<BackupInfo>, RotatorError> {
+        let now = self.clock.now();
+        let newest = self.list_backups()?.first().map(|b| b.created_at);
+        if newest.is_some_and(|at| at + BACKUP_INTERVAL_SECONDS > now) {
+            return Ok(None);
+        }
+        self.create_backup(BackupReason::Scheduled)
+    }
+
+    fn prune_backups(&self) -> Result<(), RotatorError> {
+        let retention = BackupRetention::load(&self.conn)?;
+        let backups = self.list_backups()?;
+        for backup in retention.expired(&backups, self.clock.now()) {
+            std::fs::remove_file(&backup.path)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the live database with a backup. The current state is backed
+    /// up first, so a restore can itself be restored away. Sessions that were
+    /// running in the backup are running again afterwards.
+    pub fn restore_backup(&mut self, file_name: &str) -> Result<(), RotatorError> {
+        let backup = self.list_backups()?
+            .into_iter()
+            .find(|b| b.file_name == file_name)
+            .ok_or_else(|| RotatorError::invalid(format!("no backup named {}", file_name)))?;
+        // A backup from a newer build can't be migrated, and finding that
+        // out after copying it in would leave the live database overwritten
+        let source = Connection::open_with_flags(&backup.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let found = migrations::schema_version(&source)?;
+        drop(source);
+        let latest = migrations::latest_version();
+        if found > latest {
+            return Err(MigrationError::TooNew { found, latest }.into());
+        }
+        self.create_backup(BackupReason::Restore)?;
+        let log = self.audit_records()?;
+        let last_entry_id = next_entry_id(&self.conn)? - 1;
+
+        // Copies the backup into the open connection page by page, so the
+        // connection itself stays valid throughout
+        self.conn.restore(DatabaseName::Main, &backup.path, None::<fn(Progress)>)?;
+        migrations::migrate(&mut self.conn)?;
+        self.append_audit_records(&log)?;
+        // The backup's id counter is behind; calendar apps have already seen
+        // the ids handed out since
+        retire_entry_ids(&self.conn, last_entry_id)?;
+
+        self.reload()?;
+        self.active_tracking = load_active_tracking(&self.conn)?;
+        self.orphaned = load_orphaned_sessions(&self.conn, self.clock.now())?;
+        self.pending_idle = None;
+        self.timebox = None;
+        self.audit("restore_backup", None, Value::Null, field("file_name", &file_name))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::history::RotationTrigger;
+
+    // Wednesday 2024-03-13 12:00 UTC
+    const NOW: u64 = 1_710_331_200;
+
+    fn temp_rotator(name: &str) -> (Rotator, ManualClock, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rotator-backup-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let clock = ManualClock::new(NOW);
+        let rotator = Rotator::new(Connection::open(dir.join("rotator.db")).unwrap(), clock.clone()).unwrap();
+        (rotator, clock, dir)
+    }
+
+    fn backup_at(created_at: u64) -> BackupInfo {
+        BackupInfo {
+            file_name: file_name(created_at, BackupReason::Scheduled, 0),
+            path: String::new(),
+            created_at,
+            reason: BackupReason::Scheduled,
+            size_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn file_names_round_trip() {
+        assert_eq!(file_name(NOW + 3_723, BackupReason::DeleteTask, 0), "rotator-20240313-130203-delete-task.db");
+        assert_eq!(parse_file_name("rotator-20240313-130203-delete-task.db"), Some((NOW + 3_723, BackupReason::DeleteTask, 0)));
+        assert_eq!(file_name(NOW, BackupReason::Reset, 2), "rotator-20240313-120000.2-reset.db");
+        assert_eq!(parse_file_name("rotator-20240313-120000.2-reset.db"), Some((NOW, BackupReason::Reset, 2)));
+        assert_eq!(parse_file_name("rotator.db"), None);
+        assert_eq!(parse_file_name("rotator-20240313-130203-other.db"), None);
+    }
+
+    #[test]
+    fn retention_keeps_recent_then_daily_then_weekly() {
+        let retention = BackupRetention { daily: 2, weekly: 2 };
+        // Two today, two yesterday, Monday, last Wednesday and three weeks back
+        let backups: Vec<BackupInfo> = [0, 3_600, DAY, DAY + 3_600, 2 * DAY, 7 * DAY, 21 * DAY]
+            .iter()
+            .map(|ago| backup_at(NOW - ago))
+            .collect();
+
+        let expired: Vec<u64> = retention.expired(&backups, NOW).iter().map(|b| NOW - b.created_at).collect();
+        // Within a day: kept. Yesterday's newest is the second daily and
+        // Monday shares this week with today, so last Wednesday takes the
+        // second weekly slot and three weeks back is past both windows
+        assert_eq!(expired, vec![DAY + 3_600, 2 * DAY, 21 * DAY]);
+    }
+
+    #[test]
+    fn backups_in_the_same_second_do_not_overwrite_each_other() {
+        let (mut rotator, _, dir) = temp_rotator("same-second");
+        let first = rotator.create_backup(BackupReason::DeleteTask).unwrap().unwrap();
+        rotator.add_project("Work".into()).unwrap();
+        let second = rotator.create_backup(BackupReason::DeleteTask).unwrap().unwrap();
+
+        assert_ne!(first.path, second.path);
+        let backups = rotator.list_backups().unwrap();
+        let names: Vec<&str> = backups.iter().map(|b| b.file_name.as_str()).collect();
+        assert_eq!(names, [second.file_name.as_str(), first.file_name.as_str()]);
+        assert!(backups.iter().all(|b| b.created_at == NOW));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn destructive_commands_back_up_first_and_restore_brings_data_back() {
+        let (mut rotator, clock, dir) = temp_rotator("restore");
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 3_600, 600).unwrap();
+
+        clock.advance(60);
+        rotator.reset_database().unwrap();
+        assert!(rotator.projects().is_empty());
+        let backups = rotator.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].reason, BackupReason::Reset);
+
+        clock.advance(60);
+        rotator.restore_backup(&backups[0].file_name).unwrap();
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 600);
+        assert_eq!(rotator.all_time_entries().unwrap().len(), 1);
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().id, task_id);
+        // The emptied state was kept too
+        let reasons: Vec<BackupReason> = rotator.list_backups().unwrap().iter().map(|b| b.reason).collect();
+        assert_eq!(reasons, vec![BackupReason::Restore, BackupReason::Reset]);
+        // The log is carried over, not rolled back with the data
+        let commands: Vec<String> = rotator.audit_log(None, None, 2).unwrap().into_iter().map(|r| r.command).collect();
+        assert_eq!(commands, ["restore_backup", "reset_database"]);
+        assert!(matches!(rotator.restore_backup("rotator-nope.db"), Err(RotatorError::Validation(_))));
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restore_does_not_hand_out_entry_ids_again() {
+        let (mut rotator, _, dir) = temp_rotator("restore-ids");
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 7_200, 600).unwrap();
+        let backup = rotator.create_backup(BackupReason::Manual).unwrap().unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 5_400, 600).unwrap();
+        let last = rotator.add_time_entry_manual(project_id, task_id, NOW - 3_600, 600).unwrap();
+
+        rotator.restore_backup(&backup.file_name).unwrap();
+        assert_eq!(rotator.all_time_entries().unwrap().len(), 1);
+        let added = rotator.add_time_entry_manual(project_id, task_id, NOW - 1_800, 600).unwrap();
+        assert!(added.id > last.id);
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_from_a_newer_build_is_refused_before_restoring() {
+        let (mut rotator, _, dir) = temp_rotator("restore-too-new");
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let backup = rotator.create_backup(BackupReason::Manual).unwrap().unwrap();
+        Connection::open(&backup.path)
+            .unwrap()
+            .pragma_update(None, "user_version", migrations::latest_version() + 1)
+            .unwrap();
+        rotator.add_task(project_id, "A".into()).unwrap();
+
+        assert!(matches!(rotator.restore_backup(&backup.file_name), Err(RotatorError::Validation(_))));
+        assert_eq!(rotator.projects()[0].tasks.len(), 1);
+        assert_eq!(migrations::schema_version(rotator.connection()).unwrap(), migrations::latest_version());
+        assert_eq!(rotator.list_backups().unwrap().len(), 1);
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scheduled_backups_run_once_per_interval() {
+        let (mut rotator, clock, dir) = temp_rotator("scheduled");
+        assert!(rotator.backup_if_due().unwrap().is_some());
+        clock.advance(BACKUP_INTERVAL_SECONDS - 1);
+        assert!(rotator.backup_if_due().unwrap().is_none());
+        clock.advance(1);
+        assert!(rotator.backup_if_due().unwrap().is_some());
+        assert_eq!(rotator.list_backups().unwrap().len(), 2);
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn in_memory_databases_have_no_backups() {
+        let (mut rotator, _) = crate::test_support::rotator_at(NOW);
+        assert!(rotator.create_backup(BackupReason::Manual).unwrap().is_none());
+        assert!(rotator.list_backups().unwrap().is_empty());
+    }
+}
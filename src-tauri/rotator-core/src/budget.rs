@@ -0,0 +1,393 @@
+use crate::audit::snapshot;
+use crate::error::{Entity, RotatorError};
+use crate::rotator::Rotator;
+use crate::timezone::StatsZone;
+use chrono::{Datelike, Days, Months};
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The span a budget resets over. Periods follow the stats time zone, like
+/// the daily stats; weeks start on Monday.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Day,
+    Week,
+    Month,
+    /// Never resets.
+    Total,
+}
+
+impl BudgetPeriod {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            BudgetPeriod::Day => "day",
+            BudgetPeriod::Week => "week",
+            BudgetPeriod::Month => "month",
+            BudgetPeriod::Total => "total",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(BudgetPeriod::Day),
+            "week" => Some(BudgetPeriod::Week),
+            "month" => Some(BudgetPeriod::Month),
+            "total" => Some(BudgetPeriod::Total),
+            _ => None,
+        }
+    }
+
+    /// Start and end of the period containing `now` in UTC; `Total` has no
+    /// end.
+    pub fn bounds(self, now: u64) -> (u64, Option<u64>) {
+        self.bounds_in(StatsZone::Named(Tz::UTC), now)
+    }
+
+    /// Start and end of the period containing `now`, with days starting at
+    /// local midnight in `zone`.
+    pub(crate) fn bounds_in(self, zone: StatsZone, now: u64) -> (u64, Option<u64>) {
+        let today = zone.local_time(now).date();
+        let (start, end) = match self {
+            BudgetPeriod::Day => (today, today + Days::new(1)),
+            BudgetPeriod::Week => {
+                let monday = today - Days::new(today.weekday().num_days_from_monday().into());
+                (monday, monday + Days::new(7))
+            }
+            BudgetPeriod::Month => {
+                let first = today.with_day(1).unwrap_or(today);
+                (first, first + Months::new(1))
+            }
+            BudgetPeriod::Total => return (0, None),
+        };
+        (zone.start_of_day(start), Some(zone.start_of_day(end)))
+    }
+}
+
+/// Days since the epoch to (year, month, day), after Howard Hinnant's
+/// `civil_from_days`.
+pub(crate) fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+pub(crate) fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// How much time a project, or one of its tasks, should get per period.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Budget {
+    pub id: u64,
+    pub project_id: u64,
+    /// `None` budgets the whole project.
+    pub task_id: Option<u64>,
+    pub period: BudgetPeriod,
+    pub limit_seconds: u64,
+}
+
+/// Spent vs. remaining for a budget in the current period.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetStatus {
+    pub budget: Budget,
+    pub period_start: u64,
+    pub period_end: Option<u64>,
+    /// Tracked seconds in the period, running sessions included.
+    pub spent_seconds: u64,
+    pub remaining_seconds: u64,
+    pub reached: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BudgetEvent {
+    /// Spent time crossed the limit. Fires once per budget and period.
+    Reached { status: BudgetStatus },
+}
+
+pub(crate) fn load_budgets(conn: &Connection) -> rusqlite::Result<Vec<Budget>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project_id, task_id, period, limit_seconds FROM budgets ORDER BY project_id, task_id, id"
+    )?;
+    let budget_iter = stmt.query_map([], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, String>(3)?, row.get(4)?))
+    })?;
+    let rows = budget_iter.collect::<rusqlite::Result<Vec<(u64, u64, Option<u64>, String, u64)>>>()?;
+    Ok(rows.into_iter()
+        .filter_map(|(id, project_id, task_id, period, limit_seconds)| {
+            Some(Budget { id, project_id, task_id, period: BudgetPeriod::parse(&period)?, limit_seconds })
+        })
+        .collect())
+}
+
+impl Rotator {
+    pub fn budgets(&self) -> Result<Vec<Budget>, RotatorError> {
+        Ok(load_budgets(&self.conn)?)
+    }
+
+    fn budget(&self, budget_id: u64) -> Result<Budget, RotatorError> {
+        self.budgets()?
+            .into_iter()
+            .find(|b| b.id == budget_id)
+            .ok_or(RotatorError::NotFound(Entity::Budget, budget_id))
+    }
+
+    /// Create or replace the budget for a project (or task) and period.
+    pub fn set_budget(
+        &mut self,
+        project_id: u64,
+        task_id: Option<u64>,
+        period: BudgetPeriod,
+        limit_seconds: u64,
+    ) -> Result<Budget, RotatorError> {
+        if limit_seconds == 0 {
+            return Err(RotatorError::invalid("budget must be above zero"));
+        }
+        let known = match task_id {
+            Some(task_id) => self.conn.query_row(
+                "SELECT COUNT(*) FROM tasks WHERE id = ? AND project_id = ?",
+                params![task_id, project_id],
+                |row| row.get::<_, i64>(0),
+            )?,
+            None => self.conn.query_row("SELECT COUNT(*) FROM projects WHERE id = ?", [project_id], |row| row.get(0))?,
+        };
+        if known == 0 {
+            return Err(match task_id {
+                Some(task_id) => RotatorError::NotFound(Entity::Task, task_id),
+                None => RotatorError::NotFound(Entity::Project, project_id),
+            });
+        }
+
+        let existing: Option<u64> = self.conn.query_row(
+            "SELECT id FROM budgets WHERE project_id = ? AND task_id IS ? AND period = ?",
+            params![project_id, task_id, period.as_str()],
+            |row| row.get(0),
+        ).optional()?;
+        let before = match existing {
+            Some(id) => snapshot(&self.budget(id)?),
+            None => Value::Null,
+        };
+        let id = match existing {
+            Some(id) => {
+                self.conn.execute(
+                    "UPDATE budgets SET limit_seconds = ?, reached_period_start = NULL WHERE id = ?",
+                    params![limit_seconds, id],
+                )?;
+                id
+            }
+            None => {
+                self.conn.execute(
+                    "INSERT INTO budgets (project_id, task_id, period, limit_seconds) VALUES (?, ?, ?, ?)",
+                    params![project_id, task_id, period.as_str(), limit_seconds],
+                )?;
+                self.conn.last_insert_rowid() as u64
+            }
+        };
+        let budget = Budget { id, project_id, task_id, period, limit_seconds };
+        self.audit("set_budget", Some((Entity::Budget, id)), before, snapshot(&budget))?;
+        Ok(budget)
+    }
+
+    pub fn remove_budget(&mut self, budget_id: u64) -> Result<(), RotatorError> {
+        let before = self.budget(budget_id)?;
+        self.conn.execute("DELETE FROM budgets WHERE id = ?", [budget_id])?;
+        self.audit("remove_budget", Some((Entity::Budget, budget_id)), snapshot(&before), Value::Null)?;
+        Ok(())
+    }
+
+    /// Status of every budget in its current period.
+    pub fn budget_statuses(&self) -> Result<Vec<BudgetStatus>, RotatorError> {
+        let now = self.clock.now();
+        let zone = StatsZone::load(&self.conn)?;
+        self.budgets()?.into_iter().map(|budget| self.budget_status_at(budget, zone, now)).collect()
+    }
+
+    fn budget_status_at(&self, budget: Budget, zone: StatsZone, now: u64) -> Result<BudgetStatus, RotatorError> {
+        let (start, end) = budget.period.bounds_in(zone, now);
+        let end_or_now = end.unwrap_or(now).min(now);
+
+        let mut stmt = self.conn.prepare(
+            "SELECT start_time, end_time, duration_seconds FROM time_entries
+             WHERE project_id = ?1 AND (?2 IS NULL OR task_id = ?2) AND end_time > ?3 AND start_time < ?4"
+        )?;
+        let spans = stmt.query_map(params![budget.project_id, budget.task_id, start, end_or_now], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Entries crossing a period boundary count for the share inside it
+        let mut spent: u64 = spans.into_iter()
+            .map(|(entry_start, entry_end, duration)| {
+                let span = entry_end.saturating_sub(entry_start);
+                let inside = entry_end.min(end_or_now).saturating_sub(entry_start.max(start));
+                if span == 0 || inside >= span { duration } else { duration * inside / span }
+            })
+            .sum();
+        spent += self.active_tracking.iter()
+            .filter(|t| t.project_id == budget.project_id && budget.task_id.is_none_or(|id| id == t.task_id))
+            .map(|t| t.elapsed_seconds(now).min(now.saturating_sub(start)))
+            .sum::<u64>();
+
+        Ok(BudgetStatus {
+            period_start: start,
+            period_end: end,
+            spent_seconds: spent,
+            remaining_seconds: budget.limit_seconds.saturating_sub(spent),
+            reached: spent >= budget.limit_seconds,
+            budget,
+        })
+    }
+
+    /// Budgets reached since the last check. Call it periodically while
+    /// tracking; each budget fires at most once per period.
+    pub fn check_budgets(&mut self) -> Result<Vec<BudgetEvent>, RotatorError> {
+        let mut events = Vec::new();
+        for status in self.budget_statuses()? {
+            if !status.reached {
+                continue;
+            }
+            let marked = self.conn.execute(
+                "UPDATE budgets SET reached_period_start = ?1 WHERE id = ?2 AND reached_period_start IS NOT ?1",
+                params![status.period_start, status.budget.id],
+            )?;
+            if marked > 0 {
+                events.push(BudgetEvent::Reached { status });
+            }
+        }
+        Ok(events)
+    }
+
+    /// Project and task ids whose budgets are used up for this period.
+    pub(crate) fn exhausted_budgets(&self) -> Result<(Vec<u64>, Vec<u64>), RotatorError> {
+        let mut projects = Vec::new();
+        let mut tasks = Vec::new();
+        for status in self.budget_statuses()?.into_iter().filter(|s| s.reached) {
+            match status.budget.task_id {
+                Some(task_id) => tasks.push(task_id),
+                None => projects.push(status.budget.project_id),
+            }
+        }
+        Ok((projects, tasks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    // Wednesday 2024-03-13 12:00 UTC
+    const NOW: u64 = 1_710_331_200;
+
+    #[test]
+    fn period_bounds() {
+        assert_eq!(BudgetPeriod::Day.bounds(NOW), (1_710_288_000, Some(1_710_374_400)));
+        // Monday 2024-03-11 to Monday 2024-03-18
+        assert_eq!(BudgetPeriod::Week.bounds(NOW), (1_710_115_200, Some(1_710_720_000)));
+        // 2024-03-01 to 2024-04-01
+        assert_eq!(BudgetPeriod::Month.bounds(NOW), (1_709_251_200, Some(1_711_929_600)));
+        // December rolls into the next year
+        assert_eq!(BudgetPeriod::Month.bounds(1_703_000_000), (1_701_388_800, Some(1_704_067_200)));
+        assert_eq!(BudgetPeriod::Total.bounds(NOW), (0, None));
+    }
+
+    #[test]
+    fn periods_follow_the_stats_zone() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let project_id = rotator.add_project("Client".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        // 2024-03-13 03:00 UTC is still the 12th in New York (-04:00)
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 9 * 3_600, 600).unwrap();
+        rotator.set_budget(project_id, None, BudgetPeriod::Day, 3_600).unwrap();
+        rotator.set_stats_timezone(Some("America/New_York".into())).unwrap();
+
+        let status = &rotator.budget_statuses().unwrap()[0];
+        // Local midnight on the 13th to local midnight on the 14th
+        assert_eq!((status.period_start, status.period_end), (NOW - 8 * 3_600, Some(NOW + 16 * 3_600)));
+        assert_eq!(status.spent_seconds, 0);
+
+        let zone = StatsZone::Named("America/New_York".parse().unwrap());
+        // The month started before clocks went forward on March 10th
+        assert_eq!(BudgetPeriod::Month.bounds_in(zone, NOW), (1_709_269_200, Some(1_711_944_000)));
+        // Monday 2024-03-11, after the change
+        assert_eq!(BudgetPeriod::Week.bounds_in(zone, NOW).0, 1_710_129_600);
+    }
+
+    #[test]
+    fn status_counts_period_share_and_running_sessions() {
+        let (mut rotator, clock) = rotator_at(NOW);
+        rotator.set_stats_timezone(Some("UTC".into())).unwrap();
+        let project_id = rotator.add_project("Client".into()).unwrap()[0].id;
+        let tasks = rotator.add_task(project_id, "A".into()).unwrap().tasks;
+        let a = tasks[0].id;
+        let b = rotator.add_task(project_id, "B".into()).unwrap().tasks[1].id;
+        let day_start = BudgetPeriod::Day.bounds(NOW).0;
+
+        // Half of this one falls before midnight
+        rotator.add_time_entry_manual(project_id, a, day_start - 1_800, 3_600).unwrap();
+        rotator.add_time_entry_manual(project_id, b, day_start + 3_600, 1_200).unwrap();
+        rotator.set_budget(project_id, None, BudgetPeriod::Day, 7_200).unwrap();
+        rotator.set_budget(project_id, Some(a), BudgetPeriod::Total, 3_600).unwrap();
+
+        rotator.start_tracking(project_id, b, false).unwrap();
+        clock.advance(600);
+
+        let statuses = rotator.budget_statuses().unwrap();
+        let day = statuses.iter().find(|s| s.budget.task_id.is_none()).unwrap();
+        assert_eq!((day.spent_seconds, day.remaining_seconds, day.reached), (1_800 + 1_200 + 600, 3_600, false));
+        let total = statuses.iter().find(|s| s.budget.task_id == Some(a)).unwrap();
+        assert_eq!((total.spent_seconds, total.reached), (3_600, true));
+    }
+
+    #[test]
+    fn reached_fires_once_per_period() {
+        let (mut rotator, clock) = rotator_at(NOW);
+        rotator.set_stats_timezone(Some("UTC".into())).unwrap();
+        let project_id = rotator.add_project("Client".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.set_budget(project_id, None, BudgetPeriod::Day, 600).unwrap();
+
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(599);
+        assert!(rotator.check_budgets().unwrap().is_empty());
+        clock.advance(1);
+        assert_eq!(rotator.check_budgets().unwrap().len(), 1);
+        clock.advance(60);
+        assert!(rotator.check_budgets().unwrap().is_empty());
+
+        // Next day starts from zero; another 10 minutes fires again
+        rotator.stop_tracking(None).unwrap();
+        clock.set(BudgetPeriod::Day.bounds(NOW).1.unwrap() + 3_600);
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(600);
+        assert_eq!(rotator.check_budgets().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_targets_and_empty_limits() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let project_id = rotator.add_project("Client".into()).unwrap()[0].id;
+        assert!(matches!(rotator.set_budget(project_id, None, BudgetPeriod::Week, 0), Err(RotatorError::Validation(_))));
+        assert!(matches!(rotator.set_budget(project_id, Some(99), BudgetPeriod::Week, 60), Err(RotatorError::NotFound(Entity::Task, 99))));
+
+        let first = rotator.set_budget(project_id, None, BudgetPeriod::Week, 60).unwrap();
+        let second = rotator.set_budget(project_id, None, BudgetPeriod::Week, 120).unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!(rotator.budgets().unwrap(), vec![second]);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::audit::snapshot;
+use crate::budget::civil_from_days;
+use crate::db::{load_setting, save_setting};
+use crate::error::RotatorError;
+use crate::rotator::Rotator;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const DAY: u64 = 24 * 60 * 60;
+const WEEK: u64 = 7 * DAY;
+
+/// Longest line iCalendar allows, in octets, before it has to be folded.
+const MAX_LINE_OCTETS: usize = 75;
+
+/// The local calendar feed, served at
+/// `http://127.0.0.1:<port>/<token>/calendar.ics` for calendar apps to
+/// subscribe to. Off unless turned on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarFeedSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// How many weeks back from now the feed covers.
+    pub weeks: u32,
+    /// Secret first path segment, so a web page that gets the browser to
+    /// talk to the port still can't guess where the feed is. Made once and
+    /// kept; whatever a caller passes in is ignored.
+    #[serde(default)]
+    pub token: String,
+}
+
+impl Default for CalendarFeedSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: 47_821, weeks: 4, token: String::new() }
+    }
+}
+
+impl CalendarFeedSettings {
+    /// The path the feed is served at.
+    pub fn path(&self) -> String {
+        format!("/{}/calendar.ics", self.token)
+    }
+
+    /// Loads the settings, making the token the first time.
+    fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let default = Self::default();
+        let get = |key: &str, fallback: u64| -> rusqlite::Result<u64> {
+            Ok(load_setting(conn, key)?.and_then(|v| v.parse().ok()).unwrap_or(fallback))
+        };
+        let token = match load_setting(conn, "calendar_feed_token")?.filter(|t| !t.is_empty()) {
+            Some(token) => token,
+            None => {
+                // SQLite seeds randomblob from the OS, unlike the rotation rng
+                let token: String = conn.query_row("SELECT lower(hex(randomblob(16)))", [], |row| row.get(0))?;
+                save_setting(conn, "calendar_feed_token", &token)?;
+                token
+            }
+        };
+        Ok(Self {
+            enabled: get("calendar_feed_enabled", default.enabled as u64)? != 0,
+            port: get("calendar_feed_port", default.port as u64)? as u16,
+            weeks: get("calendar_feed_weeks", default.weeks as u64)? as u32,
+            token,
+        })
+    }
+
+    fn save(&self, conn: &Connection) -> rusqlite::Result<()> {
+        save_setting(conn, "calendar_feed_enabled", &(self.enabled as u64).to_string())?;
+        save_setting(conn, "calendar_feed_port", &self.port.to_string())?;
+        save_setting(conn, "calendar_feed_weeks", &self.weeks.to_string())
+    }
+}
+
+struct CalendarEvent {
+    entry_id: u64,
+    project: String,
+    task: String,
+    start: u64,
+    end: u64,
+}
+
+/// `20240313T120000Z`
+fn format_utc(at: u64) -> String {
+    let (year, month, day) = civil_from_days(at / DAY);
+    let seconds = at % DAY;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60,
+    )
+}
+
+/// Escape a TEXT value: backslashes, semicolons, commas and line breaks.
+fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Append a content line, folding it onto continuation lines that start
+/// with a space once it gets too long. Never splits a character.
+fn push_line(out: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn render_calendar(events: &[CalendarEvent], now: u64) -> String {
+    let mut out = String::new();
+    let header = [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//Rotator//Time entries//EN",
+        "CALSCALE:GREGORIAN",
+        "X-WR-CALNAME:Rotator",
+    ];
+    for line in header {
+        push_line(&mut out, line);
+    }
+    let stamp = format_utc(now);
+    for event in events {
+        push_line(&mut out, "BEGIN:VEVENT");
+        // Entry ids are never reused (time_entries is AUTOINCREMENT), so the
+        // same entry keeps its UID across exports and edits move the event
+        // rather than adding one
+        push_line(&mut out, &format!("UID:time-entry-{}@rotator", event.entry_id));
+        push_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        push_line(&mut out, &format!("DTSTART:{}", format_utc(event.start)));
+        push_line(&mut out, &format!("DTEND:{}", format_utc(event.end)));
+        push_line(&mut out, &format!("SUMMARY:{}", escape_text(&format!("{}: {}", event.project, event.task))));
+        push_line(&mut out, &format!("CATEGORIES:{}", escape_text(&event.project)));
+        push_line(&mut out, "TRANSP:TRANSPARENT");
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+impl Rotator {
+    fn calendar_events(&self, from: u64, to: u64) -> Result<Vec<CalendarEvent>, RotatorError> {
+        if from >= to {
+            return Err(RotatorError::invalid("calendar range is empty"));
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT e.id, COALESCE(p.name, ''), COALESCE(t.name, ''), e.start_time, e.end_time
+             FROM time_entries e
+             LEFT JOIN projects p ON p.id = e.project_id
+             LEFT JOIN tasks t ON t.id = e.task_id
+             WHERE e.start_time >= ? AND e.start_time < ?
+             ORDER BY e.start_time, e.id",
+        )?;
+        let events = stmt.query_map(params![from, to], |row| {
+            Ok(CalendarEvent {
+                entry_id: row.get(0)?,
+                project: row.get(1)?,
+                task: row.get(2)?,
+                start: row.get(3)?,
+                end: row.get(4)?,
+            })
+        })?;
+        Ok(events.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Time entries starting in the range as an iCalendar file, one event
+    /// per entry.
+    pub fn export_calendar(&self, from: u64, to: u64) -> Result<String, RotatorError> {
+        Ok(render_calendar(&self.calendar_events(from, to)?, self.clock.now()))
+    }
+
+    /// Write the calendar to `path` and return how many events it holds.
+    pub fn export_calendar_to(&self, from: u64, to: u64, path: &Path) -> Result<usize, RotatorError> {
+        let events = self.calendar_events(from, to)?;
+        std::fs::write(path, render_calendar(&events, self.clock.now()))?;
+        Ok(events.len())
+    }
+
+    /// What the calendar feed serves: the last `weeks` weeks up to now.
+    pub fn calendar_feed(&self) -> Result<String, RotatorError> {
+        let weeks = CalendarFeedSettings::load(&self.conn)?.weeks as u64;
+        let now = self.clock.now();
+        self.export_calendar(now.saturating_sub(weeks * WEEK), now + 1)
+    }
+
+    pub fn calendar_feed_settings(&self) -> Result<CalendarFeedSettings, RotatorError> {
+        Ok(CalendarFeedSettings::load(&self.conn)?)
+    }
+
+    pub fn set_calendar_feed_settings(&mut self, settings: CalendarFeedSettings) -> Result<CalendarFeedSettings, RotatorError> {
+        if settings.port < 1024 {
+            return Err(RotatorError::invalid("calendar feed port must be 1024 or above"));
+        }
+        if !(1..=52).contains(&settings.weeks) {
+            return Err(RotatorError::invalid("calendar feed covers 1 to 52 weeks"));
+        }
+        let before = CalendarFeedSettings::load(&self.conn)?;
+        let settings = CalendarFeedSettings { token: before.token.clone(), ..settings };
+        settings.save(&self.conn)?;
+        self.audit("set_calendar_feed_settings", None, snapshot(&before), snapshot(&settings))?;
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    // Wednesday 2024-03-13 12:00 UTC
+    const NOW: u64 = 1_710_331_200;
+
+    #[test]
+    fn escapes_and_folds_lines() {
+        assert_eq!(escape_text("a, b; c\\d\r\ne"), "a\\, b\\; c\\\\d\\ne");
+        let mut out = String::new();
+        push_line(&mut out, &format!("SUMMARY:{}", "é".repeat(40)));
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_OCTETS));
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(format_utc(NOW), "20240313T120000Z");
+    }
+
+    #[test]
+    fn entries_become_events_with_stable_uids() {
+        let (mut rotator, clock) = rotator_at(NOW);
+        let project_id = rotator.add_project("Acme, Inc".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "Docs".into()).unwrap().tasks[0].id;
+        let entry = rotator.add_time_entry_manual(project_id, task_id, NOW - 7_200, 1_800).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 6 * WEEK, 600).unwrap();
+
+        let ics = rotator.export_calendar(NOW - DAY, NOW).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains(&format!("UID:time-entry-{}@rotator\r\n", entry.id)));
+        assert!(ics.contains("DTSTART:20240313T100000Z\r\nDTEND:20240313T103000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Acme\\, Inc: Docs\r\n"));
+
+        clock.advance(3_600);
+        rotator.update_time_entry(entry.id, NOW - 3_600, NOW - 1_800).unwrap();
+        let moved = rotator.export_calendar(NOW - DAY, NOW).unwrap();
+        assert!(moved.contains(&format!("UID:time-entry-{}@rotator\r\n", entry.id)));
+        assert!(moved.contains("DTSTART:20240313T110000Z\r\n"));
+        assert!(matches!(rotator.export_calendar(NOW, NOW), Err(RotatorError::Validation(_))));
+    }
+
+    #[test]
+    fn deleted_entries_do_not_pass_their_uid_on() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 7_200, 600).unwrap();
+        let deleted = rotator.add_time_entry_manual(project_id, task_id, NOW - 3_600, 600).unwrap();
+        rotator.delete_time_entry(deleted.id).unwrap();
+
+        let added = rotator.add_time_entry_manual(project_id, task_id, NOW - 1_800, 600).unwrap();
+        assert!(added.id > deleted.id);
+        let ics = rotator.export_calendar(NOW - DAY, NOW).unwrap();
+        assert!(!ics.contains(&format!("UID:time-entry-{}@rotator\r\n", deleted.id)));
+    }
+
+    #[test]
+    fn feed_covers_the_configured_weeks() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 600, 600).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 6 * WEEK, 600).unwrap();
+        assert_eq!(rotator.calendar_feed().unwrap().matches("BEGIN:VEVENT").count(), 1);
+
+        let token = rotator.calendar_feed_settings().unwrap().token;
+        assert_eq!(token.len(), 32);
+        let settings = CalendarFeedSettings { enabled: true, weeks: 8, ..Default::default() };
+        let saved = rotator.set_calendar_feed_settings(settings.clone()).unwrap();
+        assert_eq!(saved, CalendarFeedSettings { token: token.clone(), ..settings.clone() });
+        assert_eq!(rotator.calendar_feed_settings().unwrap(), saved);
+        assert_eq!(saved.path(), format!("/{}/calendar.ics", token));
+        assert_eq!(rotator.calendar_feed().unwrap().matches("BEGIN:VEVENT").count(), 2);
+
+        let (other, _) = rotator_at(NOW);
+        assert_ne!(other.calendar_feed_settings().unwrap().token, token);
+
+        let bad = CalendarFeedSettings { weeks: 0, ..settings };
+        assert!(matches!(rotator.set_calendar_feed_settings(bad), Err(RotatorError::Validation(_))));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::models::{ActiveTracking, Project, Task};
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub const DONE_HIDE_AFTER_SECONDS: u64 = 5 * 60 * 60; // 5 hours
+
+pub(crate) fn load_projects(conn: &Connection, now: u64) -> rusqlite::Result<Vec<Project>> {
+    let mut stmt = conn.prepare("SELECT id, name, current_task_id FROM projects WHERE archived_at IS NULL ORDER BY position, id")?;
+    let project_iter = stmt.query_map([], |row| {
+        Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<u64>>(2)?))
+    })?;
+
+    let mut projects = Vec::new();
+    for project_result in project_iter {
+        let (id, name, saved_task_id) = project_result?;
+        let tasks = load_tasks(conn, id, now)?;
+        let task_ids: Vec<u64> = tasks.iter().map(|t| t.id).collect();
+        let current_task_id = resolve_current(conn, "tasks", saved_task_id, &task_ids)?;
+        projects.push(Project { id, name, tasks, current_task_id });
+    }
+    Ok(projects)
+}
+
+pub(crate) fn load_tasks(conn: &Connection, project_id: u64, now: u64) -> rusqlite::Result<Vec<Task>> {
+    let cutoff = now.saturating_sub(DONE_HIDE_AFTER_SECONDS);
+
+    // Load tasks that are:
+    // - not archived (archived_at IS NULL)
+    // - either not done (done_at IS NULL) OR done recently (done_at > cutoff)
+    let mut stmt = conn.prepare(
+        "SELECT id, name, time_seconds, done_at FROM tasks
+         WHERE project_id = ? AND archived_at IS NULL
+         AND (done_at IS NULL OR done_at > ?)
+         ORDER BY position, id"
+    )?;
+    let task_iter = stmt.query_map(params![project_id, cutoff], |row| {
+        Ok(Task {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            time_seconds: row.get(2)?,
+            done_at: row.get(3)?,
+        })
+    })?;
+
+    task_iter.collect()
+}
+
+/// `saved` if it is among `visible`, otherwise the first of `visible` that
+/// comes after it in the stored order of `table`, wrapping to the first. So
+/// when the current row is archived or hidden, the one that followed it
+/// takes over.
+pub(crate) fn resolve_current(conn: &Connection, table: &str, saved: Option<u64>, visible: &[u64]) -> rusqlite::Result<Option<u64>> {
+    let Some(saved) = saved else {
+        return Ok(visible.first().copied());
+    };
+    if visible.contains(&saved) {
+        return Ok(Some(saved));
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM {table} WHERE (position, id) > (SELECT position, id FROM {table} WHERE id = ?) ORDER BY position, id",
+        table = table,
+    ))?;
+    let following = stmt.query_map([saved], |row| row.get::<_, u64>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(following.into_iter()
+        .find(|id| visible.contains(id))
+        .or_else(|| visible.first().copied()))
+}
+
+/// Id of the saved current project, resolved against `projects`.
+pub(crate) fn load_current_project(conn: &Connection, projects: &[Project]) -> rusqlite::Result<Option<u64>> {
+    let saved = load_setting(conn, "current_project_id")?.and_then(|v| v.parse::<u64>().ok());
+    let ids: Vec<u64> = projects.iter().map(|p| p.id).collect();
+    resolve_current(conn, "projects", saved, &ids)
+}
+
+/// Whether row `id` of `table` is archived, `None` if there is no such row.
+pub(crate) fn is_archived(conn: &Connection, table: &str, id: u64) -> rusqlite::Result<Option<bool>> {
+    conn.query_row(&format!("SELECT archived_at IS NOT NULL FROM {} WHERE id = ?", table), [id], |row| row.get(0))
+        .optional()
+}
+
+pub(crate) fn save_current_project(conn: &Connection, project_id: Option<u64>) -> rusqlite::Result<()> {
+    match project_id {
+        Some(project_id) => save_setting(conn, "current_project_id", &project_id.to_string()),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn save_current_task(conn: &Connection, project_id: u64, task_id: Option<u64>) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE projects SET current_task_id = ? WHERE id = ?",
+        params![task_id, project_id],
+    )?;
+    Ok(())
+}
+
+/// Every table a reset empties, children before their parents. The audit
+/// log is not among them.
+pub(crate) const DATA_TABLES: &[&str] = &[
+    "tracking_pauses",
+    "timebox_blocks",
+    "time_entries",
+    "active_tracking",
+    "tasks",
+    "project_time_policies",
+    "budgets",
+    "rotation_events",
+    "journal",
+    "projects",
+    "app_state",
+];
+
+/// Position after the last project, archived ones included.
+pub(crate) fn next_project_position(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT COALESCE(MAX(position), -1) + 1 FROM projects", [], |row| row.get(0))
+}
+
+/// Position after the last task of the project, archived ones included.
+pub(crate) fn next_task_position(conn: &Connection, project_id: u64) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(position), -1) + 1 FROM tasks WHERE project_id = ?",
+        [project_id],
+        |row| row.get(0),
+    )
+}
+
+pub(crate) fn get_next_id(conn: &Connection, table: &str) -> rusqlite::Result<u64> {
+    conn.query_row(
+        &format!("SELECT COALESCE(MAX(id), 0) + 1 FROM {}", table),
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Id the next time entry gets. Ids of deleted entries are not reused.
+pub(crate) fn next_entry_id(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row(
+        "SELECT MAX(
+            (SELECT COALESCE(MAX(id), 0) FROM time_entries),
+            (SELECT COALESCE(MAX(seq), 0) FROM sqlite_sequence WHERE name = 'time_entries')
+        ) + 1",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Start counting time entry ids after `last_id` and after every entry id
+/// the audit log has seen, whatever the table holds now.
+pub(crate) fn retire_entry_ids(conn: &Connection, last_id: u64) -> rusqlite::Result<()> {
+    let last = last_id.max(next_entry_id(conn)? - 1);
+    conn.execute("DELETE FROM sqlite_sequence WHERE name = 'time_entries'", [])?;
+    conn.execute(
+        "INSERT INTO sqlite_sequence (name, seq) VALUES ('time_entries', MAX(
+            ?,
+            (SELECT COALESCE(MAX(entity_id), 0) FROM audit_log WHERE entity = 'time_entry')
+        ))",
+        [last],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn load_active_tracking(conn: &Connection) -> rusqlite::Result<Vec<ActiveTracking>> {
+    let mut stmt = conn.prepare(
+        "SELECT a.project_id, a.task_id, a.started_at,
+            (SELECT MAX(paused_at) FROM tracking_pauses p
+             WHERE p.task_id = a.task_id AND p.entry_id IS NULL AND p.resumed_at IS NULL),
+            (SELECT COALESCE(SUM(resumed_at - paused_at), 0) FROM tracking_pauses p
+             WHERE p.task_id = a.task_id AND p.entry_id IS NULL AND p.resumed_at IS NOT NULL)
+         FROM active_tracking a"
+    )?;
+    let tracking_iter = stmt.query_map([], |row| {
+        Ok(ActiveTracking {
+            project_id: row.get(0)?,
+            task_id: row.get(1)?,
+            started_at: row.get(2)?,
+            paused_at: row.get(3)?,
+            paused_seconds: row.get(4)?,
+        })
+    })?;
+    tracking_iter.collect()
+}
+
+pub(crate) fn add_active_tracking(conn: &Connection, tracking: &ActiveTracking) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO active_tracking (project_id, task_id, started_at, last_heartbeat) VALUES (?, ?, ?, ?)",
+        params![tracking.project_id, tracking.task_id, tracking.started_at, tracking.started_at],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn remove_active_tracking(conn: &Connection, task_id: u64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM tracking_pauses WHERE task_id = ? AND entry_id IS NULL", [task_id])?;
+    conn.execute("DELETE FROM active_tracking WHERE task_id = ?", [task_id])?;
+    Ok(())
+}
+
+pub(crate) fn load_setting(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row("SELECT value FROM app_state WHERE key = ?", [key], |row| row.get(0)).optional()
+}
+
+pub(crate) fn save_setting(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO app_state (key, value) VALUES (?, ?)",
+        params![key, value],
+    )?;
+    Ok(())
+}
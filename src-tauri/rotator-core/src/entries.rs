@@ -0,0 +1,339 @@
+use crate::error::{Conflict, Entity, RotatorError};
+use crate::journal::{load_entry_record, Change, EntryEdit};
+use crate::models::TimeEntry;
+use crate::policy::policy_for;
+use crate::rotator::Rotator;
+use crate::totals::refresh_task_total;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+
+/// Columns read by `entry_from_row`.
+pub(crate) const ENTRY_COLUMNS: &str = "id, project_id, task_id, start_time, end_time, duration_seconds, manual, edited_at";
+
+/// A time entry selected with `ENTRY_COLUMNS`, before its time policy is applied.
+pub(crate) fn entry_from_row(row: &Row) -> rusqlite::Result<TimeEntry> {
+    Ok(TimeEntry {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        task_id: row.get(2)?,
+        start_time: row.get(3)?,
+        end_time: row.get(4)?,
+        duration_seconds: row.get(5)?,
+        billable_seconds: 0,
+        manual: row.get(6)?,
+        edited_at: row.get(7)?,
+    })
+}
+
+pub(crate) fn load_entry(conn: &Connection, entry_id: u64) -> Result<TimeEntry, RotatorError> {
+    conn.query_row(
+        &format!("SELECT {} FROM time_entries WHERE id = ?", ENTRY_COLUMNS),
+        [entry_id],
+        entry_from_row,
+    )
+    .optional()?
+    .ok_or(RotatorError::NotFound(Entity::TimeEntry, entry_id))
+    .and_then(|mut entry| {
+        entry.billable_seconds = policy_for(conn, entry.project_id)?.billable_seconds(entry.duration_seconds);
+        Ok(entry)
+    })
+}
+
+/// First entry of `task_id` overlapping `[start, end)`, ignoring `exclude`.
+pub(crate) fn find_overlap(conn: &Connection, task_id: u64, start: u64, end: u64, exclude: &[u64]) -> rusqlite::Result<Option<u64>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM time_entries
+         WHERE task_id = ? AND start_time < ? AND end_time > ?
+         ORDER BY start_time"
+    )?;
+    let ids = stmt.query_map(params![task_id, end, start], |row| row.get::<_, u64>(0))?;
+    for id in ids {
+        let id = id?;
+        if !exclude.contains(&id) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+/// Clip an entry's pauses to its span and set its duration to the span
+/// minus the pauses.
+fn refresh_entry_duration(tx: &Transaction, entry_id: u64) -> rusqlite::Result<()> {
+    let (start, end): (u64, u64) = tx.query_row(
+        "SELECT start_time, end_time FROM time_entries WHERE id = ?",
+        [entry_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    tx.execute(
+        "DELETE FROM tracking_pauses WHERE entry_id = ?1 AND (resumed_at <= ?2 OR paused_at >= ?3)",
+        params![entry_id, start, end],
+    )?;
+    tx.execute(
+        "UPDATE tracking_pauses SET paused_at = MAX(paused_at, ?2), resumed_at = MIN(resumed_at, ?3) WHERE entry_id = ?1",
+        params![entry_id, start, end],
+    )?;
+    tx.execute(
+        "UPDATE time_entries SET duration_seconds = end_time - start_time
+            - (SELECT COALESCE(SUM(resumed_at - paused_at), 0) FROM tracking_pauses WHERE entry_id = ?1)
+         WHERE id = ?1",
+        [entry_id],
+    )?;
+    Ok(())
+}
+
+impl Rotator {
+    fn validate_span(&self, task_id: u64, start: u64, end: u64, exclude: &[u64]) -> Result<(), RotatorError> {
+        if start >= end {
+            return Err(RotatorError::invalid("start must be before end"));
+        }
+        if end > self.clock.now() {
+            return Err(RotatorError::invalid("end is in the future"));
+        }
+        if self.active_tracking.iter().any(|t| t.task_id == task_id && t.started_at < end) {
+            return Err(RotatorError::Conflict(Conflict::RunningSession));
+        }
+        if let Some(id) = find_overlap(&self.conn, task_id, start, end, exclude)? {
+            return Err(RotatorError::Conflict(Conflict::TimeEntry(id)));
+        }
+        Ok(())
+    }
+
+    /// Move an entry's start and end. Its duration becomes the new span minus
+    /// whatever pauses still fall inside it.
+    pub fn update_time_entry(&mut self, entry_id: u64, start_time: u64, end_time: u64) -> Result<TimeEntry, RotatorError> {
+        let entry = load_entry(&self.conn, entry_id)?;
+        self.validate_span(entry.task_id, start_time, end_time, &[entry_id])?;
+        let before = vec![load_entry_record(&self.conn, entry_id)?];
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE time_entries SET start_time = ?, end_time = ?, edited_at = ? WHERE id = ?",
+            params![start_time, end_time, self.clock.now(), entry_id],
+        )?;
+        refresh_entry_duration(&tx, entry_id)?;
+        refresh_task_total(&tx, entry.task_id)?;
+        let after = vec![load_entry_record(&tx, entry_id)?];
+        audit.entries(&tx, "update_time_entry", &before, &after)?;
+        tx.commit()?;
+
+        self.sync_task_totals(&[entry.task_id])?;
+        self.record(Change::EntriesEdited { edit: EntryEdit::Updated, before, after })?;
+        load_entry(&self.conn, entry_id)
+    }
+
+    pub fn delete_time_entry(&mut self, entry_id: u64) -> Result<(), RotatorError> {
+        let entry = load_entry(&self.conn, entry_id)?;
+        let before = vec![load_entry_record(&self.conn, entry_id)?];
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM tracking_pauses WHERE entry_id = ?", [entry_id])?;
+        tx.execute("DELETE FROM time_entries WHERE id = ?", [entry_id])?;
+        refresh_task_total(&tx, entry.task_id)?;
+        audit.entries(&tx, "delete_time_entry", &before, &[])?;
+        tx.commit()?;
+
+        self.sync_task_totals(&[entry.task_id])?;
+        self.record(Change::EntriesEdited { edit: EntryEdit::Deleted, before, after: Vec::new() })?;
+        Ok(())
+    }
+
+    /// Split an entry in two at `at`. The original keeps the first half.
+    pub fn split_time_entry(&mut self, entry_id: u64, at: u64) -> Result<(TimeEntry, TimeEntry), RotatorError> {
+        let entry = load_entry(&self.conn, entry_id)?;
+        if at <= entry.start_time || at >= entry.end_time {
+            return Err(RotatorError::invalid("split point must fall inside the entry"));
+        }
+        let before = vec![load_entry_record(&self.conn, entry_id)?];
+
+        let now = self.clock.now();
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        tx.execute("UPDATE time_entries SET end_time = ?, edited_at = ? WHERE id = ?", params![at, now, entry_id])?;
+        tx.execute(
+            "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds, manual, edited_at)
+             VALUES (?, ?, ?, ?, 0, ?, ?)",
+            params![entry.project_id, entry.task_id, at, entry.end_time, entry.manual, now],
+        )?;
+        let second_id = tx.last_insert_rowid() as u64;
+        // A pause spanning the split point is cut in two; later pauses move over
+        tx.execute(
+            "INSERT INTO tracking_pauses (task_id, entry_id, paused_at, resumed_at)
+             SELECT task_id, ?1, ?2, resumed_at FROM tracking_pauses
+             WHERE entry_id = ?3 AND paused_at < ?2 AND resumed_at > ?2",
+            params![second_id, at, entry_id],
+        )?;
+        tx.execute(
+            "UPDATE tracking_pauses SET entry_id = ?1 WHERE entry_id = ?3 AND paused_at >= ?2",
+            params![second_id, at, entry_id],
+        )?;
+        refresh_entry_duration(&tx, entry_id)?;
+        refresh_entry_duration(&tx, second_id)?;
+        refresh_task_total(&tx, entry.task_id)?;
+        let after = vec![load_entry_record(&tx, entry_id)?, load_entry_record(&tx, second_id)?];
+        audit.entries(&tx, "split_time_entry", &before, &after)?;
+        tx.commit()?;
+
+        self.sync_task_totals(&[entry.task_id])?;
+        self.record(Change::EntriesEdited { edit: EntryEdit::Split, before, after })?;
+        Ok((load_entry(&self.conn, entry_id)?, load_entry(&self.conn, second_id)?))
+    }
+
+    /// Merge two consecutive entries of the same task into the earlier one.
+    ///
+    /// The merged entry spans both, but the gap between them is recorded as a
+    /// pause so it is not counted as work.
+    pub fn merge_time_entries(&mut self, first_id: u64, second_id: u64) -> Result<TimeEntry, RotatorError> {
+        let a = load_entry(&self.conn, first_id)?;
+        let b = load_entry(&self.conn, second_id)?;
+        if a.id == b.id {
+            return Err(RotatorError::invalid("cannot merge an entry with itself"));
+        }
+        if a.task_id != b.task_id {
+            return Err(RotatorError::invalid("entries belong to different tasks"));
+        }
+        let (first, second) = if a.start_time <= b.start_time { (a, b) } else { (b, a) };
+
+        // Nothing of the same task may sit between the two
+        if let Some(id) = find_overlap(&self.conn, first.task_id, first.start_time, second.end_time, &[first.id, second.id])? {
+            return Err(RotatorError::Conflict(Conflict::TimeEntry(id)));
+        }
+        let before = vec![load_entry_record(&self.conn, first.id)?, load_entry_record(&self.conn, second.id)?];
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        tx.execute("UPDATE tracking_pauses SET entry_id = ? WHERE entry_id = ?", params![first.id, second.id])?;
+        if second.start_time > first.end_time {
+            tx.execute(
+                "INSERT INTO tracking_pauses (task_id, entry_id, paused_at, resumed_at) VALUES (?, ?, ?, ?)",
+                params![first.task_id, first.id, first.end_time, second.start_time],
+            )?;
+        }
+        // Anything typed in by hand taints the merged entry
+        tx.execute(
+            "UPDATE time_entries SET end_time = ?, manual = ?, edited_at = ? WHERE id = ?",
+            params![first.end_time.max(second.end_time), first.manual || second.manual, self.clock.now(), first.id],
+        )?;
+        tx.execute("DELETE FROM time_entries WHERE id = ?", [second.id])?;
+        refresh_entry_duration(&tx, first.id)?;
+        refresh_task_total(&tx, first.task_id)?;
+        let after = vec![load_entry_record(&tx, first.id)?];
+        audit.entries(&tx, "merge_time_entries", &before, &after)?;
+        tx.commit()?;
+
+        self.sync_task_totals(&[first.task_id])?;
+        self.record(Change::EntriesEdited { edit: EntryEdit::Merged, before, after })?;
+        load_entry(&self.conn, first.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    fn setup() -> (Rotator, u64, u64) {
+        let (mut rotator, _) = rotator_at(100_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        (rotator, project_id, task_id)
+    }
+
+    fn task_total(rotator: &Rotator) -> u64 {
+        rotator.projects()[0].tasks[0].time_seconds
+    }
+
+    #[test]
+    fn update_trims_forgotten_timer() {
+        let (mut rotator, project_id, task_id) = setup();
+        rotator.add_time_entry_manual(project_id, task_id, 10_000, 50_000).unwrap();
+        let id = rotator.all_time_entries().unwrap()[0].id;
+
+        let entry = rotator.update_time_entry(id, 10_000, 13_600).unwrap();
+        assert_eq!(entry.duration_seconds, 3_600);
+        assert_eq!(task_total(&rotator), 3_600);
+    }
+
+    #[test]
+    fn update_rejects_invalid_spans_and_overlaps() {
+        let (mut rotator, project_id, task_id) = setup();
+        rotator.add_time_entry_manual(project_id, task_id, 10_000, 600).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, 20_000, 600).unwrap();
+        let entries = rotator.all_time_entries().unwrap();
+
+        assert!(matches!(rotator.update_time_entry(entries[0].id, 500, 500), Err(RotatorError::Validation(_))));
+        assert!(matches!(rotator.update_time_entry(entries[0].id, 500, 200_000), Err(RotatorError::Validation(_))));
+        assert!(matches!(rotator.update_time_entry(99, 500, 600), Err(RotatorError::NotFound(Entity::TimeEntry, 99))));
+        match rotator.update_time_entry(entries[0].id, 10_000, 20_100) {
+            Err(RotatorError::Conflict(Conflict::TimeEntry(id))) => assert_eq!(id, entries[1].id),
+            other => panic!("expected overlap, got {:?}", other),
+        }
+        // Touching is fine
+        rotator.update_time_entry(entries[0].id, 10_000, 20_000).unwrap();
+        assert_eq!(task_total(&rotator), 10_600);
+    }
+
+    #[test]
+    fn update_rejects_overlap_with_running_session() {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, 10_000, 600).unwrap();
+        let id = rotator.all_time_entries().unwrap()[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(600);
+
+        assert!(matches!(rotator.update_time_entry(id, 99_000, 100_100), Err(RotatorError::Conflict(Conflict::RunningSession))));
+        rotator.update_time_entry(id, 99_000, 100_000).unwrap();
+    }
+
+    #[test]
+    fn delete_updates_total() {
+        let (mut rotator, project_id, task_id) = setup();
+        rotator.add_time_entry_manual(project_id, task_id, 10_000, 600).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, 20_000, 300).unwrap();
+        let id = rotator.all_time_entries().unwrap()[0].id;
+
+        rotator.delete_time_entry(id).unwrap();
+        assert_eq!(task_total(&rotator), 300);
+        assert!(matches!(rotator.delete_time_entry(id), Err(RotatorError::NotFound(..))));
+    }
+
+    #[test]
+    fn split_then_merge_round_trips() {
+        let (mut rotator, project_id, task_id) = setup();
+        rotator.add_time_entry_manual(project_id, task_id, 10_000, 1_000).unwrap();
+        let id = rotator.all_time_entries().unwrap()[0].id;
+
+        assert!(matches!(rotator.split_time_entry(id, 10_000), Err(RotatorError::Validation(_))));
+        let (first, second) = rotator.split_time_entry(id, 10_400).unwrap();
+        assert_eq!((first.start_time, first.end_time, first.duration_seconds), (10_000, 10_400, 400));
+        assert_eq!((second.start_time, second.end_time, second.duration_seconds), (10_400, 11_000, 600));
+        assert_eq!(task_total(&rotator), 1_000);
+
+        let merged = rotator.merge_time_entries(second.id, first.id).unwrap();
+        assert_eq!(merged.id, first.id);
+        assert_eq!((merged.start_time, merged.end_time, merged.duration_seconds), (10_000, 11_000, 1_000));
+        assert_eq!(rotator.all_time_entries().unwrap().len(), 1);
+        assert_eq!(task_total(&rotator), 1_000);
+    }
+
+    #[test]
+    fn merge_excludes_gap_and_requires_adjacency() {
+        let (mut rotator, project_id, task_id) = setup();
+        let other = rotator.add_task(project_id, "B".into()).unwrap().tasks[1].id;
+        rotator.add_time_entry_manual(project_id, task_id, 10_000, 600).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, 12_000, 600).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, 14_000, 600).unwrap();
+        rotator.add_time_entry_manual(project_id, other, 11_000, 600).unwrap();
+        let ids: Vec<u64> = rotator.all_time_entries().unwrap().iter().filter(|e| e.task_id == task_id).map(|e| e.id).collect();
+        let other_id = rotator.all_time_entries().unwrap().iter().find(|e| e.task_id == other).unwrap().id;
+
+        assert!(matches!(rotator.merge_time_entries(ids[0], other_id), Err(RotatorError::Validation(_))));
+        assert!(matches!(rotator.merge_time_entries(ids[0], ids[2]), Err(RotatorError::Conflict(Conflict::TimeEntry(_)))));
+
+        let merged = rotator.merge_time_entries(ids[0], ids[1]).unwrap();
+        assert_eq!((merged.start_time, merged.end_time, merged.duration_seconds), (10_000, 12_600, 1_200));
+        assert_eq!(task_total(&rotator), 1_800);
+    }
+}
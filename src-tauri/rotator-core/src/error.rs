@@ -0,0 +1,162 @@
+use crate::migrations::MigrationError;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A kind of row, as named in `NotFound` errors and the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    Project,
+    Task,
+    TimeEntry,
+    Budget,
+}
+
+impl Entity {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Entity::Project => "project",
+            Entity::Task => "task",
+            Entity::TimeEntry => "time_entry",
+            Entity::Budget => "budget",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "project" => Some(Entity::Project),
+            "task" => Some(Entity::Task),
+            "time_entry" => Some(Entity::TimeEntry),
+            "budget" => Some(Entity::Budget),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_str().replace('_', " "))
+    }
+}
+
+/// What a change would clash with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Another time entry of the same task.
+    TimeEntry(u64),
+    /// The task's running session.
+    RunningSession,
+}
+
+/// Error returned by every fallible `Rotator` operation.
+#[derive(Debug)]
+pub enum RotatorError {
+    Db(rusqlite::Error),
+    NotFound(Entity, u64),
+    Conflict(Conflict),
+    /// The request itself doesn't make sense, e.g. an end before its start.
+    Validation(String),
+    /// Reading or writing a file next to the database, e.g. a backup.
+    Io(std::io::Error),
+}
+
+impl RotatorError {
+    pub(crate) fn invalid(reason: impl Into<String>) -> Self {
+        RotatorError::Validation(reason.into())
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            RotatorError::Db(_) => "db",
+            RotatorError::NotFound(..) => "not_found",
+            RotatorError::Conflict(_) => "conflict",
+            RotatorError::Validation(_) => "validation",
+            RotatorError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for RotatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RotatorError::Db(e) => write!(f, "database error: {}", e),
+            RotatorError::NotFound(entity, id) => write!(f, "{} {} not found", entity, id),
+            RotatorError::Conflict(Conflict::TimeEntry(id)) => write!(f, "overlaps time entry {}", id),
+            RotatorError::Conflict(Conflict::RunningSession) => write!(f, "overlaps the running session"),
+            RotatorError::Validation(reason) => write!(f, "{}", reason),
+            RotatorError::Io(e) => write!(f, "file error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RotatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RotatorError::Db(e) => Some(e),
+            RotatorError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for RotatorError {
+    fn from(e: rusqlite::Error) -> Self {
+        RotatorError::Db(e)
+    }
+}
+
+impl From<std::io::Error> for RotatorError {
+    fn from(e: std::io::Error) -> Self {
+        RotatorError::Io(e)
+    }
+}
+
+impl From<MigrationError> for RotatorError {
+    fn from(e: MigrationError) -> Self {
+        match e {
+            MigrationError::Sqlite(e) | MigrationError::Step { source: e, .. } => RotatorError::Db(e),
+            too_new @ MigrationError::TooNew { .. } => RotatorError::Validation(too_new.to_string()),
+            MigrationError::Backup(e) => *e,
+        }
+    }
+}
+
+/// Sent to the frontend as `{ kind, message }`, plus `entity` and `id` for
+/// `not_found` and the overlapped `entry_id` for conflicts with an entry.
+impl Serialize for RotatorError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RotatorError", 4)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        match self {
+            RotatorError::NotFound(entity, id) => {
+                state.serialize_field("entity", entity.as_str())?;
+                state.serialize_field("id", id)?;
+            }
+            RotatorError::Conflict(Conflict::TimeEntry(id)) => {
+                state.serialize_field("entry_id", id)?;
+            }
+            _ => {}
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_kind_message_and_details() {
+        let not_found = serde_json::to_value(RotatorError::NotFound(Entity::TimeEntry, 7)).unwrap();
+        assert_eq!(not_found, json!({ "kind": "not_found", "message": "time entry 7 not found", "entity": "time_entry", "id": 7 }));
+
+        let conflict = serde_json::to_value(RotatorError::Conflict(Conflict::TimeEntry(3))).unwrap();
+        assert_eq!(conflict, json!({ "kind": "conflict", "message": "overlaps time entry 3", "entry_id": 3 }));
+
+        let invalid = serde_json::to_value(RotatorError::invalid("start must be before end")).unwrap();
+        assert_eq!(invalid, json!({ "kind": "validation", "message": "start must be before end" }));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::budget::civil_from_days;
+use crate::error::RotatorError;
+use crate::policy::{Policies, RoundingMode, TimePolicy};
+use crate::rotator::Rotator;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// How timestamps are written: in the machine's local time with its UTC
+/// offset, e.g. `2024-03-13T13:00:00+01:00`, or in UTC with a `Z`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportTimezone {
+    #[default]
+    Local,
+    Utc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportColumn {
+    EntryId,
+    ProjectId,
+    Project,
+    TaskId,
+    Task,
+    /// Day the entry started on, `YYYY-MM-DD`.
+    Date,
+    Start,
+    End,
+    /// Duration after the export's rounding.
+    DurationSeconds,
+    /// `duration_seconds` in hours, to two decimals.
+    Hours,
+    /// Duration after the project's time policy, as in stats.
+    BillableSeconds,
+    Manual,
+    Edited,
+}
+
+impl ExportColumn {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExportColumn::EntryId => "entry_id",
+            ExportColumn::ProjectId => "project_id",
+            ExportColumn::Project => "project",
+            ExportColumn::TaskId => "task_id",
+            ExportColumn::Task => "task",
+            ExportColumn::Date => "date",
+            ExportColumn::Start => "start",
+            ExportColumn::End => "end",
+            ExportColumn::DurationSeconds => "duration_seconds",
+            ExportColumn::Hours => "hours",
+            ExportColumn::BillableSeconds => "billable_seconds",
+            ExportColumn::Manual => "manual",
+            ExportColumn::Edited => "edited",
+        }
+    }
+
+    fn value(self, row: &ExportRow, options: &ExportOptions, policies: &Policies) -> Value {
+        let offset = |offset: i64| match options.timezone {
+            ExportTimezone::Local => Some(offset),
+            ExportTimezone::Utc => None,
+        };
+        match self {
+            ExportColumn::EntryId => row.entry_id.into(),
+            ExportColumn::ProjectId => row.project_id.into(),
+            ExportColumn::Project => row.project.clone().into(),
+            ExportColumn::TaskId => row.task_id.into(),
+            ExportColumn::Task => row.task.clone().into(),
+            ExportColumn::Date => format_timestamp(row.start, offset(row.start_offset))[..10].into(),
+            ExportColumn::Start => format_timestamp(row.start, offset(row.start_offset)).into(),
+            ExportColumn::End => format_timestamp(row.end, offset(row.end_offset)).into(),
+            ExportColumn::DurationSeconds => options.duration(row.duration_seconds).into(),
+            ExportColumn::Hours => {
+                let hours = options.duration(row.duration_seconds) as f64 / 3600.0;
+                ((hours * 100.0).round() / 100.0).into()
+            }
+            ExportColumn::BillableSeconds => policies.for_project(row.project_id).billable_seconds(row.duration_seconds).into(),
+            ExportColumn::Manual => row.manual.into(),
+            ExportColumn::Edited => row.edited.into(),
+        }
+    }
+}
+
+fn default_columns() -> Vec<ExportColumn> {
+    vec![
+        ExportColumn::Date,
+        ExportColumn::Project,
+        ExportColumn::Task,
+        ExportColumn::Start,
+        ExportColumn::End,
+        ExportColumn::DurationSeconds,
+    ]
+}
+
+fn no_rounding() -> RoundingMode {
+    RoundingMode::None
+}
+
+/// What to export: entries starting in `from..to`, as `columns` in that order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub from: u64,
+    pub to: u64,
+    pub format: ExportFormat,
+    #[serde(default = "default_columns")]
+    pub columns: Vec<ExportColumn>,
+    #[serde(default)]
+    pub timezone: ExportTimezone,
+    /// Applied to each entry's duration, like the rounding of a time policy.
+    #[serde(default = "no_rounding")]
+    pub rounding: RoundingMode,
+    #[serde(default)]
+    pub round_to_minutes: u64,
+}
+
+impl ExportOptions {
+    /// The default columns in local time, unrounded.
+    pub fn new(from: u64, to: u64, format: ExportFormat) -> Self {
+        Self {
+            from,
+            to,
+            format,
+            columns: default_columns(),
+            timezone: ExportTimezone::Local,
+            rounding: RoundingMode::None,
+            round_to_minutes: 0,
+        }
+    }
+
+    fn duration(&self, duration_seconds: u64) -> u64 {
+        TimePolicy { min_session_seconds: 0, rounding: self.rounding, round_to_minutes: self.round_to_minutes }
+            .billable_seconds(duration_seconds)
+    }
+}
+
+/// A time entry joined with its names. The offsets are the local UTC offset
+/// at the start and end, which differ when the entry spans a DST change.
+struct ExportRow {
+    entry_id: u64,
+    project_id: u64,
+    project: String,
+    task_id: u64,
+    task: String,
+    start: u64,
+    end: u64,
+    start_offset: i64,
+    end_offset: i64,
+    duration_seconds: u64,
+    manual: bool,
+    edited: bool,
+}
+
+/// `2024-03-13T13:00:00+01:00`, or `2024-03-13T12:00:00Z` without an offset.
+fn format_timestamp(at: u64, offset: Option<i64>) -> String {
+    let local = at.saturating_add_signed(offset.unwrap_or(0));
+    let (year, month, day) = civil_from_days(local / DAY);
+    let seconds = local % DAY;
+    let zone = match offset {
+        None => "Z".to_string(),
+        Some(offset) => {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let minutes = offset.unsigned_abs() / 60;
+            format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+        }
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, zone,
+    )
+}
+
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+impl Rotator {
+    fn export_rows(&self, options: &ExportOptions) -> Result<Vec<ExportRow>, RotatorError> {
+        if options.from >= options.to {
+            return Err(RotatorError::invalid("export range is empty"));
+        }
+        if options.columns.is_empty() {
+            return Err(RotatorError::invalid("no columns to export"));
+        }
+        // Archived projects and tasks keep their rows, so their names still join
+        let mut stmt = self.conn.prepare(
+            "SELECT e.id, e.project_id, COALESCE(p.name, ''), e.task_id, COALESCE(t.name, ''),
+                    e.start_time, e.end_time,
+                    CAST(strftime('%s', e.start_time, 'unixepoch', 'localtime') AS INTEGER) - e.start_time,
+                    CAST(strftime('%s', e.end_time, 'unixepoch', 'localtime') AS INTEGER) - e.end_time,
+                    e.duration_seconds, e.manual, e.edited_at IS NOT NULL
+             FROM time_entries e
+             LEFT JOIN projects p ON p.id = e.project_id
+             LEFT JOIN tasks t ON t.id = e.task_id
+             WHERE e.start_time >= ? AND e.start_time < ?
+             ORDER BY e.start_time, e.id",
+        )?;
+        let rows = stmt.query_map(params![options.from, options.to], |row| {
+            Ok(ExportRow {
+                entry_id: row.get(0)?,
+                project_id: row.get(1)?,
+                project: row.get(2)?,
+                task_id: row.get(3)?,
+                task: row.get(4)?,
+                start: row.get(5)?,
+                end: row.get(6)?,
+                start_offset: row.get(7)?,
+                end_offset: row.get(8)?,
+                duration_seconds: row.get(9)?,
+                manual: row.get(10)?,
+                edited: row.get(11)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn render_export(&self, options: &ExportOptions, rows: &[ExportRow]) -> Result<String, RotatorError> {
+        let policies = Policies::load(&self.conn)?;
+        let values = |row: &ExportRow| -> Vec<Value> {
+            options.columns.iter().map(|c| c.value(row, options, &policies)).collect()
+        };
+        Ok(match options.format {
+            ExportFormat::Csv => {
+                let header: Vec<&str> = options.columns.iter().map(|c| c.as_str()).collect();
+                let mut out = header.join(",") + "\n";
+                for row in rows {
+                    let fields: Vec<String> = values(row).iter().map(csv_field).collect();
+                    out.push_str(&fields.join(","));
+                    out.push('\n');
+                }
+                out
+            }
+            ExportFormat::Json => {
+                let entries: Vec<Value> = rows.iter()
+                    .map(|row| {
+                        let object: Map<String, Value> = options.columns.iter()
+                            .map(|c| c.as_str().to_string())
+                            .zip(values(row))
+                            .collect();
+                        Value::Object(object)
+                    })
+                    .collect();
+                serde_json::to_string_pretty(&entries).unwrap_or_default()
+            }
+        })
+    }
+
+    /// Time entries starting in the range, rendered as CSV or JSON.
+    pub fn export_time_entries(&self, options: &ExportOptions) -> Result<String, RotatorError> {
+        let rows = self.export_rows(options)?;
+        self.render_export(options, &rows)
+    }
+
+    /// Write the export to `path` and return how many entries it holds.
+    pub fn export_time_entries_to(&self, options: &ExportOptions, path: &Path) -> Result<usize, RotatorError> {
+        let rows = self.export_rows(options)?;
+        std::fs::write(path, self.render_export(options, &rows)?)?;
+        Ok(rows.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+    use serde_json::json;
+
+    // Wednesday 2024-03-13 12:00 UTC
+    const NOW: u64 = 1_710_331_200;
+
+    fn utc(options: ExportOptions) -> ExportOptions {
+        ExportOptions { timezone: ExportTimezone::Utc, ..options }
+    }
+
+    #[test]
+    fn timestamps_carry_their_offset() {
+        assert_eq!(format_timestamp(NOW, None), "2024-03-13T12:00:00Z");
+        assert_eq!(format_timestamp(NOW, Some(3_600)), "2024-03-13T13:00:00+01:00");
+        assert_eq!(format_timestamp(NOW, Some(-(13 * 3_600 + 1_800))), "2024-03-12T22:30:00-13:30");
+    }
+
+    #[test]
+    fn csv_joins_names_of_archived_tasks_and_quotes_fields() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let project_id = rotator.add_project("Client, Inc".into()).unwrap()[0].id;
+        let project = rotator.add_task(project_id, "Say \"hi\"".into()).unwrap();
+        let task_id = project.tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 7_200, 1_800).unwrap();
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 2 * DAY, 600).unwrap();
+        rotator.remove_task(project_id, task_id).unwrap();
+
+        let csv = rotator.export_time_entries(&utc(ExportOptions::new(NOW - DAY, NOW, ExportFormat::Csv))).unwrap();
+        assert_eq!(
+            csv,
+            "date,project,task,start,end,duration_seconds\n\
+             2024-03-13,\"Client, Inc\",\"Say \"\"hi\"\"\",2024-03-13T10:00:00Z,2024-03-13T10:30:00Z,1800\n",
+        );
+    }
+
+    #[test]
+    fn json_uses_the_chosen_columns_and_rounding() {
+        let (mut rotator, clock) = rotator_at(NOW);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(1_000);
+        let entry_id = rotator.stop_tracking(None).unwrap()[0].entry_id.unwrap();
+
+        let options = ExportOptions {
+            columns: vec![ExportColumn::EntryId, ExportColumn::DurationSeconds, ExportColumn::Hours, ExportColumn::Manual],
+            rounding: RoundingMode::Up,
+            round_to_minutes: 15,
+            ..utc(ExportOptions::new(NOW, NOW + DAY, ExportFormat::Json))
+        };
+        let json: Value = serde_json::from_str(&rotator.export_time_entries(&options).unwrap()).unwrap();
+        assert_eq!(json, json!([{ "entry_id": entry_id, "duration_seconds": 1_800, "hours": 0.5, "manual": false }]));
+    }
+
+    #[test]
+    fn writes_to_a_file_and_rejects_empty_requests() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, NOW - 3_600, 600).unwrap();
+
+        let path = std::env::temp_dir().join(format!("rotator-export-{}.csv", std::process::id()));
+        let options = ExportOptions::new(NOW - DAY, NOW, ExportFormat::Csv);
+        assert_eq!(rotator.export_time_entries_to(&options, &path).unwrap(), 1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        std::fs::remove_file(&path).unwrap();
+
+        let empty = ExportOptions::new(NOW, NOW, ExportFormat::Csv);
+        assert!(matches!(rotator.export_time_entries(&empty), Err(RotatorError::Validation(_))));
+        let no_columns = ExportOptions { columns: Vec::new(), ..options };
+        assert!(matches!(rotator.export_time_entries(&no_columns), Err(RotatorError::Validation(_))));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::error::RotatorError;
+use crate::rotator::Rotator;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What caused a rotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationTrigger {
+    Hotkey,
+    /// The timebox moved on at the end of a block.
+    Timer,
+    /// Picked or clicked in the UI.
+    Manual,
+}
+
+impl RotationTrigger {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RotationTrigger::Hotkey => "hotkey",
+            RotationTrigger::Timer => "timer",
+            RotationTrigger::Manual => "manual",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "hotkey" => Some(RotationTrigger::Hotkey),
+            "timer" => Some(RotationTrigger::Timer),
+            "manual" => Some(RotationTrigger::Manual),
+            _ => None,
+        }
+    }
+}
+
+/// One change of the current project and/or task.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotationEvent {
+    pub id: u64,
+    pub occurred_at: u64,
+    pub from_project_id: Option<u64>,
+    pub from_task_id: Option<u64>,
+    pub to_project_id: u64,
+    pub to_task_id: Option<u64>,
+    pub trigger: RotationTrigger,
+    /// Tracking started on the new task before the next rotation.
+    pub tracking_followed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyContextSwitches {
+    pub date: String,
+    pub project_switches: u64,
+    pub task_switches: u64,
+}
+
+/// How long the current project stayed put before rotating away from it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DwellTime {
+    pub project_id: u64,
+    pub visits: u64,
+    pub average_seconds: u64,
+}
+
+impl Rotator {
+    /// The current project and its current task.
+    pub(crate) fn current_position(&self) -> (Option<u64>, Option<u64>) {
+        match self.current_project_ref() {
+            Some(project) => (Some(project.id), project.current_task_id),
+            None => (None, None),
+        }
+    }
+
+    /// Log a move away from `from` to wherever the rotator is now. Nothing is
+    /// logged if the position didn't change.
+    pub(crate) fn record_rotation(&self, from: (Option<u64>, Option<u64>), trigger: RotationTrigger) -> rusqlite::Result<()> {
+        let to = self.current_position();
+        let Some(to_project_id) = to.0 else { return Ok(()) };
+        if from == to {
+            return Ok(());
+        }
+        let to_task_tracked = to.1.is_some_and(|id| self.active_tracking.iter().any(|t| t.task_id == id));
+        self.conn.execute(
+            "INSERT INTO rotation_events
+                (occurred_at, from_project_id, from_task_id, to_project_id, to_task_id, trigger, tracking_followed)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![self.clock.now(), from.0, from.1, to_project_id, to.1, trigger.as_str(), to_task_tracked],
+        )?;
+        Ok(())
+    }
+
+    /// Flag the latest rotation as followed by tracking if it led to `task_id`.
+    pub(crate) fn mark_rotation_followed(&self, task_id: u64) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE rotation_events SET tracking_followed = 1
+             WHERE id = (SELECT MAX(id) FROM rotation_events) AND to_task_id = ?",
+            [task_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn rotation_events(&self, start_time: u64, end_time: u64) -> Result<Vec<RotationEvent>, RotatorError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, occurred_at, from_project_id, from_task_id, to_project_id, to_task_id, trigger, tracking_followed
+             FROM rotation_events
+             WHERE occurred_at >= ? AND occurred_at <= ?
+             ORDER BY occurred_at, id"
+        )?;
+
+        let events = stmt.query_map(params![start_time, end_time], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get::<_, String>(6)?,
+                row.get(7)?,
+            ))
+        })?;
+
+        let rows = events.collect::<rusqlite::Result<Vec<(u64, u64, Option<u64>, Option<u64>, u64, Option<u64>, String, bool)>>>()?;
+        Ok(rows.into_iter()
+            .filter_map(|(id, occurred_at, from_project_id, from_task_id, to_project_id, to_task_id, trigger, tracking_followed)| {
+                Some(RotationEvent {
+                    id,
+                    occurred_at,
+                    from_project_id,
+                    from_task_id,
+                    to_project_id,
+                    to_task_id,
+                    trigger: RotationTrigger::parse(&trigger)?,
+                    tracking_followed,
+                })
+            })
+            .collect())
+    }
+
+    /// Rotations per local day, split into project and task-only switches.
+    pub fn context_switches_per_day(&self, start_time: u64, end_time: u64) -> Result<Vec<DailyContextSwitches>, RotatorError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date(occurred_at, 'unixepoch', 'localtime') as day,
+                SUM(from_project_id IS NOT to_project_id),
+                SUM(from_project_id IS to_project_id)
+             FROM rotation_events
+             WHERE occurred_at >= ? AND occurred_at <= ?
+             GROUP BY day
+             ORDER BY day"
+        )?;
+
+        let days = stmt.query_map(params![start_time, end_time], |row| {
+            Ok(DailyContextSwitches {
+                date: row.get(0)?,
+                project_switches: row.get(1)?,
+                task_switches: row.get(2)?,
+            })
+        })?;
+
+        Ok(days.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Average time spent on each project between arriving at it and
+    /// rotating to another project. Task rotations within a project don't
+    /// end a visit.
+    pub fn average_dwell_times(&self, start_time: u64, end_time: u64) -> Result<Vec<DwellTime>, RotatorError> {
+        let events = self.rotation_events(start_time, end_time)?;
+        let mut totals: HashMap<u64, (u64, u64)> = HashMap::new();
+        let mut arrival: Option<(u64, u64)> = None;
+
+        for event in events.iter().filter(|e| e.from_project_id != Some(e.to_project_id)) {
+            if let Some((project_id, arrived_at)) = arrival {
+                let total = totals.entry(project_id).or_default();
+                total.0 += 1;
+                total.1 += event.occurred_at.saturating_sub(arrived_at);
+            }
+            arrival = Some((event.to_project_id, event.occurred_at));
+        }
+
+        let mut dwell: Vec<DwellTime> = totals.into_iter()
+            .map(|(project_id, (visits, seconds))| DwellTime { project_id, visits, average_seconds: seconds / visits })
+            .collect();
+        dwell.sort_by_key(|d| d.project_id);
+        Ok(dwell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    #[test]
+    fn rotations_are_logged_with_trigger_and_follow_up() {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let home = rotator.add_project("Home".into()).unwrap()[1].id;
+        let a = rotator.add_task(work, "A".into()).unwrap().tasks[0].id;
+        let b = rotator.add_task(work, "B".into()).unwrap().tasks[1].id;
+        let c = rotator.add_task(home, "C".into()).unwrap().tasks[0].id;
+
+        clock.advance(60);
+        rotator.rotate_task(RotationTrigger::Hotkey).unwrap();
+        rotator.start_tracking(work, b, false).unwrap();
+        clock.advance(60);
+        rotator.rotate_project(RotationTrigger::Manual).unwrap();
+
+        let events = rotator.rotation_events(0, i64::MAX as u64).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            (events[0].from_task_id, events[0].to_task_id, events[0].trigger, events[0].tracking_followed),
+            (Some(a), Some(b), RotationTrigger::Hotkey, true),
+        );
+        assert_eq!(
+            (events[1].from_project_id, events[1].to_project_id, events[1].to_task_id, events[1].tracking_followed),
+            (Some(work), home, Some(c), false),
+        );
+    }
+
+    #[test]
+    fn single_project_rotation_is_not_logged() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        rotator.add_task(work, "A".into()).unwrap();
+        rotator.rotate_project(RotationTrigger::Hotkey).unwrap();
+        rotator.rotate_task(RotationTrigger::Hotkey).unwrap();
+        assert!(rotator.rotation_events(0, i64::MAX as u64).unwrap().is_empty());
+    }
+
+    #[test]
+    fn counts_switches_and_dwell() {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let home = rotator.add_project("Home".into()).unwrap()[1].id;
+        rotator.add_task(work, "A".into()).unwrap();
+        rotator.add_task(work, "B".into()).unwrap();
+        rotator.add_task(home, "C".into()).unwrap();
+
+        // Home for 600s, then Work for 300s with a task switch in between
+        rotator.rotate_project(RotationTrigger::Hotkey).unwrap();
+        clock.advance(600);
+        rotator.rotate_project(RotationTrigger::Hotkey).unwrap();
+        clock.advance(100);
+        rotator.rotate_task(RotationTrigger::Hotkey).unwrap();
+        clock.advance(200);
+        rotator.rotate_project(RotationTrigger::Hotkey).unwrap();
+
+        let days = rotator.context_switches_per_day(0, i64::MAX as u64).unwrap();
+        let project_switches: u64 = days.iter().map(|d| d.project_switches).sum();
+        let task_switches: u64 = days.iter().map(|d| d.task_switches).sum();
+        assert_eq!((project_switches, task_switches), (3, 1));
+        let dwell = rotator.average_dwell_times(0, i64::MAX as u64).unwrap();
+        assert_eq!(dwell, vec![
+            DwellTime { project_id: work, visits: 1, average_seconds: 300 },
+            DwellTime { project_id: home, visits: 1, average_seconds: 600 },
+        ]);
+    }
+}
@@ -0,0 +1,455 @@
+use crate::audit::snapshot;
+use crate::db::{load_setting, save_setting};
+use crate::entries::find_overlap;
+use crate::error::{Conflict, Entity, RotatorError};
+use crate::journal::load_entry_record;
+use crate::models::ActiveTracking;
+use crate::rotator::Rotator;
+use crate::session::{close_sessions_in, insert_entry};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How long the user has been away from keyboard and mouse.
+pub trait IdleSource: Send {
+    /// Seconds since the last input, `None` if it can't be determined.
+    fn idle_seconds(&self) -> Option<u64>;
+}
+
+/// Idle source that reports whatever it was last set to. Clones share the
+/// same value, like `ManualClock`.
+#[derive(Clone, Default)]
+pub struct FakeIdleSource(Arc<AtomicU64>);
+
+impl FakeIdleSource {
+    pub fn new(idle_seconds: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(idle_seconds)))
+    }
+
+    pub fn set(&self, idle_seconds: u64) {
+        self.0.store(idle_seconds, Ordering::SeqCst);
+    }
+}
+
+impl IdleSource for FakeIdleSource {
+    fn idle_seconds(&self) -> Option<u64> {
+        Some(self.0.load(Ordering::SeqCst))
+    }
+}
+
+/// X11 screensaver idle time via `xprintidle`, falling back to the logind
+/// idle hint of the current session (Wayland, or X without the extension).
+#[cfg(target_os = "linux")]
+pub struct LinuxIdleSource;
+
+#[cfg(target_os = "linux")]
+impl LinuxIdleSource {
+    fn x11() -> Option<u64> {
+        std::env::var_os("DISPLAY")?;
+        let output = std::process::Command::new("xprintidle").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        parse_xprintidle(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn logind() -> Option<u64> {
+        let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".into());
+        let output = std::process::Command::new("loginctl")
+            .args(["show-session", &session, "-p", "IdleHint", "-p", "IdleSinceHint"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let now_micros = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_micros() as u64;
+        parse_logind(&String::from_utf8_lossy(&output.stdout), now_micros)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl IdleSource for LinuxIdleSource {
+    fn idle_seconds(&self) -> Option<u64> {
+        Self::x11().or_else(Self::logind)
+    }
+}
+
+/// `xprintidle` prints the idle time in milliseconds.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_xprintidle(output: &str) -> Option<u64> {
+    output.trim().parse::<u64>().ok().map(|ms| ms / 1000)
+}
+
+/// `loginctl show-session -p IdleHint -p IdleSinceHint` prints `key=value`
+/// lines; the hint timestamp is in microseconds.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_logind(output: &str, now_micros: u64) -> Option<u64> {
+    let mut idle = None;
+    let mut since = None;
+    for line in output.lines() {
+        match line.split_once('=') {
+            Some(("IdleHint", value)) => idle = Some(value.trim() == "yes"),
+            Some(("IdleSinceHint", value)) => since = value.trim().parse::<u64>().ok(),
+            _ => {}
+        }
+    }
+    match (idle?, since) {
+        (false, _) => Some(0),
+        (true, Some(since)) if since > 0 => Some(now_micros.saturating_sub(since) / 1_000_000),
+        (true, _) => None,
+    }
+}
+
+/// The idle source for the platform we were built for, if there is one.
+pub fn platform_idle_source() -> Option<Box<dyn IdleSource>> {
+    #[cfg(target_os = "linux")]
+    {
+        Some(Box::new(LinuxIdleSource))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// What happens to running sessions once the user has been idle long enough.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleAction {
+    /// Stop tracking, ending the sessions at the last moment of activity.
+    StopAtLastActive,
+    /// Keep tracking through the idle span and let the user decide later.
+    RecordIdle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleSettings {
+    /// Idle seconds before the action kicks in. 0 turns idle detection off.
+    pub threshold_seconds: u64,
+    pub action: IdleAction,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self { threshold_seconds: 5 * 60, action: IdleAction::StopAtLastActive }
+    }
+}
+
+impl IdleSettings {
+    pub(crate) fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let default = Self::default();
+        let threshold_seconds = load_setting(conn, "idle_threshold_seconds")?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.threshold_seconds);
+        let action = match load_setting(conn, "idle_action")?.as_deref() {
+            Some("record_idle") => IdleAction::RecordIdle,
+            Some("stop_at_last_active") => IdleAction::StopAtLastActive,
+            _ => default.action,
+        };
+        Ok(Self { threshold_seconds, action })
+    }
+
+    fn save(&self, conn: &Connection) -> rusqlite::Result<()> {
+        let action = match self.action {
+            IdleAction::StopAtLastActive => "stop_at_last_active",
+            IdleAction::RecordIdle => "record_idle",
+        };
+        save_setting(conn, "idle_threshold_seconds", &self.threshold_seconds.to_string())?;
+        save_setting(conn, "idle_action", action)
+    }
+}
+
+/// A stretch of inactivity that caught running sessions and still needs a
+/// decision from the user.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IdlePeriod {
+    /// Last moment of activity; the idle span starts here.
+    pub started_at: u64,
+    /// When the user came back, `None` while still away.
+    pub returned_at: Option<u64>,
+    pub action: IdleAction,
+    /// Sessions that were running when the idle span began.
+    pub sessions: Vec<ActiveTracking>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IdleEvent {
+    /// The threshold was crossed while tracking.
+    Detected(IdlePeriod),
+    /// Input resumed after a detected idle span.
+    Returned(IdlePeriod),
+}
+
+/// What to do with the idle span once the user is back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IdleResolution {
+    /// Count it for the tasks that were running.
+    Keep,
+    /// Drop it.
+    Discard,
+    /// Count it for another task instead.
+    Reassign { project_id: u64, task_id: u64 },
+}
+
+impl Rotator {
+    pub fn idle_settings(&self) -> IdleSettings {
+        self.idle_settings
+    }
+
+    pub fn set_idle_settings(&mut self, settings: IdleSettings) -> Result<IdleSettings, RotatorError> {
+        settings.save(&self.conn)?;
+        self.audit("set_idle_settings", None, snapshot(&self.idle_settings), snapshot(&settings))?;
+        self.idle_settings = settings;
+        Ok(settings)
+    }
+
+    pub fn pending_idle(&self) -> Option<IdlePeriod> {
+        self.pending_idle.clone()
+    }
+
+    /// Feed the current idle time in. Call it periodically; it returns an
+    /// event when the threshold is crossed and again when the user returns.
+    pub fn check_idle(&mut self, idle_seconds: u64) -> Result<Option<IdleEvent>, RotatorError> {
+        let threshold = self.idle_settings.threshold_seconds;
+        let now = self.clock.now();
+
+        if let Some(pending) = &mut self.pending_idle {
+            if pending.returned_at.is_none() && idle_seconds < threshold.max(1) {
+                pending.returned_at = Some(now.saturating_sub(idle_seconds).max(pending.started_at));
+                return Ok(Some(IdleEvent::Returned(pending.clone())));
+            }
+            return Ok(None);
+        }
+
+        // Paused sessions aren't counting, so being away from them is fine
+        let sessions: Vec<ActiveTracking> = self.active_tracking.iter()
+            .filter(|t| t.paused_at.is_none())
+            .cloned()
+            .collect();
+        if threshold == 0 || idle_seconds < threshold || sessions.is_empty() {
+            return Ok(None);
+        }
+
+        let last_active = now.saturating_sub(idle_seconds);
+        let action = self.idle_settings.action;
+
+        if action == IdleAction::StopAtLastActive {
+            // Sessions started while already idle end up shorter than the
+            // minimum and are discarded
+            let closed = self.close_sessions("check_idle", &sessions, last_active)?;
+            if closed.is_empty() {
+                return Ok(None);
+            }
+        }
+
+        let period = IdlePeriod { started_at: last_active, returned_at: None, action, sessions };
+        self.pending_idle = Some(period.clone());
+        Ok(Some(IdleEvent::Detected(period)))
+    }
+
+    /// Settle the pending idle span. With `RecordIdle` the sessions keep
+    /// running afterwards; with `StopAtLastActive` they stay stopped.
+    pub fn resolve_idle(&mut self, resolution: IdleResolution) -> Result<Vec<ActiveTracking>, RotatorError> {
+        let Some(pending) = self.pending_idle.clone() else {
+            return Ok(self.active_tracking.clone());
+        };
+        let start = pending.started_at;
+        let end = pending.returned_at.unwrap_or_else(|| self.clock.now()).max(start);
+
+        // Only sessions that are still the same run are affected; the user
+        // may have stopped or restarted tracking in the meantime
+        let running: Vec<ActiveTracking> = match pending.action {
+            IdleAction::RecordIdle => pending.sessions.iter()
+                .filter(|s| self.active_tracking.iter().any(|t| t.task_id == s.task_id && t.started_at == s.started_at))
+                .cloned()
+                .collect(),
+            IdleAction::StopAtLastActive => Vec::new(),
+        };
+
+        let credited: Vec<(u64, u64)> = match resolution {
+            IdleResolution::Keep if pending.action == IdleAction::RecordIdle => Vec::new(),
+            IdleResolution::Keep => pending.sessions.iter().map(|s| (s.project_id, s.task_id)).collect(),
+            IdleResolution::Discard => Vec::new(),
+            IdleResolution::Reassign { project_id, task_id } => {
+                if !self.projects.iter().any(|p| p.id == project_id && p.tasks.iter().any(|t| t.id == task_id)) {
+                    return Err(RotatorError::NotFound(Entity::Task, task_id));
+                }
+                vec![(project_id, task_id)]
+            }
+        };
+        let split = pending.action == IdleAction::RecordIdle && resolution != IdleResolution::Keep;
+
+        for &(_, task_id) in &credited {
+            let closing = split && running.iter().any(|r| r.task_id == task_id);
+            if !closing && self.active_tracking.iter().any(|t| t.task_id == task_id && t.started_at < end) {
+                return Err(RotatorError::Conflict(Conflict::RunningSession));
+            }
+            if let Some(id) = find_overlap(&self.conn, task_id, start, end, &[])? {
+                return Err(RotatorError::Conflict(Conflict::TimeEntry(id)));
+            }
+        }
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        let mut touched: Vec<u64> = Vec::new();
+        let mut restarted = Vec::new();
+        if split {
+            let closed = close_sessions_in(&tx, &running, start)?;
+            audit.closed(&tx, "resolve_idle", &closed)?;
+            touched.extend(closed.iter().filter(|c| !c.discarded).map(|c| c.task_id));
+            for session in &running {
+                let resumed = ActiveTracking { started_at: end, paused_at: None, paused_seconds: 0, ..session.clone() };
+                tx.execute(
+                    "INSERT INTO active_tracking (project_id, task_id, started_at, last_heartbeat) VALUES (?, ?, ?, ?)",
+                    params![resumed.project_id, resumed.task_id, resumed.started_at, resumed.started_at],
+                )?;
+                restarted.push(resumed);
+            }
+        }
+        if end > start {
+            for &(project_id, task_id) in &credited {
+                let entry_id = insert_entry(&tx, project_id, task_id, start, end, false)?;
+                audit.entries(&tx, "resolve_idle", &[], &[load_entry_record(&tx, entry_id)?])?;
+                touched.push(task_id);
+            }
+        }
+        audit.record(&tx, "resolve_idle", None, snapshot(&pending), snapshot(&resolution))?;
+        tx.commit()?;
+
+        if split {
+            self.active_tracking.retain(|t| !running.iter().any(|r| r.task_id == t.task_id));
+            self.active_tracking.extend(restarted);
+        }
+        self.pending_idle = None;
+        self.sync_task_totals(&touched)?;
+        Ok(self.active_tracking.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::test_support::rotator_at;
+    use crate::AuditSource;
+
+    /// Rotator tracking task A of a project with tasks A and B since 100_000.
+    fn tracking(action: IdleAction) -> (Rotator, ManualClock, u64, u64, u64) {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        rotator.add_task(project_id, "A".into()).unwrap();
+        let tasks = rotator.add_task(project_id, "B".into()).unwrap().tasks;
+        rotator.set_idle_settings(IdleSettings { threshold_seconds: 300, action }).unwrap();
+        rotator.start_tracking(project_id, tasks[0].id, false).unwrap();
+        (rotator, clock, project_id, tasks[0].id, tasks[1].id)
+    }
+
+    fn spans(rotator: &Rotator, task_id: u64) -> Vec<(u64, u64)> {
+        rotator.all_time_entries().unwrap().iter()
+            .filter(|e| e.task_id == task_id)
+            .map(|e| (e.start_time, e.end_time))
+            .collect()
+    }
+
+    #[test]
+    fn nothing_happens_below_threshold_or_when_disabled() {
+        let (mut rotator, clock, ..) = tracking(IdleAction::StopAtLastActive);
+        clock.advance(1_000);
+        assert_eq!(rotator.check_idle(299).unwrap(), None);
+
+        rotator.set_idle_settings(IdleSettings { threshold_seconds: 0, action: IdleAction::StopAtLastActive }).unwrap();
+        assert_eq!(rotator.check_idle(900).unwrap(), None);
+        assert_eq!(rotator.active_tracking().len(), 1);
+    }
+
+    #[test]
+    fn stop_at_last_active_trims_session_and_discard_drops_idle_span() {
+        let (mut rotator, clock, _, a, _) = tracking(IdleAction::StopAtLastActive);
+        clock.advance(1_000);
+
+        assert!(matches!(rotator.check_idle(600).unwrap(), Some(IdleEvent::Detected(_))));
+        assert!(rotator.active_tracking().is_empty());
+        assert_eq!(spans(&rotator, a), vec![(100_000, 100_400)]);
+        assert_eq!(rotator.check_idle(700).unwrap(), None);
+
+        clock.advance(100);
+        let Some(IdleEvent::Returned(period)) = rotator.check_idle(2).unwrap() else { panic!("expected return") };
+        assert_eq!((period.started_at, period.returned_at), (100_400, Some(101_098)));
+
+        rotator.resolve_idle(IdleResolution::Discard).unwrap();
+        assert_eq!(spans(&rotator, a), vec![(100_000, 100_400)]);
+        assert_eq!(rotator.pending_idle(), None);
+        assert!(rotator.active_tracking().is_empty());
+    }
+
+    #[test]
+    fn keep_records_idle_span_for_stopped_task() {
+        let (mut rotator, clock, _, a, _) = tracking(IdleAction::StopAtLastActive);
+        clock.advance(1_000);
+        rotator.check_idle(600).unwrap();
+        rotator.check_idle(0).unwrap();
+
+        rotator.resolve_idle(IdleResolution::Keep).unwrap();
+        assert_eq!(spans(&rotator, a), vec![(100_000, 100_400), (100_400, 101_000)]);
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 1_000);
+    }
+
+    #[test]
+    fn record_idle_keeps_running_and_reassign_moves_the_span() {
+        let (mut rotator, clock, project_id, a, b) = tracking(IdleAction::RecordIdle);
+        clock.advance(1_000);
+
+        assert!(matches!(rotator.check_idle(600).unwrap(), Some(IdleEvent::Detected(_))));
+        assert_eq!(rotator.active_tracking().len(), 1);
+        assert!(rotator.all_time_entries().unwrap().is_empty());
+
+        rotator.check_idle(0).unwrap();
+        let tracking = rotator.resolve_idle(IdleResolution::Reassign { project_id, task_id: b }).unwrap();
+
+        assert_eq!(spans(&rotator, a), vec![(100_000, 100_400)]);
+        assert_eq!(spans(&rotator, b), vec![(100_400, 101_000)]);
+        assert_eq!(tracking.len(), 1);
+        assert_eq!((tracking[0].task_id, tracking[0].started_at), (a, 101_000));
+    }
+
+    #[test]
+    fn idle_trims_and_reassigned_time_reach_the_audit_log() {
+        let (mut rotator, clock, project_id, a, b) = tracking(IdleAction::StopAtLastActive);
+        rotator.set_source(AuditSource::System);
+        clock.advance(1_000);
+        rotator.check_idle(600).unwrap();
+        rotator.check_idle(0).unwrap();
+        let trimmed = rotator.audit_log(None, None, 1).unwrap().remove(0);
+        assert_eq!((trimmed.command.as_str(), trimmed.source, trimmed.entity), ("check_idle", AuditSource::System, Some(Entity::TimeEntry)));
+
+        rotator.set_source(AuditSource::Ui);
+        rotator.resolve_idle(IdleResolution::Reassign { project_id, task_id: b }).unwrap();
+        let entry_id = rotator.all_time_entries().unwrap().iter().find(|e| e.task_id == b).unwrap().id;
+        let log = rotator.audit_log(Some(Entity::TimeEntry), Some(entry_id), 10).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].command.as_str(), log[0].before.is_none()), ("resolve_idle", true));
+        assert_eq!(spans(&rotator, a), vec![(100_000, 100_400)]);
+    }
+
+    #[test]
+    fn settings_persist() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let settings = IdleSettings { threshold_seconds: 120, action: IdleAction::RecordIdle };
+        rotator.set_idle_settings(settings).unwrap();
+        assert_eq!(IdleSettings::load(rotator.connection()).unwrap(), settings);
+    }
+
+    #[test]
+    fn parses_platform_output() {
+        assert_eq!(parse_xprintidle("61234\n"), Some(61));
+        assert_eq!(parse_logind("IdleHint=no\nIdleSinceHint=0\n", 5_000_000), Some(0));
+        assert_eq!(parse_logind("IdleHint=yes\nIdleSinceHint=2000000\n", 92_000_000), Some(90));
+        assert_eq!(parse_logind("", 0), None);
+    }
+}
@@ -0,0 +1,473 @@
+use crate::audit::snapshot;
+use crate::backup::BackupReason;
+use crate::budget::{civil_from_days, days_from_civil};
+use crate::db::{next_project_position, next_task_position};
+use crate::entries::find_overlap;
+use crate::error::RotatorError;
+use crate::journal::load_entry_record;
+use crate::rotator::Rotator;
+use crate::session::insert_entry;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Task name for entries that have nothing to name one after.
+const UNNAMED_TASK: &str = "General";
+
+/// Another tracker's export format.
+///
+/// Toggl Track and Clockify detailed CSV reports map their project (or the
+/// client, without one) to a Rotator project, and their task (or the
+/// description, or the first tag) to a task. Their times are in local time.
+/// Timewarrior's `timew export` JSON maps the first tag to the project and
+/// the second tag, or the annotation, to the task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    Toggl,
+    Clockify,
+    Timewarrior,
+}
+
+/// A row of the export that was not imported.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedRow {
+    /// 1-based, counting the CSV header or the first JSON interval as 1.
+    pub row: usize,
+    pub reason: String,
+}
+
+/// A row that overlaps an entry already tracked on the same task.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowConflict {
+    pub row: usize,
+    pub existing_entry_id: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewTask {
+    pub project: String,
+    pub task: String,
+}
+
+/// What an import did, or with `dry_run` would do.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackerImportReport {
+    pub dry_run: bool,
+    pub rows: usize,
+    pub projects_created: Vec<String>,
+    pub tasks_created: Vec<NewTask>,
+    pub entries_added: usize,
+    /// Rows whose task, start and end match an entry already there.
+    pub duplicates: usize,
+    pub conflicts: Vec<RowConflict>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+struct ParsedRow {
+    row: usize,
+    project: String,
+    task: String,
+    start: u64,
+    end: u64,
+}
+
+/// Split CSV text into records, handling quoted fields with commas, quotes
+/// and line breaks in them.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| r.iter().any(|f| !f.is_empty()));
+    records
+}
+
+/// `2024-03-13`, or `03/13/2024` as Clockify writes it by default.
+fn parse_date(value: &str) -> Option<(u64, u64, u64)> {
+    let parts: Vec<u64> = value.split(['-', '/']).map(|p| p.trim().parse().ok()).collect::<Option<_>>()?;
+    let (year, month, day) = match (value.contains('/'), parts.as_slice()) {
+        (false, [year, month, day]) => (*year, *month, *day),
+        (true, [month, day, year]) => (*year, *month, *day),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || !(1970..=9999).contains(&year) {
+        return None;
+    }
+    // Days past the end of the month roll over into the next one
+    (civil_from_days(days_from_civil(year, month, day)) == (year, month, day)).then_some((year, month, day))
+}
+
+/// Seconds into the day of `09:05`, `09:05:30` or `9:05:30 PM`.
+fn parse_time(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (clock, pm) = match value.get(value.len().saturating_sub(2)..).map(str::to_ascii_uppercase).as_deref() {
+        Some("AM") => (value[..value.len() - 2].trim(), Some(false)),
+        Some("PM") => (value[..value.len() - 2].trim(), Some(true)),
+        _ => (value, None),
+    };
+    let parts: Vec<u64> = clock.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (hour, minute, second) = match parts.as_slice() {
+        [hour, minute] => (*hour, *minute, 0),
+        [hour, minute, second] => (*hour, *minute, *second),
+        _ => return None,
+    };
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    (hour < 24 && minute < 60 && second < 60).then_some(hour * 3600 + minute * 60 + second)
+}
+
+/// A local date and time as a unix timestamp, using SQLite's idea of the
+/// local zone like the rest of the stats.
+fn local_timestamp(conn: &Connection, date: &str, time: &str) -> Option<u64> {
+    let (year, month, day) = parse_date(date)?;
+    let seconds = parse_time(time)?;
+    let local = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60,
+    );
+    conn.query_row("SELECT CAST(strftime('%s', ?, 'utc') AS INTEGER)", [local], |row| row.get(0)).ok()
+}
+
+/// `20240313T090000Z`, Timewarrior's UTC timestamps.
+fn parse_timewarrior_timestamp(value: &str) -> Option<u64> {
+    let value = value.strip_suffix('Z')?;
+    let (date, time) = value.split_once('T')?;
+    if !value.is_ascii() || date.len() != 8 || time.len() != 6 {
+        return None;
+    }
+    let (year, month, day) = parse_date(&format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))?;
+    let seconds = parse_time(&format!("{}:{}:{}", &time[..2], &time[2..4], &time[4..]))?;
+    Some(days_from_civil(year, month, day) * DAY + seconds)
+}
+
+fn first_non_empty<'a>(values: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    values.into_iter().map(str::trim).find(|v| !v.is_empty()).map(str::to_string)
+}
+
+fn parse_csv_rows(
+    conn: &Connection,
+    source: ImportSource,
+    text: &str,
+) -> Result<(Vec<ParsedRow>, Vec<SkippedRow>), RotatorError> {
+    let mut records = parse_csv(text).into_iter();
+    let header: Vec<String> = records.next().unwrap_or_default().iter().map(|h| h.trim().to_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let required = |name: &str| {
+        column(name).ok_or_else(|| RotatorError::invalid(format!("{:?} export is missing the \"{}\" column", source, name)))
+    };
+    let (start_date, start_time) = (required("start date")?, required("start time")?);
+    let (end_date, end_time) = (required("end date")?, required("end time")?);
+    let (project, client, task, description, tags) =
+        (column("project"), column("client"), column("task"), column("description"), column("tags"));
+
+    let mut rows = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, record) in records.enumerate() {
+        let row = index + 2;
+        let get = |column: Option<usize>| column.and_then(|c| record.get(c)).map(String::as_str).unwrap_or("");
+        let first_tag = get(tags).split(',').next().unwrap_or("");
+
+        let start = local_timestamp(conn, get(Some(start_date)), get(Some(start_time)));
+        let end = local_timestamp(conn, get(Some(end_date)), get(Some(end_time)));
+        let (Some(start), Some(end)) = (start, end) else {
+            skipped.push(SkippedRow { row, reason: "unreadable start or end".into() });
+            continue;
+        };
+        let Some(project) = first_non_empty([get(project), get(client)]) else {
+            skipped.push(SkippedRow { row, reason: "no project or client".into() });
+            continue;
+        };
+        let task = first_non_empty([get(task), get(description), first_tag]).unwrap_or_else(|| UNNAMED_TASK.into());
+        rows.push(ParsedRow { row, project, task, start, end });
+    }
+    Ok((rows, skipped))
+}
+
+fn parse_timewarrior_rows(text: &str) -> Result<(Vec<ParsedRow>, Vec<SkippedRow>), RotatorError> {
+    let intervals: Vec<Value> = serde_json::from_str(text)
+        .map_err(|e| RotatorError::invalid(format!("not a Timewarrior export: {}", e)))?;
+
+    let mut rows = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, interval) in intervals.iter().enumerate() {
+        let row = index + 1;
+        let text = |key: &str| interval.get(key).and_then(Value::as_str).unwrap_or("");
+        let tags: Vec<&str> = interval.get("tags")
+            .and_then(Value::as_array)
+            .map(|tags| tags.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        if text("end").is_empty() {
+            skipped.push(SkippedRow { row, reason: "still running".into() });
+            continue;
+        }
+        let start = parse_timewarrior_timestamp(text("start"));
+        let end = parse_timewarrior_timestamp(text("end"));
+        let (Some(start), Some(end)) = (start, end) else {
+            skipped.push(SkippedRow { row, reason: "unreadable start or end".into() });
+            continue;
+        };
+        let Some(project) = first_non_empty(tags.first().copied()) else {
+            skipped.push(SkippedRow { row, reason: "no tags".into() });
+            continue;
+        };
+        let task = first_non_empty([tags.get(1).copied().unwrap_or(""), text("annotation")])
+            .unwrap_or_else(|| UNNAMED_TASK.into());
+        rows.push(ParsedRow { row, project, task, start, end });
+    }
+    Ok((rows, skipped))
+}
+
+/// The project named `name`, preferring one that isn't archived, created
+/// if there is none.
+fn project_named(tx: &Transaction, name: &str, report: &mut TrackerImportReport) -> rusqlite::Result<u64> {
+    let existing: Option<u64> = tx.query_row(
+        "SELECT id FROM projects WHERE name = ? ORDER BY archived_at IS NOT NULL, id LIMIT 1",
+        [name],
+        |row| row.get(0),
+    ).optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    tx.execute(
+        "INSERT INTO projects (name, position) VALUES (?, ?)",
+        params![name, next_project_position(tx)?],
+    )?;
+    report.projects_created.push(name.to_string());
+    Ok(tx.last_insert_rowid() as u64)
+}
+
+fn task_named(
+    tx: &Transaction,
+    project_id: u64,
+    project: &str,
+    name: &str,
+    report: &mut TrackerImportReport,
+) -> rusqlite::Result<u64> {
+    let existing: Option<u64> = tx.query_row(
+        "SELECT id FROM tasks WHERE project_id = ? AND name = ? ORDER BY archived_at IS NOT NULL, id LIMIT 1",
+        params![project_id, name],
+        |row| row.get(0),
+    ).optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    tx.execute(
+        "INSERT INTO tasks (project_id, name, position) VALUES (?, ?, ?)",
+        params![project_id, name, next_task_position(tx, project_id)?],
+    )?;
+    report.tasks_created.push(NewTask { project: project.to_string(), task: name.to_string() });
+    Ok(tx.last_insert_rowid() as u64)
+}
+
+impl Rotator {
+    /// What importing another tracker's export would do, without writing
+    /// anything.
+    pub fn preview_tracker_import(&mut self, source: ImportSource, text: &str) -> Result<TrackerImportReport, RotatorError> {
+        self.import_tracker(source, text, true)
+    }
+
+    /// Import another tracker's export. Nothing is written unless the whole
+    /// import succeeds, and the database is backed up first.
+    pub fn run_tracker_import(&mut self, source: ImportSource, text: &str) -> Result<TrackerImportReport, RotatorError> {
+        self.create_backup(BackupReason::Import)?;
+        self.import_tracker(source, text, false)
+    }
+
+    /// Both run the import in one transaction; a dry run just never commits it.
+    fn import_tracker(&mut self, source: ImportSource, text: &str, dry_run: bool) -> Result<TrackerImportReport, RotatorError> {
+        let (rows, skipped) = match source {
+            ImportSource::Toggl | ImportSource::Clockify => parse_csv_rows(&self.conn, source, text)?,
+            ImportSource::Timewarrior => parse_timewarrior_rows(text)?,
+        };
+        let mut report = TrackerImportReport { dry_run, rows: rows.len() + skipped.len(), skipped, ..Default::default() };
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        for row in rows {
+            if row.end <= row.start {
+                report.skipped.push(SkippedRow { row: row.row, reason: "ends before it starts".into() });
+                continue;
+            }
+            let project_id = project_named(&tx, &row.project, &mut report)?;
+            let task_id = task_named(&tx, project_id, &row.project, &row.task, &mut report)?;
+            let duplicate: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM time_entries WHERE task_id = ? AND start_time = ? AND end_time = ?)",
+                params![task_id, row.start, row.end],
+                |r| r.get(0),
+            )?;
+            if duplicate {
+                report.duplicates += 1;
+                continue;
+            }
+            if let Some(existing_entry_id) = find_overlap(&tx, task_id, row.start, row.end, &[])? {
+                report.conflicts.push(RowConflict { row: row.row, existing_entry_id });
+                continue;
+            }
+            // Time from another tracker wasn't tracked here, so it counts as manual
+            let entry_id = insert_entry(&tx, project_id, task_id, row.start, row.end, true)?;
+            audit.entries(&tx, "import_tracker", &[], &[load_entry_record(&tx, entry_id)?])?;
+            report.entries_added += 1;
+        }
+        report.skipped.sort_by_key(|s| s.row);
+        if dry_run {
+            return Ok(report);
+        }
+        audit.record(&tx, "import_tracker", None, Value::Null, snapshot(&report))?;
+        tx.commit()?;
+
+        self.reload()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Entity;
+    use crate::test_support::rotator_at;
+
+    // Wednesday 2024-03-13 12:00 UTC
+    const NOW: u64 = 1_710_331_200;
+
+    const TOGGL: &str = "\u{feff}User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags\r\n\
+        Ann,ann@example.com,Acme,Website,,\"Fix header, again\",Yes,2024-03-11,09:00:00,2024-03-11,10:30:00,01:30:00,\r\n\
+        Ann,ann@example.com,Acme,,,,No,2024-03-11,11:00:00,2024-03-11,11:15:00,00:15:00,\"calls,admin\"\r\n\
+        Ann,ann@example.com,Acme,Website,,\"Fix header, again\",Yes,2024-03-11,09:00:00,2024-03-11,10:30:00,01:30:00,\r\n\
+        Ann,ann@example.com,,,,Lunch,No,2024-03-11,12:00:00,2024-03-11,13:00:00,01:00:00,\r\n";
+
+    fn local(rotator: &Rotator, date: &str, time: &str) -> u64 {
+        local_timestamp(rotator.connection(), date, time).unwrap()
+    }
+
+    #[test]
+    fn reads_quoted_csv_dates_and_times() {
+        let records = parse_csv("a,b\r\n\"x, \"\"y\"\"\",\"two\nlines\"\n\n");
+        assert_eq!(records, vec![vec!["a", "b"], vec!["x, \"y\"", "two\nlines"]]);
+        assert_eq!(parse_date("03/13/2024"), Some((2024, 3, 13)));
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("2024-02-29"), Some((2024, 2, 29)));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("04/31/2024"), None);
+        assert_eq!(parse_time("9:05:30 PM"), Some(21 * 3600 + 5 * 60 + 30));
+        assert_eq!(parse_time("12:00 AM"), Some(0));
+        assert_eq!(parse_time("25:00"), None);
+        assert_eq!(parse_timewarrior_timestamp("20240313T120000Z"), Some(NOW));
+    }
+
+    #[test]
+    fn toggl_preview_writes_nothing_then_import_creates_and_dedups() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let acme = rotator.add_project("Acme".into()).unwrap()[0].id;
+        rotator.add_task(acme, "calls".into()).unwrap();
+
+        let preview = rotator.preview_tracker_import(ImportSource::Toggl, TOGGL).unwrap();
+        assert!(preview.dry_run);
+        assert_eq!(preview.rows, 4);
+        assert_eq!(preview.projects_created, ["Website"]);
+        let website_task = NewTask { project: "Website".into(), task: "Fix header, again".into() };
+        assert_eq!(preview.tasks_created, [website_task]);
+        assert_eq!((preview.entries_added, preview.duplicates), (2, 1));
+        assert_eq!(preview.skipped, [SkippedRow { row: 5, reason: "no project or client".into() }]);
+        assert_eq!(rotator.projects().len(), 1);
+        assert!(rotator.all_time_entries().unwrap().is_empty());
+
+        let report = rotator.run_tracker_import(ImportSource::Toggl, TOGGL).unwrap();
+        assert_eq!(report, TrackerImportReport { dry_run: false, ..preview });
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!(entries[0].start_time, local(&rotator, "2024-03-11", "09:00:00"));
+        assert_eq!(entries[0].duration_seconds, 5_400);
+        assert!(entries.iter().all(|entry| entry.manual));
+        let logged = rotator.audit_log(Some(Entity::TimeEntry), None, 10).unwrap();
+        assert!(logged.iter().all(|record| record.command == "import_tracker" && record.before.is_none()));
+        assert_eq!(logged.len(), entries.len());
+        let projects = rotator.projects();
+        assert_eq!(projects[0].tasks[0].time_seconds, 900);
+        assert_eq!(projects[1].name, "Website");
+
+        let again = rotator.run_tracker_import(ImportSource::Toggl, TOGGL).unwrap();
+        assert_eq!((again.entries_added, again.duplicates), (0, 3));
+        assert!(again.projects_created.is_empty());
+    }
+
+    #[test]
+    fn clockify_twelve_hour_times_and_overlaps() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let csv = "Project,Client,Description,Task,User,Tags,Start Date,Start Time,End Date,End Time\n\
+            Research,,Reading,Papers,Bo,,03/12/2024,01:00:00 PM,03/12/2024,02:00:00 PM\n\
+            Research,,Reading,Papers,Bo,,03/12/2024,01:30:00 PM,03/12/2024,02:30:00 PM\n\
+            Research,,Reading,Papers,Bo,,03/12/2024,03:00:00 PM,03/12/2024,02:00:00 PM\n";
+        let report = rotator.run_tracker_import(ImportSource::Clockify, csv).unwrap();
+        assert_eq!(report.entries_added, 1);
+        let entry = &rotator.all_time_entries().unwrap()[0];
+        assert_eq!(entry.start_time, local(&rotator, "2024-03-12", "13:00"));
+        assert_eq!(report.conflicts, [RowConflict { row: 3, existing_entry_id: entry.id }]);
+        assert_eq!(report.skipped, [SkippedRow { row: 4, reason: "ends before it starts".into() }]);
+
+        let missing = rotator.preview_tracker_import(ImportSource::Clockify, "Project,Start Date\nA,03/12/2024\n");
+        assert!(matches!(missing, Err(RotatorError::Validation(_))));
+    }
+
+    #[test]
+    fn timewarrior_maps_tags_to_project_and_task() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let json = r#"[
+            {"id": 3, "start": "20240313T080000Z", "end": "20240313T090000Z", "tags": ["rotator", "docs"]},
+            {"id": 2, "start": "20240313T093000Z", "end": "20240313T100000Z", "tags": ["rotator"], "annotation": "review"},
+            {"id": 1, "start": "20240313T110000Z", "tags": ["rotator"]}
+        ]"#;
+        let report = rotator.run_tracker_import(ImportSource::Timewarrior, json).unwrap();
+        assert_eq!(report.entries_added, 2);
+        assert_eq!(report.skipped, [SkippedRow { row: 3, reason: "still running".into() }]);
+
+        let project = &rotator.projects()[0];
+        let tasks: Vec<(&str, u64)> = project.tasks.iter().map(|t| (t.name.as_str(), t.time_seconds)).collect();
+        assert_eq!((project.name.as_str(), tasks), ("rotator", vec![("docs", 3_600), ("review", 1_800)]));
+        assert_eq!(rotator.all_time_entries().unwrap()[0].start_time, NOW - 4 * 3_600);
+    }
+
+    #[test]
+    fn impossible_timewarrior_dates_are_skipped() {
+        let (mut rotator, _) = rotator_at(NOW);
+        let json = r#"[
+            {"start": "20240300T090000Z", "end": "20240301T100000Z", "tags": ["rotator"]},
+            {"start": "20241301T090000Z", "end": "20241301T100000Z", "tags": ["rotator"]},
+            {"start": "19691231T090000Z", "end": "19691231T100000Z", "tags": ["rotator"]},
+            {"start": "20240é1T090000Z", "end": "20240313T100000Z", "tags": ["rotator"]},
+            {"start": "20240231T090000Z", "end": "20240231T100000Z", "tags": ["rotator"]}
+        ]"#;
+        let report = rotator.run_tracker_import(ImportSource::Timewarrior, json).unwrap();
+        assert_eq!(report.entries_added, 0);
+        assert_eq!(report.skipped.len(), 5);
+        assert!(report.skipped.iter().all(|s| s.reason == "unreadable start or end"));
+    }
+}
@@ -0,0 +1,568 @@
+use crate::audit::snapshot;
+use crate::db::{add_active_tracking, load_active_tracking};
+use crate::error::{Conflict, Entity, RotatorError};
+use crate::models::ActiveTracking;
+use crate::rotator::Rotator;
+use crate::totals::refresh_task_total;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How many changes `undo` can step back through. Older ones are dropped.
+pub const JOURNAL_LIMIT: usize = 100;
+
+/// One pause inside a session or time entry.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PauseRecord {
+    pub paused_at: u64,
+    pub resumed_at: Option<u64>,
+}
+
+/// A time entry as stored, pauses included, so it can be put back with its
+/// original id.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryRecord {
+    pub id: u64,
+    pub project_id: u64,
+    pub task_id: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub duration_seconds: u64,
+    #[serde(default)]
+    pub manual: bool,
+    pub edited_at: Option<u64>,
+    pub pauses: Vec<PauseRecord>,
+}
+
+/// A running session as stored, with the pauses taken so far.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub project_id: u64,
+    pub task_id: u64,
+    pub started_at: u64,
+    pub pauses: Vec<PauseRecord>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryEdit {
+    Added,
+    Updated,
+    Deleted,
+    Split,
+    Merged,
+}
+
+/// A journaled mutation, holding enough of the state on both sides to undo
+/// and redo it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// Undoing an add archives the project rather than deleting it, so
+    /// nothing tracked against it in the meantime is lost.
+    ProjectAdded { project_id: u64 },
+    /// `tasks` are the ones the removal archived; undoing it leaves tasks
+    /// that were archived before alone. Journals from before this was
+    /// recorded have none, and undo restores every task.
+    ProjectRemoved {
+        project_id: u64,
+        #[serde(default)]
+        tasks: Option<Vec<u64>>,
+    },
+    ProjectRenamed { project_id: u64, from: String, to: String },
+    TaskAdded { project_id: u64, task_id: u64 },
+    TaskRemoved { project_id: u64, task_id: u64 },
+    TaskRenamed { project_id: u64, task_id: u64, from: String, to: String },
+    TaskDone { project_id: u64, task_id: u64, from: Option<u64>, to: Option<u64> },
+    /// Undoing a stop resumes the sessions as if they never stopped.
+    TrackingStopped { sessions: Vec<SessionRecord>, entries: Vec<EntryRecord> },
+    EntriesEdited { edit: EntryEdit, before: Vec<EntryRecord>, after: Vec<EntryRecord> },
+}
+
+/// What `undo` and `redo` would do next.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UndoState {
+    pub undo: Option<Change>,
+    pub redo: Option<Change>,
+}
+
+fn load_pauses(conn: &Connection, sql: &str, id: u64) -> rusqlite::Result<Vec<PauseRecord>> {
+    let mut stmt = conn.prepare(sql)?;
+    let pauses = stmt.query_map([id], |row| Ok(PauseRecord { paused_at: row.get(0)?, resumed_at: row.get(1)? }))?;
+    pauses.collect()
+}
+
+pub(crate) fn load_entry_record(conn: &Connection, entry_id: u64) -> Result<EntryRecord, RotatorError> {
+    let mut record = conn.query_row(
+        "SELECT id, project_id, task_id, start_time, end_time, duration_seconds, manual, edited_at FROM time_entries WHERE id = ?",
+        [entry_id],
+        |row| {
+            Ok(EntryRecord {
+                id: row.get(0)?,
+                project_id: row.get(1)?,
+                task_id: row.get(2)?,
+                start_time: row.get(3)?,
+                end_time: row.get(4)?,
+                duration_seconds: row.get(5)?,
+                manual: row.get(6)?,
+                edited_at: row.get(7)?,
+                pauses: Vec::new(),
+            })
+        },
+    )
+    .optional()?
+    .ok_or(RotatorError::NotFound(Entity::TimeEntry, entry_id))?;
+    record.pauses = load_pauses(
+        conn,
+        "SELECT paused_at, resumed_at FROM tracking_pauses WHERE entry_id = ? ORDER BY paused_at",
+        entry_id,
+    )?;
+    Ok(record)
+}
+
+pub(crate) fn load_session_record(conn: &Connection, session: &ActiveTracking) -> rusqlite::Result<SessionRecord> {
+    Ok(SessionRecord {
+        project_id: session.project_id,
+        task_id: session.task_id,
+        started_at: session.started_at,
+        pauses: load_pauses(
+            conn,
+            "SELECT paused_at, resumed_at FROM tracking_pauses WHERE task_id = ? AND entry_id IS NULL ORDER BY paused_at",
+            session.task_id,
+        )?,
+    })
+}
+
+/// Insert `entry` with its id and pauses.
+pub(crate) fn insert_entry_record(conn: &Connection, entry: &EntryRecord) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO time_entries (id, project_id, task_id, start_time, end_time, duration_seconds, manual, edited_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            entry.id,
+            entry.project_id,
+            entry.task_id,
+            entry.start_time,
+            entry.end_time,
+            entry.duration_seconds,
+            entry.manual,
+            entry.edited_at,
+        ],
+    )?;
+    for pause in &entry.pauses {
+        conn.execute(
+            "INSERT INTO tracking_pauses (task_id, entry_id, paused_at, resumed_at) VALUES (?, ?, ?, ?)",
+            params![entry.task_id, entry.id, pause.paused_at, pause.resumed_at],
+        )?;
+    }
+    Ok(())
+}
+
+/// Start `session` again with its pauses. The heartbeat is set to `now`, or
+/// the session would come back as orphaned.
+pub(crate) fn insert_session_record(conn: &Connection, session: &SessionRecord, now: u64) -> rusqlite::Result<()> {
+    add_active_tracking(conn, &ActiveTracking {
+        project_id: session.project_id,
+        task_id: session.task_id,
+        started_at: session.started_at,
+        paused_at: None,
+        paused_seconds: 0,
+    })?;
+    conn.execute("UPDATE active_tracking SET last_heartbeat = ? WHERE task_id = ?", params![now, session.task_id])?;
+    for pause in &session.pauses {
+        conn.execute(
+            "INSERT INTO tracking_pauses (task_id, paused_at, resumed_at) VALUES (?, ?, ?)",
+            params![session.task_id, pause.paused_at, pause.resumed_at],
+        )?;
+    }
+    Ok(())
+}
+
+/// The newest change still to undo, or the most recently undone one.
+fn load_next(conn: &Connection, undone: bool) -> Result<Option<(u64, Change)>, RotatorError> {
+    let sql = if undone {
+        "SELECT id, change FROM journal WHERE undone_at IS NOT NULL ORDER BY id LIMIT 1"
+    } else {
+        "SELECT id, change FROM journal WHERE undone_at IS NULL ORDER BY id DESC LIMIT 1"
+    };
+    let Some((id, json)) = conn
+        .query_row(sql, [], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?)))
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    match serde_json::from_str(&json) {
+        Ok(change) => Ok(Some((id, change))),
+        Err(e) => {
+            // Written by a newer build or damaged; it can't be replayed either way
+            conn.execute("DELETE FROM journal WHERE id = ?", [id])?;
+            Err(RotatorError::invalid(format!("unreadable journal entry {}: {}", id, e)))
+        }
+    }
+}
+
+impl Rotator {
+    /// Journal a change that just succeeded. Starting a new branch of
+    /// history drops whatever could have been redone.
+    pub(crate) fn record(&mut self, change: Change) -> Result<(), RotatorError> {
+        if self.replaying {
+            return Ok(());
+        }
+        let json = serde_json::to_string(&change).map_err(|e| RotatorError::invalid(e.to_string()))?;
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM journal WHERE undone_at IS NOT NULL", [])?;
+        tx.execute(
+            "INSERT INTO journal (change, created_at) VALUES (?, ?)",
+            params![json, self.clock.now()],
+        )?;
+        tx.execute(
+            "DELETE FROM journal WHERE id NOT IN (SELECT id FROM journal ORDER BY id DESC LIMIT ?)",
+            [JOURNAL_LIMIT],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn undo_state(&self) -> Result<UndoState, RotatorError> {
+        Ok(UndoState {
+            undo: load_next(&self.conn, false)?.map(|(_, change)| change),
+            redo: load_next(&self.conn, true)?.map(|(_, change)| change),
+        })
+    }
+
+    /// Revert the newest change. `None` if there is nothing to undo.
+    ///
+    /// A change whose project, task or entry no longer exists, e.g. after a
+    /// permanent delete, is dropped so it can't block the rest of the history.
+    pub fn undo(&mut self) -> Result<Option<Change>, RotatorError> {
+        self.step(false)
+    }
+
+    /// Apply the most recently undone change again.
+    pub fn redo(&mut self) -> Result<Option<Change>, RotatorError> {
+        self.step(true)
+    }
+
+    fn step(&mut self, redo: bool) -> Result<Option<Change>, RotatorError> {
+        let Some((id, change)) = load_next(&self.conn, redo)? else {
+            return Ok(None);
+        };
+
+        self.replaying = true;
+        let result = self.replay(&change, redo);
+        self.replaying = false;
+
+        match result {
+            Ok(()) => {
+                let undone_at = if redo { None } else { Some(self.clock.now()) };
+                self.conn.execute("UPDATE journal SET undone_at = ? WHERE id = ?", params![undone_at, id])?;
+                self.audit_step(&change, redo)?;
+                Ok(Some(change))
+            }
+            Err(e @ RotatorError::NotFound(..)) => {
+                self.conn.execute("DELETE FROM journal WHERE id = ?", [id])?;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Log an undo or redo against whatever it changed. Entry edits get a row
+    /// per entry with the entry on either side, like the edit itself.
+    fn audit_step(&self, change: &Change, redo: bool) -> rusqlite::Result<()> {
+        let command = if redo { "redo" } else { "undo" };
+        let (before, after) = match change {
+            Change::TrackingStopped { entries, .. } => (&[][..], &entries[..]),
+            Change::EntriesEdited { before, after, .. } => (&before[..], &after[..]),
+            Change::ProjectAdded { project_id }
+            | Change::ProjectRemoved { project_id, .. }
+            | Change::ProjectRenamed { project_id, .. } => {
+                return self.audit(command, Some((Entity::Project, *project_id)), Value::Null, snapshot(change));
+            }
+            Change::TaskAdded { task_id, .. }
+            | Change::TaskRemoved { task_id, .. }
+            | Change::TaskRenamed { task_id, .. }
+            | Change::TaskDone { task_id, .. } => {
+                return self.audit(command, Some((Entity::Task, *task_id)), Value::Null, snapshot(change));
+            }
+        };
+        if redo {
+            self.auditor().entries(&self.conn, command, before, after)
+        } else {
+            self.auditor().entries(&self.conn, command, after, before)
+        }
+    }
+
+    fn replay(&mut self, change: &Change, redo: bool) -> Result<(), RotatorError> {
+        let pick = |from: &String, to: &String| if redo { to.clone() } else { from.clone() };
+        match change {
+            Change::ProjectAdded { project_id } => {
+                if redo {
+                    self.restore_project(*project_id)?;
+                } else {
+                    self.remove_project(*project_id)?;
+                }
+            }
+            Change::ProjectRemoved { project_id, tasks } => {
+                if redo {
+                    self.remove_project(*project_id)?;
+                } else {
+                    self.unarchive_project(*project_id, tasks.as_deref())?;
+                }
+            }
+            Change::ProjectRenamed { project_id, from, to } => {
+                self.rename_project(*project_id, pick(from, to))?;
+            }
+            Change::TaskAdded { project_id, task_id } | Change::TaskRemoved { project_id, task_id } => {
+                if redo == matches!(change, Change::TaskAdded { .. }) {
+                    self.restore_task(*project_id, *task_id)?;
+                } else {
+                    self.remove_task(*project_id, *task_id)?;
+                }
+            }
+            Change::TaskRenamed { project_id, task_id, from, to } => {
+                self.rename_task(*project_id, *task_id, pick(from, to))?;
+            }
+            Change::TaskDone { project_id, task_id, from, to } => {
+                self.set_task_done_at(*project_id, *task_id, if redo { *to } else { *from })?;
+            }
+            Change::TrackingStopped { sessions, entries } => {
+                if redo {
+                    self.swap_rows((sessions, &[]), (&[], entries))?;
+                } else {
+                    self.swap_rows((&[], entries), (sessions, &[]))?;
+                }
+            }
+            Change::EntriesEdited { before, after, .. } => {
+                if redo {
+                    self.swap_rows((&[], before), (&[], after))?;
+                } else {
+                    self.swap_rows((&[], after), (&[], before))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the running sessions and time entries in `from` with those in
+    /// `to`, keeping their original ids.
+    fn swap_rows(
+        &mut self,
+        from: (&[SessionRecord], &[EntryRecord]),
+        to: (&[SessionRecord], &[EntryRecord]),
+    ) -> Result<(), RotatorError> {
+        let now = self.clock.now();
+        let mut touched: Vec<u64> = Vec::new();
+
+        let tx = self.conn.transaction()?;
+        for session in from.0 {
+            let running = tx.execute(
+                "DELETE FROM active_tracking WHERE task_id = ? AND started_at = ?",
+                params![session.task_id, session.started_at],
+            )?;
+            if running == 0 {
+                return Err(RotatorError::invalid(format!("task {} is no longer running that session", session.task_id)));
+            }
+            tx.execute("DELETE FROM tracking_pauses WHERE task_id = ? AND entry_id IS NULL", [session.task_id])?;
+        }
+        for entry in from.1 {
+            tx.execute("DELETE FROM tracking_pauses WHERE entry_id = ?", [entry.id])?;
+            if tx.execute("DELETE FROM time_entries WHERE id = ?", [entry.id])? == 0 {
+                return Err(RotatorError::NotFound(Entity::TimeEntry, entry.id));
+            }
+            touched.push(entry.task_id);
+        }
+        for session in to.0 {
+            let running: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM active_tracking WHERE task_id = ?)",
+                [session.task_id],
+                |row| row.get(0),
+            )?;
+            if running {
+                return Err(RotatorError::Conflict(Conflict::RunningSession));
+            }
+            insert_session_record(&tx, session, now)?;
+        }
+        for entry in to.1 {
+            let taken: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM time_entries WHERE id = ?)", [entry.id], |row| row.get(0))?;
+            if taken {
+                return Err(RotatorError::Conflict(Conflict::TimeEntry(entry.id)));
+            }
+            insert_entry_record(&tx, entry)?;
+            touched.push(entry.task_id);
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        for &task_id in &touched {
+            refresh_task_total(&tx, task_id)?;
+        }
+        tx.commit()?;
+
+        self.active_tracking = load_active_tracking(&self.conn)?;
+        self.orphaned.retain(|o| !from.0.iter().any(|s| s.task_id == o.task_id));
+        self.sync_task_totals(&touched)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::test_support::rotator_at;
+
+    fn journal_len(rotator: &Rotator) -> usize {
+        rotator.connection().query_row("SELECT COUNT(*) FROM journal", [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn project_and_task_edits_undo_and_redo() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.rename_task(project_id, task_id, "B".into()).unwrap();
+        rotator.toggle_task_done(project_id, task_id, true).unwrap();
+
+        assert!(matches!(rotator.undo().unwrap(), Some(Change::TaskDone { to: Some(1_000), .. })));
+        assert_eq!(rotator.projects()[0].tasks[0].done_at, None);
+        rotator.undo().unwrap();
+        assert_eq!(rotator.projects()[0].tasks[0].name, "A");
+        rotator.undo().unwrap();
+        assert!(rotator.projects()[0].tasks.is_empty());
+        rotator.undo().unwrap();
+        assert!(rotator.projects().is_empty());
+        assert_eq!(rotator.undo().unwrap(), None);
+
+        rotator.redo().unwrap();
+        rotator.redo().unwrap();
+        rotator.redo().unwrap();
+        let task = &rotator.projects()[0].tasks[0];
+        assert_eq!((task.id, task.name.as_str(), task.done_at), (task_id, "B", None));
+
+        // A new change forgets what could have been redone
+        rotator.rename_project(project_id, "Home".into()).unwrap();
+        assert_eq!(rotator.redo().unwrap(), None);
+        assert!(matches!(rotator.undo_state().unwrap().undo, Some(Change::ProjectRenamed { .. })));
+        // Replaying is not journaled itself
+        assert_eq!(journal_len(&rotator), 4);
+    }
+
+    #[test]
+    fn undoing_a_stop_resumes_the_session() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(300);
+        rotator.pause_tracking(None).unwrap();
+        clock.advance(60);
+        rotator.stop_tracking(None).unwrap();
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 300);
+
+        clock.advance(30);
+        rotator.undo().unwrap();
+        assert!(rotator.all_time_entries().unwrap().is_empty());
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 0);
+        let tracking = rotator.active_tracking();
+        assert_eq!((tracking[0].started_at, tracking[0].paused_at), (1_000, Some(1_300)));
+        assert!(rotator.orphaned_sessions().is_empty());
+
+        rotator.redo().unwrap();
+        assert!(rotator.active_tracking().is_empty());
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!((entries[0].end_time, entries[0].duration_seconds), (1_360, 300));
+
+        // Can't undo the stop onto a task that is running again
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        assert!(matches!(rotator.undo(), Err(RotatorError::Conflict(Conflict::RunningSession))));
+    }
+
+    #[test]
+    fn entry_edits_come_back_with_their_ids() {
+        let (mut rotator, _) = rotator_at(10_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        let entry = rotator.add_time_entry_manual(project_id, task_id, 1_000, 600).unwrap();
+        let (_, second) = rotator.split_time_entry(entry.id, 1_200).unwrap();
+        rotator.delete_time_entry(second.id).unwrap();
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 200);
+
+        rotator.undo().unwrap();
+        rotator.undo().unwrap();
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].id, entries[0].end_time), (entry.id, 1_600));
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 600);
+
+        rotator.redo().unwrap();
+        let ids: Vec<u64> = rotator.all_time_entries().unwrap().iter().map(|e| e.id).collect();
+        assert_eq!(ids, [entry.id, second.id]);
+        rotator.undo().unwrap();
+        rotator.undo().unwrap();
+        assert!(rotator.all_time_entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn changes_to_deleted_rows_are_dropped() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        rotator.add_project("Home".into()).unwrap();
+        rotator.rename_project(project_id, "Job".into()).unwrap();
+        rotator.remove_project(project_id).unwrap();
+        rotator.delete_project_permanent(project_id).unwrap();
+
+        // Neither the archive nor the rename can be reverted any more
+        assert!(matches!(rotator.undo(), Err(RotatorError::NotFound(Entity::Project, _))));
+        assert!(matches!(rotator.undo(), Err(RotatorError::NotFound(Entity::Project, _))));
+        // The next undo moves past them
+        assert!(matches!(rotator.undo().unwrap(), Some(Change::ProjectAdded { .. })));
+        assert!(rotator.projects().is_empty());
+    }
+
+    #[test]
+    fn undoing_a_project_removal_leaves_earlier_archived_tasks() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let kept = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        let archived = rotator.add_task(project_id, "B".into()).unwrap().tasks[1].id;
+        rotator.remove_task(project_id, archived).unwrap();
+        clock.advance(60);
+        rotator.remove_project(project_id).unwrap();
+
+        let archived_at = |rotator: &Rotator, id: u64| -> Option<u64> {
+            rotator.connection().query_row("SELECT archived_at FROM tasks WHERE id = ?", [id], |r| r.get(0)).unwrap()
+        };
+        assert_eq!(archived_at(&rotator, archived), Some(1_000));
+        assert!(matches!(rotator.undo().unwrap(), Some(Change::ProjectRemoved { tasks: Some(ref t), .. }) if *t == [kept]));
+        let tasks = &rotator.projects()[0].tasks;
+        assert_eq!(tasks.iter().map(|t| t.id).collect::<Vec<_>>(), [kept]);
+        assert_eq!(archived_at(&rotator, archived), Some(1_000));
+
+        // Journals written before the tasks were recorded restore them all
+        let old: Change = serde_json::from_str(r#"{"kind":"project_removed","project_id":1}"#).unwrap();
+        assert_eq!(old, Change::ProjectRemoved { project_id: 1, tasks: None });
+    }
+
+    #[test]
+    fn history_is_bounded_and_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("rotator-journal-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = ManualClock::new(1_000);
+
+        let mut rotator = Rotator::new(Connection::open(&path).unwrap(), clock.clone()).unwrap();
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        for i in 0..JOURNAL_LIMIT {
+            rotator.rename_project(project_id, format!("Work {}", i)).unwrap();
+        }
+        assert_eq!(journal_len(&rotator), JOURNAL_LIMIT);
+        rotator.undo().unwrap();
+        drop(rotator);
+
+        let mut reopened = Rotator::new(Connection::open(&path).unwrap(), clock).unwrap();
+        assert_eq!(reopened.projects()[0].name, format!("Work {}", JOURNAL_LIMIT - 2));
+        reopened.redo().unwrap();
+        assert_eq!(reopened.projects()[0].name, format!("Work {}", JOURNAL_LIMIT - 1));
+        drop(reopened);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
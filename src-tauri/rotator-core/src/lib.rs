@@ -0,0 +1,70 @@
+//! Headless core of Rotator: projects, task rotation, time tracking and
+//! stats over SQLite. The Tauri app is a thin command layer on top of this.
+
+mod archive;
+mod audit;
+mod backup;
+mod budget;
+mod calendar;
+pub mod clock;
+mod db;
+mod entries;
+mod error;
+mod export;
+mod history;
+pub mod idle;
+mod importers;
+mod journal;
+pub mod migrations;
+mod mock_data;
+mod ordering;
+pub mod models;
+mod pause;
+mod policy;
+mod recovery;
+mod rotation;
+mod rotator;
+mod session;
+mod stats;
+mod timebox;
+mod timezone;
+mod totals;
+
+pub use archive::{Archive, ImportConflict, ImportMode, ImportReport, ProjectRecord, TaskRecord, ARCHIVE_FORMAT, ARCHIVE_VERSION};
+pub use audit::{AuditRecord, AuditSource};
+pub use backup::{BackupInfo, BackupReason, BackupRetention, BACKUP_INTERVAL_SECONDS};
+pub use budget::{Budget, BudgetEvent, BudgetPeriod, BudgetStatus};
+pub use calendar::CalendarFeedSettings;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use db::DONE_HIDE_AFTER_SECONDS;
+pub use error::{Conflict, Entity, RotatorError};
+pub use export::{ExportColumn, ExportFormat, ExportOptions, ExportTimezone};
+pub use history::{DailyContextSwitches, DwellTime, RotationEvent, RotationTrigger};
+pub use idle::{FakeIdleSource, IdleAction, IdleEvent, IdlePeriod, IdleResolution, IdleSettings, IdleSource};
+pub use importers::{ImportSource, NewTask, RowConflict, SkippedRow, TrackerImportReport};
+pub use journal::{Change, EntryEdit, EntryRecord, PauseRecord, SessionRecord, UndoState, JOURNAL_LIMIT};
+pub use migrations::MigrationError;
+pub use models::*;
+pub use policy::{RoundingMode, TimePolicy};
+pub use recovery::{OrphanResolution, OrphanedSession, HEARTBEAT_INTERVAL_SECONDS, ORPHAN_AFTER_SECONDS};
+pub use rotation::{
+    Candidate, LargestDeficit, LeastRecentlyWorked, RotationStrategy, RotationStrategyKind, RoundRobin, SeededRng,
+    WeightedRandom, WeightedShare, ROTATION_WINDOW_SECONDS,
+};
+pub use rotator::Rotator;
+pub use session::MIN_SESSION_SECONDS;
+pub use timebox::{ActiveBlock, BlockKind, TimeboxBlock, TimeboxEvent, TimeboxSettings};
+pub use totals::TotalDrift;
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::{ManualClock, Rotator};
+    use rusqlite::Connection;
+
+    /// In-memory rotator whose clock starts at `now` and only moves when advanced.
+    pub fn rotator_at(now: u64) -> (Rotator, ManualClock) {
+        let clock = ManualClock::new(now);
+        let rotator = Rotator::new(Connection::open_in_memory().unwrap(), clock.clone()).unwrap();
+        (rotator, clock)
+    }
+}
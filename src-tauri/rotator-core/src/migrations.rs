@@ -0,0 +1,913 @@
+use crate::error::RotatorError;
+use rusqlite::{Connection, OptionalExtension, Transaction};
+use std::fmt;
+
+/// A single schema change. Versions are stored in `PRAGMA user_version`, so
+/// every step runs exactly once per database, inside its own transaction.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Ordered list of every schema change. Never edit or reorder an entry that
+/// has shipped - append a new one instead.
+///
+/// Databases created before versioning existed all report `user_version = 0`
+/// regardless of which ad-hoc layout they ended up on, so the early steps
+/// inspect the schema and only change what is actually missing.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create projects, tasks and app_state tables",
+        up: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        description: "add archived flags to projects and tasks",
+        up: add_archived_flags,
+    },
+    Migration {
+        version: 3,
+        description: "replace tasks.done boolean with done_at timestamp",
+        up: add_done_at,
+    },
+    Migration {
+        version: 4,
+        description: "replace archived booleans with archived_at timestamps",
+        up: add_archived_at,
+    },
+    Migration {
+        version: 5,
+        description: "allow multiple active_tracking rows",
+        up: rebuild_active_tracking,
+    },
+    Migration {
+        version: 6,
+        description: "create time_entries table",
+        up: create_time_entries,
+    },
+    Migration {
+        version: 7,
+        description: "add active_tracking.last_heartbeat",
+        up: add_tracking_heartbeat,
+    },
+    Migration {
+        version: 8,
+        description: "create project_time_policies table",
+        up: create_project_time_policies,
+    },
+    Migration {
+        version: 9,
+        description: "create tracking_pauses table",
+        up: create_tracking_pauses,
+    },
+    Migration {
+        version: 10,
+        description: "create timebox_blocks table",
+        up: create_timebox_blocks,
+    },
+    Migration {
+        version: 11,
+        description: "add rotation weights, targets and task strategy",
+        up: add_rotation_settings,
+    },
+    Migration {
+        version: 12,
+        description: "create budgets table",
+        up: create_budgets,
+    },
+    Migration {
+        version: 13,
+        description: "create rotation_events table",
+        up: create_rotation_events,
+    },
+    Migration {
+        version: 14,
+        description: "add explicit positions and store current project by id",
+        up: add_positions,
+    },
+    Migration {
+        version: 15,
+        description: "store each project's current task by id",
+        up: add_current_task_id,
+    },
+    Migration {
+        version: 16,
+        description: "create journal table",
+        up: create_journal,
+    },
+    Migration {
+        version: 17,
+        description: "create audit_log and flag manual and edited time entries",
+        up: create_audit_log,
+    },
+    Migration {
+        version: 18,
+        description: "never reuse time entry ids",
+        up: rebuild_time_entries_autoincrement,
+    },
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// Reading or writing the schema version itself failed.
+    Sqlite(rusqlite::Error),
+    /// A migration step failed and was rolled back.
+    Step {
+        version: u32,
+        description: &'static str,
+        source: rusqlite::Error,
+    },
+    /// The database was written by a newer build of the app.
+    TooNew { found: u32, latest: u32 },
+    /// The backup taken before migrating an existing database failed, so
+    /// nothing was migrated.
+    Backup(Box<RotatorError>),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "failed to read schema version: {}", e),
+            MigrationError::Step { version, description, source } => {
+                write!(f, "migration {} ({}) failed: {}", version, description, source)
+            }
+            MigrationError::TooNew { found, latest } => write!(
+                f,
+                "database schema version {} is newer than the latest supported version {}",
+                found, latest
+            ),
+            MigrationError::Backup(e) => write!(f, "backup before migrating failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Sqlite(e) => Some(e),
+            MigrationError::Step { source, .. } => Some(source),
+            MigrationError::TooNew { .. } => None,
+            MigrationError::Backup(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Whether opening `conn` would migrate data that is already there. Databases
+/// from before versioning report version 0 but still have their tables.
+pub fn has_data_to_migrate(conn: &Connection) -> rusqlite::Result<bool> {
+    if schema_version(conn)? >= latest_version() {
+        return Ok(false);
+    }
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'projects')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// The schema version `migrate` brings a database to.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Bring the database up to the latest schema version.
+/// Returns the version the database is on afterwards.
+pub fn migrate(conn: &mut Connection) -> Result<u32, MigrationError> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<u32, MigrationError> {
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+    let found = schema_version(conn)?;
+
+    if found > latest {
+        return Err(MigrationError::TooNew { found, latest });
+    }
+
+    // Rebuilding a table drops the old one, which would cascade into every
+    // table pointing at it. Foreign keys can't be switched inside a
+    // transaction, so they stay off for the whole run.
+    let enforced: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_steps(conn, migrations, found);
+    conn.pragma_update(None, "foreign_keys", enforced)?;
+    result
+}
+
+fn apply_steps(conn: &mut Connection, migrations: &[Migration], found: u32) -> Result<u32, MigrationError> {
+    let mut current = found;
+    for migration in migrations.iter().filter(|m| m.version > found) {
+        let step_error = |source| MigrationError::Step {
+            version: migration.version,
+            description: migration.description,
+            source,
+        };
+
+        let tx = conn.transaction().map_err(step_error)?;
+        (migration.up)(&tx).map_err(step_error)?;
+        tx.pragma_update(None, "user_version", migration.version).map_err(step_error)?;
+        tx.commit().map_err(step_error)?;
+
+        current = migration.version;
+    }
+
+    Ok(current)
+}
+
+fn table_exists(tx: &Transaction, table: &str) -> rusqlite::Result<bool> {
+    tx.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+        [table],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn add_column_if_missing(tx: &Transaction, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+fn create_base_tables(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS projects (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            current_task_index INTEGER NOT NULL DEFAULT 0,
+            archived INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tasks (
+            id INTEGER PRIMARY KEY,
+            project_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            time_seconds INTEGER NOT NULL DEFAULT 0,
+            archived INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS app_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn add_archived_flags(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "projects", "archived", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(tx, "tasks", "archived", "INTEGER NOT NULL DEFAULT 0")
+}
+
+fn add_done_at(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "tasks", "done_at", "INTEGER")?;
+
+    // Legacy boolean done - stamp it with the migration time
+    if has_column(tx, "tasks", "done")? {
+        tx.execute(
+            "UPDATE tasks SET done_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE done = 1 AND done_at IS NULL",
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_archived_at(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "tasks", "archived_at", "INTEGER")?;
+    add_column_if_missing(tx, "projects", "archived_at", "INTEGER")?;
+
+    tx.execute(
+        "UPDATE tasks SET archived_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE archived = 1 AND archived_at IS NULL",
+        [],
+    )?;
+    tx.execute(
+        "UPDATE projects SET archived_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE archived = 1 AND archived_at IS NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+fn rebuild_active_tracking(tx: &Transaction) -> rusqlite::Result<()> {
+    // Leftover from the old unversioned rebuild if it was interrupted
+    tx.execute("DROP TABLE IF EXISTS active_tracking_new", [])?;
+
+    tx.execute(
+        "CREATE TABLE active_tracking_new (
+            id INTEGER PRIMARY KEY,
+            project_id INTEGER NOT NULL,
+            task_id INTEGER NOT NULL,
+            started_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    if table_exists(tx, "active_tracking")? {
+        tx.execute(
+            "INSERT INTO active_tracking_new (id, project_id, task_id, started_at)
+             SELECT id, project_id, task_id, started_at FROM active_tracking",
+            [],
+        )?;
+        tx.execute("DROP TABLE active_tracking", [])?;
+    }
+
+    tx.execute("ALTER TABLE active_tracking_new RENAME TO active_tracking", [])?;
+    Ok(())
+}
+
+fn create_time_entries(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS time_entries (
+            id INTEGER PRIMARY KEY,
+            project_id INTEGER NOT NULL,
+            task_id INTEGER NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            duration_seconds INTEGER NOT NULL,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_tracking_heartbeat(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "active_tracking", "last_heartbeat", "INTEGER")?;
+    // Rows from before heartbeats existed have only their start to go on
+    tx.execute("UPDATE active_tracking SET last_heartbeat = started_at WHERE last_heartbeat IS NULL", [])?;
+    Ok(())
+}
+
+fn create_project_time_policies(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS project_time_policies (
+            project_id INTEGER PRIMARY KEY,
+            min_session_seconds INTEGER NOT NULL,
+            rounding TEXT NOT NULL,
+            round_to_minutes INTEGER NOT NULL,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Pauses of a running session have no `entry_id` yet; closing the session
+/// hands them to the time entry it creates.
+fn create_tracking_pauses(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tracking_pauses (
+            id INTEGER PRIMARY KEY,
+            task_id INTEGER NOT NULL,
+            entry_id INTEGER,
+            paused_at INTEGER NOT NULL,
+            resumed_at INTEGER,
+            FOREIGN KEY (entry_id) REFERENCES time_entries(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    tx.execute("CREATE INDEX IF NOT EXISTS idx_tracking_pauses_entry ON tracking_pauses(entry_id)", [])?;
+    tx.execute("CREATE INDEX IF NOT EXISTS idx_tracking_pauses_task ON tracking_pauses(task_id)", [])?;
+    Ok(())
+}
+
+fn create_timebox_blocks(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS timebox_blocks (
+            id INTEGER PRIMARY KEY,
+            kind TEXT NOT NULL,
+            project_id INTEGER,
+            task_id INTEGER,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER NOT NULL,
+            completed INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_rotation_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "projects", "rotation_weight", "REAL NOT NULL DEFAULT 1")?;
+    add_column_if_missing(tx, "projects", "target_seconds", "INTEGER")?;
+    add_column_if_missing(tx, "projects", "task_rotation_strategy", "TEXT")?;
+    add_column_if_missing(tx, "tasks", "rotation_weight", "REAL NOT NULL DEFAULT 1")?;
+    add_column_if_missing(tx, "tasks", "target_seconds", "INTEGER")?;
+    Ok(())
+}
+
+fn create_budgets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS budgets (
+            id INTEGER PRIMARY KEY,
+            project_id INTEGER NOT NULL,
+            task_id INTEGER,
+            period TEXT NOT NULL,
+            limit_seconds INTEGER NOT NULL,
+            reached_period_start INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_rotation_events(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS rotation_events (
+            id INTEGER PRIMARY KEY,
+            occurred_at INTEGER NOT NULL,
+            from_project_id INTEGER,
+            from_task_id INTEGER,
+            to_project_id INTEGER NOT NULL,
+            to_task_id INTEGER,
+            trigger TEXT NOT NULL,
+            tracking_followed INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    tx.execute("CREATE INDEX IF NOT EXISTS idx_rotation_events_occurred_at ON rotation_events (occurred_at)", [])?;
+    Ok(())
+}
+
+fn add_positions(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "projects", "position", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(tx, "tasks", "position", "INTEGER NOT NULL DEFAULT 0")?;
+    // Keep the order the app has always shown: by id
+    tx.execute("UPDATE projects SET position = (SELECT COUNT(*) FROM projects p WHERE p.id < projects.id)", [])?;
+    tx.execute(
+        "UPDATE tasks SET position = (SELECT COUNT(*) FROM tasks t WHERE t.project_id = tasks.project_id AND t.id < tasks.id)",
+        [],
+    )?;
+
+    // The old index counted non-archived projects in id order
+    let index: Option<i64> = tx.query_row(
+        "SELECT CAST(value AS INTEGER) FROM app_state WHERE key = 'current_project_index'",
+        [],
+        |row| row.get(0),
+    ).optional()?;
+    if let Some(index) = index {
+        tx.execute(
+            "INSERT OR REPLACE INTO app_state (key, value)
+             SELECT 'current_project_id', CAST(id AS TEXT) FROM projects
+             WHERE archived_at IS NULL ORDER BY id LIMIT 1 OFFSET ?",
+            [index],
+        )?;
+        tx.execute("DELETE FROM app_state WHERE key = 'current_project_index'", [])?;
+    }
+    Ok(())
+}
+
+fn add_current_task_id(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "projects", "current_task_id", "INTEGER")?;
+    // The old index counted the project's visible tasks in display order:
+    // not archived, and not done more than 5 hours ago. current_task_index
+    // stays behind unused so older builds can still open the file.
+    tx.execute(
+        "UPDATE projects SET current_task_id = (
+            SELECT v.id FROM (
+                SELECT t.id, t.project_id,
+                    ROW_NUMBER() OVER (PARTITION BY t.project_id ORDER BY t.position, t.id) - 1 AS idx
+                FROM tasks t
+                WHERE t.archived_at IS NULL
+                AND (t.done_at IS NULL OR t.done_at > CAST(strftime('%s', 'now') AS INTEGER) - 18000)
+            ) v
+            WHERE v.project_id = projects.id AND v.idx = projects.current_task_index
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_journal(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS journal (
+            id INTEGER PRIMARY KEY,
+            change TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            undone_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_audit_log(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "time_entries", "manual", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(tx, "time_entries", "edited_at", "INTEGER")?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY,
+            at INTEGER NOT NULL,
+            command TEXT NOT NULL,
+            source TEXT NOT NULL,
+            entity TEXT,
+            entity_id INTEGER,
+            before TEXT,
+            after TEXT
+        );
+        CREATE INDEX IF NOT EXISTS audit_log_entity ON audit_log (entity, entity_id);
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END;",
+    )
+}
+
+/// Calendar apps key events on the entry id, so a new entry must never get
+/// the id of a deleted one. Only AUTOINCREMENT guarantees that, and SQLite
+/// can only add it by rebuilding the table.
+fn rebuild_time_entries_autoincrement(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TABLE IF EXISTS time_entries_new;
+        CREATE TABLE time_entries_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            project_id INTEGER NOT NULL,
+            task_id INTEGER NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            duration_seconds INTEGER NOT NULL,
+            manual INTEGER NOT NULL DEFAULT 0,
+            edited_at INTEGER,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+        );
+        INSERT INTO time_entries_new (id, project_id, task_id, start_time, end_time, duration_seconds, manual, edited_at)
+        SELECT id, project_id, task_id, start_time, end_time, duration_seconds, manual, edited_at FROM time_entries;
+        DROP TABLE time_entries;
+        ALTER TABLE time_entries_new RENAME TO time_entries;",
+    )?;
+
+    // Entries deleted before now are gone from the table but not from the
+    // audit log, so start counting after those too
+    tx.execute("DELETE FROM sqlite_sequence WHERE name = 'time_entries'", [])?;
+    tx.execute(
+        "INSERT INTO sqlite_sequence (name, seq) VALUES ('time_entries', MAX(
+            (SELECT COALESCE(MAX(id), 0) FROM time_entries),
+            (SELECT COALESCE(MAX(entity_id), 0) FROM audit_log WHERE entity = 'time_entry')
+        ))",
+        [],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        let names = stmt.query_map([], |row| row.get::<_, String>(1)).unwrap();
+        names.map(|n| n.unwrap()).collect()
+    }
+
+    fn assert_latest_layout(conn: &Connection) {
+        assert_eq!(schema_version(conn).unwrap(), latest_version());
+
+        let project_cols = columns(conn, "projects");
+        for col in ["id", "name", "current_task_id", "archived_at", "rotation_weight", "task_rotation_strategy", "position"] {
+            assert!(project_cols.iter().any(|c| c == col), "projects.{} missing", col);
+        }
+        let task_cols = columns(conn, "tasks");
+        for col in ["id", "project_id", "name", "time_seconds", "done_at", "archived_at", "rotation_weight", "position"] {
+            assert!(task_cols.iter().any(|c| c == col), "tasks.{} missing", col);
+        }
+        assert_eq!(columns(conn, "active_tracking"), ["id", "project_id", "task_id", "started_at", "last_heartbeat"]);
+        assert!(!columns(conn, "time_entries").is_empty());
+        assert!(!columns(conn, "app_state").is_empty());
+
+        // Multiple tasks can be tracked at once
+        conn.execute("INSERT INTO active_tracking (project_id, task_id, started_at) VALUES (1, 100, 10)", []).unwrap();
+        conn.execute("INSERT INTO active_tracking (project_id, task_id, started_at) VALUES (1, 101, 20)", []).unwrap();
+    }
+
+    #[test]
+    fn migrates_fresh_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert_latest_layout(&conn);
+    }
+
+    #[test]
+    fn rerunning_is_a_no_op() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute("INSERT INTO projects (id, name) VALUES (1, 'Work')", []).unwrap();
+
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM projects", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn migrates_layout_without_archived_columns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL, current_task_index INTEGER NOT NULL DEFAULT 0);
+             CREATE TABLE tasks (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, name TEXT NOT NULL, time_seconds INTEGER NOT NULL DEFAULT 0);
+             INSERT INTO projects (id, name) VALUES (1, 'Work');
+             INSERT INTO tasks (id, project_id, name, time_seconds) VALUES (1, 1, 'Review', 120);",
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_latest_layout(&conn);
+
+        let (time, done_at, archived_at): (u64, Option<u64>, Option<u64>) = conn
+            .query_row("SELECT time_seconds, done_at, archived_at FROM tasks WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(time, 120);
+        assert_eq!(done_at, None);
+        assert_eq!(archived_at, None);
+    }
+
+    #[test]
+    fn migrates_boolean_done_and_archived_layout() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL, current_task_index INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0);
+             CREATE TABLE tasks (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, name TEXT NOT NULL, time_seconds INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0, done INTEGER NOT NULL DEFAULT 0);
+             CREATE TABLE app_state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO projects (id, name, archived) VALUES (1, 'Work', 0), (2, 'Old', 1);
+             INSERT INTO tasks (id, project_id, name, done, archived) VALUES (1, 1, 'Open', 0, 0), (2, 1, 'Finished', 1, 0), (3, 2, 'Shelved', 0, 1);",
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_latest_layout(&conn);
+
+        let task = |id: u64| -> (Option<u64>, Option<u64>) {
+            conn.query_row("SELECT done_at, archived_at FROM tasks WHERE id = ?", [id], |r| Ok((r.get(0)?, r.get(1)?)))
+                .unwrap()
+        };
+        assert_eq!(task(1), (None, None));
+        assert!(task(2).0.is_some() && task(2).1.is_none());
+        assert!(task(3).0.is_none() && task(3).1.is_some());
+
+        let project_archived: Vec<Option<u64>> = {
+            let mut stmt = conn.prepare("SELECT archived_at FROM projects ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |r| r.get(0)).unwrap();
+            rows.map(|r| r.unwrap()).collect()
+        };
+        assert!(project_archived[0].is_none());
+        assert!(project_archived[1].is_some());
+    }
+
+    #[test]
+    fn migrates_single_row_active_tracking_layout() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL, current_task_index INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0, archived_at INTEGER);
+             CREATE TABLE tasks (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, name TEXT NOT NULL, time_seconds INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0, done INTEGER NOT NULL DEFAULT 0, done_at INTEGER, archived_at INTEGER);
+             CREATE TABLE app_state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             CREATE TABLE active_tracking (id INTEGER PRIMARY KEY CHECK (id = 1), project_id INTEGER NOT NULL, task_id INTEGER NOT NULL, started_at INTEGER NOT NULL);
+             INSERT INTO active_tracking (id, project_id, task_id, started_at) VALUES (1, 1, 1, 1700000000);",
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let started_at: u64 = conn
+            .query_row("SELECT started_at FROM active_tracking WHERE task_id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(started_at, 1700000000);
+        assert_latest_layout(&conn);
+    }
+
+    #[test]
+    fn migrates_unversioned_current_layout() {
+        // What the old ad-hoc init_db left behind, including an interrupted rebuild
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL, current_task_index INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0, archived_at INTEGER);
+             CREATE TABLE tasks (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, name TEXT NOT NULL, time_seconds INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0, done INTEGER NOT NULL DEFAULT 0, done_at INTEGER, archived_at INTEGER);
+             CREATE TABLE app_state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             CREATE TABLE active_tracking (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, task_id INTEGER NOT NULL, started_at INTEGER NOT NULL);
+             CREATE TABLE active_tracking_new (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, task_id INTEGER NOT NULL, started_at INTEGER NOT NULL);
+             CREATE TABLE time_entries (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, task_id INTEGER NOT NULL, start_time INTEGER NOT NULL, end_time INTEGER NOT NULL, duration_seconds INTEGER NOT NULL);
+             INSERT INTO projects (id, name) VALUES (1, 'Work');
+             INSERT INTO tasks (id, project_id, name, done_at) VALUES (1, 1, 'Review', 1700000500);
+             INSERT INTO app_state (key, value) VALUES ('current_project_index', '0');
+             INSERT INTO active_tracking (project_id, task_id, started_at) VALUES (1, 1, 1700000000);
+             INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (1, 1, 1700000000, 1700000060, 60);",
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let done_at: u64 = conn.query_row("SELECT done_at FROM tasks WHERE id = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(done_at, 1700000500);
+        let entries: i64 = conn.query_row("SELECT COUNT(*) FROM time_entries", [], |r| r.get(0)).unwrap();
+        assert_eq!(entries, 1);
+        let tracking: i64 = conn.query_row("SELECT COUNT(*) FROM active_tracking", [], |r| r.get(0)).unwrap();
+        assert_eq!(tracking, 1);
+        assert_latest_layout(&conn);
+    }
+
+    #[test]
+    fn deleted_entry_ids_are_not_handed_out_again() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply(&mut conn, &MIGRATIONS[..17]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO projects (id, name) VALUES (1, 'Work');
+             INSERT INTO tasks (id, project_id, name) VALUES (1, 1, 'Review');
+             INSERT INTO time_entries (id, project_id, task_id, start_time, end_time, duration_seconds) VALUES (3, 1, 1, 100, 160, 60);
+             INSERT INTO tracking_pauses (task_id, entry_id, paused_at, resumed_at) VALUES (1, 3, 120, 130);
+             INSERT INTO audit_log (at, command, source, entity, entity_id) VALUES (200, 'delete_time_entry', 'ui', 'time_entry', 5);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        let manual: i64 = conn.query_row("SELECT manual FROM time_entries WHERE id = 3", [], |r| r.get(0)).unwrap();
+        assert_eq!(manual, 0);
+        let pauses: i64 = conn.query_row("SELECT COUNT(*) FROM tracking_pauses WHERE entry_id = 3", [], |r| r.get(0)).unwrap();
+        assert_eq!(pauses, 1);
+        let enforced: bool = conn.query_row("PRAGMA foreign_keys", [], |r| r.get(0)).unwrap();
+        assert!(enforced);
+
+        conn.execute("DELETE FROM time_entries", []).unwrap();
+        conn.execute(
+            "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (1, 1, 300, 360, 60)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(conn.last_insert_rowid(), 6);
+    }
+
+    #[test]
+    fn positions_follow_id_order_and_current_index_becomes_an_id() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply(&mut conn, &MIGRATIONS[..13]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO projects (id, name, archived_at) VALUES (1, 'Old', 1700000000);
+             INSERT INTO projects (id, name) VALUES (2, 'Work'), (5, 'Home');
+             INSERT INTO tasks (id, project_id, name) VALUES (7, 5, 'B'), (3, 5, 'A');
+             INSERT INTO app_state (key, value) VALUES ('current_project_index', '1');",
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let positions: Vec<(u64, i64)> = {
+            let mut stmt = conn.prepare("SELECT id, position FROM tasks ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+            rows.map(|r| r.unwrap()).collect()
+        };
+        assert_eq!(positions, [(3, 0), (7, 1)]);
+        let current: String = conn
+            .query_row("SELECT value FROM app_state WHERE key = 'current_project_id'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(current, "5");
+    }
+
+    #[test]
+    fn current_task_index_becomes_an_id() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply(&mut conn, &MIGRATIONS[..14]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO projects (id, name, current_task_index) VALUES (1, 'Work', 1), (2, 'Home', 3);
+             INSERT INTO tasks (id, project_id, name, position) VALUES (4, 1, 'A', 0), (5, 1, 'B', 2), (9, 1, 'C', 1);
+             INSERT INTO tasks (id, project_id, name, archived_at) VALUES (6, 1, 'Gone', 1700000000);
+             INSERT INTO tasks (id, project_id, name) VALUES (8, 2, 'D');",
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let current: Vec<(u64, Option<u64>)> = {
+            let mut stmt = conn.prepare("SELECT id, current_task_id FROM projects ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+            rows.map(|r| r.unwrap()).collect()
+        };
+        // Index 1 in position order is C; Home's index was already out of range
+        assert_eq!(current, [(1, Some(9)), (2, None)]);
+    }
+
+    #[test]
+    fn failed_step_is_reported_and_rolled_back() {
+        fn create_widgets(tx: &Transaction) -> rusqlite::Result<()> {
+            tx.execute("CREATE TABLE widgets (id INTEGER PRIMARY KEY)", [])?;
+            Ok(())
+        }
+        fn broken(tx: &Transaction) -> rusqlite::Result<()> {
+            tx.execute("CREATE TABLE gadgets (id INTEGER PRIMARY KEY)", [])?;
+            tx.execute("ALTER TABLE missing ADD COLUMN x INTEGER", [])?;
+            Ok(())
+        }
+        let migrations = [
+            Migration { version: 1, description: "widgets", up: create_widgets },
+            Migration { version: 2, description: "broken", up: broken },
+        ];
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        match apply(&mut conn, &migrations) {
+            Err(MigrationError::Step { version: 2, .. }) => {}
+            other => panic!("expected step 2 to fail, got {:?}", other.map_err(|e| e.to_string())),
+        }
+
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        let gadgets: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE name = 'gadgets'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(gadgets, 0);
+    }
+
+    #[test]
+    fn rejects_database_from_newer_build() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        assert!(matches!(migrate(&mut conn), Err(MigrationError::TooNew { .. })));
+    }
+
+    #[test]
+    fn existing_database_is_backed_up_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("rotator-migrate-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rotator.db");
+        let mut conn = Connection::open(&path).unwrap();
+        apply(&mut conn, &MIGRATIONS[..14]).unwrap();
+        drop(conn);
+
+        let rotator = crate::Rotator::new(Connection::open(&path).unwrap(), crate::ManualClock::new(1_000)).unwrap();
+        assert_eq!(schema_version(rotator.connection()).unwrap(), latest_version());
+        let backups = rotator.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].reason, crate::BackupReason::Migration);
+        assert_eq!(schema_version(&Connection::open(&backups[0].path).unwrap()).unwrap(), 14);
+
+        // Up to date now, so reopening takes no further backup
+        drop(rotator);
+        let rotator = crate::Rotator::new(Connection::open(&path).unwrap(), crate::ManualClock::new(2_000)).unwrap();
+        assert_eq!(rotator.list_backups().unwrap().len(), 1);
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unversioned_database_is_backed_up_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("rotator-migrate-legacy-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rotator.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL, current_task_index INTEGER NOT NULL DEFAULT 0);
+                 CREATE TABLE tasks (id INTEGER PRIMARY KEY, project_id INTEGER NOT NULL, name TEXT NOT NULL, time_seconds INTEGER NOT NULL DEFAULT 0, done INTEGER NOT NULL DEFAULT 0, archived INTEGER NOT NULL DEFAULT 0);
+                 INSERT INTO projects (id, name) VALUES (1, 'Work');",
+            )
+            .unwrap();
+
+        let rotator = crate::Rotator::new(Connection::open(&path).unwrap(), crate::ManualClock::new(1_000)).unwrap();
+        let backups = rotator.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].reason, crate::BackupReason::Migration);
+        assert!(std::path::Path::new(&backups[0].path).exists());
+        assert_eq!(schema_version(&Connection::open(&backups[0].path).unwrap()).unwrap(), 0);
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_database_is_not_backed_up() {
+        let dir = std::env::temp_dir().join(format!("rotator-migrate-fresh-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let rotator = crate::Rotator::new(Connection::open(dir.join("rotator.db")).unwrap(), crate::ManualClock::new(1_000)).unwrap();
+        assert!(rotator.list_backups().unwrap().is_empty());
+
+        drop(rotator);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::db::{next_project_position, next_task_position, save_current_project, save_current_task};
+use crate::error::RotatorError;
+use crate::models::{Project, Task};
+use crate::rotator::Rotator;
+use rusqlite::params;
+use serde_json::Value;
+
+impl Rotator {
+    /// Seed three demo projects with a month of time entries.
+    pub fn add_mock_data(&mut self) -> Result<Vec<Project>, RotatorError> {
+        let now = self.clock.now();
+        let day_seconds: u64 = 24 * 60 * 60;
+
+        let mock_projects = vec![
+            ("Work", vec!["Code review", "Write documentation", "Fix bugs", "Team meeting"]),
+            ("Personal", vec!["Exercise", "Read book", "Learn Rust", "Side project"]),
+            ("Learning", vec!["Online course", "Practice coding", "Watch tutorials"]),
+        ];
+
+        // Time entries for each task (days_ago, hour, duration_minutes)
+        let time_entries_template: Vec<(u64, u64, u64)> = vec![
+            (0, 9, 45), (0, 14, 30), (0, 16, 20),
+            (1, 10, 60), (1, 15, 25),
+            (2, 9, 50), (2, 11, 40), (2, 14, 35),
+            (3, 10, 55), (3, 13, 30), (3, 16, 20),
+            (4, 9, 45), (4, 11, 30),
+            (5, 14, 60), (5, 16, 25),
+            (6, 10, 40), (6, 15, 50),
+            (7, 9, 35), (7, 11, 45), (7, 14, 30),
+            (10, 10, 50), (10, 15, 40),
+            (14, 9, 60), (14, 14, 45),
+            (21, 10, 55), (21, 16, 35),
+            (30, 11, 45), (30, 15, 30),
+        ];
+
+        // Calculate the start of today (midnight UTC) for proper day offset calculation
+        let today_start = (now / day_seconds) * day_seconds;
+
+        let mut task_counter: usize = 0;
+        let mut next_project_id = self.next_project_id;
+        let mut next_task_id = self.next_task_id;
+        let mut projects = Vec::new();
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+
+        for (project_name, tasks) in mock_projects {
+            let project_id = next_project_id;
+            tx.execute(
+                "INSERT INTO projects (id, name, position) VALUES (?, ?, ?)",
+                params![project_id, project_name, next_project_position(&tx)?],
+            )?;
+
+            let mut project_tasks = Vec::new();
+            for task_name in tasks {
+                let task_id = next_task_id;
+
+                // Generate time entries for this task
+                let mut total_time: u64 = 0;
+                let entries_offset = task_counter % 5; // Offset entries to vary by task
+
+                // Calculate total time first
+                for (days_ago, _, duration_minutes) in &time_entries_template {
+                    if (*days_ago + entries_offset as u64).is_multiple_of(3) {
+                        continue;
+                    }
+                    total_time += duration_minutes * 60;
+                }
+
+                // Insert task FIRST (before time entries due to foreign key constraint)
+                tx.execute(
+                    "INSERT INTO tasks (id, project_id, name, time_seconds, position) VALUES (?, ?, ?, ?, ?)",
+                    params![task_id, project_id, task_name, total_time, next_task_position(&tx, project_id)?],
+                )?;
+
+                // Now insert time entries (task exists, foreign key satisfied)
+                for (days_ago, hour, duration_minutes) in &time_entries_template {
+                    // Skip some entries based on task to create variation
+                    if (*days_ago + entries_offset as u64).is_multiple_of(3) {
+                        continue;
+                    }
+
+                    let duration_seconds = duration_minutes * 60;
+                    // Calculate proper timestamp: start of day X days ago + hour offset
+                    let day_start = today_start - (days_ago * day_seconds);
+                    let start_time = day_start + (hour * 3600);
+                    let end_time = start_time + duration_seconds;
+
+                    tx.execute(
+                        "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
+                        params![project_id, task_id, start_time, end_time, duration_seconds],
+                    )?;
+                }
+
+                project_tasks.push(Task {
+                    id: task_id,
+                    name: task_name.to_string(),
+                    time_seconds: total_time,
+                    done_at: None,
+                });
+                next_task_id += 1;
+                task_counter += 1;
+            }
+
+            let current_task_id = project_tasks.first().map(|t| t.id);
+            save_current_task(&tx, project_id, current_task_id)?;
+            projects.push(Project {
+                id: project_id,
+                name: project_name.to_string(),
+                tasks: project_tasks,
+                current_task_id,
+            });
+            next_project_id += 1;
+        }
+
+        let current_project_id = self.current_project_id.or_else(|| projects.first().map(|p: &Project| p.id));
+        save_current_project(&tx, current_project_id)?;
+        audit.record(&tx, "add_mock_data", None, Value::Null, Value::Null)?;
+        tx.commit()?;
+
+        self.projects.extend(projects);
+        self.current_project_id = current_project_id;
+        self.next_project_id = next_project_id;
+        self.next_task_id = next_task_id;
+        Ok(self.projects.clone())
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Task {
+    pub id: u64,
+    pub name: String,
+    pub time_seconds: u64,
+    pub done_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Project {
+    pub id: u64,
+    pub name: String,
+    pub tasks: Vec<Task>,
+    /// `None` only while the project has no visible tasks.
+    pub current_task_id: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActiveTracking {
+    pub project_id: u64,
+    pub task_id: u64,
+    pub started_at: u64,
+    /// Set while the session is paused.
+    pub paused_at: Option<u64>,
+    /// Length of the pauses already resumed from.
+    pub paused_seconds: u64,
+}
+
+impl ActiveTracking {
+    /// Tracked seconds so far, not counting pauses. Frozen while paused.
+    pub fn elapsed_seconds(&self, now: u64) -> u64 {
+        self.paused_at.unwrap_or(now)
+            .saturating_sub(self.started_at)
+            .saturating_sub(self.paused_seconds)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimeEntry {
+    pub id: u64,
+    pub project_id: u64,
+    pub task_id: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    /// Raw tracked seconds.
+    pub duration_seconds: u64,
+    /// `duration_seconds` after the project's time policy.
+    pub billable_seconds: u64,
+    /// Logged by hand rather than tracked live.
+    pub manual: bool,
+    /// When the entry was last moved, split or merged after being recorded.
+    pub edited_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HourlyActivity {
+    pub hour: u32,
+    pub total_seconds: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DailyActivity {
+    pub date: String,
+    pub total_seconds: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProjectTimeStats {
+    pub project_id: u64,
+    pub project_name: String,
+    pub total_seconds: u64,
+    pub billable_seconds: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProjectWithStatus {
+    pub id: u64,
+    pub name: String,
+    pub tasks: Vec<TaskWithStatus>,
+    pub current_task_id: Option<u64>,
+    pub archived_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskWithStatus {
+    pub id: u64,
+    pub name: String,
+    pub time_seconds: u64,
+    pub archived_at: Option<u64>,
+    pub done_at: Option<u64>,
+}
+
+/// Outcome of stopping one tracked task.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClosedSession {
+    pub project_id: u64,
+    pub task_id: u64,
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub duration_seconds: u64,
+    /// Duration after the project's time policy, 0 when discarded.
+    pub billable_seconds: u64,
+    /// Id of the recorded time entry, `None` if the session was discarded.
+    pub entry_id: Option<u64>,
+    /// The session was shorter than the project's minimum and not recorded.
+    pub discarded: bool,
+}
@@ -0,0 +1,174 @@
+use crate::audit::field;
+use crate::db::{load_current_project, load_projects};
+use crate::error::{Entity, RotatorError};
+use crate::models::Project;
+use crate::rotator::Rotator;
+use rusqlite::{params, Transaction};
+use serde_json::Value;
+
+/// Give `ids` (rows of `table`) the positions they already hold between
+/// them, in the new order. Archived and hidden rows keep their own
+/// positions, so they come back where they were when restored.
+fn permute_positions(tx: &Transaction, table: &str, ids: &[u64]) -> rusqlite::Result<()> {
+    let mut positions = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let position: i64 = tx.query_row(&format!("SELECT position FROM {} WHERE id = ?", table), [id], |row| row.get(0))?;
+        positions.push(position);
+    }
+    positions.sort_unstable();
+    for i in 1..positions.len() {
+        if positions[i] <= positions[i - 1] {
+            positions[i] = positions[i - 1] + 1;
+        }
+    }
+    for (&id, position) in ids.iter().zip(positions) {
+        tx.execute(&format!("UPDATE {} SET position = ? WHERE id = ?", table), params![position, id])?;
+    }
+    Ok(())
+}
+
+/// `ids` first, in that order, then everything else in its current order.
+fn arrange(current: &[u64], ids: &[u64]) -> Vec<u64> {
+    let mut order: Vec<u64> = ids.iter().copied().filter(|id| current.contains(id)).collect();
+    order.dedup();
+    order.extend(current.iter().filter(|id| !ids.contains(id)));
+    order
+}
+
+/// `current` with `id` moved to `to_index`, clamped to the end.
+fn move_to(current: &[u64], id: u64, to_index: usize) -> Vec<u64> {
+    let mut order: Vec<u64> = current.iter().copied().filter(|&other| other != id).collect();
+    if order.len() < current.len() {
+        order.insert(to_index.min(order.len()), id);
+    }
+    order
+}
+
+impl Rotator {
+    /// Reload the cached projects from the database, staying on the same
+    /// current project.
+    pub(crate) fn reload_projects(&mut self) -> rusqlite::Result<()> {
+        self.projects = load_projects(&self.conn, self.clock.now())?;
+        self.current_project_id = load_current_project(&self.conn, &self.projects)?;
+        Ok(())
+    }
+
+    /// Put projects in the order of `project_ids`. Projects left out keep
+    /// their relative order after the listed ones.
+    pub fn reorder_projects(&mut self, project_ids: &[u64]) -> Result<Vec<Project>, RotatorError> {
+        let current: Vec<u64> = self.projects.iter().map(|p| p.id).collect();
+        let order = arrange(&current, project_ids);
+        self.apply_project_order(&order)
+    }
+
+    pub fn move_project(&mut self, project_id: u64, to_index: usize) -> Result<Vec<Project>, RotatorError> {
+        self.project_mut(project_id)?;
+        let current: Vec<u64> = self.projects.iter().map(|p| p.id).collect();
+        let order = move_to(&current, project_id, to_index);
+        self.apply_project_order(&order)
+    }
+
+    fn apply_project_order(&mut self, order: &[u64]) -> Result<Vec<Project>, RotatorError> {
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        permute_positions(&tx, "projects", order)?;
+        audit.record(&tx, "reorder_projects", None, Value::Null, field("order", &order))?;
+        tx.commit()?;
+        self.projects.sort_by_key(|p| order.iter().position(|&id| id == p.id));
+        Ok(self.projects.clone())
+    }
+
+    /// Put a project's tasks in the order of `task_ids`, like `reorder_projects`.
+    pub fn reorder_tasks(&mut self, project_id: u64, task_ids: &[u64]) -> Result<Project, RotatorError> {
+        let project = self.project_mut(project_id)?;
+        let current: Vec<u64> = project.tasks.iter().map(|t| t.id).collect();
+        let order = arrange(&current, task_ids);
+        self.apply_task_order(project_id, &order)
+    }
+
+    pub fn move_task(&mut self, project_id: u64, task_id: u64, to_index: usize) -> Result<Project, RotatorError> {
+        let project = self.project_mut(project_id)?;
+        let current: Vec<u64> = project.tasks.iter().map(|t| t.id).collect();
+        if !current.contains(&task_id) {
+            return Err(RotatorError::NotFound(Entity::Task, task_id));
+        }
+        let order = move_to(&current, task_id, to_index);
+        self.apply_task_order(project_id, &order)
+    }
+
+    fn apply_task_order(&mut self, project_id: u64, order: &[u64]) -> Result<Project, RotatorError> {
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        permute_positions(&tx, "tasks", order)?;
+        audit.record(&tx, "reorder_tasks", Some((Entity::Project, project_id)), Value::Null, field("order", &order))?;
+        tx.commit()?;
+        let project = self.project_mut(project_id)?;
+        project.tasks.sort_by_key(|t| order.iter().position(|&id| id == t.id));
+        Ok(project.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::test_support::rotator_at;
+    use rusqlite::Connection;
+
+    fn names(projects: &[Project]) -> Vec<&str> {
+        projects.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn order_and_current_project_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("rotator-core-ordering-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = ManualClock::new(100_000);
+
+        let mut rotator = Rotator::new(Connection::open(&path).unwrap(), clock.clone()).unwrap();
+        for name in ["A", "B", "C"] {
+            rotator.add_project(name.into()).unwrap();
+        }
+        rotator.set_current_project(2).unwrap();
+        let projects = rotator.move_project(rotator.projects()[2].id, 0).unwrap();
+        assert_eq!(names(&projects), ["C", "A", "B"]);
+        assert_eq!(rotator.current_project().unwrap().name, "C");
+        drop(rotator);
+
+        let rotator = Rotator::new(Connection::open(&path).unwrap(), clock).unwrap();
+        assert_eq!(names(&rotator.projects()), ["C", "A", "B"]);
+        assert_eq!(rotator.current_project().unwrap().name, "C");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn selecting_a_project_no_longer_reorders() {
+        let (mut rotator, _) = rotator_at(100_000);
+        for name in ["A", "B", "C"] {
+            rotator.add_project(name.into()).unwrap();
+        }
+        assert_eq!(rotator.set_current_project(2).unwrap(), 2);
+        assert_eq!(names(&rotator.projects()), ["A", "B", "C"]);
+    }
+
+    #[test]
+    fn restored_items_return_to_their_position() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let ids: Vec<u64> = ["A", "B", "C"].iter().map(|n| rotator.add_project(n.to_string()).unwrap().last().unwrap().id).collect();
+        rotator.set_current_project(2).unwrap();
+        rotator.remove_project(ids[1]).unwrap();
+        assert_eq!(rotator.current_project().unwrap().name, "C");
+        assert_eq!(names(&rotator.restore_project(ids[1]).unwrap()), ["A", "B", "C"]);
+        assert_eq!(rotator.current_project().unwrap().name, "C");
+
+        let project_id = ids[0];
+        let tasks: Vec<u64> = ["x", "y", "z"].iter()
+            .map(|n| rotator.add_task(project_id, n.to_string()).unwrap().tasks.last().unwrap().id)
+            .collect();
+        rotator.reorder_tasks(project_id, &[tasks[2], tasks[0]]).unwrap();
+        rotator.remove_task(project_id, tasks[0]).unwrap();
+        let project = rotator.restore_task(project_id, tasks[0]).unwrap();
+        let order: Vec<u64> = project.tasks.iter().map(|t| t.id).collect();
+        assert_eq!(order, [tasks[2], tasks[0], tasks[1]]);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::audit::field;
+use crate::error::{Entity, RotatorError};
+use crate::models::ActiveTracking;
+use crate::rotator::Rotator;
+use rusqlite::params;
+use serde_json::Value;
+
+impl Rotator {
+    /// Pause `task_id`, or every running task when `None`. The session stays
+    /// open and becomes a single time entry once stopped.
+    pub fn pause_tracking(&mut self, task_id: Option<u64>) -> Result<Vec<ActiveTracking>, RotatorError> {
+        let now = self.clock.now();
+        let mut paused = Vec::new();
+        for tracking in self.active_tracking.iter_mut()
+            .filter(|t| task_id.is_none_or(|id| t.task_id == id) && t.paused_at.is_none())
+        {
+            self.conn.execute(
+                "INSERT INTO tracking_pauses (task_id, paused_at) VALUES (?, ?)",
+                params![tracking.task_id, now],
+            )?;
+            tracking.paused_at = Some(now);
+            paused.push(tracking.task_id);
+        }
+        for task_id in paused {
+            self.audit("pause_tracking", Some((Entity::Task, task_id)), Value::Null, field("paused_at", &now))?;
+        }
+        Ok(self.active_tracking.clone())
+    }
+
+    /// Resume `task_id`, or every paused task when `None`.
+    pub fn resume_tracking(&mut self, task_id: Option<u64>) -> Result<Vec<ActiveTracking>, RotatorError> {
+        let now = self.clock.now();
+        let mut resumed = Vec::new();
+        for tracking in self.active_tracking.iter_mut()
+            .filter(|t| task_id.is_none_or(|id| t.task_id == id))
+        {
+            let Some(paused_at) = tracking.paused_at else { continue };
+            self.conn.execute(
+                "UPDATE tracking_pauses SET resumed_at = ?
+                 WHERE task_id = ? AND entry_id IS NULL AND resumed_at IS NULL",
+                params![now, tracking.task_id],
+            )?;
+            tracking.paused_seconds += now.saturating_sub(paused_at);
+            tracking.paused_at = None;
+            resumed.push((tracking.task_id, paused_at));
+        }
+        for (task_id, paused_at) in resumed {
+            self.audit("resume_tracking", Some((Entity::Task, task_id)), field("paused_at", &paused_at), field("resumed_at", &now))?;
+        }
+        Ok(self.active_tracking.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::rotator_at;
+    use crate::Rotator;
+    use rusqlite::Connection;
+
+    #[test]
+    fn pauses_are_excluded_from_one_entry() {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(600);
+        rotator.pause_tracking(None).unwrap();
+        clock.advance(300);
+        assert_eq!(rotator.active_tracking()[0].elapsed_seconds(rotator.now()), 600);
+
+        // Starting a paused task resumes it rather than opening a new session
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(200);
+        assert_eq!(rotator.active_tracking()[0].elapsed_seconds(rotator.now()), 800);
+        rotator.pause_tracking(Some(task_id)).unwrap();
+        clock.advance(50);
+
+        let closed = rotator.stop_tracking(None).unwrap();
+        assert_eq!(closed[0].duration_seconds, 800);
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].start_time, entries[0].end_time, entries[0].duration_seconds), (100_000, 101_150, 800));
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 800);
+    }
+
+    #[test]
+    fn split_and_edit_keep_pauses_inside_their_entry() {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+
+        // Work 100_000..100_100, pause until 100_300, work until 100_400
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(100);
+        rotator.pause_tracking(None).unwrap();
+        clock.advance(200);
+        rotator.resume_tracking(None).unwrap();
+        clock.advance(100);
+        rotator.stop_tracking(None).unwrap();
+        let id = rotator.all_time_entries().unwrap()[0].id;
+
+        let (first, second) = rotator.split_time_entry(id, 100_200).unwrap();
+        assert_eq!((first.duration_seconds, second.duration_seconds), (100, 100));
+
+        let merged = rotator.merge_time_entries(first.id, second.id).unwrap();
+        assert_eq!(merged.duration_seconds, 200);
+
+        let trimmed = rotator.update_time_entry(id, 100_050, 100_400).unwrap();
+        assert_eq!(trimmed.duration_seconds, 150);
+    }
+
+    #[test]
+    fn paused_state_survives_reopen() {
+        let clock = crate::ManualClock::new(1_000);
+        let path = std::env::temp_dir().join(format!("rotator-core-pause-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut rotator = Rotator::new(Connection::open(&path).unwrap(), clock.clone()).unwrap();
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(10);
+        rotator.pause_tracking(None).unwrap();
+        clock.advance(10);
+        rotator.resume_tracking(None).unwrap();
+        clock.advance(10);
+        rotator.pause_tracking(None).unwrap();
+        drop(rotator);
+
+        let reopened = Rotator::new(Connection::open(&path).unwrap(), clock).unwrap();
+        let tracking = &reopened.active_tracking()[0];
+        assert_eq!((tracking.paused_at, tracking.paused_seconds), (Some(1_030), 10));
+        drop(reopened);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use crate::audit::snapshot;
+use crate::db::{load_setting, save_setting};
+use crate::error::{Entity, RotatorError};
+use crate::models::TimeEntry;
+use crate::rotator::Rotator;
+use crate::session::MIN_SESSION_SECONDS;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    None,
+    Up,
+    Down,
+    Nearest,
+}
+
+impl RoundingMode {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RoundingMode::None => "none",
+            RoundingMode::Up => "up",
+            RoundingMode::Down => "down",
+            RoundingMode::Nearest => "nearest",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(RoundingMode::None),
+            "up" => Some(RoundingMode::Up),
+            "down" => Some(RoundingMode::Down),
+            "nearest" => Some(RoundingMode::Nearest),
+            _ => None,
+        }
+    }
+}
+
+/// How raw tracked time turns into reported time. Time entries always keep
+/// the raw seconds; the policy is applied when sessions close and whenever
+/// time is reported, so it can be changed after the fact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimePolicy {
+    /// Sessions shorter than this are discarded when closed and reported as 0.
+    pub min_session_seconds: u64,
+    pub rounding: RoundingMode,
+    /// Rounding increment. 0 leaves durations unrounded.
+    pub round_to_minutes: u64,
+}
+
+impl Default for TimePolicy {
+    fn default() -> Self {
+        Self { min_session_seconds: MIN_SESSION_SECONDS, rounding: RoundingMode::None, round_to_minutes: 0 }
+    }
+}
+
+impl TimePolicy {
+    /// Reported seconds for a raw duration.
+    pub fn billable_seconds(&self, duration_seconds: u64) -> u64 {
+        if duration_seconds < self.min_session_seconds {
+            return 0;
+        }
+        let step = self.round_to_minutes * 60;
+        if step == 0 {
+            return duration_seconds;
+        }
+        let down = duration_seconds / step * step;
+        match self.rounding {
+            RoundingMode::None => duration_seconds,
+            RoundingMode::Down => down,
+            RoundingMode::Up => duration_seconds.div_ceil(step) * step,
+            RoundingMode::Nearest if duration_seconds - down >= step.div_ceil(2) => down + step,
+            RoundingMode::Nearest => down,
+        }
+    }
+
+    fn load_global(conn: &Connection) -> rusqlite::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            min_session_seconds: load_setting(conn, "min_session_seconds")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.min_session_seconds),
+            rounding: load_setting(conn, "rounding_mode")?
+                .and_then(|v| RoundingMode::parse(&v))
+                .unwrap_or(default.rounding),
+            round_to_minutes: load_setting(conn, "round_to_minutes")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.round_to_minutes),
+        })
+    }
+
+    fn save_global(&self, conn: &Connection) -> rusqlite::Result<()> {
+        save_setting(conn, "min_session_seconds", &self.min_session_seconds.to_string())?;
+        save_setting(conn, "rounding_mode", self.rounding.as_str())?;
+        save_setting(conn, "round_to_minutes", &self.round_to_minutes.to_string())
+    }
+}
+
+pub(crate) fn load_project_policy(conn: &Connection, project_id: u64) -> rusqlite::Result<Option<TimePolicy>> {
+    conn.query_row(
+        "SELECT min_session_seconds, rounding, round_to_minutes FROM project_time_policies WHERE project_id = ?",
+        [project_id],
+        |row| Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, u64>(2)?)),
+    )
+    .optional()
+    .map(|row| {
+        row.map(|(min_session_seconds, rounding, round_to_minutes)| TimePolicy {
+            min_session_seconds,
+            rounding: RoundingMode::parse(&rounding).unwrap_or(RoundingMode::None),
+            round_to_minutes,
+        })
+    })
+}
+
+/// The policy that applies to `project_id`: its own, or the global default.
+pub(crate) fn policy_for(conn: &Connection, project_id: u64) -> rusqlite::Result<TimePolicy> {
+    match load_project_policy(conn, project_id)? {
+        Some(policy) => Ok(policy),
+        None => TimePolicy::load_global(conn),
+    }
+}
+
+/// Every policy at once, for reporting over many entries.
+pub(crate) struct Policies {
+    global: TimePolicy,
+    projects: HashMap<u64, TimePolicy>,
+}
+
+impl Policies {
+    pub(crate) fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let mut projects = HashMap::new();
+        let mut stmt = conn.prepare("SELECT project_id FROM project_time_policies")?;
+        let ids = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<Vec<u64>>>()?;
+        for id in ids {
+            if let Some(policy) = load_project_policy(conn, id)? {
+                projects.insert(id, policy);
+            }
+        }
+        Ok(Self { global: TimePolicy::load_global(conn)?, projects })
+    }
+
+    pub(crate) fn for_project(&self, project_id: u64) -> &TimePolicy {
+        self.projects.get(&project_id).unwrap_or(&self.global)
+    }
+
+    pub(crate) fn apply(&self, entry: &mut TimeEntry) {
+        entry.billable_seconds = self.for_project(entry.project_id).billable_seconds(entry.duration_seconds);
+    }
+}
+
+impl Rotator {
+    pub fn time_policy(&self) -> Result<TimePolicy, RotatorError> {
+        Ok(TimePolicy::load_global(&self.conn)?)
+    }
+
+    pub fn set_time_policy(&mut self, policy: TimePolicy) -> Result<TimePolicy, RotatorError> {
+        let before = TimePolicy::load_global(&self.conn)?;
+        policy.save_global(&self.conn)?;
+        self.audit("set_time_policy", None, snapshot(&before), snapshot(&policy))?;
+        Ok(policy)
+    }
+
+    /// The project's own policy, `None` if it follows the global default.
+    pub fn project_time_policy(&self, project_id: u64) -> Result<Option<TimePolicy>, RotatorError> {
+        Ok(load_project_policy(&self.conn, project_id)?)
+    }
+
+    /// Override the policy for one project, or go back to the default with `None`.
+    pub fn set_project_time_policy(&mut self, project_id: u64, policy: Option<TimePolicy>) -> Result<(), RotatorError> {
+        let before = load_project_policy(&self.conn, project_id)?;
+        match policy {
+            Some(policy) => {
+                self.conn.execute(
+                    "INSERT OR REPLACE INTO project_time_policies (project_id, min_session_seconds, rounding, round_to_minutes)
+                     VALUES (?, ?, ?, ?)",
+                    params![project_id, policy.min_session_seconds, policy.rounding.as_str(), policy.round_to_minutes],
+                )?;
+            }
+            None => {
+                self.conn.execute("DELETE FROM project_time_policies WHERE project_id = ?", [project_id])?;
+            }
+        }
+        self.audit("set_project_time_policy", Some((Entity::Project, project_id)), snapshot(&before), snapshot(&policy))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    fn policy(min_session_seconds: u64, rounding: RoundingMode, round_to_minutes: u64) -> TimePolicy {
+        TimePolicy { min_session_seconds, rounding, round_to_minutes }
+    }
+
+    #[test]
+    fn rounds_to_increments() {
+        assert_eq!(policy(0, RoundingMode::Up, 6).billable_seconds(361), 720);
+        assert_eq!(policy(0, RoundingMode::Up, 6).billable_seconds(360), 360);
+        assert_eq!(policy(0, RoundingMode::Down, 15).billable_seconds(1799), 900);
+        assert_eq!(policy(0, RoundingMode::Nearest, 6).billable_seconds(179), 0);
+        assert_eq!(policy(0, RoundingMode::Nearest, 6).billable_seconds(180), 360);
+        assert_eq!(policy(0, RoundingMode::Nearest, 0).billable_seconds(180), 180);
+        assert_eq!(policy(60, RoundingMode::Up, 6).billable_seconds(59), 0);
+    }
+
+    #[test]
+    fn project_policy_overrides_default_when_closing_and_reporting() {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let home = rotator.add_project("Home".into()).unwrap()[1].id;
+        let a = rotator.add_task(work, "A".into()).unwrap().tasks[0].id;
+        let b = rotator.add_task(home, "B".into()).unwrap().tasks[0].id;
+
+        rotator.set_time_policy(policy(60, RoundingMode::None, 0)).unwrap();
+        rotator.set_project_time_policy(work, Some(policy(0, RoundingMode::Up, 15))).unwrap();
+
+        rotator.start_tracking(work, a, true).unwrap();
+        rotator.start_tracking(home, b, true).unwrap();
+        clock.advance(45);
+        let closed = rotator.stop_tracking(None).unwrap();
+
+        // Work keeps the 45s raw and bills a quarter hour; Home drops it
+        assert_eq!((closed[0].discarded, closed[0].billable_seconds), (false, 900));
+        assert_eq!((closed[1].discarded, closed[1].billable_seconds), (true, 0));
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].duration_seconds, entries[0].billable_seconds), (45, 900));
+
+        // Changing the policy later re-rounds the stored raw time
+        rotator.set_project_time_policy(work, None).unwrap();
+        assert_eq!(rotator.all_time_entries().unwrap()[0].billable_seconds, 0);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::audit::snapshot;
+use crate::db::remove_active_tracking;
+use crate::error::{Entity, RotatorError};
+use crate::models::ActiveTracking;
+use crate::rotator::Rotator;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+/// How often the app should call `Rotator::heartbeat` while tracking.
+pub const HEARTBEAT_INTERVAL_SECONDS: u64 = 60;
+
+/// A running session whose heartbeat is older than this at startup is
+/// assumed to have outlived a crash, reboot or quit.
+pub const ORPHAN_AFTER_SECONDS: u64 = 5 * HEARTBEAT_INTERVAL_SECONDS;
+
+/// A session found running at startup that stopped receiving heartbeats.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrphanedSession {
+    pub project_id: u64,
+    pub task_id: u64,
+    pub started_at: u64,
+    /// Last moment the app is known to have been running with this session.
+    pub last_heartbeat: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanResolution {
+    /// Record the session up to its last heartbeat and stop it.
+    CloseAtLastHeartbeat,
+    /// Treat the whole gap as work and keep the session running.
+    KeepRunning,
+    /// Drop the session without recording anything.
+    Discard,
+}
+
+pub(crate) fn load_orphaned_sessions(conn: &Connection, now: u64) -> rusqlite::Result<Vec<OrphanedSession>> {
+    let mut stmt = conn.prepare(
+        "SELECT project_id, task_id, started_at, COALESCE(last_heartbeat, started_at) FROM active_tracking
+         WHERE COALESCE(last_heartbeat, started_at) < ?"
+    )?;
+    let orphan_iter = stmt.query_map([now.saturating_sub(ORPHAN_AFTER_SECONDS)], |row| {
+        Ok(OrphanedSession {
+            project_id: row.get(0)?,
+            task_id: row.get(1)?,
+            started_at: row.get(2)?,
+            last_heartbeat: row.get(3)?,
+        })
+    })?;
+    orphan_iter.collect()
+}
+
+impl Rotator {
+    /// Mark every running session as alive now. Orphaned sessions are left
+    /// alone until the user decides what to do with them.
+    pub fn heartbeat(&mut self) -> Result<(), RotatorError> {
+        let now = self.clock.now();
+        for tracking in &self.active_tracking {
+            if self.orphaned.iter().any(|o| o.task_id == tracking.task_id) {
+                continue;
+            }
+            self.conn.execute(
+                "UPDATE active_tracking SET last_heartbeat = ? WHERE task_id = ?",
+                params![now, tracking.task_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sessions found stale at startup that still await a decision.
+    pub fn orphaned_sessions(&self) -> Vec<OrphanedSession> {
+        self.orphaned.iter()
+            .filter(|o| self.active_tracking.iter().any(|t| t.task_id == o.task_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn resolve_orphaned_session(&mut self, task_id: u64, resolution: OrphanResolution) -> Result<Vec<ActiveTracking>, RotatorError> {
+        let Some(orphan) = self.orphaned_sessions().into_iter().find(|o| o.task_id == task_id) else {
+            return Ok(self.active_tracking.clone());
+        };
+
+        match resolution {
+            OrphanResolution::CloseAtLastHeartbeat => {
+                let sessions: Vec<ActiveTracking> = self.active_tracking.iter()
+                    .filter(|t| t.task_id == task_id)
+                    .cloned()
+                    .collect();
+                // Left flagged if the close fails so the user can retry
+                self.close_sessions("resolve_orphaned_session", &sessions, orphan.last_heartbeat)?;
+            }
+            OrphanResolution::KeepRunning => {
+                self.orphaned.retain(|o| o.task_id != task_id);
+                self.heartbeat()?;
+            }
+            OrphanResolution::Discard => {
+                remove_active_tracking(&self.conn, task_id)?;
+                self.active_tracking.retain(|t| t.task_id != task_id);
+            }
+        }
+
+        self.orphaned.retain(|o| o.task_id != task_id);
+        self.audit("resolve_orphaned_session", Some((Entity::Task, task_id)), snapshot(&orphan), snapshot(&resolution))?;
+        Ok(self.active_tracking.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    struct TempDb(std::path::PathBuf);
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Track one task from 1_000, beat at `last_beat`, then reopen at `reopen_at`.
+    fn reopen_after(name: &str, last_beat: u64, reopen_at: u64) -> (Rotator, TempDb, u64) {
+        let db = TempDb(std::env::temp_dir().join(format!("rotator-core-{}-{}.db", name, std::process::id())));
+        let _ = std::fs::remove_file(&db.0);
+        let clock = ManualClock::new(1_000);
+
+        let mut rotator = Rotator::new(Connection::open(&db.0).unwrap(), clock.clone()).unwrap();
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.set(last_beat);
+        rotator.heartbeat().unwrap();
+        drop(rotator);
+
+        clock.set(reopen_at);
+        let reopened = Rotator::new(Connection::open(&db.0).unwrap(), clock).unwrap();
+        (reopened, db, task_id)
+    }
+
+    #[test]
+    fn fresh_heartbeat_is_not_orphaned() {
+        let (rotator, _db, _) = reopen_after("fresh", 2_000, 2_000 + ORPHAN_AFTER_SECONDS);
+        assert!(rotator.orphaned_sessions().is_empty());
+    }
+
+    #[test]
+    fn close_at_last_heartbeat_skips_the_gap() {
+        let (mut rotator, _db, task_id) = reopen_after("close", 2_000, 500_000);
+        assert_eq!(rotator.orphaned_sessions()[0].last_heartbeat, 2_000);
+
+        let tracking = rotator.resolve_orphaned_session(task_id, OrphanResolution::CloseAtLastHeartbeat).unwrap();
+        assert!(tracking.is_empty());
+        assert!(rotator.orphaned_sessions().is_empty());
+        let entries = rotator.all_time_entries().unwrap();
+        assert_eq!((entries[0].start_time, entries[0].end_time), (1_000, 2_000));
+    }
+
+    #[test]
+    fn keep_running_and_discard() {
+        let (mut rotator, _db, task_id) = reopen_after("keep", 2_000, 500_000);
+        rotator.resolve_orphaned_session(task_id, OrphanResolution::KeepRunning).unwrap();
+        assert!(rotator.orphaned_sessions().is_empty());
+        assert_eq!(rotator.active_tracking()[0].started_at, 1_000);
+        drop(rotator);
+
+        let (mut rotator, _db, task_id) = reopen_after("discard", 2_000, 500_000);
+        assert!(rotator.resolve_orphaned_session(task_id, OrphanResolution::Discard).unwrap().is_empty());
+        assert!(rotator.all_time_entries().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,447 @@
+use crate::audit::snapshot;
+use crate::db::{load_setting, save_setting};
+use crate::error::{Entity, RotatorError};
+use crate::rotator::Rotator;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Time entries older than this don't count towards shares and deficits.
+pub const ROTATION_WINDOW_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Something that can be rotated to: a project, or a task within one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub id: u64,
+    /// Index in the project or task list.
+    pub position: usize,
+    /// Configured share, relative to the other candidates.
+    pub weight: f64,
+    /// Target seconds over the rotation window, if one is set.
+    pub target_seconds: Option<u64>,
+    /// Seconds worked within the rotation window.
+    pub worked_seconds: u64,
+    pub last_worked_at: Option<u64>,
+    /// A budget for this period is used up; skipped while anything else is left.
+    pub over_budget: bool,
+}
+
+/// Small deterministic PRNG (SplitMix64) so random strategies can be
+/// replayed from a seed.
+#[derive(Clone, Debug)]
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Picks the next candidate. `candidates` never includes the current one
+/// unless it is the only choice; `current_position` is where rotation
+/// starts from.
+pub trait RotationStrategy {
+    fn pick(&self, candidates: &[Candidate], current_position: usize, rng: &mut SeededRng) -> Option<usize>;
+}
+
+/// The next candidate after the current one, wrapping around.
+pub struct RoundRobin;
+
+impl RotationStrategy for RoundRobin {
+    fn pick(&self, candidates: &[Candidate], current_position: usize, _rng: &mut SeededRng) -> Option<usize> {
+        candidates.iter()
+            .position(|c| c.position > current_position)
+            .or(if candidates.is_empty() { None } else { Some(0) })
+    }
+}
+
+/// The candidate furthest below its configured share of recent work time.
+pub struct WeightedShare;
+
+impl RotationStrategy for WeightedShare {
+    fn pick(&self, candidates: &[Candidate], current_position: usize, rng: &mut SeededRng) -> Option<usize> {
+        let total_weight: f64 = candidates.iter().map(|c| c.weight.max(0.0)).sum();
+        let total_worked: u64 = candidates.iter().map(|c| c.worked_seconds).sum();
+        if total_weight <= 0.0 {
+            return RoundRobin.pick(candidates, current_position, rng);
+        }
+        best_by(candidates, current_position, |c| {
+            let share = c.weight.max(0.0) / total_weight;
+            let worked = if total_worked == 0 { 0.0 } else { c.worked_seconds as f64 / total_worked as f64 };
+            share - worked
+        })
+    }
+}
+
+/// The candidate that was worked on longest ago; never-worked ones first.
+pub struct LeastRecentlyWorked;
+
+impl RotationStrategy for LeastRecentlyWorked {
+    fn pick(&self, candidates: &[Candidate], current_position: usize, _rng: &mut SeededRng) -> Option<usize> {
+        best_by(candidates, current_position, |c| -(c.last_worked_at.map_or(-1.0, |t| t as f64)))
+    }
+}
+
+/// The candidate with the most target time left to work. Candidates without
+/// a target only come up once every target is met.
+pub struct LargestDeficit;
+
+impl RotationStrategy for LargestDeficit {
+    fn pick(&self, candidates: &[Candidate], current_position: usize, _rng: &mut SeededRng) -> Option<usize> {
+        best_by(candidates, current_position, |c| match c.target_seconds {
+            Some(target) => target as f64 - c.worked_seconds as f64,
+            None => 0.0,
+        })
+    }
+}
+
+/// A random candidate, with odds proportional to its weight.
+pub struct WeightedRandom;
+
+impl RotationStrategy for WeightedRandom {
+    fn pick(&self, candidates: &[Candidate], current_position: usize, rng: &mut SeededRng) -> Option<usize> {
+        let total: f64 = candidates.iter().map(|c| c.weight.max(0.0)).sum();
+        if total <= 0.0 {
+            return RoundRobin.pick(candidates, current_position, rng);
+        }
+        let mut roll = rng.next_f64() * total;
+        for (i, c) in candidates.iter().enumerate() {
+            roll -= c.weight.max(0.0);
+            if roll < 0.0 {
+                return Some(i);
+            }
+        }
+        candidates.iter().rposition(|c| c.weight > 0.0)
+    }
+}
+
+/// Highest score wins; ties go to the first candidate after the current one
+/// so equal scores still rotate.
+fn best_by(candidates: &[Candidate], current_position: usize, score: impl Fn(&Candidate) -> f64) -> Option<usize> {
+    let order = |c: &Candidate| (c.position <= current_position, c.position);
+    candidates.iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            score(a).total_cmp(&score(b)).then_with(|| order(b).cmp(&order(a)))
+        })
+        .map(|(i, _)| i)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationStrategyKind {
+    #[default]
+    RoundRobin,
+    WeightedShare,
+    LeastRecentlyWorked,
+    LargestDeficit,
+    WeightedRandom,
+}
+
+impl RotationStrategyKind {
+    pub fn strategy(self) -> Box<dyn RotationStrategy> {
+        match self {
+            RotationStrategyKind::RoundRobin => Box::new(RoundRobin),
+            RotationStrategyKind::WeightedShare => Box::new(WeightedShare),
+            RotationStrategyKind::LeastRecentlyWorked => Box::new(LeastRecentlyWorked),
+            RotationStrategyKind::LargestDeficit => Box::new(LargestDeficit),
+            RotationStrategyKind::WeightedRandom => Box::new(WeightedRandom),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            RotationStrategyKind::RoundRobin => "round_robin",
+            RotationStrategyKind::WeightedShare => "weighted_share",
+            RotationStrategyKind::LeastRecentlyWorked => "least_recently_worked",
+            RotationStrategyKind::LargestDeficit => "largest_deficit",
+            RotationStrategyKind::WeightedRandom => "weighted_random",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "round_robin" => Some(RotationStrategyKind::RoundRobin),
+            "weighted_share" => Some(RotationStrategyKind::WeightedShare),
+            "least_recently_worked" => Some(RotationStrategyKind::LeastRecentlyWorked),
+            "largest_deficit" => Some(RotationStrategyKind::LargestDeficit),
+            "weighted_random" => Some(RotationStrategyKind::WeightedRandom),
+            _ => None,
+        }
+    }
+}
+
+/// Pick from `candidates` with `kind`, leaving out the current one and any
+/// over budget while there is anything else to rotate to. Returns the chosen
+/// `position`.
+pub(crate) fn choose(
+    kind: RotationStrategyKind,
+    candidates: &[Candidate],
+    current_position: usize,
+    rng: &mut SeededRng,
+) -> Option<usize> {
+    let others: Vec<Candidate> = candidates.iter()
+        .filter(|c| c.position != current_position)
+        .cloned()
+        .collect();
+    let in_budget: Vec<Candidate> = others.iter().filter(|c| !c.over_budget).cloned().collect();
+    let pool = if !in_budget.is_empty() {
+        &in_budget[..]
+    } else if !others.is_empty() {
+        &others[..]
+    } else {
+        candidates
+    };
+    kind.strategy().pick(pool, current_position, rng).map(|i| pool[i].position)
+}
+
+/// Candidates for `items` (position, id) from `table` ("projects" or
+/// "tasks"), with their weights, targets and recent history. `history_column`
+/// is the matching time_entries column.
+pub(crate) fn load_candidates(
+    conn: &Connection,
+    table: &str,
+    history_column: &str,
+    since: u64,
+    items: impl Iterator<Item = (usize, u64)>,
+) -> rusqlite::Result<Vec<Candidate>> {
+    let mut stats = HashMap::new();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.id, t.rotation_weight, t.target_seconds,
+            (SELECT COALESCE(SUM(duration_seconds), 0) FROM time_entries e WHERE e.{col} = t.id AND e.end_time > ?),
+            (SELECT MAX(end_time) FROM time_entries e WHERE e.{col} = t.id)
+         FROM {table} t",
+        col = history_column,
+        table = table,
+    ))?;
+    let rows = stmt.query_map([since], |row| {
+        Ok((row.get::<_, u64>(0)?, (row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+    })?;
+    for row in rows {
+        let (id, values) = row?;
+        stats.insert(id, values);
+    }
+
+    Ok(items.map(|(position, id)| {
+        let (weight, target_seconds, worked_seconds, last_worked_at) =
+            stats.get(&id).copied().unwrap_or((1.0, None, 0, None));
+        Candidate { id, position, weight, target_seconds, worked_seconds, last_worked_at, over_budget: false }
+    }).collect())
+}
+
+pub(crate) fn load_project_strategy(conn: &Connection) -> rusqlite::Result<RotationStrategyKind> {
+    Ok(load_setting(conn, "project_rotation_strategy")?
+        .and_then(|v| RotationStrategyKind::parse(&v))
+        .unwrap_or_default())
+}
+
+pub(crate) fn load_task_strategy(conn: &Connection, project_id: u64) -> rusqlite::Result<RotationStrategyKind> {
+    Ok(conn.query_row(
+        "SELECT task_rotation_strategy FROM projects WHERE id = ?",
+        [project_id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()?
+    .flatten()
+    .and_then(|v| RotationStrategyKind::parse(&v))
+    .unwrap_or_default())
+}
+
+fn check_weight(weight: f64) -> Result<(), RotatorError> {
+    if !weight.is_finite() || weight < 0.0 {
+        return Err(RotatorError::invalid("rotation weight must be a non-negative number"));
+    }
+    Ok(())
+}
+
+impl Rotator {
+    /// Seed the generator used by `WeightedRandom`, for reproducible runs.
+    pub fn set_rotation_seed(&mut self, seed: u64) {
+        self.rng = SeededRng::new(seed);
+    }
+
+    pub fn project_rotation_strategy(&self) -> Result<RotationStrategyKind, RotatorError> {
+        Ok(load_project_strategy(&self.conn)?)
+    }
+
+    pub fn set_project_rotation_strategy(&mut self, kind: RotationStrategyKind) -> Result<RotationStrategyKind, RotatorError> {
+        let before = load_project_strategy(&self.conn)?;
+        save_setting(&self.conn, "project_rotation_strategy", kind.as_str())?;
+        self.audit("set_project_rotation_strategy", None, snapshot(&before), snapshot(&kind))?;
+        Ok(kind)
+    }
+
+    pub fn task_rotation_strategy(&self, project_id: u64) -> Result<RotationStrategyKind, RotatorError> {
+        Ok(load_task_strategy(&self.conn, project_id)?)
+    }
+
+    pub fn set_task_rotation_strategy(&mut self, project_id: u64, kind: RotationStrategyKind) -> Result<RotationStrategyKind, RotatorError> {
+        let before = load_task_strategy(&self.conn, project_id)?;
+        let updated = self.conn.execute(
+            "UPDATE projects SET task_rotation_strategy = ? WHERE id = ?",
+            params![kind.as_str(), project_id],
+        )?;
+        if updated == 0 {
+            return Err(RotatorError::NotFound(Entity::Project, project_id));
+        }
+        self.audit("set_task_rotation_strategy", Some((Entity::Project, project_id)), snapshot(&before), snapshot(&kind))?;
+        Ok(kind)
+    }
+
+    /// Set a project's share and optional target for weighted strategies.
+    pub fn set_project_rotation_weight(&mut self, project_id: u64, weight: f64, target_seconds: Option<u64>) -> Result<(), RotatorError> {
+        check_weight(weight)?;
+        let updated = self.conn.execute(
+            "UPDATE projects SET rotation_weight = ?, target_seconds = ? WHERE id = ?",
+            params![weight, target_seconds, project_id],
+        )?;
+        if updated == 0 {
+            return Err(RotatorError::NotFound(Entity::Project, project_id));
+        }
+        let after = json!({ "weight": weight, "target_seconds": target_seconds });
+        self.audit("set_project_rotation_weight", Some((Entity::Project, project_id)), Value::Null, after)?;
+        Ok(())
+    }
+
+    pub fn set_task_rotation_weight(&mut self, task_id: u64, weight: f64, target_seconds: Option<u64>) -> Result<(), RotatorError> {
+        check_weight(weight)?;
+        let updated = self.conn.execute(
+            "UPDATE tasks SET rotation_weight = ?, target_seconds = ? WHERE id = ?",
+            params![weight, target_seconds, task_id],
+        )?;
+        if updated == 0 {
+            return Err(RotatorError::NotFound(Entity::Task, task_id));
+        }
+        let after = json!({ "weight": weight, "target_seconds": target_seconds });
+        self.audit("set_task_rotation_weight", Some((Entity::Task, task_id)), Value::Null, after)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RotationTrigger;
+    use crate::test_support::rotator_at;
+
+    fn candidate(position: usize, weight: f64, worked_seconds: u64, last_worked_at: Option<u64>) -> Candidate {
+        Candidate {
+            id: position as u64 + 1,
+            position,
+            weight,
+            target_seconds: None,
+            worked_seconds,
+            last_worked_at,
+            over_budget: false,
+        }
+    }
+
+    #[test]
+    fn round_robin_wraps_past_gaps() {
+        let candidates = [candidate(0, 1.0, 0, None), candidate(2, 1.0, 0, None)];
+        let mut rng = SeededRng::new(1);
+        assert_eq!(RoundRobin.pick(&candidates, 1, &mut rng), Some(1));
+        assert_eq!(RoundRobin.pick(&candidates, 2, &mut rng), Some(0));
+    }
+
+    #[test]
+    fn weighted_share_favours_the_most_underworked() {
+        // 80/20 split, but the big client only got half the time so far
+        let candidates = [candidate(0, 4.0, 3_600, None), candidate(1, 1.0, 3_600, None)];
+        assert_eq!(WeightedShare.pick(&candidates, 5, &mut SeededRng::new(1)), Some(0));
+    }
+
+    #[test]
+    fn least_recently_worked_prefers_never_worked() {
+        let candidates = [candidate(0, 1.0, 0, Some(500)), candidate(1, 1.0, 0, None), candidate(2, 1.0, 0, Some(100))];
+        assert_eq!(LeastRecentlyWorked.pick(&candidates, 0, &mut SeededRng::new(1)), Some(1));
+    }
+
+    #[test]
+    fn largest_deficit_uses_targets() {
+        let mut a = candidate(0, 1.0, 3_000, None);
+        a.target_seconds = Some(3_600);
+        let mut b = candidate(1, 1.0, 0, None);
+        b.target_seconds = Some(1_800);
+        assert_eq!(LargestDeficit.pick(&[a, b], 0, &mut SeededRng::new(1)), Some(1));
+    }
+
+    #[test]
+    fn weighted_random_is_reproducible_and_follows_weights() {
+        let candidates = [candidate(0, 9.0, 0, None), candidate(1, 1.0, 0, None)];
+        let run = |seed| {
+            let mut rng = SeededRng::new(seed);
+            (0..1_000).map(|_| WeightedRandom.pick(&candidates, 5, &mut rng).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(run(42), run(42));
+        let heavy = run(42).iter().filter(|&&i| i == 0).count();
+        assert!((850..950).contains(&heavy), "picked heavy {} times", heavy);
+    }
+
+    #[test]
+    fn choose_passes_over_exhausted_budgets_unless_nothing_else_is_left() {
+        let mut candidates = [candidate(0, 1.0, 0, None), candidate(1, 1.0, 0, None), candidate(2, 1.0, 0, None)];
+        candidates[1].over_budget = true;
+        let mut rng = SeededRng::new(1);
+        assert_eq!(choose(RotationStrategyKind::RoundRobin, &candidates, 0, &mut rng), Some(2));
+        candidates[2].over_budget = true;
+        assert_eq!(choose(RotationStrategyKind::RoundRobin, &candidates, 0, &mut rng), Some(1));
+    }
+
+    #[test]
+    fn rotate_project_uses_the_configured_strategy_and_history() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let projects = ["Client", "Side", "Admin"].map(|name| rotator.add_project(name.into()).unwrap().last().unwrap().id);
+        let tasks: Vec<u64> = projects.iter()
+            .map(|&p| rotator.add_task(p, "T".into()).unwrap().tasks[0].id)
+            .collect();
+        rotator.add_time_entry_manual(projects[1], tasks[1], 90_000, 600).unwrap();
+        rotator.add_time_entry_manual(projects[2], tasks[2], 95_000, 600).unwrap();
+
+        // Plain round-robin from Client goes to Side
+        assert_eq!(rotator.rotate_project(RotationTrigger::Hotkey).unwrap().0, 1);
+
+        // Least recently worked from Side: Client was never worked
+        rotator.set_project_rotation_strategy(RotationStrategyKind::LeastRecentlyWorked).unwrap();
+        assert_eq!(rotator.rotate_project(RotationTrigger::Hotkey).unwrap().0, 0);
+
+        rotator.set_project_rotation_strategy(RotationStrategyKind::LargestDeficit).unwrap();
+        rotator.set_project_rotation_weight(projects[2], 1.0, Some(3_600)).unwrap();
+        assert_eq!(rotator.rotate_project(RotationTrigger::Hotkey).unwrap().0, 2);
+    }
+
+    #[test]
+    fn rotate_task_strategy_is_per_project_and_skips_done_tasks() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let home = rotator.add_project("Home".into()).unwrap()[1].id;
+        for name in ["A", "B", "C", "D"] {
+            rotator.add_task(work, name.into()).unwrap();
+        }
+        let ids: Vec<u64> = rotator.projects()[0].tasks.iter().map(|t| t.id).collect();
+        rotator.add_time_entry_manual(work, ids[0], 80_000, 600).unwrap();
+        rotator.add_time_entry_manual(work, ids[2], 90_000, 600).unwrap();
+        rotator.toggle_task_done(work, ids[1], true).unwrap();
+        rotator.set_task_rotation_strategy(work, RotationStrategyKind::WeightedShare).unwrap();
+        assert_eq!(rotator.task_rotation_strategy(home).unwrap(), RotationStrategyKind::RoundRobin);
+
+        // A and C already had their share, so D is next and B is done
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().id, ids[3]);
+        rotator.set_task_rotation_weight(ids[2], 4.0, None).unwrap();
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().id, ids[2]);
+    }
+}
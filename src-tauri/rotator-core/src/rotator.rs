@@ -0,0 +1,1073 @@
+use crate::archive::{project_snapshot, task_snapshot};
+use crate::audit::{field, snapshot, AuditSource};
+use crate::backup::{backup_database, BackupReason};
+use crate::clock::{Clock, SystemClock};
+use crate::db::{
+    add_active_tracking, get_next_id, is_archived, load_active_tracking, load_current_project, load_projects,
+    load_tasks, next_project_position, next_task_position, remove_active_tracking, resolve_current,
+    save_current_project, save_current_task, DATA_TABLES,
+};
+use crate::entries::{find_overlap, load_entry};
+use crate::error::{Conflict, Entity, RotatorError};
+use crate::history::RotationTrigger;
+use crate::idle::{IdlePeriod, IdleSettings};
+use crate::journal::{load_entry_record, load_session_record, Change, EntryEdit};
+use crate::migrations::{self, MigrationError};
+use crate::models::{ActiveTracking, ClosedSession, Project, Task, TimeEntry};
+use crate::recovery::{load_orphaned_sessions, OrphanedSession};
+use crate::rotation::{self, SeededRng, ROTATION_WINDOW_SECONDS};
+use crate::session::close_sessions_in;
+use crate::timebox::Timebox;
+use crate::totals::refresh_task_total;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Projects, tasks and time tracking over a single SQLite connection.
+///
+/// Non-archived projects and their visible tasks are cached in memory; every
+/// mutation writes through to the database and only touches the cache once
+/// the write succeeded.
+pub struct Rotator {
+    pub(crate) conn: Connection,
+    pub(crate) clock: Box<dyn Clock>,
+    pub(crate) projects: Vec<Project>,
+    /// `None` only while there are no projects.
+    pub(crate) current_project_id: Option<u64>,
+    pub(crate) next_project_id: u64,
+    pub(crate) next_task_id: u64,
+    pub(crate) active_tracking: Vec<ActiveTracking>,
+    pub(crate) idle_settings: IdleSettings,
+    pub(crate) pending_idle: Option<IdlePeriod>,
+    pub(crate) orphaned: Vec<OrphanedSession>,
+    pub(crate) timebox: Option<Timebox>,
+    pub(crate) rng: SeededRng,
+    /// Set while undo or redo replays a change, which must not be journaled again.
+    pub(crate) replaying: bool,
+    /// Recorded in the audit log with every change.
+    pub(crate) source: AuditSource,
+}
+
+impl Rotator {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MigrationError> {
+        let conn = Connection::open(path)?;
+        Self::new(conn, SystemClock)
+    }
+
+    pub fn open_in_memory() -> Result<Self, MigrationError> {
+        Self::new(Connection::open_in_memory()?, SystemClock)
+    }
+
+    /// Migrate `conn` to the latest schema and load the cached state from it.
+    /// An existing database is backed up before it is migrated.
+    pub fn new(mut conn: Connection, clock: impl Clock + 'static) -> Result<Self, MigrationError> {
+        let now = clock.now();
+        if migrations::has_data_to_migrate(&conn)? {
+            backup_database(&conn, now, BackupReason::Migration).map_err(|e| MigrationError::Backup(Box::new(e)))?;
+        }
+        migrations::migrate(&mut conn)?;
+
+        let projects = load_projects(&conn, now)?;
+        let current_project_id = load_current_project(&conn, &projects)?;
+        let next_project_id = get_next_id(&conn, "projects")?;
+        let next_task_id = get_next_id(&conn, "tasks")?;
+        let active_tracking = load_active_tracking(&conn)?;
+        let idle_settings = IdleSettings::load(&conn)?;
+        let orphaned = load_orphaned_sessions(&conn, now)?;
+
+        Ok(Self {
+            conn,
+            clock: Box::new(clock),
+            projects,
+            current_project_id,
+            next_project_id,
+            next_task_id,
+            active_tracking,
+            idle_settings,
+            pending_idle: None,
+            orphaned,
+            timebox: None,
+            rng: SeededRng::new(now),
+            replaying: false,
+            source: AuditSource::Cli,
+        })
+    }
+
+    /// Throw the in-memory cache away and load it again from the database,
+    /// e.g. after a command panicked halfway through an update.
+    pub fn reload(&mut self) -> Result<(), RotatorError> {
+        self.reload_projects()?;
+        self.next_project_id = get_next_id(&self.conn, "projects")?;
+        self.next_task_id = get_next_id(&self.conn, "tasks")?;
+        self.active_tracking = load_active_tracking(&self.conn)?;
+        self.idle_settings = IdleSettings::load(&self.conn)?;
+        Ok(())
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    pub fn projects(&self) -> Vec<Project> {
+        self.projects.clone()
+    }
+
+    /// Where the current project sits in `projects()`, 0 if there is none.
+    pub fn current_project_index(&self) -> usize {
+        self.current_project_id
+            .and_then(|id| self.projects.iter().position(|p| p.id == id))
+            .unwrap_or(0)
+    }
+
+    pub fn current_project_id(&self) -> Option<u64> {
+        self.current_project_id
+    }
+
+    pub fn current_project(&self) -> Option<Project> {
+        self.current_project_ref().cloned()
+    }
+
+    pub(crate) fn current_project_ref(&self) -> Option<&Project> {
+        let id = self.current_project_id?;
+        self.projects.iter().find(|p| p.id == id)
+    }
+
+    /// A non-archived project from the cache.
+    pub(crate) fn project_mut(&mut self, project_id: u64) -> Result<&mut Project, RotatorError> {
+        self.projects.iter_mut()
+            .find(|p| p.id == project_id)
+            .ok_or(RotatorError::NotFound(Entity::Project, project_id))
+    }
+
+    pub fn active_tracking(&self) -> Vec<ActiveTracking> {
+        self.active_tracking.clone()
+    }
+
+    pub fn add_project(&mut self, name: String) -> Result<Vec<Project>, RotatorError> {
+        let project = Project {
+            id: self.next_project_id,
+            name: name.clone(),
+            tasks: Vec::new(),
+            current_task_id: None,
+        };
+        let becomes_current = self.current_project_id.is_none();
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO projects (id, name, position) VALUES (?, ?, ?)",
+            params![project.id, name, next_project_position(&tx)?],
+        )?;
+        if becomes_current {
+            save_current_project(&tx, Some(project.id))?;
+        }
+        audit.record(&tx, "add_project", Some((Entity::Project, project.id)), Value::Null, field("name", &name))?;
+        tx.commit()?;
+
+        if becomes_current {
+            self.current_project_id = Some(project.id);
+        }
+        let project_id = project.id;
+        self.projects.push(project);
+        self.next_project_id += 1;
+
+        self.record(Change::ProjectAdded { project_id })?;
+        Ok(self.projects.clone())
+    }
+
+    pub fn remove_project(&mut self, project_id: u64) -> Result<Vec<Project>, RotatorError> {
+        let pos = self.projects.iter()
+            .position(|p| p.id == project_id)
+            .ok_or(RotatorError::NotFound(Entity::Project, project_id))?;
+        let now = self.clock.now();
+        let tracked: Vec<u64> = self.active_tracking.iter()
+            .filter(|t| t.project_id == project_id)
+            .map(|t| t.task_id)
+            .collect();
+        // Archiving the current project moves on to the one after it
+        let remaining: Vec<u64> = self.projects.iter().map(|p| p.id).filter(|&id| id != project_id).collect();
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        // Drop this project's running sessions without recording them
+        for &task_id in &tracked {
+            remove_active_tracking(&tx, task_id)?;
+        }
+        // Archive instead of delete - set archived_at to current timestamp.
+        // Tasks archived earlier keep their own timestamp
+        let archived = {
+            let mut stmt = tx.prepare("SELECT id FROM tasks WHERE project_id = ? AND archived_at IS NULL ORDER BY id")?;
+            let ids = stmt.query_map([project_id], |row| row.get(0))?;
+            ids.collect::<rusqlite::Result<Vec<u64>>>()?
+        };
+        tx.execute(
+            "UPDATE tasks SET archived_at = ? WHERE project_id = ? AND archived_at IS NULL",
+            params![now, project_id],
+        )?;
+        tx.execute("UPDATE projects SET archived_at = ? WHERE id = ?", params![now, project_id])?;
+        let current = resolve_current(&tx, "projects", self.current_project_id, &remaining)?;
+        save_current_project(&tx, current)?;
+        audit.record(&tx, "remove_project", Some((Entity::Project, project_id)), Value::Null, field("archived_at", &now))?;
+        tx.commit()?;
+
+        self.active_tracking.retain(|t| t.project_id != project_id);
+        self.projects.remove(pos);
+        self.current_project_id = current;
+        self.record(Change::ProjectRemoved { project_id, tasks: Some(archived) })?;
+        Ok(self.projects.clone())
+    }
+
+    pub fn rename_project(&mut self, project_id: u64, new_name: String) -> Result<Vec<Project>, RotatorError> {
+        let from = self.project_mut(project_id)?.name.clone();
+        self.conn.execute(
+            "UPDATE projects SET name = ? WHERE id = ?",
+            params![new_name, project_id],
+        )?;
+        self.project_mut(project_id)?.name = new_name.clone();
+
+        self.audit("rename_project", Some((Entity::Project, project_id)), field("name", &from), field("name", &new_name))?;
+        self.record(Change::ProjectRenamed { project_id, from, to: new_name })?;
+        Ok(self.projects.clone())
+    }
+
+    pub fn rotate_project(&mut self, trigger: RotationTrigger) -> Result<(usize, Option<Project>), RotatorError> {
+        if self.projects.is_empty() {
+            return Ok((0, None));
+        }
+        let from = self.current_position();
+
+        let kind = rotation::load_project_strategy(&self.conn)?;
+        let since = self.clock.now().saturating_sub(ROTATION_WINDOW_SECONDS);
+        let (exhausted, _) = self.exhausted_budgets()?;
+        let mut candidates = rotation::load_candidates(
+            &self.conn,
+            "projects",
+            "project_id",
+            since,
+            self.projects.iter().map(|p| p.id).enumerate(),
+        )?;
+
+        for candidate in &mut candidates {
+            candidate.over_budget = exhausted.contains(&candidate.id);
+        }
+
+        let current = self.current_project_index();
+        let next = rotation::choose(kind, &candidates, current, &mut self.rng)
+            .unwrap_or((current + 1) % self.projects.len());
+        let next_id = self.projects[next].id;
+        save_current_project(&self.conn, Some(next_id))?;
+        self.current_project_id = Some(next_id);
+        self.record_rotation(from, trigger)?;
+        Ok((next, Some(self.projects[next].clone())))
+    }
+
+    pub fn set_current_project(&mut self, index: usize) -> Result<usize, RotatorError> {
+        let project_id = self.projects.get(index)
+            .map(|p| p.id)
+            .ok_or_else(|| RotatorError::invalid(format!("no project at position {}", index)))?;
+        self.select_project(project_id)?;
+        Ok(self.current_project_index())
+    }
+
+    /// Make `project_id` the current project.
+    pub fn select_project(&mut self, project_id: u64) -> Result<Project, RotatorError> {
+        let project = self.project_mut(project_id)?.clone();
+        let from = self.current_position();
+        save_current_project(&self.conn, Some(project_id))?;
+        self.current_project_id = Some(project_id);
+        self.record_rotation(from, RotationTrigger::Manual)?;
+        Ok(project)
+    }
+
+    /// Move the current project on to its next task. `None` if there is no
+    /// project or all of its tasks are done.
+    pub fn rotate_task(&mut self, trigger: RotationTrigger) -> Result<Option<Task>, RotatorError> {
+        let Some(project_id) = self.current_project_id else {
+            return Ok(None);
+        };
+        let from = self.current_position();
+
+        let kind = rotation::load_task_strategy(&self.conn, project_id)?;
+        let since = self.clock.now().saturating_sub(ROTATION_WINDOW_SECONDS);
+        let (_, exhausted) = self.exhausted_budgets()?;
+
+        let project = self.project_mut(project_id)?.clone();
+        let current = project.tasks.iter().position(|t| Some(t.id) == project.current_task_id).unwrap_or(0);
+        // Done tasks are never rotated to
+        let mut candidates = rotation::load_candidates(
+            &self.conn,
+            "tasks",
+            "task_id",
+            since,
+            project.tasks.iter().enumerate().filter(|(_, t)| t.done_at.is_none()).map(|(i, t)| (i, t.id)),
+        )?;
+
+        for candidate in &mut candidates {
+            candidate.over_budget = exhausted.contains(&candidate.id);
+        }
+
+        let Some(next) = rotation::choose(kind, &candidates, current, &mut self.rng) else {
+            return Ok(None);
+        };
+        let task = project.tasks[next].clone();
+        save_current_task(&self.conn, project_id, Some(task.id))?;
+        self.project_mut(project_id)?.current_task_id = Some(task.id);
+        self.record_rotation(from, trigger)?;
+        Ok(Some(task))
+    }
+
+    pub fn add_task(&mut self, project_id: u64, name: String) -> Result<Project, RotatorError> {
+        let becomes_current = self.project_mut(project_id)?.current_task_id.is_none();
+        let task = Task {
+            id: self.next_task_id,
+            name: name.clone(),
+            time_seconds: 0,
+            done_at: None,
+        };
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO tasks (id, project_id, name, time_seconds, done_at, position) VALUES (?, ?, ?, 0, NULL, ?)",
+            params![task.id, project_id, name, next_task_position(&tx, project_id)?],
+        )?;
+        if becomes_current {
+            save_current_task(&tx, project_id, Some(task.id))?;
+        }
+        let after = json!({ "project_id": project_id, "name": name });
+        audit.record(&tx, "add_task", Some((Entity::Task, task.id)), Value::Null, after)?;
+        tx.commit()?;
+
+        self.next_task_id += 1;
+        let task_id = task.id;
+        let project = self.project_mut(project_id)?;
+        if becomes_current {
+            project.current_task_id = Some(task.id);
+        }
+        project.tasks.push(task);
+        let project = project.clone();
+
+        self.record(Change::TaskAdded { project_id, task_id })?;
+        Ok(project)
+    }
+
+    pub fn remove_task(&mut self, project_id: u64, task_id: u64) -> Result<Project, RotatorError> {
+        let project = self.project_mut(project_id)?;
+        let pos = project.tasks.iter()
+            .position(|t| t.id == task_id)
+            .ok_or(RotatorError::NotFound(Entity::Task, task_id))?;
+        // Archiving the current task moves on to the one after it
+        let remaining: Vec<u64> = project.tasks.iter().map(|t| t.id).filter(|&id| id != task_id).collect();
+        let current_task_id = project.current_task_id;
+        let now = self.clock.now();
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        // Drop the running session without recording it
+        remove_active_tracking(&tx, task_id)?;
+        // Archive instead of delete - set archived_at to current timestamp
+        tx.execute("UPDATE tasks SET archived_at = ? WHERE id = ?", params![now, task_id])?;
+        let current = resolve_current(&tx, "tasks", current_task_id, &remaining)?;
+        save_current_task(&tx, project_id, current)?;
+        audit.record(&tx, "remove_task", Some((Entity::Task, task_id)), Value::Null, field("archived_at", &now))?;
+        tx.commit()?;
+
+        self.active_tracking.retain(|t| t.task_id != task_id);
+        let project = self.project_mut(project_id)?;
+        project.tasks.remove(pos);
+        project.current_task_id = current;
+        let project = project.clone();
+
+        self.record(Change::TaskRemoved { project_id, task_id })?;
+        Ok(project)
+    }
+
+    /// A visible task of a non-archived project from the cache.
+    fn task_mut(&mut self, project_id: u64, task_id: u64) -> Result<&mut Task, RotatorError> {
+        self.project_mut(project_id)?
+            .tasks
+            .iter_mut()
+            .find(|t| t.id == task_id)
+            .ok_or(RotatorError::NotFound(Entity::Task, task_id))
+    }
+
+    pub fn rename_task(&mut self, project_id: u64, task_id: u64, new_name: String) -> Result<Project, RotatorError> {
+        let from = self.task_mut(project_id, task_id)?.name.clone();
+        self.conn.execute(
+            "UPDATE tasks SET name = ? WHERE id = ?",
+            params![new_name, task_id],
+        )?;
+        self.task_mut(project_id, task_id)?.name = new_name.clone();
+
+        self.audit("rename_task", Some((Entity::Task, task_id)), field("name", &from), field("name", &new_name))?;
+        self.record(Change::TaskRenamed { project_id, task_id, from, to: new_name })?;
+        Ok(self.project_mut(project_id)?.clone())
+    }
+
+    pub fn toggle_task_done(&mut self, project_id: u64, task_id: u64, done: bool) -> Result<Project, RotatorError> {
+        self.task_mut(project_id, task_id)?;
+        let done_at = if done { Some(self.clock.now()) } else { None };
+        self.set_task_done_at(project_id, task_id, done_at)
+    }
+
+    /// Works on tasks hidden for having been done a while ago too, so undo
+    /// can bring them back.
+    pub(crate) fn set_task_done_at(&mut self, project_id: u64, task_id: u64, done_at: Option<u64>) -> Result<Project, RotatorError> {
+        self.project_mut(project_id)?;
+        let from: Option<u64> = self.conn
+            .query_row(
+                "SELECT done_at FROM tasks WHERE id = ? AND project_id = ? AND archived_at IS NULL",
+                params![task_id, project_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(RotatorError::NotFound(Entity::Task, task_id))?;
+
+        self.conn.execute(
+            "UPDATE tasks SET done_at = ? WHERE id = ?",
+            params![done_at, task_id],
+        )?;
+        let tasks = load_tasks(&self.conn, project_id, self.clock.now())?;
+        let project = self.project_mut(project_id)?;
+        project.tasks = tasks;
+        let project = project.clone();
+
+        self.audit("toggle_task_done", Some((Entity::Task, task_id)), field("done_at", &from), field("done_at", &done_at))?;
+        self.record(Change::TaskDone { project_id, task_id, from, to: done_at })?;
+        Ok(project)
+    }
+
+    pub fn start_tracking(&mut self, project_id: u64, task_id: u64, allow_multiple: bool) -> Result<Vec<ActiveTracking>, RotatorError> {
+        // Check if this task is already being tracked; starting a paused task resumes it
+        if let Some(tracking) = self.active_tracking.iter().find(|t| t.task_id == task_id) {
+            if tracking.paused_at.is_some() {
+                return self.resume_tracking(Some(task_id));
+            }
+            return Ok(self.active_tracking.clone());
+        }
+        self.task_mut(project_id, task_id)?;
+
+        // If not allowing multiple and there are existing trackings, stop them first
+        if !allow_multiple && !self.active_tracking.is_empty() {
+            let running = self.active_tracking.clone();
+            self.close_sessions("start_tracking", &running, self.clock.now())?;
+        }
+
+        let new_tracking = ActiveTracking {
+            project_id,
+            task_id,
+            started_at: self.clock.now(),
+            paused_at: None,
+            paused_seconds: 0,
+        };
+        add_active_tracking(&self.conn, &new_tracking)?;
+        self.audit("start_tracking", Some((Entity::Task, task_id)), Value::Null, snapshot(&new_tracking))?;
+        self.active_tracking.push(new_tracking);
+        self.mark_rotation_followed(task_id)?;
+
+        Ok(self.active_tracking.clone())
+    }
+
+    /// Stop tracking `task_id`, or every running task when `None`.
+    pub fn stop_tracking(&mut self, task_id: Option<u64>) -> Result<Vec<ClosedSession>, RotatorError> {
+        let sessions: Vec<ActiveTracking> = self.active_tracking.iter()
+            .filter(|t| task_id.is_none_or(|id| t.task_id == id))
+            .cloned()
+            .collect();
+        let now = self.clock.now();
+        let records = sessions.iter()
+            .map(|s| load_session_record(&self.conn, s))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let closed = self.close_sessions("stop_tracking", &sessions, now)?;
+
+        if !closed.is_empty() {
+            let entries = closed.iter()
+                .filter_map(|c| c.entry_id)
+                .map(|id| load_entry_record(&self.conn, id))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.record(Change::TrackingStopped { sessions: records, entries })?;
+        }
+        Ok(closed)
+    }
+
+    /// Close `sessions` at `ended_at`, logging what they recorded under
+    /// `command`.
+    pub(crate) fn close_sessions(&mut self, command: &str, sessions: &[ActiveTracking], ended_at: u64) -> Result<Vec<ClosedSession>, RotatorError> {
+        if sessions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // On failure the transaction is rolled back, so the sessions keep running
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        let closed = close_sessions_in(&tx, sessions, ended_at)?;
+        audit.closed(&tx, command, &closed)?;
+        tx.commit()?;
+
+        let recorded: Vec<u64> = closed.iter().filter(|c| !c.discarded).map(|c| c.task_id).collect();
+        self.active_tracking.retain(|t| !closed.iter().any(|c| c.task_id == t.task_id));
+        self.orphaned.retain(|o| !closed.iter().any(|c| c.task_id == o.task_id));
+        self.sync_task_totals(&recorded)?;
+
+        Ok(closed)
+    }
+
+    pub fn restore_project(&mut self, project_id: u64) -> Result<Vec<Project>, RotatorError> {
+        self.unarchive_project(project_id, None)
+    }
+
+    /// Restore the project with `tasks`, or with all of its tasks when `None`.
+    pub(crate) fn unarchive_project(
+        &mut self,
+        project_id: u64,
+        tasks: Option<&[u64]>,
+    ) -> Result<Vec<Project>, RotatorError> {
+        // Restore project and its tasks - set archived_at to NULL
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        if tx.execute("UPDATE projects SET archived_at = NULL WHERE id = ?", [project_id])? == 0 {
+            return Err(RotatorError::NotFound(Entity::Project, project_id));
+        }
+        match tasks {
+            Some(tasks) => {
+                for &task_id in tasks {
+                    tx.execute(
+                        "UPDATE tasks SET archived_at = NULL WHERE id = ? AND project_id = ?",
+                        params![task_id, project_id],
+                    )?;
+                }
+            }
+            None => {
+                tx.execute("UPDATE tasks SET archived_at = NULL WHERE project_id = ?", [project_id])?;
+            }
+        }
+        audit.record(&tx, "restore_project", Some((Entity::Project, project_id)), Value::Null, field("archived_at", &None::<u64>))?;
+        tx.commit()?;
+
+        // Reload so the project comes back at its old position
+        self.reload_projects()?;
+        Ok(self.projects.clone())
+    }
+
+    pub fn restore_task(&mut self, project_id: u64, task_id: u64) -> Result<Project, RotatorError> {
+        self.project_mut(project_id)?;
+        // Restore the task - set archived_at to NULL
+        let restored = self.conn.execute(
+            "UPDATE tasks SET archived_at = NULL WHERE id = ? AND project_id = ?",
+            params![task_id, project_id],
+        )?;
+        if restored == 0 {
+            return Err(RotatorError::NotFound(Entity::Task, task_id));
+        }
+
+        // Reload the project's tasks so the task comes back at its old position
+        let now = self.clock.now();
+        let tasks = load_tasks(&self.conn, project_id, now)?;
+        let current_task_id = match self.project_mut(project_id)?.current_task_id {
+            Some(id) => Some(id),
+            None => {
+                let first = tasks.first().map(|t| t.id);
+                save_current_task(&self.conn, project_id, first)?;
+                first
+            }
+        };
+        let project = self.project_mut(project_id)?;
+        project.tasks = tasks;
+        project.current_task_id = current_task_id;
+        let project = project.clone();
+
+        self.audit("restore_task", Some((Entity::Task, task_id)), Value::Null, field("archived_at", &None::<u64>))?;
+        Ok(project)
+    }
+
+    /// Delete an archived task and its time entries for good. Live tasks
+    /// have to be archived first, which also stops their tracking.
+    pub fn delete_task_permanent(&mut self, task_id: u64) -> Result<(), RotatorError> {
+        if !is_archived(&self.conn, "tasks", task_id)?.ok_or(RotatorError::NotFound(Entity::Task, task_id))? {
+            return Err(RotatorError::invalid("only archived tasks can be deleted for good"));
+        }
+        self.create_backup(BackupReason::DeleteTask)?;
+
+        // Permanently delete the task and its time entries
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        let before = task_snapshot(&tx, task_id)?;
+        tx.execute("DELETE FROM tracking_pauses WHERE task_id = ?", [task_id])?;
+        tx.execute("DELETE FROM time_entries WHERE task_id = ?", [task_id])?;
+        tx.execute("DELETE FROM budgets WHERE task_id = ?", [task_id])?;
+        if tx.execute("DELETE FROM tasks WHERE id = ?", [task_id])? == 0 {
+            return Err(RotatorError::NotFound(Entity::Task, task_id));
+        }
+        audit.record(&tx, "delete_task_permanent", Some((Entity::Task, task_id)), before, Value::Null)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Delete an archived project with its tasks and time entries for good.
+    pub fn delete_project_permanent(&mut self, project_id: u64) -> Result<(), RotatorError> {
+        if !is_archived(&self.conn, "projects", project_id)?.ok_or(RotatorError::NotFound(Entity::Project, project_id))? {
+            return Err(RotatorError::invalid("only archived projects can be deleted for good"));
+        }
+        self.create_backup(BackupReason::DeleteProject)?;
+
+        // Permanently delete the project, its tasks, and time entries
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        let before = project_snapshot(&tx, project_id)?;
+        tx.execute(
+            "DELETE FROM tracking_pauses WHERE task_id IN (SELECT id FROM tasks WHERE project_id = ?)",
+            [project_id],
+        )?;
+        tx.execute("DELETE FROM time_entries WHERE project_id = ?", [project_id])?;
+        tx.execute("DELETE FROM tasks WHERE project_id = ?", [project_id])?;
+        tx.execute("DELETE FROM project_time_policies WHERE project_id = ?", [project_id])?;
+        tx.execute("DELETE FROM budgets WHERE project_id = ?", [project_id])?;
+        if tx.execute("DELETE FROM projects WHERE id = ?", [project_id])? == 0 {
+            return Err(RotatorError::NotFound(Entity::Project, project_id));
+        }
+        audit.record(&tx, "delete_project_permanent", Some((Entity::Project, project_id)), before, Value::Null)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn reset_database(&mut self) -> Result<Vec<Project>, RotatorError> {
+        self.create_backup(BackupReason::Reset)?;
+
+        // Clear all data from tables; the audit log is kept
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        for table in DATA_TABLES {
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+        audit.record(&tx, "reset_database", None, Value::Null, Value::Null)?;
+        tx.commit()?;
+
+        // Reset app state
+        self.projects = Vec::new();
+        self.current_project_id = None;
+        self.next_project_id = 1;
+        self.next_task_id = 1;
+        self.active_tracking.clear();
+        self.idle_settings = IdleSettings::default();
+        self.pending_idle = None;
+        self.orphaned.clear();
+        self.timebox = None;
+        Ok(self.projects.clone())
+    }
+
+    pub fn add_time_entry_manual(
+        &mut self,
+        project_id: u64,
+        task_id: u64,
+        start_time: u64,
+        duration_seconds: u64,
+    ) -> Result<TimeEntry, RotatorError> {
+        if duration_seconds == 0 {
+            return Err(RotatorError::invalid("duration must be more than zero"));
+        }
+        let end_time = start_time
+            .checked_add(duration_seconds)
+            .ok_or_else(|| RotatorError::invalid("entry ends too far in the future"))?;
+
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        // Archived tasks are fine; time is often logged after the fact
+        let known: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM tasks WHERE id = ? AND project_id = ?",
+            params![task_id, project_id],
+            |row| row.get(0),
+        )?;
+        if known == 0 {
+            return Err(RotatorError::NotFound(Entity::Task, task_id));
+        }
+        if let Some(id) = find_overlap(&tx, task_id, start_time, end_time, &[])? {
+            return Err(RotatorError::Conflict(Conflict::TimeEntry(id)));
+        }
+        tx.execute(
+            "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds, manual) VALUES (?, ?, ?, ?, ?, 1)",
+            params![project_id, task_id, start_time, end_time, duration_seconds],
+        )?;
+        let entry_id = tx.last_insert_rowid() as u64;
+        refresh_task_total(&tx, task_id)?;
+        let after = vec![load_entry_record(&tx, entry_id)?];
+        audit.entries(&tx, "add_time_entry_manual", &[], &after)?;
+        tx.commit()?;
+
+        self.sync_task_totals(&[task_id])?;
+        self.record(Change::EntriesEdited { edit: EntryEdit::Added, before: Vec::new(), after })?;
+        load_entry(&self.conn, entry_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::error::Entity;
+    use crate::test_support::rotator_at;
+
+    fn entry_count(rotator: &Rotator) -> i64 {
+        rotator.connection().query_row("SELECT COUNT(*) FROM time_entries", [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn rotate_project_wraps_around() {
+        let (mut rotator, _) = rotator_at(1_000);
+        assert!(rotator.rotate_project(RotationTrigger::Hotkey).unwrap().1.is_none());
+
+        rotator.add_project("Work".into()).unwrap();
+        rotator.add_project("Personal".into()).unwrap();
+
+        let (index, project) = rotator.rotate_project(RotationTrigger::Hotkey).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(project.unwrap().name, "Personal");
+        assert_eq!(rotator.rotate_project(RotationTrigger::Hotkey).unwrap().0, 0);
+    }
+
+    #[test]
+    fn rotate_task_skips_done_tasks() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        for name in ["A", "B", "C"] {
+            rotator.add_task(project_id, name.into()).unwrap();
+        }
+        let b = rotator.projects()[0].tasks[1].id;
+        rotator.toggle_task_done(project_id, b, true).unwrap();
+
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().name, "C");
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().name, "A");
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().name, "C");
+    }
+
+    #[test]
+    fn rotate_task_returns_none_when_all_done() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.toggle_task_done(project_id, task_id, true).unwrap();
+
+        assert!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().is_none());
+    }
+
+    #[test]
+    fn stop_tracking_records_time_entry() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(90);
+
+        let closed = rotator.stop_tracking(Some(task_id)).unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].duration_seconds, 90);
+        assert!(!closed[0].discarded);
+        assert!(rotator.active_tracking().is_empty());
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 90);
+
+        let entries = rotator.time_entries(0, 10_000).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].start_time, entries[0].end_time), (1_000, 1_090));
+        assert_eq!(closed[0].entry_id, Some(entries[0].id));
+    }
+
+    #[test]
+    fn short_sessions_are_not_recorded() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(2);
+        let closed = rotator.stop_tracking(None).unwrap();
+
+        assert!(closed[0].discarded);
+        assert_eq!(closed[0].entry_id, None);
+        assert!(rotator.active_tracking().is_empty());
+        assert!(load_active_tracking(rotator.connection()).unwrap().is_empty());
+
+        assert_eq!(entry_count(&rotator), 0);
+        assert_eq!(rotator.projects()[0].tasks[0].time_seconds, 0);
+    }
+
+    #[test]
+    fn starting_without_allow_multiple_stops_other_tasks() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        rotator.add_task(project_id, "A".into()).unwrap();
+        let tasks = rotator.add_task(project_id, "B".into()).unwrap().tasks;
+
+        rotator.start_tracking(project_id, tasks[0].id, false).unwrap();
+        clock.advance(60);
+        let tracking = rotator.start_tracking(project_id, tasks[1].id, false).unwrap();
+
+        assert_eq!(tracking.len(), 1);
+        assert_eq!(tracking[0].task_id, tasks[1].id);
+        assert_eq!(entry_count(&rotator), 1);
+
+        clock.advance(30);
+        assert_eq!(rotator.start_tracking(project_id, tasks[0].id, true).unwrap().len(), 2);
+        clock.advance(10);
+        let closed = rotator.stop_tracking(None).unwrap();
+        assert_eq!(closed.iter().map(|c| c.duration_seconds).collect::<Vec<_>>(), [40, 10]);
+        assert!(closed.iter().all(|c| c.entry_id.is_some()));
+        assert_eq!(entry_count(&rotator), 3);
+    }
+
+    #[test]
+    fn stopping_untracked_task_closes_nothing() {
+        let (mut rotator, _) = rotator_at(1_000);
+        assert!(rotator.stop_tracking(None).unwrap().is_empty());
+        assert!(rotator.stop_tracking(Some(42)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_close_keeps_sessions_running() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let tasks = rotator.add_task(project_id, "A".into()).unwrap().tasks;
+        rotator.start_tracking(project_id, tasks[0].id, false).unwrap();
+        clock.advance(60);
+
+        // Make the time entry insert fail after the task total was bumped
+        rotator.connection().execute_batch(
+            "CREATE TRIGGER reject_entries BEFORE INSERT ON time_entries
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+        ).unwrap();
+
+        assert!(matches!(rotator.stop_tracking(None), Err(RotatorError::Db(_))));
+        assert_eq!(rotator.active_tracking().len(), 1);
+        assert_eq!(load_active_tracking(rotator.connection()).unwrap().len(), 1);
+        let time: u64 = rotator.connection()
+            .query_row("SELECT time_seconds FROM tasks WHERE id = ?", [tasks[0].id], |r| r.get(0))
+            .unwrap();
+        assert_eq!(time, 0);
+    }
+
+    #[test]
+    fn tracking_survives_reopen() {
+        let clock = ManualClock::new(1_000);
+        let path = std::env::temp_dir().join(format!("rotator-core-reopen-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut rotator = Rotator::new(Connection::open(&path).unwrap(), clock.clone()).unwrap();
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        drop(rotator);
+
+        let reopened = Rotator::new(Connection::open(&path).unwrap(), clock).unwrap();
+        assert_eq!(reopened.active_tracking().len(), 1);
+        assert_eq!(reopened.projects()[0].tasks[0].name, "A");
+        drop(reopened);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn remove_project_archives_and_restore_brings_it_back() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+
+        assert!(rotator.remove_project(project_id).unwrap().is_empty());
+        assert!(rotator.active_tracking().is_empty());
+
+        let restored = rotator.restore_project(project_id).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].tasks.len(), 1);
+    }
+
+    #[test]
+    fn current_task_follows_its_id_through_archive_and_restore() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let ids: Vec<u64> = ["A", "B", "C"].iter()
+            .map(|n| rotator.add_task(project_id, n.to_string()).unwrap().tasks.last().unwrap().id)
+            .collect();
+        assert_eq!(rotator.current_project().unwrap().current_task_id, Some(ids[0]));
+
+        rotator.rotate_task(RotationTrigger::Hotkey).unwrap();
+        // Archiving an earlier task no longer shifts the pointer
+        let project = rotator.remove_task(project_id, ids[0]).unwrap();
+        assert_eq!(project.current_task_id, Some(ids[1]));
+        let project = rotator.restore_task(project_id, ids[0]).unwrap();
+        assert_eq!(project.current_task_id, Some(ids[1]));
+
+        // Archiving the current task moves on to the one after it
+        let project = rotator.remove_task(project_id, ids[1]).unwrap();
+        assert_eq!(project.current_task_id, Some(ids[2]));
+        assert_eq!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().unwrap().id, ids[0]);
+    }
+
+    #[test]
+    fn hidden_current_task_resolves_to_the_next_one_on_reload() {
+        let clock = ManualClock::new(1_000_000);
+        let path = std::env::temp_dir().join(format!("rotator-core-current-task-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut rotator = Rotator::new(Connection::open(&path).unwrap(), clock.clone()).unwrap();
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let ids: Vec<u64> = ["A", "B", "C"].iter()
+            .map(|n| rotator.add_task(project_id, n.to_string()).unwrap().tasks.last().unwrap().id)
+            .collect();
+        rotator.rotate_task(RotationTrigger::Hotkey).unwrap();
+        rotator.toggle_task_done(project_id, ids[1], true).unwrap();
+        drop(rotator);
+
+        clock.advance(crate::db::DONE_HIDE_AFTER_SECONDS + 1);
+        let reopened = Rotator::new(Connection::open(&path).unwrap(), clock).unwrap();
+        let project = reopened.current_project().unwrap();
+        assert_eq!(project.tasks.len(), 2);
+        assert_eq!(project.current_task_id, Some(ids[2]));
+        drop(reopened);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn current_project_is_none_without_projects() {
+        let (mut rotator, _) = rotator_at(1_000);
+        assert!(rotator.current_project().is_none());
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        assert_eq!(rotator.current_project_id(), Some(project_id));
+
+        rotator.remove_project(project_id).unwrap();
+        assert!(rotator.current_project().is_none());
+        assert_eq!(rotator.current_project_index(), 0);
+        assert!(rotator.rotate_task(RotationTrigger::Hotkey).unwrap().is_none());
+    }
+
+    #[test]
+    fn done_tasks_are_hidden_after_reload() {
+        let (mut rotator, clock) = rotator_at(1_000_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.toggle_task_done(project_id, task_id, true).unwrap();
+
+        clock.advance(crate::db::DONE_HIDE_AFTER_SECONDS + 1);
+        assert!(load_tasks(rotator.connection(), project_id, clock.now()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_ids_are_not_found() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+
+        assert!(matches!(rotator.add_task(99, "B".into()), Err(RotatorError::NotFound(Entity::Project, 99))));
+        assert!(matches!(rotator.rename_task(project_id, 99, "B".into()), Err(RotatorError::NotFound(Entity::Task, 99))));
+        assert!(matches!(rotator.start_tracking(project_id, 99, false), Err(RotatorError::NotFound(Entity::Task, 99))));
+        assert!(matches!(rotator.add_time_entry_manual(99, task_id, 0, 60), Err(RotatorError::NotFound(Entity::Task, _))));
+        assert!(matches!(rotator.set_current_project(5), Err(RotatorError::Validation(_))));
+        assert!(rotator.active_tracking().is_empty());
+        assert_eq!(entry_count(&rotator), 0);
+    }
+
+    #[test]
+    fn manual_entries_may_not_overlap() {
+        let (mut rotator, _) = rotator_at(10_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        let first = rotator.add_time_entry_manual(project_id, task_id, 1_000, 600).unwrap();
+
+        let overlapping = rotator.add_time_entry_manual(project_id, task_id, 1_300, 600);
+        assert!(matches!(overlapping, Err(RotatorError::Conflict(Conflict::TimeEntry(id))) if id == first.id));
+        rotator.add_time_entry_manual(project_id, task_id, 1_600, 600).unwrap();
+        assert_eq!(entry_count(&rotator), 2);
+    }
+
+    #[test]
+    fn manual_entries_need_a_sane_duration() {
+        let (mut rotator, _) = rotator_at(10_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+
+        assert!(matches!(rotator.add_time_entry_manual(project_id, task_id, 1_000, 0), Err(RotatorError::Validation(_))));
+        let overflowing = rotator.add_time_entry_manual(project_id, task_id, u64::MAX - 10, 60);
+        assert!(matches!(overflowing, Err(RotatorError::Validation(_))));
+        assert_eq!(entry_count(&rotator), 0);
+    }
+
+    #[test]
+    fn permanent_delete_reports_missing_rows() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.add_time_entry_manual(project_id, task_id, 100, 60).unwrap();
+
+        rotator.remove_task(project_id, task_id).unwrap();
+        rotator.delete_task_permanent(task_id).unwrap();
+        assert_eq!(entry_count(&rotator), 0);
+        assert!(matches!(rotator.delete_task_permanent(task_id), Err(RotatorError::NotFound(Entity::Task, _))));
+        rotator.remove_project(project_id).unwrap();
+        rotator.delete_project_permanent(project_id).unwrap();
+        assert!(matches!(rotator.delete_project_permanent(project_id), Err(RotatorError::NotFound(Entity::Project, _))));
+    }
+
+    #[test]
+    fn permanent_delete_refuses_live_items() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+        clock.advance(60);
+
+        assert!(matches!(rotator.delete_task_permanent(task_id), Err(RotatorError::Validation(_))));
+        assert!(matches!(rotator.delete_project_permanent(project_id), Err(RotatorError::Validation(_))));
+        assert_eq!(rotator.active_tracking().len(), 1);
+        assert_eq!(rotator.stop_tracking(None).unwrap()[0].duration_seconds, 60);
+        assert_eq!(entry_count(&rotator), 1);
+        assert_eq!(rotator.projects()[0].current_task_id, Some(task_id));
+    }
+
+    #[test]
+    fn permanent_delete_keeps_what_it_removed_in_the_audit_log() {
+        let (mut rotator, clock) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        let other_id = rotator.add_task(project_id, "B".into()).unwrap().tasks[1].id;
+        for id in [task_id, other_id] {
+            rotator.start_tracking(project_id, id, false).unwrap();
+            clock.advance(60);
+            rotator.stop_tracking(None).unwrap();
+        }
+
+        rotator.remove_task(project_id, task_id).unwrap();
+        rotator.delete_task_permanent(task_id).unwrap();
+        let record = &rotator.audit_log(Some(Entity::Task), Some(task_id), 1).unwrap()[0];
+        let before = record.before.as_ref().unwrap();
+        assert_eq!(before["task"]["name"], "A");
+        assert_eq!(before["time_entries"].as_array().unwrap().len(), 1);
+        assert_eq!(before["time_entries"][0]["duration_seconds"], 60);
+
+        rotator.remove_project(project_id).unwrap();
+        rotator.delete_project_permanent(project_id).unwrap();
+        let record = &rotator.audit_log(Some(Entity::Project), Some(project_id), 1).unwrap()[0];
+        let before = record.before.as_ref().unwrap();
+        assert_eq!(before["project"]["name"], "Work");
+        assert_eq!(before["tasks"].as_array().unwrap().len(), 1);
+        assert_eq!(before["time_entries"][0]["task_id"], other_id);
+    }
+
+    #[test]
+    fn reload_rebuilds_the_cache_from_the_database() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        let task_id = rotator.add_task(project_id, "A".into()).unwrap().tasks[0].id;
+        rotator.start_tracking(project_id, task_id, false).unwrap();
+
+        // As if a command had died between the write and the cache update
+        rotator.projects.clear();
+        rotator.active_tracking.clear();
+        rotator.next_task_id = 1;
+
+        rotator.reload().unwrap();
+        assert_eq!(rotator.projects()[0].tasks[0].id, task_id);
+        assert_eq!(rotator.active_tracking().len(), 1);
+        assert_eq!(rotator.add_task(project_id, "B".into()).unwrap().tasks[1].id, task_id + 1);
+    }
+}
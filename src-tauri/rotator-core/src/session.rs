@@ -0,0 +1,98 @@
+use crate::models::{ActiveTracking, ClosedSession};
+use crate::policy::policy_for;
+use crate::totals::refresh_task_total;
+use rusqlite::{params, Transaction};
+
+/// Default minimum session length; shorter sessions are treated as
+/// accidental starts and dropped. Projects can override it with a `TimePolicy`.
+pub const MIN_SESSION_SECONDS: u64 = 3;
+
+/// Close `sessions` at `ended_at`: record a time entry for each one that is
+/// long enough, rebuild the task's total and remove the active_tracking row.
+/// Runs inside the caller's transaction, so either all sessions are closed
+/// or none are.
+pub(crate) fn close_sessions_in(
+    tx: &Transaction,
+    sessions: &[ActiveTracking],
+    ended_at: u64,
+) -> rusqlite::Result<Vec<ClosedSession>> {
+    let mut closed = Vec::with_capacity(sessions.len());
+
+    for session in sessions {
+        let paused_seconds = paused_seconds_within(tx, session.task_id, session.started_at, ended_at)?;
+        let duration_seconds = ended_at.saturating_sub(session.started_at).saturating_sub(paused_seconds);
+        let policy = policy_for(tx, session.project_id)?;
+        let discarded = duration_seconds == 0 || duration_seconds < policy.min_session_seconds;
+
+        let entry_id = if discarded {
+            tx.execute("DELETE FROM tracking_pauses WHERE task_id = ? AND entry_id IS NULL", [session.task_id])?;
+            None
+        } else {
+            tx.execute(
+                "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
+                params![session.project_id, session.task_id, session.started_at, ended_at, duration_seconds],
+            )?;
+            let entry_id = tx.last_insert_rowid() as u64;
+            // Hand the session's pauses to the entry, ending any still open
+            tx.execute(
+                "DELETE FROM tracking_pauses WHERE task_id = ? AND entry_id IS NULL AND paused_at >= ?",
+                params![session.task_id, ended_at],
+            )?;
+            tx.execute(
+                "UPDATE tracking_pauses SET entry_id = ?1, resumed_at = MIN(COALESCE(resumed_at, ?2), ?2)
+                 WHERE task_id = ?3 AND entry_id IS NULL",
+                params![entry_id, ended_at, session.task_id],
+            )?;
+            refresh_task_total(tx, session.task_id)?;
+            Some(entry_id)
+        };
+
+        tx.execute("DELETE FROM active_tracking WHERE task_id = ?", [session.task_id])?;
+
+        closed.push(ClosedSession {
+            project_id: session.project_id,
+            task_id: session.task_id,
+            started_at: session.started_at,
+            ended_at,
+            duration_seconds,
+            billable_seconds: policy.billable_seconds(duration_seconds),
+            entry_id,
+            discarded,
+        });
+    }
+
+    Ok(closed)
+}
+
+/// Seconds of the running session of `task_id` spent paused inside `[start, end)`.
+fn paused_seconds_within(tx: &Transaction, task_id: u64, start: u64, end: u64) -> rusqlite::Result<u64> {
+    let mut stmt = tx.prepare("SELECT paused_at, resumed_at FROM tracking_pauses WHERE task_id = ? AND entry_id IS NULL")?;
+    let pauses = stmt.query_map([task_id], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<u64>>(1)?)))?;
+    let mut total = 0;
+    for pause in pauses {
+        let (paused_at, resumed_at) = pause?;
+        let from = paused_at.max(start);
+        let to = resumed_at.unwrap_or(end).min(end);
+        total += to.saturating_sub(from);
+    }
+    Ok(total)
+}
+
+/// Record `[start_time, end_time)` for a task and rebuild its total.
+/// `manual` marks time that was entered rather than tracked.
+pub(crate) fn insert_entry(
+    tx: &Transaction,
+    project_id: u64,
+    task_id: u64,
+    start_time: u64,
+    end_time: u64,
+    manual: bool,
+) -> rusqlite::Result<u64> {
+    tx.execute(
+        "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds, manual) VALUES (?, ?, ?, ?, ?, ?)",
+        params![project_id, task_id, start_time, end_time, end_time - start_time, manual],
+    )?;
+    let entry_id = tx.last_insert_rowid() as u64;
+    refresh_task_total(tx, task_id)?;
+    Ok(entry_id)
+}
@@ -0,0 +1,240 @@
+use crate::entries::{entry_from_row, ENTRY_COLUMNS};
+use crate::error::RotatorError;
+use crate::models::{
+    DailyActivity, HourlyActivity, ProjectTimeStats, ProjectWithStatus, TaskWithStatus, TimeEntry,
+};
+use crate::policy::Policies;
+use crate::rotator::Rotator;
+use crate::timezone::{share_out, split_by_local_hour, HourSlice, StatsZone};
+use chrono::NaiveDate;
+use rusqlite::params;
+use std::collections::BTreeMap;
+
+impl Rotator {
+    pub fn time_entries(&self, start_time: u64, end_time: u64) -> Result<Vec<TimeEntry>, RotatorError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM time_entries
+             WHERE start_time >= ? AND start_time <= ?
+             ORDER BY start_time",
+            ENTRY_COLUMNS,
+        ))?;
+
+        let entries = stmt.query_map(params![start_time, end_time], entry_from_row)?;
+
+        let policies = Policies::load(&self.conn)?;
+        let entries = entries
+            .map(|e| {
+                e.map(|mut e| {
+                    policies.apply(&mut e);
+                    e
+                })
+            })
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+
+    pub fn all_time_entries(&self) -> Result<Vec<TimeEntry>, RotatorError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM time_entries ORDER BY start_time",
+            ENTRY_COLUMNS,
+        ))?;
+
+        let entries = stmt.query_map([], entry_from_row)?;
+
+        let policies = Policies::load(&self.conn)?;
+        let entries = entries
+            .map(|e| {
+                e.map(|mut e| {
+                    policies.apply(&mut e);
+                    e
+                })
+            })
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+
+    /// Time spent in each local hour of the day, across the days in the
+    /// range. Entries are split over the hours they span.
+    pub fn hourly_activity(&self, start_time: u64, end_time: u64) -> Result<Vec<HourlyActivity>, RotatorError> {
+        let mut totals: BTreeMap<u32, u64> = BTreeMap::new();
+        for slice in self.hour_slices(start_time, end_time)? {
+            *totals.entry(slice.hour).or_default() += slice.seconds;
+        }
+        Ok(totals.into_iter()
+            .filter(|(_, total)| *total > 0)
+            .map(|(hour, total_seconds)| HourlyActivity { hour, total_seconds })
+            .collect())
+    }
+
+    /// Time spent on each local day in the range. Entries that run past
+    /// midnight count towards both days.
+    pub fn daily_activity(&self, start_time: u64, end_time: u64) -> Result<Vec<DailyActivity>, RotatorError> {
+        let mut totals: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+        for slice in self.hour_slices(start_time, end_time)? {
+            *totals.entry(slice.date).or_default() += slice.seconds;
+        }
+        Ok(totals.into_iter()
+            .filter(|(_, total)| *total > 0)
+            .map(|(date, total_seconds)| DailyActivity { date: date.format("%Y-%m-%d").to_string(), total_seconds })
+            .collect())
+    }
+
+    /// The part of every entry inside `[start_time, end_time]`, cut at local
+    /// hour boundaries in the stats time zone. Each entry's recorded duration
+    /// is shared out over its slices by length.
+    fn hour_slices(&self, start_time: u64, end_time: u64) -> Result<Vec<HourSlice>, RotatorError> {
+        let zone = StatsZone::load(&self.conn)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT start_time, end_time, duration_seconds FROM time_entries
+             WHERE end_time > ? AND start_time <= ?
+             ORDER BY start_time",
+        )?;
+        let entries = stmt.query_map(params![start_time, end_time], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?))
+        })?;
+
+        let mut slices = Vec::new();
+        for entry in entries {
+            let (start, end, duration) = entry?;
+            let span = end.saturating_sub(start);
+            let mut entry_slices = split_by_local_hour(zone, start.max(start_time), end.min(end_time.saturating_add(1)));
+            let inside: u64 = entry_slices.iter().map(|s| s.seconds).sum();
+            if let Some(share) = (duration * inside).checked_div(span) {
+                share_out(share, &mut entry_slices);
+            }
+            slices.extend(entry_slices);
+        }
+        Ok(slices)
+    }
+
+    pub fn project_time_stats(&self, start_time: u64, end_time: u64) -> Result<Vec<ProjectTimeStats>, RotatorError> {
+        let mut stats: Vec<ProjectTimeStats> = Vec::new();
+        for entry in self.time_entries(start_time, end_time)? {
+            match stats.iter_mut().find(|s| s.project_id == entry.project_id) {
+                Some(s) => {
+                    s.total_seconds += entry.duration_seconds;
+                    s.billable_seconds += entry.billable_seconds;
+                }
+                None => stats.push(ProjectTimeStats {
+                    project_id: entry.project_id,
+                    project_name: self.projects.iter()
+                        .find(|p| p.id == entry.project_id)
+                        .map(|p| p.name.clone())
+                        .unwrap_or_else(|| "Unknown".to_string()),
+                    total_seconds: entry.duration_seconds,
+                    billable_seconds: entry.billable_seconds,
+                }),
+            }
+        }
+        stats.sort_by_key(|s| std::cmp::Reverse(s.total_seconds));
+        Ok(stats)
+    }
+
+    pub fn all_projects_with_status(&self) -> Result<Vec<ProjectWithStatus>, RotatorError> {
+        let mut stmt = self.conn.prepare("SELECT id, name, current_task_id, archived_at FROM projects ORDER BY archived_at IS NOT NULL, id")?;
+        let project_iter = stmt.query_map([], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<u64>>(2)?, row.get::<_, Option<u64>>(3)?))
+        })?;
+
+        let mut projects = Vec::new();
+        for project_result in project_iter {
+            let (id, name, current_task_id, archived_at) = project_result?;
+            // Load all tasks for this project
+            let mut task_stmt = self.conn.prepare("SELECT id, name, time_seconds, archived_at, done_at FROM tasks WHERE project_id = ? ORDER BY archived_at IS NOT NULL, id")?;
+            let task_iter = task_stmt.query_map([id], |row| {
+                Ok(TaskWithStatus {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    time_seconds: row.get(2)?,
+                    archived_at: row.get(3)?,
+                    done_at: row.get(4)?,
+                })
+            })?;
+            let tasks = task_iter.collect::<rusqlite::Result<Vec<TaskWithStatus>>>()?;
+            projects.push(ProjectWithStatus { id, name, tasks, current_task_id, archived_at });
+        }
+        Ok(projects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::rotator_at;
+
+    #[test]
+    fn project_stats_sum_entries_in_range() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let home = rotator.add_project("Home".into()).unwrap()[1].id;
+        let a = rotator.add_task(work, "A".into()).unwrap().tasks[0].id;
+        let b = rotator.add_task(home, "B".into()).unwrap().tasks[0].id;
+
+        rotator.add_time_entry_manual(work, a, 10_000, 600).unwrap();
+        rotator.add_time_entry_manual(work, a, 20_000, 300).unwrap();
+        rotator.add_time_entry_manual(home, b, 15_000, 1_200).unwrap();
+        rotator.add_time_entry_manual(home, b, 90_000, 60).unwrap();
+
+        let stats = rotator.project_time_stats(0, 50_000).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].project_name.as_str(), stats[0].total_seconds), ("Home", 1_200));
+        assert_eq!((stats[1].project_name.as_str(), stats[1].total_seconds), ("Work", 900));
+
+        assert_eq!(rotator.time_entries(0, 50_000).unwrap().len(), 3);
+        assert_eq!(rotator.all_time_entries().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn hourly_activity_buckets_by_local_hour() {
+        let (mut rotator, _) = rotator_at(1_000);
+        rotator.set_stats_timezone(Some("UTC".into())).unwrap();
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let a = rotator.add_task(work, "A".into()).unwrap().tasks[0].id;
+
+        rotator.add_time_entry_manual(work, a, 9 * 3600, 600).unwrap();
+        rotator.add_time_entry_manual(work, a, 9 * 3600 + 1800, 600).unwrap();
+        rotator.add_time_entry_manual(work, a, 86_400 + 14 * 3600, 300).unwrap();
+
+        let hourly = rotator.hourly_activity(0, 2 * 86_400).unwrap();
+        assert_eq!(hourly.len(), 2);
+        assert_eq!((hourly[0].hour, hourly[0].total_seconds), (9, 1_200));
+        assert_eq!((hourly[1].hour, hourly[1].total_seconds), (14, 300));
+    }
+
+    #[test]
+    fn sessions_are_split_across_hours_and_days_in_the_stats_zone() {
+        let (mut rotator, _) = rotator_at(1_000);
+        rotator.set_stats_timezone(Some("Europe/Berlin".into())).unwrap();
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let a = rotator.add_task(work, "A".into()).unwrap().tasks[0].id;
+
+        // 2024-03-30 22:30 to 2024-03-31 01:15 UTC, across midnight in Berlin
+        // (23:30 CET to 03:15 CEST) and the spring-forward gap at 02:00
+        rotator.add_time_entry_manual(work, a, 1_711_837_800, 9_900).unwrap();
+
+        let hourly = rotator.hourly_activity(0, 2_000_000_000).unwrap();
+        let hours: Vec<(u32, u64)> = hourly.iter().map(|h| (h.hour, h.total_seconds)).collect();
+        assert_eq!(hours, [(0, 3_600), (1, 3_600), (3, 900), (23, 1_800)]);
+
+        let daily = rotator.daily_activity(0, 2_000_000_000).unwrap();
+        let days: Vec<(&str, u64)> = daily.iter().map(|d| (d.date.as_str(), d.total_seconds)).collect();
+        assert_eq!(days, [("2024-03-30", 1_800), ("2024-03-31", 8_100)]);
+
+        // Only the part inside the range counts
+        let clipped = rotator.daily_activity(1_711_839_600, 2_000_000_000).unwrap();
+        assert_eq!((clipped[0].date.as_str(), clipped[0].total_seconds), ("2024-03-31", 8_100));
+        assert_eq!(clipped.len(), 1);
+    }
+
+    #[test]
+    fn projects_with_status_include_archived() {
+        let (mut rotator, _) = rotator_at(1_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        rotator.add_project("Home".into()).unwrap();
+        rotator.remove_project(work).unwrap();
+
+        let all = rotator.all_projects_with_status().unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].name, "Home");
+        assert_eq!(all[1].archived_at, Some(1_000));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::audit::snapshot;
+use crate::db::{load_setting, save_setting};
+use crate::error::RotatorError;
+use crate::history::RotationTrigger;
+use crate::rotator::Rotator;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Work,
+    Break,
+    LongBreak,
+}
+
+impl BlockKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            BlockKind::Work => "work",
+            BlockKind::Break => "break",
+            BlockKind::LongBreak => "long_break",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "break" => BlockKind::Break,
+            "long_break" => BlockKind::LongBreak,
+            _ => BlockKind::Work,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeboxSettings {
+    pub work_seconds: u64,
+    /// 0 skips breaks and rotates straight into the next work block.
+    pub break_seconds: u64,
+    pub long_break_seconds: u64,
+    /// Every n-th break is a long one. 0 never takes a long break.
+    pub long_break_every: u32,
+}
+
+impl Default for TimeboxSettings {
+    fn default() -> Self {
+        Self { work_seconds: 25 * 60, break_seconds: 5 * 60, long_break_seconds: 15 * 60, long_break_every: 4 }
+    }
+}
+
+impl TimeboxSettings {
+    fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let default = Self::default();
+        let get = |key: &str, fallback: u64| -> rusqlite::Result<u64> {
+            Ok(load_setting(conn, key)?.and_then(|v| v.parse().ok()).unwrap_or(fallback))
+        };
+        Ok(Self {
+            work_seconds: get("timebox_work_seconds", default.work_seconds)?,
+            break_seconds: get("timebox_break_seconds", default.break_seconds)?,
+            long_break_seconds: get("timebox_long_break_seconds", default.long_break_seconds)?,
+            long_break_every: get("timebox_long_break_every", default.long_break_every as u64)? as u32,
+        })
+    }
+
+    fn save(&self, conn: &Connection) -> rusqlite::Result<()> {
+        save_setting(conn, "timebox_work_seconds", &self.work_seconds.to_string())?;
+        save_setting(conn, "timebox_break_seconds", &self.break_seconds.to_string())?;
+        save_setting(conn, "timebox_long_break_seconds", &self.long_break_seconds.to_string())?;
+        save_setting(conn, "timebox_long_break_every", &self.long_break_every.to_string())
+    }
+}
+
+/// The block currently counting down.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActiveBlock {
+    pub kind: BlockKind,
+    /// The task being worked on; `None` during breaks.
+    pub project_id: Option<u64>,
+    pub task_id: Option<u64>,
+    pub started_at: u64,
+    pub ends_at: u64,
+    /// Work blocks finished since the timebox was started.
+    pub work_blocks_done: u32,
+}
+
+/// A finished block, as stored in the block log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimeboxBlock {
+    pub id: u64,
+    pub kind: BlockKind,
+    pub project_id: Option<u64>,
+    pub task_id: Option<u64>,
+    pub started_at: u64,
+    pub ended_at: u64,
+    /// Ran its full length, as opposed to being stopped early.
+    pub completed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimeboxEvent {
+    BlockStarted { block: ActiveBlock },
+    BlockEnded { block: TimeboxBlock },
+    /// The current project moved on to another task for the next work block.
+    Rotated { project_id: u64, task_id: u64 },
+    /// The timebox stopped, either on request or because no task is left.
+    Stopped,
+}
+
+pub(crate) struct Timebox {
+    block: ActiveBlock,
+    allow_multiple: bool,
+}
+
+impl Rotator {
+    pub fn timebox_settings(&self) -> Result<TimeboxSettings, RotatorError> {
+        Ok(TimeboxSettings::load(&self.conn)?)
+    }
+
+    pub fn set_timebox_settings(&mut self, settings: TimeboxSettings) -> Result<TimeboxSettings, RotatorError> {
+        let before = TimeboxSettings::load(&self.conn)?;
+        settings.save(&self.conn)?;
+        self.audit("set_timebox_settings", None, snapshot(&before), snapshot(&settings))?;
+        Ok(settings)
+    }
+
+    pub fn active_block(&self) -> Option<ActiveBlock> {
+        self.timebox.as_ref().map(|t| t.block.clone())
+    }
+
+    /// Start a work block on the current task of the current project,
+    /// rotating past it if it is done.
+    pub fn start_timebox(&mut self, allow_multiple: bool) -> Result<Vec<TimeboxEvent>, RotatorError> {
+        if self.timebox.is_some() {
+            return Ok(Vec::new());
+        }
+        let mut events = Vec::new();
+        let now = self.clock.now();
+        let Some((project_id, task_id)) = self.next_work_task(false, &mut events)? else {
+            return Ok(events);
+        };
+        events.push(self.begin_work(project_id, task_id, now, 0, allow_multiple)?);
+        Ok(events)
+    }
+
+    /// End the current block early. Tracking is left as it is.
+    pub fn stop_timebox(&mut self) -> Result<Vec<TimeboxEvent>, RotatorError> {
+        let Some(timebox) = self.timebox.take() else {
+            return Ok(Vec::new());
+        };
+        let now = self.clock.now();
+        let block = self.log_block(&timebox.block, now.min(timebox.block.ends_at), false)?;
+        Ok(vec![TimeboxEvent::BlockEnded { block }, TimeboxEvent::Stopped])
+    }
+
+    /// Advance the timebox if the current block is over. Call it regularly;
+    /// it is a no-op until the block's end time has passed.
+    pub fn tick_timebox(&mut self) -> Result<Vec<TimeboxEvent>, RotatorError> {
+        let now = self.clock.now();
+        let Some(timebox) = self.timebox.take() else {
+            return Ok(Vec::new());
+        };
+        if now < timebox.block.ends_at {
+            self.timebox = Some(timebox);
+            return Ok(Vec::new());
+        }
+
+        let ended = timebox.block.clone();
+        let mut events = Vec::new();
+
+        if ended.kind == BlockKind::Work {
+            // Close the session exactly at the end of the block
+            let sessions: Vec<_> = self.active_tracking.iter()
+                .filter(|t| Some(t.task_id) == ended.task_id)
+                .cloned()
+                .collect();
+            // Keep the block so the next tick retries
+            if let Err(e) = self.close_sessions("tick_timebox", &sessions, ended.ends_at) {
+                self.timebox = Some(timebox);
+                return Err(e);
+            }
+        }
+        let block = self.log_block(&ended, ended.ends_at, true)?;
+        events.push(TimeboxEvent::BlockEnded { block });
+
+        let settings = self.timebox_settings()?;
+        if ended.kind == BlockKind::Work {
+            let done = ended.work_blocks_done + 1;
+            let long = settings.long_break_every > 0 && done.is_multiple_of(settings.long_break_every);
+            let (kind, length) = if long {
+                (BlockKind::LongBreak, settings.long_break_seconds)
+            } else {
+                (BlockKind::Break, settings.break_seconds)
+            };
+            if length > 0 {
+                let block = ActiveBlock {
+                    kind,
+                    project_id: None,
+                    task_id: None,
+                    started_at: ended.ends_at,
+                    ends_at: ended.ends_at + length,
+                    work_blocks_done: done,
+                };
+                self.timebox = Some(Timebox { block: block.clone(), allow_multiple: timebox.allow_multiple });
+                events.push(TimeboxEvent::BlockStarted { block });
+                return Ok(events);
+            }
+        }
+
+        // Break over (or skipped): rotate and get back to work
+        let done = if ended.kind == BlockKind::Work { ended.work_blocks_done + 1 } else { ended.work_blocks_done };
+        match self.next_work_task(true, &mut events)? {
+            Some((project_id, task_id)) => {
+                events.push(self.begin_work(project_id, task_id, now, done, timebox.allow_multiple)?);
+            }
+            None => events.push(TimeboxEvent::Stopped),
+        }
+        Ok(events)
+    }
+
+    /// Finished blocks that started inside `[start_time, end_time]`.
+    pub fn timebox_blocks(&self, start_time: u64, end_time: u64) -> Result<Vec<TimeboxBlock>, RotatorError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, kind, project_id, task_id, started_at, ended_at, completed
+             FROM timebox_blocks
+             WHERE started_at >= ? AND started_at <= ?
+             ORDER BY started_at"
+        )?;
+        let blocks = stmt.query_map(params![start_time, end_time], |row| {
+            Ok(TimeboxBlock {
+                id: row.get(0)?,
+                kind: BlockKind::parse(&row.get::<_, String>(1)?),
+                project_id: row.get(2)?,
+                task_id: row.get(3)?,
+                started_at: row.get(4)?,
+                ended_at: row.get(5)?,
+                completed: row.get(6)?,
+            })
+        })?;
+        Ok(blocks.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The task the next work block should track. With `rotate` the current
+    /// project moves on to its next non-done task first.
+    fn next_work_task(&mut self, rotate: bool, events: &mut Vec<TimeboxEvent>) -> Result<Option<(u64, u64)>, RotatorError> {
+        let Some(project) = self.current_project() else {
+            return Ok(None);
+        };
+        let current = project.tasks.iter().find(|t| Some(t.id) == project.current_task_id && t.done_at.is_none());
+        let task = match current {
+            Some(task) if !rotate => task.clone(),
+            _ => {
+                let Some(task) = self.rotate_task(RotationTrigger::Timer)? else {
+                    return Ok(None);
+                };
+                events.push(TimeboxEvent::Rotated { project_id: project.id, task_id: task.id });
+                task
+            }
+        };
+        Ok(Some((project.id, task.id)))
+    }
+
+    fn begin_work(&mut self, project_id: u64, task_id: u64, now: u64, done: u32, allow_multiple: bool) -> Result<TimeboxEvent, RotatorError> {
+        self.start_tracking(project_id, task_id, allow_multiple)?;
+        let block = ActiveBlock {
+            kind: BlockKind::Work,
+            project_id: Some(project_id),
+            task_id: Some(task_id),
+            started_at: now,
+            ends_at: now + self.timebox_settings()?.work_seconds,
+            work_blocks_done: done,
+        };
+        self.timebox = Some(Timebox { block: block.clone(), allow_multiple });
+        Ok(TimeboxEvent::BlockStarted { block })
+    }
+
+    fn log_block(&self, block: &ActiveBlock, ended_at: u64, completed: bool) -> rusqlite::Result<TimeboxBlock> {
+        self.conn.execute(
+            "INSERT INTO timebox_blocks (kind, project_id, task_id, started_at, ended_at, completed) VALUES (?, ?, ?, ?, ?, ?)",
+            params![block.kind.as_str(), block.project_id, block.task_id, block.started_at, ended_at, completed],
+        )?;
+        Ok(TimeboxBlock {
+            id: self.conn.last_insert_rowid() as u64,
+            kind: block.kind,
+            project_id: block.project_id,
+            task_id: block.task_id,
+            started_at: block.started_at,
+            ended_at,
+            completed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    fn setup() -> (Rotator, crate::ManualClock, Vec<u64>) {
+        let (mut rotator, clock) = rotator_at(100_000);
+        let project_id = rotator.add_project("Work".into()).unwrap()[0].id;
+        for name in ["A", "B", "C"] {
+            rotator.add_task(project_id, name.into()).unwrap();
+        }
+        rotator.set_timebox_settings(TimeboxSettings {
+            work_seconds: 1_500,
+            break_seconds: 300,
+            long_break_seconds: 900,
+            long_break_every: 2,
+        }).unwrap();
+        let tasks = rotator.projects()[0].tasks.iter().map(|t| t.id).collect();
+        (rotator, clock, tasks)
+    }
+
+    fn tracked_task(rotator: &Rotator) -> Option<u64> {
+        rotator.active_tracking().first().map(|t| t.task_id)
+    }
+
+    #[test]
+    fn work_break_then_rotate_to_next_task() {
+        let (mut rotator, clock, tasks) = setup();
+        rotator.start_timebox(false).unwrap();
+        assert_eq!(tracked_task(&rotator), Some(tasks[0]));
+
+        clock.advance(1_499);
+        assert!(rotator.tick_timebox().unwrap().is_empty());
+
+        // Work ends a few seconds late: the entry still stops at the block end
+        clock.advance(5);
+        let events = rotator.tick_timebox().unwrap();
+        assert!(matches!(&events[..], [TimeboxEvent::BlockEnded { .. }, TimeboxEvent::BlockStarted { block }] if block.kind == BlockKind::Break));
+        assert_eq!(tracked_task(&rotator), None);
+        assert_eq!(rotator.all_time_entries().unwrap()[0].end_time, 101_500);
+
+        clock.set(101_800);
+        let events = rotator.tick_timebox().unwrap();
+        assert!(matches!(events[1], TimeboxEvent::Rotated { task_id, .. } if task_id == tasks[1]));
+        assert_eq!(tracked_task(&rotator), Some(tasks[1]));
+        assert_eq!(rotator.active_block().unwrap().work_blocks_done, 1);
+
+        let kinds: Vec<_> = rotator.timebox_blocks(0, 200_000).unwrap().iter().map(|b| b.kind).collect();
+        assert_eq!(kinds, [BlockKind::Work, BlockKind::Break]);
+    }
+
+    #[test]
+    fn every_nth_break_is_long_and_done_tasks_are_skipped() {
+        let (mut rotator, clock, tasks) = setup();
+        rotator.toggle_task_done(rotator.projects()[0].id, tasks[1], true).unwrap();
+        rotator.start_timebox(false).unwrap();
+
+        clock.advance(1_500);
+        rotator.tick_timebox().unwrap();
+        clock.advance(300);
+        rotator.tick_timebox().unwrap();
+        assert_eq!(tracked_task(&rotator), Some(tasks[2]));
+
+        clock.advance(1_500);
+        let events = rotator.tick_timebox().unwrap();
+        assert!(matches!(&events[1], TimeboxEvent::BlockStarted { block } if block.kind == BlockKind::LongBreak));
+        assert_eq!(rotator.active_block().unwrap().ends_at, rotator.now() + 900);
+    }
+
+    #[test]
+    fn stop_logs_an_incomplete_block_and_keeps_tracking() {
+        let (mut rotator, clock, tasks) = setup();
+        rotator.start_timebox(false).unwrap();
+        clock.advance(600);
+
+        let events = rotator.stop_timebox().unwrap();
+        assert!(matches!(events.last(), Some(TimeboxEvent::Stopped)));
+        assert_eq!(rotator.active_block(), None);
+        assert_eq!(tracked_task(&rotator), Some(tasks[0]));
+
+        let blocks = rotator.timebox_blocks(0, 200_000).unwrap();
+        assert_eq!((blocks[0].ended_at, blocks[0].completed), (100_600, false));
+    }
+
+    #[test]
+    fn stops_when_every_task_is_done() {
+        let (mut rotator, clock, tasks) = setup();
+        rotator.start_timebox(false).unwrap();
+        let project_id = rotator.projects()[0].id;
+        for id in &tasks {
+            rotator.toggle_task_done(project_id, *id, true).unwrap();
+        }
+
+        clock.advance(1_800);
+        rotator.tick_timebox().unwrap();
+        assert!(matches!(rotator.tick_timebox().unwrap().last(), Some(TimeboxEvent::Stopped)));
+        assert_eq!(rotator.active_block(), None);
+    }
+}
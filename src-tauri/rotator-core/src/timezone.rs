@@ -0,0 +1,182 @@
+use crate::audit::field;
+use crate::db::{load_setting, save_setting};
+use crate::error::RotatorError;
+use crate::rotator::Rotator;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Timelike};
+use chrono_tz::Tz;
+use rusqlite::Connection;
+
+const HOUR: u64 = 60 * 60;
+
+/// The zone hourly and daily stats are bucketed in: the IANA zone set in
+/// settings, or the machine's own zone without one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StatsZone {
+    Local,
+    Named(Tz),
+}
+
+impl StatsZone {
+    pub(crate) fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let zone = load_setting(conn, "stats_timezone")?.and_then(|name| name.parse::<Tz>().ok());
+        Ok(zone.map_or(StatsZone::Local, StatsZone::Named))
+    }
+
+    pub(crate) fn local_time(self, at: u64) -> NaiveDateTime {
+        let utc = DateTime::from_timestamp(at as i64, 0).unwrap_or_default();
+        match self {
+            StatsZone::Local => utc.with_timezone(&Local).naive_local(),
+            StatsZone::Named(zone) => utc.with_timezone(&zone).naive_local(),
+        }
+    }
+
+    /// When `date` starts in this zone. A day whose midnight falls in a DST
+    /// gap starts when the clocks jump.
+    pub(crate) fn start_of_day(self, date: NaiveDate) -> u64 {
+        let midnight = date.and_time(NaiveTime::MIN);
+        let at = |local: NaiveDateTime| match self {
+            StatsZone::Local => Local.from_local_datetime(&local).earliest().map(|t| t.timestamp()),
+            StatsZone::Named(zone) => zone.from_local_datetime(&local).earliest().map(|t| t.timestamp()),
+        };
+        let start = at(midnight).or_else(|| at(midnight + TimeDelta::hours(1))).unwrap_or_default();
+        start.max(0) as u64
+    }
+}
+
+/// One stretch of an entry that falls in a single local hour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct HourSlice {
+    pub date: NaiveDate,
+    pub hour: u32,
+    pub seconds: u64,
+}
+
+/// Cut `[start, end)` at every local hour boundary in `zone`.
+///
+/// Offset changes happen on a local hour boundary, so the next boundary is
+/// always the rest of the current local hour away, even across DST. A
+/// repeated hour after clocks go back yields two slices with the same hour.
+pub(crate) fn split_by_local_hour(zone: StatsZone, start: u64, end: u64) -> Vec<HourSlice> {
+    let mut slices = Vec::new();
+    let mut at = start;
+    while at < end {
+        let local = zone.local_time(at);
+        let into_hour = (local.minute() * 60 + local.second()) as u64;
+        let next = (at + HOUR - into_hour).min(end);
+        slices.push(HourSlice { date: local.date(), hour: local.hour(), seconds: next - at });
+        at = next;
+    }
+    slices
+}
+
+/// Share `total` out over `slices` in proportion to their length, rounding
+/// so the shares still add up to `total`.
+pub(crate) fn share_out(total: u64, slices: &mut [HourSlice]) {
+    let span: u64 = slices.iter().map(|s| s.seconds).sum();
+    if span == 0 || span == total {
+        return;
+    }
+    let mut covered = 0;
+    for slice in slices {
+        let before = total * covered / span;
+        covered += slice.seconds;
+        slice.seconds = total * covered / span - before;
+    }
+}
+
+impl Rotator {
+    /// The IANA zone stats are bucketed in, `None` for the machine's own.
+    pub fn stats_timezone(&self) -> Result<Option<String>, RotatorError> {
+        Ok(match StatsZone::load(&self.conn)? {
+            StatsZone::Local => None,
+            StatsZone::Named(zone) => Some(zone.name().to_string()),
+        })
+    }
+
+    /// Bucket hourly and daily stats in `zone`, e.g. `Europe/Berlin`, or in
+    /// the machine's zone with `None`.
+    pub fn set_stats_timezone(&mut self, zone: Option<String>) -> Result<Option<String>, RotatorError> {
+        let zone = match zone.as_deref().map(str::trim).filter(|z| !z.is_empty()) {
+            Some(name) => Some(
+                name.parse::<Tz>()
+                    .map_err(|_| RotatorError::invalid(format!("unknown time zone \"{}\"", name)))?
+                    .name()
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let before = self.stats_timezone()?;
+        save_setting(&self.conn, "stats_timezone", zone.as_deref().unwrap_or(""))?;
+        self.audit("set_stats_timezone", None, field("timezone", &before), field("timezone", &zone))?;
+        Ok(zone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rotator_at;
+
+    fn zone(name: &str) -> StatsZone {
+        StatsZone::Named(name.parse().unwrap())
+    }
+
+    fn hours(slices: &[HourSlice]) -> Vec<(String, u32, u64)> {
+        slices.iter().map(|s| (s.date.to_string(), s.hour, s.seconds)).collect()
+    }
+
+    #[test]
+    fn splits_at_local_hours_in_odd_offsets() {
+        // 2024-03-13 03:50 UTC is 09:20 in Kolkata (+05:30)
+        let slices = split_by_local_hour(zone("Asia/Kolkata"), 1_710_301_800, 1_710_301_800 + 3_000);
+        assert_eq!(hours(&slices), [("2024-03-13".into(), 9, 2_400), ("2024-03-13".into(), 10, 600)]);
+    }
+
+    #[test]
+    fn spring_forward_skips_the_missing_hour() {
+        // New York 2024-03-10: 01:30 EST (06:30 UTC) to 03:30 EDT (07:30 UTC)
+        let slices = split_by_local_hour(zone("America/New_York"), 1_710_052_200, 1_710_055_800);
+        assert_eq!(hours(&slices), [("2024-03-10".into(), 1, 1_800), ("2024-03-10".into(), 3, 1_800)]);
+    }
+
+    #[test]
+    fn fall_back_counts_the_repeated_hour_twice() {
+        // New York 2024-11-03: 01:30 EDT (05:30 UTC) to 01:30 EST (06:30 UTC)
+        let slices = split_by_local_hour(zone("America/New_York"), 1_730_611_800, 1_730_615_400);
+        assert_eq!(hours(&slices), [("2024-11-03".into(), 1, 1_800), ("2024-11-03".into(), 1, 1_800)]);
+    }
+
+    #[test]
+    fn half_hour_dst_shift_in_lord_howe() {
+        // Lord Howe 2024-10-06: 01:30 +10:30 (15:00 UTC the day before) to
+        // 03:00 +11:00, with 02:00 to 02:30 skipped
+        let slices = split_by_local_hour(zone("Australia/Lord_Howe"), 1_728_140_400, 1_728_144_000);
+        assert_eq!(hours(&slices), [("2024-10-06".into(), 1, 1_800), ("2024-10-06".into(), 2, 1_800)]);
+    }
+
+    #[test]
+    fn day_without_a_midnight_starts_when_clocks_jump() {
+        // Santiago 2024-09-08: midnight -04:00 jumps to 01:00 -03:00 (04:00 UTC)
+        let date = NaiveDate::from_ymd_opt(2024, 9, 8).unwrap();
+        assert_eq!(zone("America/Santiago").start_of_day(date), 1_725_768_000);
+        assert_eq!(zone("UTC").start_of_day(date), 1_725_753_600);
+    }
+
+    #[test]
+    fn shares_add_up_to_the_total() {
+        let mut slices = split_by_local_hour(zone("UTC"), 1_800, 1_800 + 3 * 3_600);
+        share_out(100, &mut slices);
+        assert_eq!(slices.iter().map(|s| s.seconds).collect::<Vec<_>>(), [16, 34, 33, 17]);
+    }
+
+    #[test]
+    fn setting_rejects_unknown_zones() {
+        let (mut rotator, _) = rotator_at(1_000);
+        assert_eq!(rotator.stats_timezone().unwrap(), None);
+        assert_eq!(rotator.set_stats_timezone(Some(" Europe/Berlin ".into())).unwrap(), Some("Europe/Berlin".into()));
+        assert_eq!(rotator.stats_timezone().unwrap(), Some("Europe/Berlin".into()));
+        assert!(matches!(rotator.set_stats_timezone(Some("Mars/Olympus".into())), Err(RotatorError::Validation(_))));
+        assert_eq!(rotator.set_stats_timezone(None).unwrap(), None);
+        assert_eq!(rotator.stats_timezone().unwrap(), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::audit::field;
+use crate::error::{Entity, RotatorError};
+use crate::rotator::Rotator;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// A task whose cached `time_seconds` disagreed with its time entries.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TotalDrift {
+    pub task_id: u64,
+    pub stored_seconds: u64,
+    pub actual_seconds: u64,
+}
+
+/// Rebuild the cached `tasks.time_seconds` of `task_id` from its time entries.
+///
+/// `tasks.time_seconds` is only ever written here, never incremented on its
+/// own, so it cannot disagree with `time_entries` once this has run in the
+/// same transaction as the entry change.
+pub(crate) fn refresh_task_total(conn: &Connection, task_id: u64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE tasks SET time_seconds = (
+             SELECT COALESCE(SUM(duration_seconds), 0) FROM time_entries WHERE task_id = tasks.id
+         ) WHERE id = ?",
+        [task_id],
+    )?;
+    Ok(())
+}
+
+impl Rotator {
+    /// Find every task whose cached total drifted from its time entries,
+    /// rebuild it, and report what was changed.
+    pub fn recompute_totals(&mut self) -> Result<Vec<TotalDrift>, RotatorError> {
+        let audit = self.auditor();
+        let tx = self.conn.transaction()?;
+        let drift: Vec<TotalDrift> = {
+            let mut stmt = tx.prepare(
+                "SELECT t.id, t.time_seconds, COALESCE(SUM(e.duration_seconds), 0) AS actual
+                 FROM tasks t LEFT JOIN time_entries e ON e.task_id = t.id
+                 GROUP BY t.id
+                 HAVING t.time_seconds != actual
+                 ORDER BY t.id"
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(TotalDrift {
+                    task_id: row.get(0)?,
+                    stored_seconds: row.get(1)?,
+                    actual_seconds: row.get(2)?,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        for d in &drift {
+            tx.execute(
+                "UPDATE tasks SET time_seconds = ? WHERE id = ?",
+                params![d.actual_seconds, d.task_id],
+            )?;
+            let (before, after) = (field("time_seconds", &d.stored_seconds), field("time_seconds", &d.actual_seconds));
+            audit.record(&tx, "recompute_totals", Some((Entity::Task, d.task_id)), before, after)?;
+        }
+        tx.commit()?;
+
+        let task_ids: Vec<u64> = drift.iter().map(|d| d.task_id).collect();
+        self.sync_task_totals(&task_ids)?;
+        Ok(drift)
+    }
+
+    /// Copy the stored totals of `task_ids` into the in-memory projects.
+    pub(crate) fn sync_task_totals(&mut self, task_ids: &[u64]) -> rusqlite::Result<()> {
+        for &task_id in task_ids {
+            let total: Option<u64> = self.conn.query_row(
+                "SELECT time_seconds FROM tasks WHERE id = ?",
+                [task_id],
+                |row| row.get(0),
+            ).optional()?;
+            let Some(total) = total else { continue };
+
+            if let Some(task) = self.projects.iter_mut()
+                .flat_map(|p| p.tasks.iter_mut())
+                .find(|t| t.id == task_id)
+            {
+                task.time_seconds = total;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::rotator_at;
+
+    #[test]
+    fn recompute_fixes_drifted_totals() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let tasks = rotator.add_task(work, "A".into()).unwrap().tasks;
+        let a = tasks[0].id;
+        let b = rotator.add_task(work, "B".into()).unwrap().tasks[1].id;
+
+        rotator.add_time_entry_manual(work, a, 10_000, 600).unwrap();
+        rotator.add_time_entry_manual(work, b, 20_000, 300).unwrap();
+        assert!(rotator.recompute_totals().unwrap().is_empty());
+
+        // Simulate an old build that bumped the counter without an entry
+        rotator.connection().execute("UPDATE tasks SET time_seconds = 999 WHERE id = ?", [a]).unwrap();
+        rotator.connection().execute("DELETE FROM time_entries WHERE task_id = ?", [b]).unwrap();
+
+        let drift = rotator.recompute_totals().unwrap();
+        assert_eq!(drift.len(), 2);
+        assert_eq!((drift[0].task_id, drift[0].stored_seconds, drift[0].actual_seconds), (a, 999, 600));
+        assert_eq!((drift[1].task_id, drift[1].stored_seconds, drift[1].actual_seconds), (b, 300, 0));
+
+        let project = &rotator.projects()[0];
+        assert_eq!(project.tasks[0].time_seconds, 600);
+        assert_eq!(project.tasks[1].time_seconds, 0);
+        assert!(rotator.recompute_totals().unwrap().is_empty());
+    }
+
+    #[test]
+    fn manual_entry_for_archived_task_keeps_total_in_sync() {
+        let (mut rotator, _) = rotator_at(100_000);
+        let work = rotator.add_project("Work".into()).unwrap()[0].id;
+        let a = rotator.add_task(work, "A".into()).unwrap().tasks[0].id;
+        rotator.remove_task(work, a).unwrap();
+
+        rotator.add_time_entry_manual(work, a, 10_000, 600).unwrap();
+        rotator.add_time_entry_manual(work, a, 20_000, 60).unwrap();
+
+        let total: u64 = rotator.connection()
+            .query_row("SELECT time_seconds FROM tasks WHERE id = ?", [a], |r| r.get(0))
+            .unwrap();
+        assert_eq!(total, 660);
+        assert!(rotator.recompute_totals().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+//! Read-only HTTP feed of tracked time at
+//! `http://127.0.0.1:<port>/<token>/calendar.ics`, for calendar apps to
+//! subscribe to. Only listens on loopback, and only answers requests that
+//! name loopback as their host, so a web page can't reach it by rebinding
+//! its own domain to 127.0.0.1.
+
+use crate::state::AppState;
+use rotator_core::{AuditSource, CalendarFeedSettings};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const ACCEPT_POLL: Duration = Duration::from_millis(250);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+// Stop flag of the server that is running, if any
+static RUNNING: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+
+/// Stop the feed if it is running, then start it again on the configured
+/// port if it is enabled.
+pub fn apply(handle: &AppHandle, settings: CalendarFeedSettings) -> std::io::Result<()> {
+    // The flag is swapped in one step, so a panic elsewhere can't leave it
+    // half-written
+    let mut running = RUNNING.lock().unwrap_or_else(|poisoned| {
+        RUNNING.clear_poison();
+        poisoned.into_inner()
+    });
+    if let Some(stop) = running.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    // The old listener lets go of the port within one poll
+    let listener = bind_with_retry(settings.port)?;
+    listener.set_nonblocking(true)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    *running = Some(stop.clone());
+
+    let handle = handle.clone();
+    let hosts = [format!("127.0.0.1:{}", settings.port), format!("localhost:{}", settings.port)];
+    let path = settings.path();
+    std::thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = respond(&handle, stream, &hosts, &path) {
+                        eprintln!("Calendar feed request failed: {}", e);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(ACCEPT_POLL),
+                Err(e) => eprintln!("Calendar feed accept failed: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn bind_with_retry(port: u16) -> std::io::Result<TcpListener> {
+    let mut attempts = 0;
+    loop {
+        match TcpListener::bind(("127.0.0.1", port)) {
+            Err(e) if e.kind() == ErrorKind::AddrInUse && attempts < 4 => {
+                attempts += 1;
+                std::thread::sleep(ACCEPT_POLL * 2);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn respond(handle: &AppHandle, mut stream: TcpStream, hosts: &[String], feed_path: &str) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut lines = request.lines();
+    let mut parts = lines.next().unwrap_or("").split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or("");
+    let host = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim().to_ascii_lowercase());
+    let known_host = host.is_some_and(|host| hosts.contains(&host));
+
+    let (status, content_type, body) = match (method, path) {
+        _ if !known_host => ("403 Forbidden", "text/plain; charset=utf-8", "Forbidden\n".to_string()),
+        ("GET" | "HEAD", path) if path == feed_path => {
+            match handle.state::<AppState>().rotator_from(AuditSource::System).calendar_feed() {
+                Ok(ics) => ("200 OK", "text/calendar; charset=utf-8", ics),
+                Err(e) => ("500 Internal Server Error", "text/plain; charset=utf-8", e.to_string()),
+            }
+        }
+        (_, path) if path == feed_path => ("405 Method Not Allowed", "text/plain; charset=utf-8", "GET only\n".to_string()),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "Not found\n".to_string()),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len(),
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+    stream.write_all(response.as_bytes())
+}
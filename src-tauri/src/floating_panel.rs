@@ -96,6 +96,18 @@ pub struct TimerEntry {
     pub project_name: String,
     pub task_name: String,
     pub started_at: u64, // Unix timestamp
+    pub paused_at: Option<u64>, // Unix timestamp if paused
+    pub paused_seconds: u64,
+}
+
+#[cfg(target_os = "macos")]
+impl TimerEntry {
+    /// Tracked seconds excluding pauses, frozen while paused
+    pub fn elapsed(&self, now: u64) -> u64 {
+        self.paused_at.unwrap_or(now)
+            .saturating_sub(self.started_at)
+            .saturating_sub(self.paused_seconds)
+    }
 }
 
 #[derive(Clone)]
@@ -537,7 +549,7 @@ extern "C" fn draw_rect(this: &Object, _cmd: Sel, _dirty_rect: NSRect) {
                     draw_text("▶", 22.0, row_y, bold_font, blue_color);
                 }
             } else {
-                let max_entry = state.entries.iter().max_by_key(|e| now.saturating_sub(e.started_at));
+                let max_entry = state.entries.iter().max_by_key(|e| e.elapsed(now));
                 if let Some(entry) = max_entry {
                     // Green indicator dot
                     let dot_rect = NSRect::new(
@@ -545,11 +557,12 @@ extern "C" fn draw_rect(this: &Object, _cmd: Sel, _dirty_rect: NSRect) {
                         NSSize::new(6.0, 6.0),
                     );
                     let dot_path: id = msg_send![class!(NSBezierPath), bezierPathWithOvalInRect: dot_rect];
-                    let () = msg_send![green_color, setFill];
+                    let dot_color = if entry.paused_at.is_some() { gray_color } else { green_color };
+                    let () = msg_send![dot_color, setFill];
                     let () = msg_send![dot_path, fill];
 
-                    // Format time - calculate from started_at
-                    let elapsed = now.saturating_sub(entry.started_at);
+                    // Format time - excludes pauses, frozen while paused
+                    let elapsed = entry.elapsed(now);
                     let hours = elapsed / 3600;
                     let minutes = (elapsed % 3600) / 60;
                     let seconds = elapsed % 60;
@@ -560,7 +573,11 @@ extern "C" fn draw_rect(this: &Object, _cmd: Sel, _dirty_rect: NSRect) {
                     };
 
                     // Center time in collapsed view
-                    draw_text(&time_str, 22.0, row_y, bold_font, white_color);
+                    if entry.paused_at.is_some() {
+                        draw_text(&format!("⏸ {}", time_str), 22.0, row_y, bold_font, gray_color);
+                    } else {
+                        draw_text(&time_str, 22.0, row_y, bold_font, white_color);
+                    }
                 }
             }
         } else if only_preview {
@@ -709,13 +726,14 @@ extern "C" fn draw_rect(this: &Object, _cmd: Sel, _dirty_rect: NSRect) {
             for (i, entry) in state.entries.iter().enumerate() {
                 let row_y = bounds.size.height - padding - ((i as f64 + 1.0 + preview_offset) * row_height) + row_height / 2.0 - 6.0;
 
-                // Green indicator dot
+                // Green indicator dot, gray while paused
                 let dot_rect = NSRect::new(
                     NSPoint::new(10.0, row_y + 3.0),
                     NSSize::new(6.0, 6.0),
                 );
                 let dot_path: id = msg_send![class!(NSBezierPath), bezierPathWithOvalInRect: dot_rect];
-                let () = msg_send![green_color, setFill];
+                let dot_color = if entry.paused_at.is_some() { gray_color } else { green_color };
+                let () = msg_send![dot_color, setFill];
                 let () = msg_send![dot_path, fill];
 
                 // Project name (truncated with ellipsis if needed)
@@ -739,8 +757,8 @@ extern "C" fn draw_rect(this: &Object, _cmd: Sel, _dirty_rect: NSRect) {
                 };
                 draw_text(&task_text, 22.0 + project_width + 12.0, row_y, font, gray_color);
 
-                // Format time - calculate from started_at
-                let elapsed = now.saturating_sub(entry.started_at);
+                // Format time - excludes pauses, frozen while paused
+                let elapsed = entry.elapsed(now);
                 let hours = elapsed / 3600;
                 let minutes = (elapsed % 3600) / 60;
                 let seconds = elapsed % 60;
@@ -750,9 +768,15 @@ extern "C" fn draw_rect(this: &Object, _cmd: Sel, _dirty_rect: NSRect) {
                     format!("{}:{:02}", minutes, seconds)
                 };
 
-                // Time (before stop button)
-                let time_w = text_width(&time_str, bold_font);
-                draw_text(&time_str, bounds.size.width - time_w - 40.0, row_y, bold_font, white_color);
+                // Time (before stop button), grayed out with a pause mark while paused
+                if entry.paused_at.is_some() {
+                    let paused_str = format!("⏸ {}", time_str);
+                    let time_w = text_width(&paused_str, bold_font);
+                    draw_text(&paused_str, bounds.size.width - time_w - 40.0, row_y, bold_font, gray_color);
+                } else {
+                    let time_w = text_width(&time_str, bold_font);
+                    draw_text(&time_str, bounds.size.width - time_w - 40.0, row_y, bold_font, white_color);
+                }
 
                 // Stop button (square icon)
                 let is_hovered = HOVERED_STOP_BUTTON.lock().ok().and_then(|h| *h).map(|h| h == i).unwrap_or(false);
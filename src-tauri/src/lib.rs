@@ -1,11 +1,22 @@
+mod calendar_feed;
 mod floating_panel;
+mod state;
 
 use floating_panel::{FloatingPanel, TimerState, pop_stopped_task, set_app_handle, set_rotation_preview, clear_rotation_preview};
-use rusqlite::{Connection, params};
+use rotator_core::idle::platform_idle_source;
+use rotator_core::{
+    ActiveBlock, ActiveTracking, AuditRecord, AuditSource, BackupInfo, BackupReason, BackupRetention, BlockKind, Budget,
+    BudgetEvent, BudgetPeriod, BudgetStatus, CalendarFeedSettings, Change, ClosedSession, DailyActivity, DailyContextSwitches, DwellTime,
+    Entity, ExportFormat, ExportOptions, HourlyActivity, IdleEvent, IdlePeriod, IdleResolution, IdleSettings,
+    ImportMode, ImportReport, ImportSource, OrphanResolution, OrphanedSession, Project, ProjectTimeStats,
+    ProjectWithStatus, RotationEvent, RotationStrategyKind, RotationTrigger, Rotator, RotatorError, Task, TimeEntry,
+    TimePolicy, TimeboxBlock, TimeboxEvent, TimeboxSettings, TotalDrift, TrackerImportReport, UndoState,
+    HEARTBEAT_INTERVAL_SECONDS,
+};
 use serde::{Deserialize, Serialize};
+use state::AppState;
 use std::path::PathBuf;
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use tauri::{
     image::Image,
     tray::TrayIconBuilder,
@@ -15,66 +26,9 @@ use once_cell::sync::Lazy;
 
 static FLOATING_PANEL: Lazy<FloatingPanel> = Lazy::new(FloatingPanel::new);
 
-#[derive(Clone, Serialize, Deserialize)]
-struct Task {
-    id: u64,
-    name: String,
-    time_seconds: u64,
-    done_at: Option<u64>,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct Project {
-    id: u64,
-    name: String,
-    tasks: Vec<Task>,
-    current_task_index: usize,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct ActiveTracking {
-    project_id: u64,
-    task_id: u64,
-    started_at: u64,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct TimeEntry {
-    id: u64,
-    project_id: u64,
-    task_id: u64,
-    start_time: u64,
-    end_time: u64,
-    duration_seconds: u64,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct HourlyActivity {
-    hour: u32,
-    total_seconds: u64,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct DailyActivity {
-    date: String,
-    total_seconds: u64,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct ProjectTimeStats {
-    project_id: u64,
-    project_name: String,
-    total_seconds: u64,
-}
-
-struct AppState {
-    db: Mutex<Connection>,
-    projects: Mutex<Vec<Project>>,
-    current_project_index: Mutex<usize>,
-    next_project_id: Mutex<u64>,
-    next_task_id: Mutex<u64>,
-    active_tracking: Mutex<Vec<ActiveTracking>>,
-}
+const IDLE_POLL_SECONDS: u64 = 15;
+const BUDGET_POLL_SECONDS: u64 = 30;
+const BACKUP_POLL_SECONDS: u64 = 60 * 60;
 
 fn get_db_path() -> PathBuf {
     let db_name = std::env::var("ROTATOR_DB_NAME").unwrap_or_else(|_| "rotator.db".to_string());
@@ -85,773 +39,182 @@ fn get_db_path() -> PathBuf {
     path
 }
 
-fn init_db(conn: &Connection) {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS projects (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            current_task_index INTEGER NOT NULL DEFAULT 0,
-            archived INTEGER NOT NULL DEFAULT 0
-        )",
-        [],
-    ).expect("Failed to create projects table");
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS tasks (
-            id INTEGER PRIMARY KEY,
-            project_id INTEGER NOT NULL,
-            name TEXT NOT NULL,
-            time_seconds INTEGER NOT NULL DEFAULT 0,
-            archived INTEGER NOT NULL DEFAULT 0,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        )",
-        [],
-    ).expect("Failed to create tasks table");
-
-    // Migration: Add archived column if it doesn't exist
-    conn.execute(
-        "ALTER TABLE projects ADD COLUMN archived INTEGER NOT NULL DEFAULT 0",
-        [],
-    ).ok();
-    conn.execute(
-        "ALTER TABLE tasks ADD COLUMN archived INTEGER NOT NULL DEFAULT 0",
-        [],
-    ).ok();
-
-    // Migration: Add done column if it doesn't exist (legacy boolean)
-    conn.execute(
-        "ALTER TABLE tasks ADD COLUMN done INTEGER NOT NULL DEFAULT 0",
-        [],
-    ).ok();
-
-    // Migration: Add done_at timestamp column (replaces done boolean)
-    conn.execute(
-        "ALTER TABLE tasks ADD COLUMN done_at INTEGER",
-        [],
-    ).ok();
-
-    // Migration: Add archived_at timestamp column for tasks (replaces archived boolean)
-    conn.execute(
-        "ALTER TABLE tasks ADD COLUMN archived_at INTEGER",
-        [],
-    ).ok();
-
-    // Migration: Add archived_at timestamp column for projects (replaces archived boolean)
-    conn.execute(
-        "ALTER TABLE projects ADD COLUMN archived_at INTEGER",
-        [],
-    ).ok();
-
-    // Migrate legacy boolean done to done_at timestamp
-    let now = now_seconds();
-    conn.execute(
-        "UPDATE tasks SET done_at = ? WHERE done = 1 AND done_at IS NULL",
-        params![now],
-    ).ok();
-
-    // Migrate legacy boolean archived to archived_at timestamp for tasks
-    conn.execute(
-        "UPDATE tasks SET archived_at = ? WHERE archived = 1 AND archived_at IS NULL",
-        params![now],
-    ).ok();
-
-    // Migrate legacy boolean archived to archived_at timestamp for projects
-    conn.execute(
-        "UPDATE projects SET archived_at = ? WHERE archived = 1 AND archived_at IS NULL",
-        params![now],
-    ).ok();
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS app_state (
-            key TEXT PRIMARY KEY,
-            value TEXT NOT NULL
-        )",
-        [],
-    ).expect("Failed to create app_state table");
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS active_tracking (
-            id INTEGER PRIMARY KEY,
-            project_id INTEGER NOT NULL,
-            task_id INTEGER NOT NULL,
-            started_at INTEGER NOT NULL
-        )",
-        [],
-    ).expect("Failed to create active_tracking table");
-
-    // Migration: Remove single-row constraint for multi-task tracking
-    // This is handled by recreating the table if the constraint exists
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS active_tracking_new (
-            id INTEGER PRIMARY KEY,
-            project_id INTEGER NOT NULL,
-            task_id INTEGER NOT NULL,
-            started_at INTEGER NOT NULL
-        )",
-        [],
-    ).ok();
-    conn.execute(
-        "INSERT OR IGNORE INTO active_tracking_new SELECT * FROM active_tracking",
-        [],
-    ).ok();
-    conn.execute("DROP TABLE IF EXISTS active_tracking", []).ok();
-    conn.execute("ALTER TABLE active_tracking_new RENAME TO active_tracking", []).ok();
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS time_entries (
-            id INTEGER PRIMARY KEY,
-            project_id INTEGER NOT NULL,
-            task_id INTEGER NOT NULL,
-            start_time INTEGER NOT NULL,
-            end_time INTEGER NOT NULL,
-            duration_seconds INTEGER NOT NULL,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
-            FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
-        )",
-        [],
-    ).expect("Failed to create time_entries table");
-}
-
-fn load_projects(conn: &Connection) -> Vec<Project> {
-    let mut stmt = conn.prepare("SELECT id, name, current_task_index FROM projects WHERE archived_at IS NULL ORDER BY id").unwrap();
-    let project_iter = stmt.query_map([], |row| {
-        Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, usize>(2)?))
-    }).unwrap();
-
-    let mut projects = Vec::new();
-    for project_result in project_iter {
-        let (id, name, current_task_index) = project_result.unwrap();
-        let tasks = load_tasks(conn, id);
-        projects.push(Project { id, name, tasks, current_task_index });
-    }
-    projects
-}
-
-const DONE_HIDE_AFTER_SECONDS: u64 = 5 * 60 * 60; // 5 hours
-
-fn load_tasks(conn: &Connection, project_id: u64) -> Vec<Task> {
-    let now = now_seconds();
-    let cutoff = now.saturating_sub(DONE_HIDE_AFTER_SECONDS);
-
-    // Load tasks that are:
-    // - not archived (archived_at IS NULL)
-    // - either not done (done_at IS NULL) OR done recently (done_at > cutoff)
-    let mut stmt = conn.prepare(
-        "SELECT id, name, time_seconds, done_at FROM tasks
-         WHERE project_id = ? AND archived_at IS NULL
-         AND (done_at IS NULL OR done_at > ?)
-         ORDER BY id"
-    ).unwrap();
-    let task_iter = stmt.query_map(params![project_id, cutoff], |row| {
-        Ok(Task {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            time_seconds: row.get(2)?,
-            done_at: row.get(3)?,
-        })
-    }).unwrap();
-
-    task_iter.filter_map(|t| t.ok()).collect()
-}
-
-fn load_current_project_index(conn: &Connection) -> usize {
-    conn.query_row(
-        "SELECT value FROM app_state WHERE key = 'current_project_index'",
-        [],
-        |row| row.get::<_, String>(0),
-    )
-    .ok()
-    .and_then(|v| v.parse().ok())
-    .unwrap_or(0)
-}
-
-fn save_current_project_index(conn: &Connection, index: usize) {
-    conn.execute(
-        "INSERT OR REPLACE INTO app_state (key, value) VALUES ('current_project_index', ?)",
-        [index.to_string()],
-    ).ok();
-}
-
-fn get_next_id(conn: &Connection, table: &str) -> u64 {
-    conn.query_row(
-        &format!("SELECT COALESCE(MAX(id), 0) + 1 FROM {}", table),
-        [],
-        |row| row.get(0),
-    ).unwrap_or(1)
-}
-
-fn load_active_tracking(conn: &Connection) -> Vec<ActiveTracking> {
-    let mut stmt = conn.prepare(
-        "SELECT project_id, task_id, started_at FROM active_tracking"
-    ).unwrap();
-    let tracking_iter = stmt.query_map([], |row| {
-        Ok(ActiveTracking {
-            project_id: row.get(0)?,
-            task_id: row.get(1)?,
-            started_at: row.get(2)?,
-        })
-    }).unwrap();
-    tracking_iter.filter_map(|t| t.ok()).collect()
+#[tauri::command]
+fn get_projects(state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    Ok(state.rotator().projects())
 }
 
-fn add_active_tracking(conn: &Connection, tracking: &ActiveTracking) {
-    conn.execute(
-        "INSERT INTO active_tracking (project_id, task_id, started_at) VALUES (?, ?, ?)",
-        params![tracking.project_id, tracking.task_id, tracking.started_at],
-    ).ok();
+#[tauri::command]
+fn get_current_project_index(state: State<AppState>) -> Result<usize, RotatorError> {
+    Ok(state.rotator().current_project_index())
 }
 
-fn remove_active_tracking(conn: &Connection, task_id: u64) {
-    conn.execute("DELETE FROM active_tracking WHERE task_id = ?", [task_id]).ok();
+#[tauri::command]
+fn add_project(name: String, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().add_project(name)
 }
 
-fn clear_all_active_tracking(conn: &Connection) {
-    conn.execute("DELETE FROM active_tracking", []).ok();
+#[tauri::command]
+fn remove_project(project_id: u64, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().remove_project(project_id)
 }
 
-fn now_seconds() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+#[tauri::command]
+fn rotate_project(trigger: RotationTrigger, state: State<AppState>) -> Result<(usize, Option<Project>), RotatorError> {
+    state.rotator().rotate_project(trigger)
 }
 
 #[tauri::command]
-fn get_projects(state: State<AppState>) -> Vec<Project> {
-    state.projects.lock().unwrap().clone()
+fn set_current_project(index: usize, state: State<AppState>) -> Result<usize, RotatorError> {
+    state.rotator().set_current_project(index)
 }
 
 #[tauri::command]
-fn get_current_project_index(state: State<AppState>) -> usize {
-    *state.current_project_index.lock().unwrap()
+fn select_project(project_id: u64, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().select_project(project_id)
 }
 
 #[tauri::command]
-fn add_project(name: String, state: State<AppState>) -> Vec<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let mut next_id = state.next_project_id.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    let project = Project {
-        id: *next_id,
-        name: name.clone(),
-        tasks: Vec::new(),
-        current_task_index: 0,
-    };
-
-    db.execute(
-        "INSERT INTO projects (id, name, current_task_index) VALUES (?, ?, 0)",
-        params![*next_id, name],
-    ).ok();
-
-    projects.push(project);
-    *next_id += 1;
-
-    projects.clone()
+fn rotate_task(trigger: RotationTrigger, state: State<AppState>) -> Result<Option<Task>, RotatorError> {
+    state.rotator().rotate_task(trigger)
 }
 
 #[tauri::command]
-fn remove_project(project_id: u64, state: State<AppState>) -> Vec<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let mut current = state.current_project_index.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if let Some(pos) = projects.iter().position(|p| p.id == project_id) {
-        // Remove all active tracking for this project's tasks
-        tracking.retain(|t| {
-            if t.project_id == project_id {
-                remove_active_tracking(&db, t.task_id);
-                false
-            } else {
-                true
-            }
-        });
-
-        // Archive instead of delete - set archived_at to current timestamp
-        let now = now_seconds();
-        db.execute("UPDATE tasks SET archived_at = ? WHERE project_id = ?", params![now, project_id]).ok();
-        db.execute("UPDATE projects SET archived_at = ? WHERE id = ?", params![now, project_id]).ok();
-
-        projects.remove(pos);
-        if *current >= projects.len() && !projects.is_empty() {
-            *current = 0;
-        }
-        save_current_project_index(&db, *current);
-    }
-
-    projects.clone()
+fn add_task(project_id: u64, name: String, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().add_task(project_id, name)
 }
 
 #[tauri::command]
-fn rotate_project(state: State<AppState>) -> (usize, Option<Project>) {
-    let projects = state.projects.lock().unwrap();
-    let mut current = state.current_project_index.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if projects.is_empty() {
-        return (0, None);
-    }
-
-    *current = (*current + 1) % projects.len();
-    save_current_project_index(&db, *current);
-    (*current, Some(projects[*current].clone()))
+fn remove_task(project_id: u64, task_id: u64, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().remove_task(project_id, task_id)
 }
 
 #[tauri::command]
-fn set_current_project(index: usize, state: State<AppState>) -> usize {
-    let mut projects = state.projects.lock().unwrap();
-    let mut current = state.current_project_index.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if index < projects.len() && index > 0 {
-        // Move selected project to top
-        let project = projects.remove(index);
-        projects.insert(0, project);
-        *current = 0;
-        save_current_project_index(&db, *current);
-    } else if index < projects.len() {
-        *current = index;
-        save_current_project_index(&db, *current);
-    }
-
-    *current
+fn rename_project(project_id: u64, new_name: String, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().rename_project(project_id, new_name)
 }
 
 #[tauri::command]
-fn rotate_task(state: State<AppState>) -> Option<Task> {
-    let mut projects = state.projects.lock().unwrap();
-    let current_idx = state.current_project_index.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if projects.is_empty() {
-        return None;
-    }
-
-    let project = &mut projects[*current_idx];
-    if project.tasks.is_empty() {
-        return None;
-    }
-
-    // Find the next non-done task
-    let task_count = project.tasks.len();
-    let start_index = project.current_task_index;
-
-    for i in 1..=task_count {
-        let next_index = (start_index + i) % task_count;
-        if project.tasks[next_index].done_at.is_none() {
-            project.current_task_index = next_index;
-            db.execute(
-                "UPDATE projects SET current_task_index = ? WHERE id = ?",
-                params![project.current_task_index, project.id],
-            ).ok();
-            return Some(project.tasks[project.current_task_index].clone());
-        }
-    }
-
-    // All tasks are done, return None
-    None
+fn move_project(project_id: u64, to_index: usize, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().move_project(project_id, to_index)
 }
 
 #[tauri::command]
-fn add_task(project_id: u64, name: String, state: State<AppState>) -> Option<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let mut next_task_id = state.next_task_id.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-        let task = Task {
-            id: *next_task_id,
-            name: name.clone(),
-            time_seconds: 0,
-            done_at: None,
-        };
-
-        db.execute(
-            "INSERT INTO tasks (id, project_id, name, time_seconds, done_at) VALUES (?, ?, ?, 0, NULL)",
-            params![*next_task_id, project_id, name],
-        ).ok();
-
-        project.tasks.push(task);
-        *next_task_id += 1;
-        return Some(project.clone());
-    }
-
-    None
+fn reorder_projects(project_ids: Vec<u64>, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().reorder_projects(&project_ids)
 }
 
 #[tauri::command]
-fn remove_task(project_id: u64, task_id: u64, state: State<AppState>) -> Option<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-        // Remove active tracking for this task if it exists
-        if tracking.iter().any(|t| t.task_id == task_id) {
-            tracking.retain(|t| t.task_id != task_id);
-            remove_active_tracking(&db, task_id);
-        }
-
-        if let Some(pos) = project.tasks.iter().position(|t| t.id == task_id) {
-            // Archive instead of delete - set archived_at to current timestamp
-            let now = now_seconds();
-            db.execute("UPDATE tasks SET archived_at = ? WHERE id = ?", params![now, task_id]).ok();
-
-            project.tasks.remove(pos);
-            if project.current_task_index >= project.tasks.len() && !project.tasks.is_empty() {
-                project.current_task_index = 0;
-            }
-
-            db.execute(
-                "UPDATE projects SET current_task_index = ? WHERE id = ?",
-                params![project.current_task_index, project_id],
-            ).ok();
-        }
-        return Some(project.clone());
-    }
-
-    None
+fn move_task(project_id: u64, task_id: u64, to_index: usize, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().move_task(project_id, task_id, to_index)
 }
 
 #[tauri::command]
-fn rename_project(project_id: u64, new_name: String, state: State<AppState>) -> Vec<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-        project.name = new_name.clone();
-        db.execute(
-            "UPDATE projects SET name = ? WHERE id = ?",
-            params![new_name, project_id],
-        ).ok();
-    }
-
-    projects.clone()
+fn reorder_tasks(project_id: u64, task_ids: Vec<u64>, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().reorder_tasks(project_id, &task_ids)
 }
 
 #[tauri::command]
-fn rename_task(project_id: u64, task_id: u64, new_name: String, state: State<AppState>) -> Option<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-        if let Some(task) = project.tasks.iter_mut().find(|t| t.id == task_id) {
-            task.name = new_name.clone();
-            db.execute(
-                "UPDATE tasks SET name = ? WHERE id = ?",
-                params![new_name, task_id],
-            ).ok();
-        }
-        return Some(project.clone());
-    }
-
-    None
+fn rename_task(project_id: u64, task_id: u64, new_name: String, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().rename_task(project_id, task_id, new_name)
 }
 
 #[tauri::command]
-fn start_tracking(project_id: u64, task_id: u64, allow_multiple: bool, state: State<AppState>) -> Vec<ActiveTracking> {
-    let projects = state.projects.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    // Check if this task is already being tracked
-    if tracking.iter().any(|t| t.task_id == task_id) {
-        return tracking.clone();
-    }
-
-    // If not allowing multiple and there are existing trackings, stop them first
-    if !allow_multiple && !tracking.is_empty() {
-        drop(tracking);
-        drop(projects);
-        drop(db);
-        stop_all_tracking_internal(&state);
-        return start_tracking_internal(project_id, task_id, &state);
-    }
-
-    if projects.iter().any(|p| p.id == project_id && p.tasks.iter().any(|t| t.id == task_id)) {
-        let new_tracking = ActiveTracking {
-            project_id,
-            task_id,
-            started_at: now_seconds(),
-        };
-        add_active_tracking(&db, &new_tracking);
-        tracking.push(new_tracking);
-        return tracking.clone();
-    }
-
-    tracking.clone()
+fn start_tracking(project_id: u64, task_id: u64, allow_multiple: bool, source: Option<AuditSource>, state: State<AppState>) -> Result<Vec<ActiveTracking>, RotatorError> {
+    state.rotator_from(source.unwrap_or(AuditSource::Ui)).start_tracking(project_id, task_id, allow_multiple)
 }
 
-fn start_tracking_internal(project_id: u64, task_id: u64, state: &State<AppState>) -> Vec<ActiveTracking> {
-    let projects = state.projects.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if projects.iter().any(|p| p.id == project_id && p.tasks.iter().any(|t| t.id == task_id)) {
-        let new_tracking = ActiveTracking {
-            project_id,
-            task_id,
-            started_at: now_seconds(),
-        };
-        add_active_tracking(&db, &new_tracking);
-        tracking.push(new_tracking);
-        return tracking.clone();
-    }
-
-    tracking.clone()
+#[tauri::command]
+fn stop_tracking(task_id: Option<u64>, source: Option<AuditSource>, state: State<AppState>) -> Result<Vec<ClosedSession>, RotatorError> {
+    state.rotator_from(source.unwrap_or(AuditSource::Ui)).stop_tracking(task_id)
 }
 
-fn stop_all_tracking_internal(state: &State<AppState>) {
-    let mut projects = state.projects.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    let end_time = now_seconds();
-
-    for t in tracking.iter() {
-        let elapsed = end_time - t.started_at;
-
-        // Only save if elapsed >= 3 seconds
-        if elapsed >= 3 {
-            if let Some(project) = projects.iter_mut().find(|p| p.id == t.project_id) {
-                if let Some(task) = project.tasks.iter_mut().find(|tk| tk.id == t.task_id) {
-                    task.time_seconds += elapsed;
-                    db.execute(
-                        "UPDATE tasks SET time_seconds = ? WHERE id = ?",
-                        params![task.time_seconds, task.id],
-                    ).ok();
-
-                    // Save time entry
-                    db.execute(
-                        "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
-                        params![t.project_id, t.task_id, t.started_at, end_time, elapsed],
-                    ).ok();
-                }
-            }
-        }
-    }
-
-    clear_all_active_tracking(&db);
-    tracking.clear();
-}
-
-fn stop_tracking_for_task_internal(state: &State<AppState>, task_id: u64) -> Option<u64> {
-    let mut projects = state.projects.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if let Some(t) = tracking.iter().find(|t| t.task_id == task_id).cloned() {
-        let end_time = now_seconds();
-        let elapsed = end_time - t.started_at;
-
-        // Only save if elapsed >= 3 seconds
-        if elapsed >= 3 {
-            if let Some(project) = projects.iter_mut().find(|p| p.id == t.project_id) {
-                if let Some(task) = project.tasks.iter_mut().find(|tk| tk.id == t.task_id) {
-                    task.time_seconds += elapsed;
-                    db.execute(
-                        "UPDATE tasks SET time_seconds = ? WHERE id = ?",
-                        params![task.time_seconds, task.id],
-                    ).ok();
-
-                    // Save time entry
-                    db.execute(
-                        "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
-                        params![t.project_id, t.task_id, t.started_at, end_time, elapsed],
-                    ).ok();
-                }
-            }
-        }
-
-        remove_active_tracking(&db, task_id);
-        tracking.retain(|t| t.task_id != task_id);
-        return Some(elapsed);
-    }
-
-    None
+#[tauri::command]
+fn pause_tracking(task_id: Option<u64>, source: Option<AuditSource>, state: State<AppState>) -> Result<Vec<ActiveTracking>, RotatorError> {
+    state.rotator_from(source.unwrap_or(AuditSource::Ui)).pause_tracking(task_id)
 }
 
 #[tauri::command]
-fn stop_tracking(task_id: Option<u64>, state: State<AppState>) -> Option<u64> {
-    // If task_id is provided, stop only that task
-    if let Some(tid) = task_id {
-        return stop_tracking_for_task_internal(&state, tid);
-    }
-
-    // Otherwise stop all tracking
-    let mut projects = state.projects.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    if tracking.is_empty() {
-        return None;
-    }
-
-    let end_time = now_seconds();
-    let mut total_elapsed: u64 = 0;
-
-    for t in tracking.iter() {
-        let elapsed = end_time - t.started_at;
-        total_elapsed += elapsed;
-
-        // Only save if elapsed >= 3 seconds
-        if elapsed >= 3 {
-            if let Some(project) = projects.iter_mut().find(|p| p.id == t.project_id) {
-                if let Some(task) = project.tasks.iter_mut().find(|tk| tk.id == t.task_id) {
-                    task.time_seconds += elapsed;
-                    db.execute(
-                        "UPDATE tasks SET time_seconds = ? WHERE id = ?",
-                        params![task.time_seconds, task.id],
-                    ).ok();
-
-                    // Save time entry
-                    db.execute(
-                        "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
-                        params![t.project_id, t.task_id, t.started_at, end_time, elapsed],
-                    ).ok();
-                }
-            }
-        }
-    }
-
-    clear_all_active_tracking(&db);
-    tracking.clear();
-    Some(total_elapsed)
+fn resume_tracking(task_id: Option<u64>, source: Option<AuditSource>, state: State<AppState>) -> Result<Vec<ActiveTracking>, RotatorError> {
+    state.rotator_from(source.unwrap_or(AuditSource::Ui)).resume_tracking(task_id)
 }
 
 #[tauri::command]
-fn get_active_tracking(state: State<AppState>) -> Vec<ActiveTracking> {
-    state.active_tracking.lock().unwrap().clone()
+fn get_active_tracking(state: State<AppState>) -> Result<Vec<ActiveTracking>, RotatorError> {
+    Ok(state.rotator().active_tracking())
 }
 
 #[tauri::command]
-fn get_current_project(state: State<AppState>) -> Option<Project> {
-    let projects = state.projects.lock().unwrap();
-    let current = state.current_project_index.lock().unwrap();
-
-    if projects.is_empty() {
-        None
-    } else {
-        Some(projects[*current].clone())
-    }
+fn get_current_project(state: State<AppState>) -> Result<Option<Project>, RotatorError> {
+    Ok(state.rotator().current_project())
 }
 
 #[tauri::command]
-fn get_time_entries(state: State<AppState>, start_time: u64, end_time: u64) -> Vec<TimeEntry> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db.prepare(
-        "SELECT id, project_id, task_id, start_time, end_time, duration_seconds
-         FROM time_entries
-         WHERE start_time >= ? AND start_time <= ?
-         ORDER BY start_time"
-    ).unwrap();
-
-    let entries = stmt.query_map(params![start_time, end_time], |row| {
-        Ok(TimeEntry {
-            id: row.get(0)?,
-            project_id: row.get(1)?,
-            task_id: row.get(2)?,
-            start_time: row.get(3)?,
-            end_time: row.get(4)?,
-            duration_seconds: row.get(5)?,
-        })
-    }).unwrap();
-
-    entries.filter_map(|e| e.ok()).collect()
+fn get_time_entries(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<TimeEntry>, RotatorError> {
+    state.rotator().time_entries(start_time, end_time)
 }
 
 #[tauri::command]
-fn get_hourly_activity(state: State<AppState>, start_time: u64, end_time: u64) -> Vec<HourlyActivity> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db.prepare(
-        "SELECT (start_time % 86400) / 3600 as hour, SUM(duration_seconds) as total
-         FROM time_entries
-         WHERE start_time >= ? AND start_time <= ?
-         GROUP BY hour
-         ORDER BY hour"
-    ).unwrap();
-
-    let activities = stmt.query_map(params![start_time, end_time], |row| {
-        Ok(HourlyActivity {
-            hour: row.get(0)?,
-            total_seconds: row.get(1)?,
-        })
-    }).unwrap();
+fn get_hourly_activity(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<HourlyActivity>, RotatorError> {
+    state.rotator().hourly_activity(start_time, end_time)
+}
 
-    activities.filter_map(|a| a.ok()).collect()
+#[tauri::command]
+fn get_daily_activity(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<DailyActivity>, RotatorError> {
+    state.rotator().daily_activity(start_time, end_time)
 }
 
 #[tauri::command]
-fn get_daily_activity(state: State<AppState>, start_time: u64, end_time: u64) -> Vec<DailyActivity> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db.prepare(
-        "SELECT date(start_time, 'unixepoch', 'localtime') as day, SUM(duration_seconds) as total
-         FROM time_entries
-         WHERE start_time >= ? AND start_time <= ?
-         GROUP BY day
-         ORDER BY day"
-    ).unwrap();
+fn get_stats_timezone(state: State<AppState>) -> Result<Option<String>, RotatorError> {
+    state.rotator().stats_timezone()
+}
 
-    let activities = stmt.query_map(params![start_time, end_time], |row| {
-        Ok(DailyActivity {
-            date: row.get(0)?,
-            total_seconds: row.get(1)?,
-        })
-    }).unwrap();
+/// Bucket hourly and daily stats in an IANA zone, or the system's with `None`.
+#[tauri::command]
+fn set_stats_timezone(timezone: Option<String>, state: State<AppState>) -> Result<Option<String>, RotatorError> {
+    state.rotator().set_stats_timezone(timezone)
+}
 
-    activities.filter_map(|a| a.ok()).collect()
+#[tauri::command]
+fn get_rotation_events(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<RotationEvent>, RotatorError> {
+    state.rotator().rotation_events(start_time, end_time)
 }
 
 #[tauri::command]
-fn get_project_time_stats(state: State<AppState>, start_time: u64, end_time: u64) -> Vec<ProjectTimeStats> {
-    let db = state.db.lock().unwrap();
-    let projects = state.projects.lock().unwrap();
+fn get_context_switches(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<DailyContextSwitches>, RotatorError> {
+    state.rotator().context_switches_per_day(start_time, end_time)
+}
 
-    let mut stmt = db.prepare(
-        "SELECT project_id, SUM(duration_seconds) as total
-         FROM time_entries
-         WHERE start_time >= ? AND start_time <= ?
-         GROUP BY project_id
-         ORDER BY total DESC"
-    ).unwrap();
+#[tauri::command]
+fn get_dwell_times(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<DwellTime>, RotatorError> {
+    state.rotator().average_dwell_times(start_time, end_time)
+}
 
-    let stats = stmt.query_map(params![start_time, end_time], |row| {
-        Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?))
-    }).unwrap();
+#[tauri::command]
+fn get_project_time_stats(state: State<AppState>, start_time: u64, end_time: u64) -> Result<Vec<ProjectTimeStats>, RotatorError> {
+    state.rotator().project_time_stats(start_time, end_time)
+}
 
-    stats.filter_map(|s| s.ok())
-        .map(|(project_id, total_seconds)| {
-            let project_name = projects.iter()
-                .find(|p| p.id == project_id)
-                .map(|p| p.name.clone())
-                .unwrap_or_else(|| "Unknown".to_string());
-            ProjectTimeStats { project_id, project_name, total_seconds }
-        })
-        .collect()
-}
-
-#[tauri::command]
-fn get_all_time_entries(state: State<AppState>) -> Vec<TimeEntry> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db.prepare(
-        "SELECT id, project_id, task_id, start_time, end_time, duration_seconds
-         FROM time_entries
-         ORDER BY start_time"
-    ).unwrap();
-
-    let entries = stmt.query_map([], |row| {
-        Ok(TimeEntry {
-            id: row.get(0)?,
-            project_id: row.get(1)?,
-            task_id: row.get(2)?,
-            start_time: row.get(3)?,
-            end_time: row.get(4)?,
-            duration_seconds: row.get(5)?,
-        })
-    }).unwrap();
+#[tauri::command]
+fn get_all_time_entries(state: State<AppState>) -> Result<Vec<TimeEntry>, RotatorError> {
+    state.rotator().all_time_entries()
+}
 
-    entries.filter_map(|e| e.ok()).collect()
+/// Write time entries to `path`, or to the Downloads folder when it is left
+/// out, and return where they went.
+#[tauri::command]
+fn export_time_entries(options: ExportOptions, path: Option<String>, state: State<AppState>) -> Result<String, RotatorError> {
+    let path = path.map(PathBuf::from).unwrap_or_else(|| {
+        let extension = match options.format {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        };
+        dirs::download_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(format!("rotator-time-entries.{}", extension))
+    });
+    state.rotator().export_time_entries_to(&options, &path)?;
+    Ok(path.display().to_string())
 }
 
 #[tauri::command]
@@ -888,6 +251,10 @@ struct FloatingTimerEntry {
     project_name: String,
     task_name: String,
     started_at: u64, // Unix timestamp
+    #[serde(default)]
+    paused_at: Option<u64>,
+    #[serde(default)]
+    paused_seconds: u64,
 }
 
 #[tauri::command]
@@ -900,6 +267,8 @@ fn update_floating_timer(entries: Vec<FloatingTimerEntry>) -> Result<(), String>
                 project_name: e.project_name,
                 task_name: e.task_name,
                 started_at: e.started_at,
+                paused_at: e.paused_at,
+                paused_seconds: e.paused_seconds,
             })
             .collect(),
     });
@@ -939,346 +308,389 @@ fn emit_tracking_updated(app: AppHandle) -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct ProjectWithStatus {
-    id: u64,
-    name: String,
-    tasks: Vec<TaskWithStatus>,
-    current_task_index: usize,
-    archived_at: Option<u64>,
+#[tauri::command]
+fn get_all_projects_with_status(state: State<AppState>) -> Result<Vec<ProjectWithStatus>, RotatorError> {
+    state.rotator().all_projects_with_status()
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct TaskWithStatus {
-    id: u64,
-    name: String,
-    time_seconds: u64,
-    archived_at: Option<u64>,
-    done_at: Option<u64>,
-}
-
-#[tauri::command]
-fn get_all_projects_with_status(state: State<AppState>) -> Vec<ProjectWithStatus> {
-    let db = state.db.lock().unwrap();
-    let mut stmt = db.prepare("SELECT id, name, current_task_index, archived_at FROM projects ORDER BY archived_at IS NOT NULL, id").unwrap();
-    let project_iter = stmt.query_map([], |row| {
-        Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, usize>(2)?, row.get::<_, Option<u64>>(3)?))
-    }).unwrap();
-
-    let mut projects = Vec::new();
-    for project_result in project_iter {
-        let (id, name, current_task_index, archived_at) = project_result.unwrap();
-        // Load all tasks for this project
-        let mut task_stmt = db.prepare("SELECT id, name, time_seconds, archived_at, done_at FROM tasks WHERE project_id = ? ORDER BY archived_at IS NOT NULL, id").unwrap();
-        let task_iter = task_stmt.query_map([id], |row| {
-            Ok(TaskWithStatus {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                time_seconds: row.get(2)?,
-                archived_at: row.get(3)?,
-                done_at: row.get(4)?,
-            })
-        }).unwrap();
-        let tasks: Vec<TaskWithStatus> = task_iter.filter_map(|t| t.ok()).collect();
-        projects.push(ProjectWithStatus { id, name, tasks, current_task_index, archived_at });
-    }
-    projects
+#[tauri::command]
+fn restore_project(project_id: u64, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().restore_project(project_id)
 }
 
 #[tauri::command]
-fn restore_project(project_id: u64, state: State<AppState>) -> Vec<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let db = state.db.lock().unwrap();
+fn restore_task(project_id: u64, task_id: u64, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().restore_task(project_id, task_id)
+}
 
-    // Restore project and its tasks - set archived_at to NULL
-    db.execute("UPDATE projects SET archived_at = NULL WHERE id = ?", [project_id]).ok();
-    db.execute("UPDATE tasks SET archived_at = NULL WHERE project_id = ?", [project_id]).ok();
+#[tauri::command]
+fn toggle_task_done(project_id: u64, task_id: u64, done: bool, state: State<AppState>) -> Result<Project, RotatorError> {
+    state.rotator().toggle_task_done(project_id, task_id, done)
+}
 
-    // Reload the project
-    let mut stmt = db.prepare("SELECT id, name, current_task_index FROM projects WHERE id = ?").unwrap();
-    if let Ok((id, name, current_task_index)) = stmt.query_row([project_id], |row| {
-        Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, usize>(2)?))
-    }) {
-        let tasks = load_tasks(&db, id);
-        projects.push(Project { id, name, tasks, current_task_index });
-    }
+#[tauri::command]
+fn delete_task_permanent(task_id: u64, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().delete_task_permanent(task_id)
+}
 
-    projects.clone()
+#[tauri::command]
+fn delete_project_permanent(project_id: u64, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().delete_project_permanent(project_id)
 }
 
 #[tauri::command]
-fn restore_task(project_id: u64, task_id: u64, state: State<AppState>) -> Option<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let db = state.db.lock().unwrap();
+fn reset_database(state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().reset_database()
+}
 
-    // Restore the task - set archived_at to NULL
-    db.execute("UPDATE tasks SET archived_at = NULL WHERE id = ?", [task_id]).ok();
+#[tauri::command]
+fn add_time_entry_manual(
+    project_id: u64,
+    task_id: u64,
+    start_time: u64,
+    duration_seconds: u64,
+    state: State<AppState>,
+) -> Result<TimeEntry, RotatorError> {
+    state.rotator().add_time_entry_manual(project_id, task_id, start_time, duration_seconds)
+}
 
-    // Find the project and reload its tasks
-    if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-        let mut stmt = db.prepare("SELECT id, name, time_seconds, done_at FROM tasks WHERE id = ?").unwrap();
-        if let Ok(task) = stmt.query_row([task_id], |row| {
-            Ok(Task {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                time_seconds: row.get(2)?,
-                done_at: row.get(3)?,
-            })
-        }) {
-            project.tasks.push(task);
-        }
-        return Some(project.clone());
-    }
+#[tauri::command]
+fn update_time_entry(entry_id: u64, start_time: u64, end_time: u64, state: State<AppState>) -> Result<TimeEntry, RotatorError> {
+    state.rotator().update_time_entry(entry_id, start_time, end_time)
+}
 
-    None
+#[tauri::command]
+fn delete_time_entry(entry_id: u64, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().delete_time_entry(entry_id)
 }
 
 #[tauri::command]
-fn toggle_task_done(project_id: u64, task_id: u64, done: bool, state: State<AppState>) -> Option<Project> {
-    let mut projects = state.projects.lock().unwrap();
-    let db = state.db.lock().unwrap();
+fn split_time_entry(entry_id: u64, at: u64, state: State<AppState>) -> Result<(TimeEntry, TimeEntry), RotatorError> {
+    state.rotator().split_time_entry(entry_id, at)
+}
 
-    let done_at = if done { Some(now_seconds()) } else { None };
+#[tauri::command]
+fn merge_time_entries(first_id: u64, second_id: u64, state: State<AppState>) -> Result<TimeEntry, RotatorError> {
+    state.rotator().merge_time_entries(first_id, second_id)
+}
 
-    if done {
-        db.execute(
-            "UPDATE tasks SET done_at = ? WHERE id = ?",
-            params![done_at, task_id],
-        ).ok();
-    } else {
-        db.execute(
-            "UPDATE tasks SET done_at = NULL WHERE id = ?",
-            params![task_id],
-        ).ok();
-    }
+#[tauri::command]
+fn get_idle_settings(state: State<AppState>) -> Result<IdleSettings, RotatorError> {
+    Ok(state.rotator().idle_settings())
+}
 
-    if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-        if let Some(task) = project.tasks.iter_mut().find(|t| t.id == task_id) {
-            task.done_at = done_at;
-        }
-        return Some(project.clone());
-    }
+#[tauri::command]
+fn set_idle_settings(settings: IdleSettings, state: State<AppState>) -> Result<IdleSettings, RotatorError> {
+    state.rotator().set_idle_settings(settings)
+}
 
-    None
+#[tauri::command]
+fn get_pending_idle(state: State<AppState>) -> Result<Option<IdlePeriod>, RotatorError> {
+    Ok(state.rotator().pending_idle())
 }
 
 #[tauri::command]
-fn delete_task_permanent(task_id: u64, state: State<AppState>) -> bool {
-    let db = state.db.lock().unwrap();
+fn resolve_idle(resolution: IdleResolution, state: State<AppState>) -> Result<Vec<ActiveTracking>, RotatorError> {
+    state.rotator().resolve_idle(resolution)
+}
 
-    // Permanently delete the task and its time entries
-    db.execute("DELETE FROM time_entries WHERE task_id = ?", [task_id]).ok();
-    db.execute("DELETE FROM tasks WHERE id = ?", [task_id]).ok();
+#[tauri::command]
+fn get_orphaned_sessions(state: State<AppState>) -> Result<Vec<OrphanedSession>, RotatorError> {
+    Ok(state.rotator().orphaned_sessions())
+}
 
-    true
+#[tauri::command]
+fn resolve_orphaned_session(task_id: u64, resolution: OrphanResolution, state: State<AppState>) -> Result<Vec<ActiveTracking>, RotatorError> {
+    state.rotator().resolve_orphaned_session(task_id, resolution)
 }
 
 #[tauri::command]
-fn delete_project_permanent(project_id: u64, state: State<AppState>) -> bool {
-    let db = state.db.lock().unwrap();
+fn get_time_policy(state: State<AppState>) -> Result<TimePolicy, RotatorError> {
+    state.rotator().time_policy()
+}
 
-    // Permanently delete the project, its tasks, and time entries
-    db.execute("DELETE FROM time_entries WHERE project_id = ?", [project_id]).ok();
-    db.execute("DELETE FROM tasks WHERE project_id = ?", [project_id]).ok();
-    db.execute("DELETE FROM projects WHERE id = ?", [project_id]).ok();
+#[tauri::command]
+fn set_time_policy(policy: TimePolicy, state: State<AppState>) -> Result<TimePolicy, RotatorError> {
+    state.rotator().set_time_policy(policy)
+}
 
-    true
+#[tauri::command]
+fn get_project_time_policy(project_id: u64, state: State<AppState>) -> Result<Option<TimePolicy>, RotatorError> {
+    state.rotator().project_time_policy(project_id)
 }
 
 #[tauri::command]
-fn reset_database(state: State<AppState>) -> Vec<Project> {
-    let db = state.db.lock().unwrap();
-    let mut projects = state.projects.lock().unwrap();
-    let mut current_index = state.current_project_index.lock().unwrap();
-    let mut next_project_id = state.next_project_id.lock().unwrap();
-    let mut next_task_id = state.next_task_id.lock().unwrap();
-    let mut tracking = state.active_tracking.lock().unwrap();
+fn set_project_time_policy(project_id: u64, policy: Option<TimePolicy>, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().set_project_time_policy(project_id, policy)
+}
 
-    // Clear all data from tables
-    db.execute("DELETE FROM time_entries", []).ok();
-    db.execute("DELETE FROM active_tracking", []).ok();
-    db.execute("DELETE FROM tasks", []).ok();
-    db.execute("DELETE FROM projects", []).ok();
-    db.execute("DELETE FROM app_state", []).ok();
+#[tauri::command]
+fn get_project_rotation_strategy(state: State<AppState>) -> Result<RotationStrategyKind, RotatorError> {
+    state.rotator().project_rotation_strategy()
+}
 
-    // Reset app state
-    *projects = Vec::new();
-    *current_index = 0;
-    *next_project_id = 1;
-    *next_task_id = 1;
-    tracking.clear();
+#[tauri::command]
+fn set_project_rotation_strategy(strategy: RotationStrategyKind, state: State<AppState>) -> Result<RotationStrategyKind, RotatorError> {
+    state.rotator().set_project_rotation_strategy(strategy)
+}
 
-    projects.clone()
+#[tauri::command]
+fn get_task_rotation_strategy(project_id: u64, state: State<AppState>) -> Result<RotationStrategyKind, RotatorError> {
+    state.rotator().task_rotation_strategy(project_id)
 }
 
 #[tauri::command]
-fn add_time_entry_manual(
+fn set_task_rotation_strategy(project_id: u64, strategy: RotationStrategyKind, state: State<AppState>) -> Result<RotationStrategyKind, RotatorError> {
+    state.rotator().set_task_rotation_strategy(project_id, strategy)
+}
+
+#[tauri::command]
+fn set_project_rotation_weight(project_id: u64, weight: f64, target_seconds: Option<u64>, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().set_project_rotation_weight(project_id, weight, target_seconds)
+}
+
+#[tauri::command]
+fn set_task_rotation_weight(task_id: u64, weight: f64, target_seconds: Option<u64>, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().set_task_rotation_weight(task_id, weight, target_seconds)
+}
+
+#[tauri::command]
+fn get_budgets(state: State<AppState>) -> Result<Vec<Budget>, RotatorError> {
+    state.rotator().budgets()
+}
+
+#[tauri::command]
+fn set_budget(
     project_id: u64,
-    task_id: u64,
-    start_time: u64,
-    duration_seconds: u64,
+    task_id: Option<u64>,
+    period: BudgetPeriod,
+    limit_seconds: u64,
     state: State<AppState>,
-) -> bool {
-    let mut projects = state.projects.lock().unwrap();
-    let db = state.db.lock().unwrap();
-
-    let end_time = start_time + duration_seconds;
-
-    // Insert the time entry
-    if db.execute(
-        "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
-        params![project_id, task_id, start_time, end_time, duration_seconds],
-    ).is_ok() {
-        // Update the task's total time
-        db.execute(
-            "UPDATE tasks SET time_seconds = time_seconds + ? WHERE id = ?",
-            params![duration_seconds, task_id],
-        ).ok();
-
-        // Update in-memory project state
-        if let Some(project) = projects.iter_mut().find(|p| p.id == project_id) {
-            if let Some(task) = project.tasks.iter_mut().find(|t| t.id == task_id) {
-                task.time_seconds += duration_seconds;
-            }
-        }
+) -> Result<Budget, RotatorError> {
+    state.rotator().set_budget(project_id, task_id, period, limit_seconds)
+}
+
+#[tauri::command]
+fn remove_budget(budget_id: u64, state: State<AppState>) -> Result<(), RotatorError> {
+    state.rotator().remove_budget(budget_id)
+}
 
-        return true;
+#[tauri::command]
+fn get_budget_statuses(state: State<AppState>) -> Result<Vec<BudgetStatus>, RotatorError> {
+    state.rotator().budget_statuses()
+}
+
+/// Tell the frontend about reached budgets and nudge the floating panel to
+/// suggest moving on.
+fn emit_budget_events(app: &AppHandle, rotator: &Rotator, events: &[BudgetEvent]) {
+    for event in events {
+        let _ = app.emit("budget-reached", event);
+        let BudgetEvent::Reached { status } = event;
+        let project = rotator.projects().into_iter().find(|p| p.id == status.budget.project_id);
+        let target = match (&project, status.budget.task_id) {
+            (Some(project), Some(task_id)) => project.tasks.iter()
+                .find(|t| t.id == task_id)
+                .map(|t| t.name.clone())
+                .unwrap_or_default(),
+            (Some(project), None) => project.name.clone(),
+            (None, _) => continue,
+        };
+        set_rotation_preview("Budget reached".to_string(), target, None, false, None);
     }
+}
 
-    false
-}
-
-#[tauri::command]
-fn add_mock_data(state: State<AppState>) -> Vec<Project> {
-    let db = state.db.lock().unwrap();
-    let mut projects = state.projects.lock().unwrap();
-    let mut next_project_id = state.next_project_id.lock().unwrap();
-    let mut next_task_id = state.next_task_id.lock().unwrap();
-
-    let now = now_seconds();
-    let day_seconds: u64 = 24 * 60 * 60;
-
-    let mock_projects = vec![
-        ("Work", vec!["Code review", "Write documentation", "Fix bugs", "Team meeting"]),
-        ("Personal", vec!["Exercise", "Read book", "Learn Rust", "Side project"]),
-        ("Learning", vec!["Online course", "Practice coding", "Watch tutorials"]),
-    ];
-
-    // Time entries for each task (days_ago, hour, duration_minutes)
-    let time_entries_template: Vec<(u64, u64, u64)> = vec![
-        (0, 9, 45), (0, 14, 30), (0, 16, 20),
-        (1, 10, 60), (1, 15, 25),
-        (2, 9, 50), (2, 11, 40), (2, 14, 35),
-        (3, 10, 55), (3, 13, 30), (3, 16, 20),
-        (4, 9, 45), (4, 11, 30),
-        (5, 14, 60), (5, 16, 25),
-        (6, 10, 40), (6, 15, 50),
-        (7, 9, 35), (7, 11, 45), (7, 14, 30),
-        (10, 10, 50), (10, 15, 40),
-        (14, 9, 60), (14, 14, 45),
-        (21, 10, 55), (21, 16, 35),
-        (30, 11, 45), (30, 15, 30),
-    ];
-
-    // Calculate the start of today (midnight UTC) for proper day offset calculation
-    let today_start = (now / day_seconds) * day_seconds;
-
-    let mut task_counter: usize = 0;
-
-    for (project_name, tasks) in mock_projects {
-        let project_id = *next_project_id;
-        db.execute(
-            "INSERT INTO projects (id, name, current_task_index) VALUES (?, ?, 0)",
-            params![project_id, project_name],
-        ).ok();
-
-        let mut project_tasks = Vec::new();
-        for task_name in tasks {
-            let task_id = *next_task_id;
-
-            // Generate time entries for this task
-            let mut total_time: u64 = 0;
-            let entries_offset = task_counter % 5; // Offset entries to vary by task
-
-            // Calculate total time first
-            for (days_ago, _, duration_minutes) in &time_entries_template {
-                if (*days_ago + entries_offset as u64) % 3 == 0 {
-                    continue;
-                }
-                total_time += duration_minutes * 60;
+/// Forward timebox events to the frontend and point the floating panel's
+/// rotation preview at whatever block just started.
+fn emit_timebox_events(app: &AppHandle, rotator: &Rotator, events: &[TimeboxEvent]) {
+    for event in events {
+        let _ = app.emit("timebox", event);
+        if let TimeboxEvent::BlockStarted { block } = event {
+            if let (BlockKind::Work, Some(project_id), Some(task_id)) = (block.kind, block.project_id, block.task_id) {
+                let project = rotator.projects().into_iter().find(|p| p.id == project_id);
+                let task_name = project.as_ref()
+                    .and_then(|p| p.tasks.iter().find(|t| t.id == task_id))
+                    .map(|t| t.name.clone())
+                    .unwrap_or_default();
+                let project_name = project.map(|p| p.name).unwrap_or_default();
+                set_rotation_preview(project_name, task_name, Some(task_id), true, Some(block.started_at));
+            } else {
+                let minutes = block.ends_at.saturating_sub(block.started_at) / 60;
+                set_rotation_preview("Break".to_string(), format!("{} min", minutes), None, false, None);
             }
+        }
+    }
+    if !events.is_empty() {
+        let _ = app.emit("tracking-updated", ());
+    }
+}
 
-            // Insert task FIRST (before time entries due to foreign key constraint)
-            db.execute(
-                "INSERT INTO tasks (id, project_id, name, time_seconds) VALUES (?, ?, ?, ?)",
-                params![task_id, project_id, task_name, total_time],
-            ).ok();
-
-            // Now insert time entries (task exists, foreign key satisfied)
-            for (days_ago, hour, duration_minutes) in &time_entries_template {
-                // Skip some entries based on task to create variation
-                if (*days_ago + entries_offset as u64) % 3 == 0 {
-                    continue;
-                }
+#[tauri::command]
+fn get_timebox_settings(state: State<AppState>) -> Result<TimeboxSettings, RotatorError> {
+    state.rotator().timebox_settings()
+}
 
-                let duration_seconds = duration_minutes * 60;
-                // Calculate proper timestamp: start of day X days ago + hour offset
-                let day_start = today_start - (days_ago * day_seconds);
-                let start_time = day_start + (hour * 3600);
-                let end_time = start_time + duration_seconds;
+#[tauri::command]
+fn set_timebox_settings(settings: TimeboxSettings, state: State<AppState>) -> Result<TimeboxSettings, RotatorError> {
+    state.rotator().set_timebox_settings(settings)
+}
 
-                db.execute(
-                    "INSERT INTO time_entries (project_id, task_id, start_time, end_time, duration_seconds) VALUES (?, ?, ?, ?, ?)",
-                    params![project_id, task_id, start_time, end_time, duration_seconds],
-                ).ok();
-            }
+#[tauri::command]
+fn get_active_block(state: State<AppState>) -> Result<Option<ActiveBlock>, RotatorError> {
+    Ok(state.rotator().active_block())
+}
 
-            project_tasks.push(Task {
-                id: task_id,
-                name: task_name.to_string(),
-                time_seconds: total_time,
-                done_at: None,
-            });
-            *next_task_id += 1;
-            task_counter += 1;
-        }
+#[tauri::command]
+fn start_timebox(allow_multiple: bool, app: AppHandle, state: State<AppState>) -> Result<Option<ActiveBlock>, RotatorError> {
+    let mut rotator = state.rotator();
+    let events = rotator.start_timebox(allow_multiple)?;
+    emit_timebox_events(&app, &rotator, &events);
+    Ok(rotator.active_block())
+}
 
-        projects.push(Project {
-            id: project_id,
-            name: project_name.to_string(),
-            tasks: project_tasks,
-            current_task_index: 0,
-        });
-        *next_project_id += 1;
-    }
+#[tauri::command]
+fn stop_timebox(app: AppHandle, state: State<AppState>) -> Result<(), RotatorError> {
+    let mut rotator = state.rotator();
+    let events = rotator.stop_timebox()?;
+    emit_timebox_events(&app, &rotator, &events);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_timebox_blocks(start_time: u64, end_time: u64, state: State<AppState>) -> Result<Vec<TimeboxBlock>, RotatorError> {
+    state.rotator().timebox_blocks(start_time, end_time)
+}
+
+#[tauri::command]
+fn recompute_totals(state: State<AppState>) -> Result<Vec<TotalDrift>, RotatorError> {
+    state.rotator().recompute_totals()
+}
+
+#[tauri::command]
+fn add_mock_data(state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    state.rotator().add_mock_data()
+}
+
+#[tauri::command]
+fn list_backups(state: State<AppState>) -> Result<Vec<BackupInfo>, RotatorError> {
+    state.rotator().list_backups()
+}
+
+#[tauri::command]
+fn create_backup(state: State<AppState>) -> Result<Option<BackupInfo>, RotatorError> {
+    state.rotator().create_backup(BackupReason::Manual)
+}
+
+#[tauri::command]
+fn restore_backup(file_name: String, app: AppHandle, state: State<AppState>) -> Result<Vec<Project>, RotatorError> {
+    let mut rotator = state.rotator();
+    rotator.restore_backup(&file_name)?;
+    let _ = app.emit("tracking-updated", ());
+    Ok(rotator.projects())
+}
+
+#[tauri::command]
+fn get_backup_retention(state: State<AppState>) -> Result<BackupRetention, RotatorError> {
+    state.rotator().backup_retention()
+}
+
+#[tauri::command]
+fn set_backup_retention(retention: BackupRetention, state: State<AppState>) -> Result<BackupRetention, RotatorError> {
+    state.rotator().set_backup_retention(retention)
+}
 
-    projects.clone()
+#[tauri::command]
+fn undo(app: AppHandle, state: State<AppState>) -> Result<Option<Change>, RotatorError> {
+    let change = state.rotator().undo()?;
+    let _ = app.emit("tracking-updated", ());
+    Ok(change)
+}
+
+#[tauri::command]
+fn redo(app: AppHandle, state: State<AppState>) -> Result<Option<Change>, RotatorError> {
+    let change = state.rotator().redo()?;
+    let _ = app.emit("tracking-updated", ());
+    Ok(change)
+}
+
+#[tauri::command]
+fn get_undo_state(state: State<AppState>) -> Result<UndoState, RotatorError> {
+    state.rotator().undo_state()
+}
+
+/// Write the whole database as a JSON archive to `path`, or to the Downloads
+/// folder when it is left out, and return where it went.
+#[tauri::command]
+fn export_archive(path: Option<String>, state: State<AppState>) -> Result<String, RotatorError> {
+    let path = path.map(PathBuf::from).unwrap_or_else(|| {
+        dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")).join("rotator-archive.json")
+    });
+    state.rotator().export_archive_to(&path)?;
+    Ok(path.display().to_string())
+}
+
+#[tauri::command]
+fn import_archive(app: AppHandle, path: String, mode: ImportMode, state: State<AppState>) -> Result<ImportReport, RotatorError> {
+    let report = state.rotator().import_archive_from(&PathBuf::from(path), mode)?;
+    let _ = app.emit("tracking-updated", ());
+    Ok(report)
+}
+
+/// What importing another tracker's export at `path` would do, without
+/// writing anything.
+#[tauri::command]
+fn preview_tracker_import(source: ImportSource, path: String, state: State<AppState>) -> Result<TrackerImportReport, RotatorError> {
+    let text = std::fs::read_to_string(path)?;
+    state.rotator().preview_tracker_import(source, &text)
+}
+
+#[tauri::command]
+fn import_tracker(app: AppHandle, source: ImportSource, path: String, state: State<AppState>) -> Result<TrackerImportReport, RotatorError> {
+    let text = std::fs::read_to_string(path)?;
+    let report = state.rotator().run_tracker_import(source, &text)?;
+    let _ = app.emit("tracking-updated", ());
+    Ok(report)
+}
+
+/// Write the time entries starting in the range as an iCalendar file to
+/// `path`, or to the Downloads folder, and return where it went.
+#[tauri::command]
+fn export_calendar(from: u64, to: u64, path: Option<String>, state: State<AppState>) -> Result<String, RotatorError> {
+    let path = path.map(PathBuf::from).unwrap_or_else(|| {
+        dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")).join("rotator-time-entries.ics")
+    });
+    state.rotator().export_calendar_to(from, to, &path)?;
+    Ok(path.display().to_string())
+}
+
+#[tauri::command]
+fn get_calendar_feed_settings(state: State<AppState>) -> Result<CalendarFeedSettings, RotatorError> {
+    state.rotator().calendar_feed_settings()
+}
+
+/// Save the feed settings and start, stop or move the feed to match.
+#[tauri::command]
+fn set_calendar_feed_settings(app: AppHandle, settings: CalendarFeedSettings, state: State<AppState>) -> Result<CalendarFeedSettings, RotatorError> {
+    let settings = state.rotator().set_calendar_feed_settings(settings)?;
+    calendar_feed::apply(&app, settings.clone())?;
+    Ok(settings)
+}
+
+#[tauri::command]
+fn get_audit_log(entity: Option<Entity>, entity_id: Option<u64>, limit: usize, state: State<AppState>) -> Result<Vec<AuditRecord>, RotatorError> {
+    state.rotator().audit_log(entity, entity_id, limit)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let db_path = get_db_path();
-    let conn = Connection::open(&db_path).expect("Failed to open database");
-    init_db(&conn);
-
-    let projects = load_projects(&conn);
-    let current_project_index = load_current_project_index(&conn);
-    let next_project_id = get_next_id(&conn, "projects");
-    let next_task_id = get_next_id(&conn, "tasks");
-    let active_tracking = load_active_tracking(&conn);
+    let rotator = Rotator::open(&db_path)
+        .unwrap_or_else(|e| panic!("Failed to open database {}: {}", db_path.display(), e));
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_http::init())
-        .manage(AppState {
-            db: Mutex::new(conn),
-            projects: Mutex::new(projects),
-            current_project_index: Mutex::new(current_project_index),
-            next_project_id: Mutex::new(next_project_id),
-            next_task_id: Mutex::new(next_task_id),
-            active_tracking: Mutex::new(active_tracking),
-        })
+        .manage(AppState::new(rotator))
         .setup(|app| {
             // Store app handle for floating panel to use
             set_app_handle(app.handle().clone());
@@ -1314,6 +726,80 @@ pub fn run() {
                 })
                 .build(app)?;
 
+            // Keep running sessions' heartbeat fresh so a crash can be told apart from work
+            let handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(Duration::from_secs(HEARTBEAT_INTERVAL_SECONDS));
+                if let Err(e) = handle.state::<AppState>().rotator_from(AuditSource::System).heartbeat() {
+                    eprintln!("Heartbeat failed: {}", e);
+                }
+            });
+
+            // Drive the timebox; blocks end on their own even with the window hidden
+            let handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(Duration::from_secs(1));
+                let state = handle.state::<AppState>();
+                let mut rotator = state.rotator_from(AuditSource::System);
+                match rotator.tick_timebox() {
+                    Ok(events) => emit_timebox_events(&handle, &rotator, &events),
+                    Err(e) => eprintln!("Timebox tick failed: {}", e),
+                }
+            });
+
+            // Watch budgets so the user hears about them while tracking
+            let handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(Duration::from_secs(BUDGET_POLL_SECONDS));
+                let state = handle.state::<AppState>();
+                let mut rotator = state.rotator_from(AuditSource::System);
+                match rotator.check_budgets() {
+                    Ok(events) => emit_budget_events(&handle, &rotator, &events),
+                    Err(e) => eprintln!("Budget check failed: {}", e),
+                }
+            });
+
+            // Keep a daily backup; retention prunes the older ones
+            let handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                if let Err(e) = handle.state::<AppState>().rotator_from(AuditSource::System).backup_if_due() {
+                    eprintln!("Scheduled backup failed: {}", e);
+                }
+                std::thread::sleep(Duration::from_secs(BACKUP_POLL_SECONDS));
+            });
+
+            // Serve the calendar feed if it was left on
+            let feed_settings = app.state::<AppState>().rotator_from(AuditSource::System).calendar_feed_settings();
+            match feed_settings {
+                Ok(settings) => {
+                    if let Err(e) = calendar_feed::apply(app.handle(), settings) {
+                        eprintln!("Failed to start the calendar feed: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to load calendar feed settings: {}", e),
+            }
+
+            // Poll for inactivity; the core trims or flags running sessions
+            if let Some(source) = platform_idle_source() {
+                let handle = app.handle().clone();
+                std::thread::spawn(move || loop {
+                    std::thread::sleep(Duration::from_secs(IDLE_POLL_SECONDS));
+                    let Some(idle_seconds) = source.idle_seconds() else { continue };
+                    let event = handle.state::<AppState>().rotator_from(AuditSource::System).check_idle(idle_seconds);
+                    match event {
+                        Ok(Some(event @ IdleEvent::Detected(_))) => {
+                            let _ = handle.emit("idle-detected", &event);
+                            let _ = handle.emit("tracking-updated", ());
+                        }
+                        Ok(Some(event @ IdleEvent::Returned(_))) => {
+                            let _ = handle.emit("idle-returned", &event);
+                        }
+                        Ok(None) => {}
+                        Err(e) => eprintln!("Idle check failed: {}", e),
+                    }
+                });
+            }
+
             // Hide window instead of closing when close button is clicked
             if let Some(window) = app.get_webview_window("main") {
                 let window_clone = window.clone();
@@ -1335,19 +821,32 @@ pub fn run() {
             rename_project,
             rotate_project,
             set_current_project,
+            select_project,
             rotate_task,
             add_task,
             remove_task,
             rename_task,
+            move_project,
+            reorder_projects,
+            move_task,
+            reorder_tasks,
             start_tracking,
             stop_tracking,
             get_active_tracking,
+            pause_tracking,
+            resume_tracking,
             get_current_project,
             get_time_entries,
             get_hourly_activity,
             get_daily_activity,
+            get_stats_timezone,
+            set_stats_timezone,
+            get_rotation_events,
+            get_context_switches,
+            get_dwell_times,
             get_project_time_stats,
             get_all_time_entries,
+            export_time_entries,
             update_tray_title,
             show_floating_timer,
             hide_floating_timer,
@@ -1364,7 +863,54 @@ pub fn run() {
             delete_project_permanent,
             reset_database,
             add_mock_data,
-            add_time_entry_manual
+            add_time_entry_manual,
+            update_time_entry,
+            delete_time_entry,
+            split_time_entry,
+            merge_time_entries,
+            get_idle_settings,
+            set_idle_settings,
+            get_pending_idle,
+            resolve_idle,
+            get_orphaned_sessions,
+            resolve_orphaned_session,
+            get_time_policy,
+            set_time_policy,
+            get_project_time_policy,
+            set_project_time_policy,
+            get_project_rotation_strategy,
+            set_project_rotation_strategy,
+            get_task_rotation_strategy,
+            set_task_rotation_strategy,
+            set_project_rotation_weight,
+            set_task_rotation_weight,
+            get_budgets,
+            set_budget,
+            remove_budget,
+            get_budget_statuses,
+            get_timebox_settings,
+            set_timebox_settings,
+            get_active_block,
+            start_timebox,
+            stop_timebox,
+            get_timebox_blocks,
+            recompute_totals,
+            list_backups,
+            create_backup,
+            restore_backup,
+            get_backup_retention,
+            set_backup_retention,
+            undo,
+            redo,
+            get_undo_state,
+            get_audit_log,
+            export_archive,
+            import_archive,
+            preview_tracker_import,
+            import_tracker,
+            export_calendar,
+            get_calendar_feed_settings,
+            set_calendar_feed_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,38 @@
+use rotator_core::{AuditSource, Rotator};
+use std::sync::{Mutex, MutexGuard};
+
+/// Sole owner of the app's data. The rotator holds the SQLite connection
+/// together with its in-memory cache, and every command and background
+/// thread goes through the one lock below, so there is no lock ordering to
+/// get wrong and the cache never moves apart from the database.
+pub struct AppState {
+    rotator: Mutex<Rotator>,
+}
+
+impl AppState {
+    pub fn new(rotator: Rotator) -> Self {
+        Self { rotator: Mutex::new(rotator) }
+    }
+
+    /// Lock the rotator for a change made from the main window.
+    pub fn rotator(&self) -> MutexGuard<'_, Rotator> {
+        self.rotator_from(AuditSource::Ui)
+    }
+
+    /// Lock the rotator, attributing whatever it changes to `source` in the
+    /// audit log. A command that panicked while holding the lock may have
+    /// left the cache half-updated, so it is reloaded from the database
+    /// instead of poisoning every later command.
+    pub fn rotator_from(&self, source: AuditSource) -> MutexGuard<'_, Rotator> {
+        let mut rotator = self.rotator.lock().unwrap_or_else(|poisoned| {
+            let mut rotator = poisoned.into_inner();
+            if let Err(e) = rotator.reload() {
+                eprintln!("Failed to reload state after a panic: {}", e);
+            }
+            self.rotator.clear_poison();
+            rotator
+        });
+        rotator.set_source(source);
+        rotator
+    }
+}
@@ -1,6 +1,7 @@
 import { useState, useEffect, useCallback, useRef } from "react";
 import { usePostHog } from "posthog-js/react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { register, unregister } from "@tauri-apps/plugin-global-shortcut";
 import { fetch } from "@tauri-apps/plugin-http";
 import { openUrl } from "@tauri-apps/plugin-opener";
@@ -36,13 +37,199 @@ interface Project {
   id: number;
   name: string;
   tasks: Task[];
-  current_task_index: number;
+  current_task_id: number | null;
 }
 
 interface ActiveTracking {
   project_id: number;
   task_id: number;
   started_at: number;
+  paused_at: number | null;
+  paused_seconds: number;
+}
+
+// Elapsed seconds not counting pauses; frozen while paused
+const trackedSeconds = (t: ActiveTracking, now: number): number =>
+  Math.max(0, (t.paused_at ?? now) - t.started_at - t.paused_seconds);
+
+interface ClosedSession {
+  project_id: number;
+  task_id: number;
+  started_at: number;
+  ended_at: number;
+  duration_seconds: number;
+  billable_seconds: number;
+  entry_id: number | null;
+  discarded: boolean;
+}
+
+interface OrphanedSession {
+  project_id: number;
+  task_id: number;
+  started_at: number;
+  last_heartbeat: number;
+}
+
+type OrphanResolution = "close_at_last_heartbeat" | "keep_running" | "discard";
+
+type BudgetPeriod = "day" | "week" | "month" | "total";
+
+interface Budget {
+  id: number;
+  project_id: number;
+  task_id: number | null;
+  period: BudgetPeriod;
+  limit_seconds: number;
+}
+
+interface BudgetStatus {
+  budget: Budget;
+  period_start: number;
+  period_end: number | null;
+  spent_seconds: number;
+  remaining_seconds: number;
+  reached: boolean;
+}
+
+const BUDGET_PERIOD_LABELS: Record<BudgetPeriod, string> = {
+  day: "per day",
+  week: "per week",
+  month: "per month",
+  total: "in total",
+};
+
+// IANA zones the stats can be counted in; supportedValuesOf is newer than the ES2020 lib types
+const TIME_ZONES: string[] =
+  (Intl as { supportedValuesOf?: (key: "timeZone") => string[] }).supportedValuesOf?.("timeZone") ?? [];
+
+type BlockKind = "work" | "break" | "long_break";
+
+interface ActiveBlock {
+  kind: BlockKind;
+  project_id: number | null;
+  task_id: number | null;
+  started_at: number;
+  ends_at: number;
+  work_blocks_done: number;
+}
+
+interface TimeboxSettings {
+  work_seconds: number;
+  break_seconds: number;
+  long_break_seconds: number;
+  long_break_every: number;
+}
+
+type RoundingMode = "none" | "up" | "down" | "nearest";
+
+interface TimePolicy {
+  min_session_seconds: number;
+  rounding: RoundingMode;
+  round_to_minutes: number;
+}
+
+type RotationStrategyKind =
+  | "round_robin"
+  | "weighted_share"
+  | "least_recently_worked"
+  | "largest_deficit"
+  | "weighted_random";
+
+const ROTATION_STRATEGY_LABELS: Record<RotationStrategyKind, string> = {
+  round_robin: "In order",
+  weighted_share: "By share",
+  least_recently_worked: "Least recent",
+  largest_deficit: "Furthest behind target",
+  weighted_random: "Random (weighted)",
+};
+
+type IdleAction = "stop_at_last_active" | "record_idle";
+
+interface IdleSettings {
+  threshold_seconds: number;
+  action: IdleAction;
+}
+
+interface IdlePeriod {
+  started_at: number;
+  returned_at: number | null;
+  action: IdleAction;
+  sessions: ActiveTracking[];
+}
+
+type IdleResolution =
+  | { kind: "keep" }
+  | { kind: "discard" }
+  | { kind: "reassign"; project_id: number; task_id: number };
+
+// Shape of every error a rotator command rejects with
+interface RotatorError {
+  kind: "db" | "not_found" | "conflict" | "validation" | "io";
+  message: string;
+  entity?: "project" | "task" | "time_entry" | "budget";
+  id?: number;
+  entry_id?: number;
+}
+
+const errorMessage = (e: unknown) =>
+  typeof e === "object" && e !== null && "message" in e ? (e as RotatorError).message : String(e);
+
+type BackupReason = "scheduled" | "manual" | "reset" | "delete_project" | "delete_task" | "migration" | "restore" | "import";
+
+interface BackupInfo {
+  file_name: string;
+  path: string;
+  created_at: number;
+  reason: BackupReason;
+  size_bytes: number;
+}
+
+interface ImportReport {
+  projects_added: number;
+  tasks_added: number;
+  entries_added: number;
+  sessions_added: number;
+  duplicates: number;
+  conflicts: ({ kind: "time_entry"; archive_entry_id: number; existing_entry_id: number } | { kind: "running_session"; task_id: number })[];
+}
+
+type ImportSource = "toggl" | "clockify" | "timewarrior";
+
+interface TrackerImportReport {
+  dry_run: boolean;
+  rows: number;
+  projects_created: string[];
+  tasks_created: { project: string; task: string }[];
+  entries_added: number;
+  duplicates: number;
+  conflicts: { row: number; existing_entry_id: number }[];
+  skipped: { row: number; reason: string }[];
+}
+
+interface CalendarFeedSettings {
+  enabled: boolean;
+  port: number;
+  weeks: number;
+  token: string;
+}
+
+interface BackupRetention {
+  daily: number;
+  weekly: number;
+}
+
+// A journaled change, as returned by undo and redo
+interface Change {
+  kind:
+    | "project_added"
+    | "project_removed"
+    | "project_renamed"
+    | "task_added"
+    | "task_removed"
+    | "task_renamed"
+    | "task_done"
+    | "tracking_stopped"
+    | "entries_edited";
 }
 
 interface TimeEntry {
@@ -52,6 +239,9 @@ interface TimeEntry {
   start_time: number;
   end_time: number;
   duration_seconds: number;
+  billable_seconds: number;
+  manual: boolean;
+  edited_at: number | null;
 }
 
 interface HourlyActivity {
@@ -64,10 +254,23 @@ interface DailyActivity {
   total_seconds: number;
 }
 
+interface DailyContextSwitches {
+  date: string;
+  project_switches: number;
+  task_switches: number;
+}
+
+interface DwellTime {
+  project_id: number;
+  visits: number;
+  average_seconds: number;
+}
+
 interface ProjectTimeStats {
   project_id: number;
   project_name: string;
   total_seconds: number;
+  billable_seconds: number;
 }
 
 interface TaskWithStatus {
@@ -82,7 +285,7 @@ interface ProjectWithStatus {
   id: number;
   name: string;
   tasks: TaskWithStatus[];
-  current_task_index: number;
+  current_task_id: number | null;
   archived_at: number | null;
 }
 
@@ -136,6 +339,8 @@ function App() {
   const [editing, setEditing] = useState<{ type: "project" | "task"; id: number; value: string } | null>(null);
   const [hourlyActivity, setHourlyActivity] = useState<HourlyActivity[]>([]);
   const [dailyActivity, setDailyActivity] = useState<DailyActivity[]>([]);
+  const [contextSwitches, setContextSwitches] = useState<DailyContextSwitches[]>([]);
+  const [dwellTimes, setDwellTimes] = useState<DwellTime[]>([]);
   const [projectStats, setProjectStats] = useState<ProjectTimeStats[]>([]);
   const [allProjectsWithStatus, setAllProjectsWithStatus] = useState<ProjectWithStatus[]>([]);
   const [confirmModal, setConfirmModal] = useState<{
@@ -146,6 +351,33 @@ function App() {
     danger?: boolean;
     onConfirm: () => void;
   } | null>(null);
+  const [idleSettings, setIdleSettings] = useState<IdleSettings>({ threshold_seconds: 300, action: "stop_at_last_active" });
+  const [activeBlock, setActiveBlock] = useState<ActiveBlock | null>(null);
+  const [blockNow, setBlockNow] = useState(() => Math.floor(Date.now() / 1000));
+  const [timeboxSettings, setTimeboxSettings] = useState<TimeboxSettings>({
+    work_seconds: 1500, break_seconds: 300, long_break_seconds: 900, long_break_every: 4,
+  });
+  const [timePolicy, setTimePolicy] = useState<TimePolicy>({ min_session_seconds: 3, rounding: "none", round_to_minutes: 0 });
+  const [budgetStatuses, setBudgetStatuses] = useState<BudgetStatus[]>([]);
+  const [budgetAlert, setBudgetAlert] = useState<BudgetStatus | null>(null);
+  const [budgetEditor, setBudgetEditor] = useState<{ projectId: number; period: BudgetPeriod; hours: string } | null>(null);
+  const [projectRotation, setProjectRotation] = useState<RotationStrategyKind>("round_robin");
+  const [taskRotation, setTaskRotation] = useState<RotationStrategyKind>("round_robin");
+  const [orphanedSessions, setOrphanedSessions] = useState<OrphanedSession[]>([]);
+  const [idlePrompt, setIdlePrompt] = useState<IdlePeriod | null>(null);
+  const [idleReassignTarget, setIdleReassignTarget] = useState("");
+  const [backups, setBackups] = useState<BackupInfo[]>([]);
+  const [backupRetention, setBackupRetention] = useState<BackupRetention>({ daily: 7, weekly: 4 });
+  const [archivePath, setArchivePath] = useState("");
+  const [archiveMode, setArchiveMode] = useState<"merge" | "replace">("merge");
+  const [archiveMessage, setArchiveMessage] = useState("");
+  const [trackerSource, setTrackerSource] = useState<ImportSource>("toggl");
+  const [trackerPath, setTrackerPath] = useState("");
+  const [trackerPreview, setTrackerPreview] = useState<TrackerImportReport | null>(null);
+  const [trackerMessage, setTrackerMessage] = useState("");
+  const [calendarFeed, setCalendarFeed] = useState<CalendarFeedSettings>({ enabled: false, port: 47821, weeks: 4, token: "" });
+  const [calendarFeedMessage, setCalendarFeedMessage] = useState("");
+  const [statsTimezone, setStatsTimezone] = useState<string | null>(null);
   const [floatingTimerEnabled, setFloatingTimerEnabled] = useState(() => {
     const saved = localStorage.getItem("floatingTimerEnabled");
     return saved ? JSON.parse(saved) : false;
@@ -168,7 +400,7 @@ function App() {
     try {
       await register(hotkeySettings.projectHotkey, async (event) => {
         if (event.state === "Pressed") {
-          const [index] = await invoke<[number, Project | null]>("rotate_project");
+          const [index] = await invoke<[number, Project | null]>("rotate_project", { trigger: "hotkey" });
           setCurrentProjectIndex(index);
           const loadedProjects = await invoke<Project[]>("get_projects");
           setProjects(loadedProjects);
@@ -179,7 +411,7 @@ function App() {
             if (project) {
               const activeTasks = project.tasks.filter(t => t.done_at === null);
               const currentTask = activeTasks.length > 0
-                ? activeTasks[project.current_task_index % activeTasks.length]
+                ? (activeTasks.find(t => t.id === project.current_task_id) ?? activeTasks[0])
                 : null;
               if (currentTask) {
                 const trackingEntry = tracking.find(t => t.task_id === currentTask.id);
@@ -200,7 +432,7 @@ function App() {
       });
       await register(hotkeySettings.taskHotkey, async (event) => {
         if (event.state === "Pressed") {
-          await invoke<Task | null>("rotate_task");
+          await invoke<Task | null>("rotate_task", { trigger: "hotkey" });
           const loadedProjects = await invoke<Project[]>("get_projects");
           setProjects(loadedProjects);
           const currentIdx = await invoke<number>("get_current_project_index");
@@ -211,7 +443,7 @@ function App() {
             if (project) {
               const activeTasks = project.tasks.filter(t => t.done_at === null);
               const currentTask = activeTasks.length > 0
-                ? activeTasks[project.current_task_index % activeTasks.length]
+                ? (activeTasks.find(t => t.id === project.current_task_id) ?? activeTasks[0])
                 : null;
               if (currentTask) {
                 const trackingEntry = tracking.find(t => t.task_id === currentTask.id);
@@ -235,7 +467,7 @@ function App() {
           const currentTracking = await invoke<ActiveTracking[]>("get_active_tracking");
           if (currentTracking.length > 0) {
             posthog.capture("timer_stopped", { source: "hotkey" });
-            await invoke<number | null>("stop_tracking", { taskId: null });
+            await invoke<ClosedSession[]>("stop_tracking", { taskId: null, source: "hotkey" });
             setActiveTracking([]);
             setElapsedTimes({});
             invoke("emit_tracking_updated").catch(console.error);
@@ -249,7 +481,7 @@ function App() {
             if (project) {
               const activeTasks = project.tasks.filter(t => t.done_at === null);
               const currentTask = activeTasks.length > 0
-                ? activeTasks[project.current_task_index % activeTasks.length]
+                ? (activeTasks.find(t => t.id === project.current_task_id) ?? activeTasks[0])
                 : null;
               if (currentTask) {
                 const savedSettings = localStorage.getItem("trackingSettings");
@@ -258,6 +490,7 @@ function App() {
                   projectId: project.id,
                   taskId: currentTask.id,
                   allowMultiple: settings.allowMultipleTasks,
+                  source: "hotkey",
                 });
                 setActiveTracking(tracking);
                 if (tracking.length > 0) {
@@ -301,12 +534,14 @@ function App() {
                   project_name: project?.name || "",
                   task_name: task?.name || "",
                   started_at: t.started_at,
+                  paused_at: t.paused_at,
+                  paused_seconds: t.paused_seconds,
                 };
               });
             invoke("update_floating_timer", { entries: remainingEntries }).catch(console.error);
 
             // Run backend operations in parallel
-            invoke("stop_tracking", { taskId }).then(() => {
+            invoke("stop_tracking", { taskId, source: "floating_panel" }).then(() => {
               Promise.all([
                 invoke<Project[]>("get_projects"),
                 invoke<ActiveTracking[]>("get_active_tracking")
@@ -325,6 +560,241 @@ function App() {
     }
   }, [floatingTimerEnabled, activeTracking, projects]);
 
+  useEffect(() => {
+    invoke<TimeboxSettings>("get_timebox_settings").then(setTimeboxSettings).catch(console.error);
+    invoke<ActiveBlock | null>("get_active_block").then(setActiveBlock).catch(console.error);
+
+    // Blocks end in the backend; pick up the new block, task and tracking state
+    const unlisten = listen("timebox", () => {
+      invoke<ActiveBlock | null>("get_active_block").then(setActiveBlock).catch(console.error);
+      Promise.all([
+        invoke<Project[]>("get_projects"),
+        invoke<number>("get_current_project_index"),
+        invoke<ActiveTracking[]>("get_active_tracking")
+      ]).then(([newProjects, index, newTracking]) => {
+        setProjects(newProjects);
+        setCurrentProjectIndex(index);
+        setActiveTracking(newTracking);
+      });
+    });
+    return () => {
+      unlisten.then(f => f());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (!activeBlock) return;
+    const interval = setInterval(() => setBlockNow(Math.floor(Date.now() / 1000)), 1000);
+    return () => clearInterval(interval);
+  }, [activeBlock]);
+
+  const toggleTimebox = async () => {
+    if (activeBlock) {
+      await invoke("stop_timebox");
+      setActiveBlock(null);
+      posthog.capture("timebox_stopped");
+    } else {
+      setActiveBlock(await invoke<ActiveBlock | null>("start_timebox", {
+        allowMultiple: trackingSettings.allowMultipleTasks,
+      }));
+      posthog.capture("timebox_started");
+    }
+  };
+
+  const updateTimeboxSettings = async (settings: TimeboxSettings) => {
+    setTimeboxSettings(await invoke<TimeboxSettings>("set_timebox_settings", { settings }));
+  };
+
+  useEffect(() => {
+    invoke<TimePolicy>("get_time_policy").then(setTimePolicy).catch(console.error);
+  }, []);
+
+  const updateTimePolicy = async (policy: TimePolicy) => {
+    setTimePolicy(await invoke<TimePolicy>("set_time_policy", { policy }));
+  };
+
+  const loadBackups = useCallback(async () => {
+    setBackups(await invoke<BackupInfo[]>("list_backups"));
+  }, []);
+
+  useEffect(() => {
+    invoke<BackupRetention>("get_backup_retention").then(setBackupRetention).catch(console.error);
+    loadBackups().catch(console.error);
+  }, [loadBackups]);
+
+  const updateBackupRetention = async (retention: BackupRetention) => {
+    setBackupRetention(await invoke<BackupRetention>("set_backup_retention", { retention }));
+    await loadBackups();
+  };
+
+  useEffect(() => {
+    invoke<CalendarFeedSettings>("get_calendar_feed_settings").then(setCalendarFeed).catch(console.error);
+  }, []);
+
+  useEffect(() => {
+    invoke<string | null>("get_stats_timezone").then(setStatsTimezone).catch(console.error);
+  }, []);
+
+  const updateCalendarFeed = async (settings: CalendarFeedSettings) => {
+    try {
+      setCalendarFeed(await invoke<CalendarFeedSettings>("set_calendar_feed_settings", { settings }));
+      setCalendarFeedMessage("");
+    } catch (e) {
+      setCalendarFeedMessage("Could not update the feed: " + errorMessage(e));
+    }
+  };
+
+  const importArchive = async () => {
+    try {
+      const report = await invoke<ImportReport>("import_archive", { path: archivePath, mode: archiveMode });
+      await loadData();
+      await loadBackups();
+      const skipped = report.conflicts.length > 0 ? `, ${report.conflicts.length} skipped as conflicting` : "";
+      setArchiveMessage(`Imported ${report.entries_added} entries into ${report.projects_added} new projects${skipped}`);
+    } catch (e) {
+      console.error("Import archive error:", e);
+      setArchiveMessage("Import failed: " + errorMessage(e));
+    }
+  };
+
+  const previewTrackerImport = async () => {
+    try {
+      setTrackerPreview(await invoke<TrackerImportReport>("preview_tracker_import", { source: trackerSource, path: trackerPath }));
+      setTrackerMessage("");
+    } catch (e) {
+      setTrackerPreview(null);
+      setTrackerMessage("Preview failed: " + errorMessage(e));
+    }
+  };
+
+  const importTracker = async () => {
+    try {
+      const report = await invoke<TrackerImportReport>("import_tracker", { source: trackerSource, path: trackerPath });
+      await loadData();
+      await loadBackups();
+      setTrackerPreview(null);
+      setTrackerMessage(`Imported ${report.entries_added} entries, ${report.duplicates} already there`);
+    } catch (e) {
+      console.error("Tracker import error:", e);
+      setTrackerMessage("Import failed: " + errorMessage(e));
+    }
+  };
+
+  useEffect(() => {
+    invoke<BudgetStatus[]>("get_budget_statuses").then(setBudgetStatuses).catch(console.error);
+  }, [activeTracking, projects]);
+
+  useEffect(() => {
+    const unlisten = listen<{ kind: "reached"; status: BudgetStatus }>("budget-reached", (event) => {
+      setBudgetAlert(event.payload.status);
+      invoke<BudgetStatus[]>("get_budget_statuses").then(setBudgetStatuses).catch(console.error);
+    });
+    return () => {
+      unlisten.then(f => f());
+    };
+  }, []);
+
+  const saveBudget = async () => {
+    if (!budgetEditor) return;
+    const hours = parseFloat(budgetEditor.hours);
+    const existing = budgetStatuses.find(s => s.budget.project_id === budgetEditor.projectId && s.budget.task_id === null);
+    if (existing && existing.budget.period !== budgetEditor.period) {
+      await invoke("remove_budget", { budgetId: existing.budget.id });
+    }
+    if (!hours || hours <= 0) {
+      if (existing) await invoke("remove_budget", { budgetId: existing.budget.id });
+    } else {
+      await invoke<Budget>("set_budget", {
+        projectId: budgetEditor.projectId,
+        taskId: null,
+        period: budgetEditor.period,
+        limitSeconds: Math.round(hours * 3600),
+      });
+      posthog.capture("budget_set", { period: budgetEditor.period });
+    }
+    setBudgetEditor(null);
+    setBudgetStatuses(await invoke<BudgetStatus[]>("get_budget_statuses"));
+  };
+
+  const projectBudget = (projectId: number) =>
+    budgetStatuses.find(s => s.budget.project_id === projectId && s.budget.task_id === null);
+
+  useEffect(() => {
+    invoke<RotationStrategyKind>("get_project_rotation_strategy").then(setProjectRotation).catch(console.error);
+  }, []);
+
+  useEffect(() => {
+    if (!currentProject) return;
+    invoke<RotationStrategyKind>("get_task_rotation_strategy", { projectId: currentProject.id })
+      .then(setTaskRotation)
+      .catch(console.error);
+  }, [currentProject?.id]);
+
+  const updateProjectRotation = async (strategy: RotationStrategyKind) => {
+    setProjectRotation(await invoke<RotationStrategyKind>("set_project_rotation_strategy", { strategy }));
+    posthog.capture("rotation_strategy_changed", { level: "project", strategy });
+  };
+
+  const updateTaskRotation = async (strategy: RotationStrategyKind) => {
+    if (!currentProject) return;
+    setTaskRotation(await invoke<RotationStrategyKind>("set_task_rotation_strategy", { projectId: currentProject.id, strategy }));
+    posthog.capture("rotation_strategy_changed", { level: "task", strategy });
+  };
+
+  useEffect(() => {
+    invoke<OrphanedSession[]>("get_orphaned_sessions").then(setOrphanedSessions).catch(console.error);
+  }, []);
+
+  const resolveOrphanedSession = async (taskId: number, resolution: OrphanResolution) => {
+    const tracking = await invoke<ActiveTracking[]>("resolve_orphaned_session", { taskId, resolution });
+    setActiveTracking(tracking);
+    setProjects(await invoke<Project[]>("get_projects"));
+    setOrphanedSessions(await invoke<OrphanedSession[]>("get_orphaned_sessions"));
+    invoke("emit_tracking_updated").catch(console.error);
+  };
+
+  useEffect(() => {
+    invoke<IdleSettings>("get_idle_settings").then(setIdleSettings).catch(console.error);
+    invoke<IdlePeriod | null>("get_pending_idle").then(period => {
+      if (period?.returned_at) setIdlePrompt(period);
+    }).catch(console.error);
+
+    const unlistenDetected = listen("idle-detected", () => {
+      Promise.all([
+        invoke<Project[]>("get_projects"),
+        invoke<ActiveTracking[]>("get_active_tracking")
+      ]).then(([newProjects, newTracking]) => {
+        setProjects(newProjects);
+        setActiveTracking(newTracking);
+      });
+    });
+    const unlistenReturned = listen<IdlePeriod>("idle-returned", (event) => {
+      setIdleReassignTarget("");
+      setIdlePrompt(event.payload);
+    });
+
+    return () => {
+      unlistenDetected.then(unlisten => unlisten());
+      unlistenReturned.then(unlisten => unlisten());
+    };
+  }, []);
+
+  const resolveIdle = async (resolution: IdleResolution) => {
+    try {
+      const tracking = await invoke<ActiveTracking[]>("resolve_idle", { resolution });
+      setActiveTracking(tracking);
+      setProjects(await invoke<Project[]>("get_projects"));
+      invoke("emit_tracking_updated").catch(console.error);
+      setIdlePrompt(null);
+    } catch (e) {
+      console.error("Failed to resolve idle time:", errorMessage(e));
+    }
+  };
+
+  const updateIdleSettings = async (settings: IdleSettings) => {
+    setIdleSettings(await invoke<IdleSettings>("set_idle_settings", { settings }));
+  };
+
   useEffect(() => {
     posthog.capture("$pageview", { view: currentView });
   }, [currentView, posthog]);
@@ -360,7 +830,7 @@ function App() {
       const now = Math.floor(Date.now() / 1000);
       const newElapsed: Record<number, number> = {};
       activeTracking.forEach(t => {
-        newElapsed[t.task_id] = now - t.started_at;
+        newElapsed[t.task_id] = trackedSeconds(t, now);
       });
       setElapsedTimes(newElapsed);
 
@@ -373,6 +843,8 @@ function App() {
             project_name: project?.name || "",
             task_name: task?.name || "",
             started_at: t.started_at,
+            paused_at: t.paused_at,
+            paused_seconds: t.paused_seconds,
           };
         });
         invoke("update_floating_timer", { entries }).catch(console.error);
@@ -397,7 +869,7 @@ function App() {
         if (currentProject) {
           const activeTasks = currentProject.tasks.filter(t => t.done_at === null);
           const currentTask = activeTasks.length > 0
-            ? activeTasks[currentProject.current_task_index % activeTasks.length]
+            ? (activeTasks.find(t => t.id === currentProject.current_task_id) ?? activeTasks[0])
             : null;
 
           if (activeTracking.length > 0) {
@@ -409,7 +881,8 @@ function App() {
             const projectName = project ? project.name : "";
             const elapsed = elapsedTimes[t.task_id] || 0;
             const suffix = activeTracking.length > 1 ? ` +${activeTracking.length - 1}` : "";
-            title = `[${truncateName(projectName, 8)}] ${truncateName(taskName, 10)}${suffix} │ ${formatTime(elapsed)}`;
+            const state = t.paused_at !== null ? "⏸ " : "";
+            title = `[${truncateName(projectName, 8)}] ${truncateName(taskName, 10)}${suffix} │ ${state}${formatTime(elapsed)}`;
           } else if (currentTask) {
             title = `[${truncateName(currentProject.name, 10)}] ${truncateName(currentTask.name, 15)}`;
           } else {
@@ -504,7 +977,7 @@ function App() {
   const addTask = async (projectId: number) => {
     const taskName = newTaskNames[projectId] || "";
     if (!taskName.trim()) return;
-    const updated = await invoke<Project | null>("add_task", { projectId, name: taskName.trim() });
+    const updated = await invoke<Project>("add_task", { projectId, name: taskName.trim() });
     if (updated) {
       setProjects(projects.map(p => p.id === projectId ? updated : p));
     }
@@ -513,7 +986,7 @@ function App() {
   };
 
   const removeTask = async (projectId: number, taskId: number) => {
-    const updated = await invoke<Project | null>("remove_task", { projectId, taskId });
+    const updated = await invoke<Project>("remove_task", { projectId, taskId });
     if (updated) {
       setProjects(projects.map(p => p.id === projectId ? updated : p));
     }
@@ -523,7 +996,7 @@ function App() {
   };
 
   const toggleTaskDone = async (projectId: number, taskId: number, done: boolean) => {
-    const updated = await invoke<Project | null>("toggle_task_done", { projectId, taskId, done });
+    const updated = await invoke<Project>("toggle_task_done", { projectId, taskId, done });
     if (updated) {
       setProjects(projects.map(p => p.id === projectId ? updated : p));
     }
@@ -546,7 +1019,7 @@ function App() {
   const stopTracking = async (taskId?: number) => {
     const elapsed = taskId ? elapsedTimes[taskId] : Object.values(elapsedTimes).reduce((sum, t) => sum + t, 0);
     posthog.capture("timer_stopped", { duration_seconds: elapsed });
-    await invoke<number | null>("stop_tracking", { taskId: taskId ?? null });
+    await invoke<ClosedSession[]>("stop_tracking", { taskId: taskId ?? null });
     if (taskId) {
       setActiveTracking(prev => prev.filter(t => t.task_id !== taskId));
       setElapsedTimes(prev => {
@@ -562,6 +1035,12 @@ function App() {
     await loadData();
   };
 
+  const togglePause = async (t: ActiveTracking) => {
+    const command = t.paused_at !== null ? "resume_tracking" : "pause_tracking";
+    setActiveTracking(await invoke<ActiveTracking[]>(command, { taskId: t.task_id }));
+    invoke("emit_tracking_updated").catch(console.error);
+  };
+
   const updateRotationPreview = useCallback(async (projectsList: Project[], projectIndex: number, tracking: ActiveTracking[]) => {
     if (!floatingTimerEnabled) return;
 
@@ -573,7 +1052,7 @@ function App() {
 
     const activeTasks = project.tasks.filter(t => t.done_at === null);
     const currentTask = activeTasks.length > 0
-      ? activeTasks[project.current_task_index % activeTasks.length]
+      ? (activeTasks.find(t => t.id === project.current_task_id) ?? activeTasks[0])
       : null;
 
     if (!currentTask) {
@@ -597,7 +1076,7 @@ function App() {
 
   const rotateManually = async () => {
     if (projects.length === 0) return;
-    const [index] = await invoke<[number, Project | null]>("rotate_project");
+    const [index] = await invoke<[number, Project | null]>("rotate_project", { trigger: "manual" });
     setCurrentProjectIndex(index);
     const loadedProjects = await invoke<Project[]>("get_projects");
     setProjects(loadedProjects);
@@ -606,7 +1085,7 @@ function App() {
   };
 
   const rotateTask = async () => {
-    await invoke<Task | null>("rotate_task");
+    await invoke<Task | null>("rotate_task", { trigger: "manual" });
     const loadedProjects = await invoke<Project[]>("get_projects");
     setProjects(loadedProjects);
     updateRotationPreview(loadedProjects, currentProjectIndex, activeTracking);
@@ -614,7 +1093,9 @@ function App() {
   };
 
   const selectProject = async (index: number) => {
-    await invoke<number>("set_current_project", { index });
+    const project = projects[index];
+    if (!project) return;
+    await invoke<Project>("select_project", { projectId: project.id });
     const loadedProjects = await invoke<Project[]>("get_projects");
     const currentIdx = await invoke<number>("get_current_project_index");
     setProjects(loadedProjects);
@@ -623,6 +1104,20 @@ function App() {
     posthog.capture("project_selected");
   };
 
+  const moveProject = async (projectId: number, toIndex: number) => {
+    if (toIndex < 0 || toIndex >= projects.length) return;
+    setProjects(await invoke<Project[]>("move_project", { projectId, toIndex }));
+    setCurrentProjectIndex(await invoke<number>("get_current_project_index"));
+  };
+
+  const moveTask = async (project: Project, taskId: number, toIndex: number) => {
+    if (toIndex < 0 || toIndex >= project.tasks.length) return;
+    const updated = await invoke<Project>("move_task", { projectId: project.id, taskId, toIndex });
+    if (updated) {
+      setProjects(prev => prev.map(p => p.id === updated.id ? updated : p));
+    }
+  };
+
   const getTaskTime = (task: Task): number => {
     const trackingEntry = activeTracking.find(t => t.task_id === task.id);
     if (trackingEntry) {
@@ -715,6 +1210,24 @@ function App() {
     return () => document.removeEventListener("keydown", handleEsc);
   }, []);
 
+  // Cmd/Ctrl+Z undoes the last change, with Shift it redoes; text fields keep their own undo
+  useEffect(() => {
+    const handleUndo = async (e: KeyboardEvent) => {
+      if (!(e.metaKey || e.ctrlKey) || e.key.toLowerCase() !== "z") return;
+      const target = e.target as HTMLElement;
+      if (target.tagName === "INPUT" || target.tagName === "TEXTAREA") return;
+      e.preventDefault();
+      try {
+        await invoke<Change | null>(e.shiftKey ? "redo" : "undo");
+        await loadData();
+      } catch (err) {
+        alert((e.shiftKey ? "Failed to redo: " : "Failed to undo: ") + errorMessage(err));
+      }
+    };
+    document.addEventListener("keydown", handleUndo);
+    return () => document.removeEventListener("keydown", handleUndo);
+  }, [loadData]);
+
   const renameProject = async (projectId: number, newName: string) => {
     if (!newName.trim()) return;
     const updated = await invoke<Project[]>("rename_project", { projectId, newName: newName.trim() });
@@ -724,7 +1237,7 @@ function App() {
 
   const renameTask = async (projectId: number, taskId: number, newName: string) => {
     if (!newName.trim()) return;
-    const updated = await invoke<Project | null>("rename_task", { projectId, taskId, newName: newName.trim() });
+    const updated = await invoke<Project>("rename_task", { projectId, taskId, newName: newName.trim() });
     if (updated) {
       setProjects(projects.map(p => p.id === projectId ? updated : p));
     }
@@ -739,7 +1252,7 @@ function App() {
   };
 
   const restoreTask = async (projectId: number, taskId: number) => {
-    const updated = await invoke<Project | null>("restore_task", { projectId, taskId });
+    const updated = await invoke<Project>("restore_task", { projectId, taskId });
     if (updated) {
       setProjects(projects.map(p => p.id === projectId ? updated : p));
     }
@@ -748,13 +1261,13 @@ function App() {
   };
 
   const deleteTaskPermanent = async (taskId: number) => {
-    await invoke<boolean>("delete_task_permanent", { taskId });
+    await invoke("delete_task_permanent", { taskId });
     const allProjects = await invoke<ProjectWithStatus[]>("get_all_projects_with_status");
     setAllProjectsWithStatus(allProjects);
   };
 
   const deleteProjectPermanent = async (projectId: number) => {
-    await invoke<boolean>("delete_project_permanent", { projectId });
+    await invoke("delete_project_permanent", { projectId });
     const allProjects = await invoke<ProjectWithStatus[]>("get_all_projects_with_status");
     setAllProjectsWithStatus(allProjects);
   };
@@ -762,14 +1275,19 @@ function App() {
   const loadDatabaseData = useCallback(async () => {
     const now = Math.floor(Date.now() / 1000);
     const oneYearAgo = now - 365 * 86400;
-    const [hourly, daily, stats, allProjects] = await Promise.all([
+    const oneWeekAgo = now - 7 * 86400;
+    const [hourly, daily, stats, allProjects, switches, dwell] = await Promise.all([
       invoke<HourlyActivity[]>("get_hourly_activity", { startTime: oneYearAgo, endTime: now }),
       invoke<DailyActivity[]>("get_daily_activity", { startTime: oneYearAgo, endTime: now }),
       invoke<ProjectTimeStats[]>("get_project_time_stats", { startTime: oneYearAgo, endTime: now }),
       invoke<ProjectWithStatus[]>("get_all_projects_with_status"),
+      invoke<DailyContextSwitches[]>("get_context_switches", { startTime: oneWeekAgo, endTime: now }),
+      invoke<DwellTime[]>("get_dwell_times", { startTime: oneWeekAgo, endTime: now }),
     ]);
     setHourlyActivity(hourly);
     setDailyActivity(daily);
+    setContextSwitches(switches);
+    setDwellTimes(dwell);
     setProjectStats(stats);
     setAllProjectsWithStatus(allProjects);
   }, []);
@@ -801,24 +1319,66 @@ function App() {
     dateObj.setHours(12, 0, 0, 0);
     const startTime = Math.floor(dateObj.getTime() / 1000);
 
-    const success = await invoke<boolean>("add_time_entry_manual", {
-      projectId: manualEntryProject,
-      taskId: manualEntryTask,
-      startTime,
-      durationSeconds,
-    });
-
-    if (success) {
+    try {
+      await invoke<TimeEntry>("add_time_entry_manual", {
+        projectId: manualEntryProject,
+        taskId: manualEntryTask,
+        startTime,
+        durationSeconds,
+      });
       setManualEntryHours("");
       setManualEntryMinutes("");
       loadDatabaseData();
       loadData();
       posthog.capture("manual_time_entry_added");
+    } catch (e) {
+      console.error("Failed to add time entry:", errorMessage(e));
     }
   };
 
   const selectedProjectTasks = allProjectsWithStatus.find(p => p.id === manualEntryProject)?.tasks.filter(t => !t.archived_at) || [];
 
+  // This month's entries, written to the Downloads folder by the backend
+  const exportEntries = async (format: "csv" | "json") => {
+    setExporting(true);
+    setExportMessage("");
+    try {
+      const now = new Date();
+      const from = Math.floor(new Date(now.getFullYear(), now.getMonth(), 1).getTime() / 1000);
+      const to = Math.floor(now.getTime() / 1000) + 1;
+      const columns = ["date", "project", "task", "start", "end", "duration_seconds", "hours", "billable_seconds"];
+      const path = await invoke<string>("export_time_entries", {
+        options: { from, to, format, columns, timezone: "local" },
+        path: null,
+      });
+      setExportMessage(`Saved to ${path}`);
+      setTimeout(() => setExportMessage(""), 3000);
+    } catch (e) {
+      console.error("Export error:", e);
+      setExportMessage("Export failed: " + errorMessage(e));
+    } finally {
+      setExporting(false);
+    }
+  };
+
+  const exportCalendar = async () => {
+    setExporting(true);
+    setExportMessage("");
+    try {
+      const now = new Date();
+      const from = Math.floor(new Date(now.getFullYear(), now.getMonth(), 1).getTime() / 1000);
+      const to = Math.floor(now.getTime() / 1000) + 1;
+      const path = await invoke<string>("export_calendar", { from, to, path: null });
+      setExportMessage(`Saved to ${path}`);
+      setTimeout(() => setExportMessage(""), 3000);
+    } catch (e) {
+      console.error("Calendar export error:", e);
+      setExportMessage("Export failed: " + errorMessage(e));
+    } finally {
+      setExporting(false);
+    }
+  };
+
   const exportToXlsx = async () => {
     setExporting(true);
     setExportMessage("");
@@ -827,18 +1387,18 @@ function App() {
       const entries = await invoke<TimeEntry[]>("get_all_time_entries");
 
       const projectsSheet = allProjects
-        .filter(p => p.tasks.reduce((sum, t) => sum + t.time_seconds, 0) >= 3)
+        .filter(p => p.tasks.some(t => t.time_seconds > 0))
         .map(p => ({
           "Project ID": p.id,
           "Project Name": p.name,
           "Total Time (seconds)": p.tasks.reduce((sum, t) => sum + t.time_seconds, 0),
           "Total Time": formatTime(p.tasks.reduce((sum, t) => sum + t.time_seconds, 0)),
-          "Task Count": p.tasks.filter(t => t.time_seconds >= 3).length,
+          "Task Count": p.tasks.filter(t => t.time_seconds > 0).length,
         }));
 
       const tasksSheet = allProjects.flatMap(p =>
         p.tasks
-          .filter(t => t.time_seconds >= 3)
+          .filter(t => t.time_seconds > 0)
           .map(t => ({
             "Task ID": t.id,
             "Task Name": t.name,
@@ -850,7 +1410,7 @@ function App() {
       );
 
       const entriesSheet = entries
-        .filter(e => e.duration_seconds >= 3)
+        .filter(e => e.billable_seconds > 0)
         .map(e => {
           const project = allProjects.find(p => p.id === e.project_id);
           const task = project?.tasks.find(t => t.id === e.task_id);
@@ -862,6 +1422,8 @@ function App() {
             "End": new Date(e.end_time * 1000).toLocaleString(),
             "Duration (seconds)": e.duration_seconds,
             "Duration": formatTime(e.duration_seconds),
+            "Billable (seconds)": e.billable_seconds,
+            "Billable": formatTime(e.billable_seconds),
           };
         });
 
@@ -978,7 +1540,7 @@ function App() {
           {currentProject && currentProject.tasks.length > 0 && (() => {
             const activeTasks = currentProject.tasks.filter(t => t.done_at === null);
             const currentTask = activeTasks.length > 0
-              ? activeTasks[currentProject.current_task_index % activeTasks.length]
+              ? (activeTasks.find(t => t.id === currentProject.current_task_id) ?? activeTasks[0])
               : null;
             if (!currentTask) return null;
             const isCurrentTaskTracking = isTaskTracking(currentTask.id);
@@ -1015,6 +1577,18 @@ function App() {
                   </span>
                   <span className="hotkey-badge stop-hotkey">{formatHotkeyShort(hotkeySettings.stopHotkey)}</span>
                 </div>
+                <div className="timebox-controls">
+                  <button className={`timebox-btn ${activeBlock ? "active" : ""}`} onClick={toggleTimebox}>
+                    {activeBlock ? "Stop timebox" : `Focus ${Math.round(timeboxSettings.work_seconds / 60)} min`}
+                  </button>
+                  {activeBlock && (
+                    <span className="timebox-status">
+                      {activeBlock.kind === "work" ? "Focus" : activeBlock.kind === "long_break" ? "Long break" : "Break"}
+                      {" · "}
+                      {formatTime(Math.max(0, activeBlock.ends_at - blockNow))} left
+                    </span>
+                  )}
+                </div>
                 {totalActiveCount > 0 && (
                   <div className="active-tracking-list">
                     {activeTracking.map(t => {
@@ -1022,12 +1596,28 @@ function App() {
                       const task = project?.tasks.find(tk => tk.id === t.task_id);
                       if (!task) return null;
                       return (
-                        <div key={t.task_id} className="active-tracking-item">
+                        <div key={t.task_id} className={`active-tracking-item ${t.paused_at !== null ? "paused" : ""}`}>
                           <div className="active-tracking-info">
                             <span className="active-tracking-project">{project?.name}</span>
                             <span className="active-tracking-task">{task.name}</span>
                           </div>
                           <span className="active-tracking-time">{formatTime(elapsedTimes[t.task_id] || 0)}</span>
+                          <button
+                            className="track-btn pause small"
+                            title={t.paused_at !== null ? "Resume" : "Pause"}
+                            onClick={() => togglePause(t)}
+                          >
+                            {t.paused_at !== null ? (
+                              <svg width="12" height="12" viewBox="0 0 24 24" fill="currentColor">
+                                <polygon points="5 3 19 12 5 21 5 3"></polygon>
+                              </svg>
+                            ) : (
+                              <svg width="12" height="12" viewBox="0 0 24 24" fill="currentColor">
+                                <rect x="6" y="5" width="4" height="14" rx="1"></rect>
+                                <rect x="14" y="5" width="4" height="14" rx="1"></rect>
+                              </svg>
+                            )}
+                          </button>
                           <button
                             className="track-btn stop small"
                             onClick={() => stopTracking(t.task_id)}
@@ -1116,6 +1706,33 @@ function App() {
                         >
                           Edit
                         </DropdownMenu.Item>
+                        <DropdownMenu.Item
+                          className="dropdown-item"
+                          onSelect={() => {
+                            const budget = projectBudget(project.id)?.budget;
+                            setBudgetEditor({
+                              projectId: project.id,
+                              period: budget?.period ?? "week",
+                              hours: budget ? String(budget.limit_seconds / 3600) : "",
+                            });
+                          }}
+                        >
+                          Budget…
+                        </DropdownMenu.Item>
+                        <DropdownMenu.Item
+                          className="dropdown-item"
+                          disabled={index === 0}
+                          onSelect={() => moveProject(project.id, index - 1)}
+                        >
+                          Move up
+                        </DropdownMenu.Item>
+                        <DropdownMenu.Item
+                          className="dropdown-item"
+                          disabled={index === projects.length - 1}
+                          onSelect={() => moveProject(project.id, index + 1)}
+                        >
+                          Move down
+                        </DropdownMenu.Item>
                         <DropdownMenu.Item
                           className="dropdown-item danger"
                           onSelect={() => removeProject(project.id)}
@@ -1141,6 +1758,17 @@ function App() {
                   ) : (
                     <span className="project-name">{project.name}</span>
                   )}
+                  {(() => {
+                    const budget = projectBudget(project.id);
+                    return budget && (
+                      <span
+                        className={`project-budget ${budget.reached ? "reached" : ""}`}
+                        title={`${formatTime(budget.remaining_seconds)} left ${BUDGET_PERIOD_LABELS[budget.budget.period]}`}
+                      >
+                        {formatTime(budget.spent_seconds)} / {formatTime(budget.budget.limit_seconds)}
+                      </span>
+                    );
+                  })()}
                   <span className="project-time">{formatTime(getProjectTotalTime(project))}</span>
                 </div>
 
@@ -1174,7 +1802,7 @@ function App() {
                         {(() => {
                           const activeTasks = project.tasks.filter(t => t.done_at === null);
                           const currentTaskId = activeTasks.length > 0
-                            ? activeTasks[project.current_task_index % activeTasks.length]?.id
+                            ? (activeTasks.find(t => t.id === project.current_task_id) ?? activeTasks[0])?.id
                             : null;
                           return [...project.tasks].sort((a, b) => {
                             const aDone = a.done_at !== null ? 1 : 0;
@@ -1214,6 +1842,18 @@ function App() {
                                     >
                                       Edit
                                     </DropdownMenu.Item>
+                                    <DropdownMenu.Item
+                                      className="dropdown-item"
+                                      onSelect={() => moveTask(project, task.id, project.tasks.findIndex(t => t.id === task.id) - 1)}
+                                    >
+                                      Move up
+                                    </DropdownMenu.Item>
+                                    <DropdownMenu.Item
+                                      className="dropdown-item"
+                                      onSelect={() => moveTask(project, task.id, project.tasks.findIndex(t => t.id === task.id) + 1)}
+                                    >
+                                      Move down
+                                    </DropdownMenu.Item>
                                     <DropdownMenu.Item
                                       className="dropdown-item danger"
                                       onSelect={() => removeTask(project.id, task.id)}
@@ -1409,6 +2049,126 @@ function App() {
                 <span className="toggle-label">{menubarTitleEnabled ? "Enabled" : "Disabled"}</span>
               </button>
             </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Timebox</span>
+                <span className="tracking-toggle-description">Focus block, then break, then rotate task</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={`${timeboxSettings.work_seconds}:${timeboxSettings.break_seconds}`}
+                onChange={(e) => {
+                  const [work, rest] = e.target.value.split(":").map(Number);
+                  updateTimeboxSettings({ ...timeboxSettings, work_seconds: work, break_seconds: rest });
+                }}
+              >
+                <option value="1500:300">25 / 5 min</option>
+                <option value="3000:600">50 / 10 min</option>
+                <option value="900:0">15 min, no break</option>
+                <option value="2700:0">45 min, no break</option>
+              </select>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Project Rotation</span>
+                <span className="tracking-toggle-description">How the next project is picked</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={projectRotation}
+                onChange={(e) => updateProjectRotation(e.target.value as RotationStrategyKind)}
+              >
+                {Object.entries(ROTATION_STRATEGY_LABELS).map(([value, label]) => (
+                  <option key={value} value={value}>{label}</option>
+                ))}
+              </select>
+            </div>
+            {currentProject && (
+              <div className="tracking-toggle-container">
+                <div className="tracking-toggle-info">
+                  <span className="tracking-toggle-label">Task Rotation</span>
+                  <span className="tracking-toggle-description">How the next task in {currentProject.name} is picked</span>
+                </div>
+                <select
+                  className="manual-entry-select"
+                  value={taskRotation}
+                  onChange={(e) => updateTaskRotation(e.target.value as RotationStrategyKind)}
+                >
+                  {Object.entries(ROTATION_STRATEGY_LABELS).map(([value, label]) => (
+                    <option key={value} value={value}>{label}</option>
+                  ))}
+                </select>
+              </div>
+            )}
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Minimum Session</span>
+                <span className="tracking-toggle-description">Drop sessions shorter than this</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={timePolicy.min_session_seconds}
+                onChange={(e) => updateTimePolicy({ ...timePolicy, min_session_seconds: Number(e.target.value) })}
+              >
+                <option value={3}>3 sec</option>
+                <option value={30}>30 sec</option>
+                <option value={60}>1 min</option>
+                <option value={300}>5 min</option>
+              </select>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Rounding</span>
+                <span className="tracking-toggle-description">Applied to reports; raw times are kept</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={timePolicy.round_to_minutes === 0 ? "none" : `${timePolicy.rounding}:${timePolicy.round_to_minutes}`}
+                onChange={(e) => {
+                  const [rounding, minutes] = e.target.value === "none" ? ["none", "0"] : e.target.value.split(":");
+                  updateTimePolicy({ ...timePolicy, rounding: rounding as RoundingMode, round_to_minutes: Number(minutes) });
+                }}
+              >
+                <option value="none">Exact</option>
+                <option value="up:6">Up to 6 min</option>
+                <option value="nearest:6">Nearest 6 min</option>
+                <option value="up:15">Up to 15 min</option>
+                <option value="nearest:15">Nearest 15 min</option>
+                <option value="down:15">Down to 15 min</option>
+              </select>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Idle Detection</span>
+                <span className="tracking-toggle-description">Act on running timers when you step away</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={idleSettings.threshold_seconds}
+                onChange={(e) => updateIdleSettings({ ...idleSettings, threshold_seconds: Number(e.target.value) })}
+              >
+                <option value={0}>Off</option>
+                <option value={120}>2 min</option>
+                <option value={300}>5 min</option>
+                <option value={600}>10 min</option>
+                <option value={900}>15 min</option>
+              </select>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">When Idle</span>
+                <span className="tracking-toggle-description">Stop at last activity, or keep counting and ask later</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={idleSettings.action}
+                disabled={idleSettings.threshold_seconds === 0}
+                onChange={(e) => updateIdleSettings({ ...idleSettings, action: e.target.value as IdleAction })}
+              >
+                <option value="stop_at_last_active">Stop timer</option>
+                <option value="record_idle">Keep counting</option>
+              </select>
+            </div>
           </div>
           <div className="settings-section">
             <h2>Keyboard Shortcuts</h2>
@@ -1466,10 +2226,297 @@ function App() {
               Reset to Defaults
             </button>
           </div>
+          <div className="settings-section">
+            <h2>Backups</h2>
+            <p className="settings-description">
+              A copy of the database is kept daily and before every reset, permanent delete and upgrade.
+            </p>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Keep Daily</span>
+                <span className="tracking-toggle-description">Newest backup of each recent day</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={backupRetention.daily}
+                onChange={(e) => updateBackupRetention({ ...backupRetention, daily: Number(e.target.value) })}
+              >
+                {[3, 7, 14, 30].map((n) => (
+                  <option key={n} value={n}>{n} days</option>
+                ))}
+              </select>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Keep Weekly</span>
+                <span className="tracking-toggle-description">Newest backup of each recent week</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={backupRetention.weekly}
+                onChange={(e) => updateBackupRetention({ ...backupRetention, weekly: Number(e.target.value) })}
+              >
+                {[0, 4, 8, 12].map((n) => (
+                  <option key={n} value={n}>{n} weeks</option>
+                ))}
+              </select>
+            </div>
+            <div className="backup-list">
+              {backups.map((backup) => (
+                <div key={backup.file_name} className="hotkey-item">
+                  <div className="hotkey-info">
+                    <span className="hotkey-label">{new Date(backup.created_at * 1000).toLocaleString()}</span>
+                    <span className="hotkey-description">
+                      {backup.reason.replace("_", " ")} · {(backup.size_bytes / 1024).toFixed(0)} KB
+                    </span>
+                  </div>
+                  <button
+                    className="secondary-btn"
+                    onClick={() => {
+                      setConfirmModal({
+                        title: "Restore Backup",
+                        message: `Replace all current data with the backup from ${new Date(backup.created_at * 1000).toLocaleString()}? The current data is backed up first.`,
+                        confirmText: "Restore",
+                        danger: true,
+                        onConfirm: async () => {
+                          try {
+                            await invoke("restore_backup", { fileName: backup.file_name });
+                            await loadData();
+                            await loadBackups();
+                            setCurrentView("main");
+                          } catch (e) {
+                            console.error("Restore backup error:", e);
+                            alert("Failed to restore backup: " + errorMessage(e));
+                          }
+                        },
+                      });
+                    }}
+                  >
+                    Restore
+                  </button>
+                </div>
+              ))}
+            </div>
+            <button
+              className="reset-hotkeys-btn"
+              onClick={async () => {
+                try {
+                  await invoke("create_backup");
+                  await loadBackups();
+                } catch (e) {
+                  alert("Failed to back up: " + errorMessage(e));
+                }
+              }}
+            >
+              Back Up Now
+            </button>
+          </div>
+          <div className="settings-section">
+            <h2>Archive</h2>
+            <p className="settings-description">
+              Move your data to another machine: export everything as one JSON file, then import it there.
+            </p>
+            <button
+              className="reset-hotkeys-btn"
+              onClick={async () => {
+                try {
+                  const path = await invoke<string>("export_archive", { path: null });
+                  setArchiveMessage(`Saved to ${path}`);
+                } catch (e) {
+                  setArchiveMessage("Export failed: " + errorMessage(e));
+                }
+              }}
+            >
+              Export Archive
+            </button>
+            <div className="tracking-toggle-container">
+              <input
+                type="text"
+                className="inline-add-input"
+                placeholder="Path to rotator-archive.json"
+                value={archivePath}
+                onChange={(e) => setArchivePath(e.target.value)}
+              />
+              <select
+                className="manual-entry-select"
+                value={archiveMode}
+                onChange={(e) => setArchiveMode(e.target.value as "merge" | "replace")}
+              >
+                <option value="merge">Merge</option>
+                <option value="replace">Replace</option>
+              </select>
+              <button
+                className="secondary-btn"
+                disabled={!archivePath}
+                onClick={() => {
+                  if (archiveMode === "merge") {
+                    importArchive();
+                    return;
+                  }
+                  setConfirmModal({
+                    title: "Replace With Archive",
+                    message: "Replace all current data with the archive? The current data is backed up first.",
+                    confirmText: "Replace",
+                    danger: true,
+                    onConfirm: importArchive,
+                  });
+                }}
+              >
+                Import
+              </button>
+            </div>
+            {archiveMessage && <p className="settings-description">{archiveMessage}</p>}
+          </div>
+          <div className="settings-section">
+            <h2>Import From Another Tracker</h2>
+            <p className="settings-description">
+              Bring in time from a Toggl or Clockify CSV report, or from timew export. Missing projects and tasks are
+              created, and entries already here are skipped.
+            </p>
+            <div className="tracking-toggle-container">
+              <select
+                className="manual-entry-select"
+                value={trackerSource}
+                onChange={(e) => {
+                  setTrackerSource(e.target.value as ImportSource);
+                  setTrackerPreview(null);
+                }}
+              >
+                <option value="toggl">Toggl</option>
+                <option value="clockify">Clockify</option>
+                <option value="timewarrior">Timewarrior</option>
+              </select>
+              <input
+                type="text"
+                className="inline-add-input"
+                placeholder="Path to the export"
+                value={trackerPath}
+                onChange={(e) => {
+                  setTrackerPath(e.target.value);
+                  setTrackerPreview(null);
+                }}
+              />
+              <button className="secondary-btn" disabled={!trackerPath} onClick={previewTrackerImport}>
+                Preview
+              </button>
+            </div>
+            {trackerPreview && (
+              <>
+                <p className="settings-description">
+                  {trackerPreview.rows} rows: {trackerPreview.entries_added} entries to add,{" "}
+                  {trackerPreview.duplicates} already here, {trackerPreview.conflicts.length} overlapping,{" "}
+                  {trackerPreview.skipped.length} unreadable. Creates {trackerPreview.projects_created.length} projects
+                  and {trackerPreview.tasks_created.length} tasks.
+                </p>
+                {trackerPreview.skipped.slice(0, 5).map((s) => (
+                  <p key={s.row} className="settings-description">
+                    Row {s.row}: {s.reason}
+                  </p>
+                ))}
+                <button
+                  className="secondary-btn"
+                  disabled={trackerPreview.entries_added === 0}
+                  onClick={importTracker}
+                >
+                  Import {trackerPreview.entries_added} Entries
+                </button>
+              </>
+            )}
+            {trackerMessage && <p className="settings-description">{trackerMessage}</p>}
+          </div>
+          <div className="settings-section">
+            <h2>Calendar Feed</h2>
+            <p className="settings-description">
+              Subscribe to your tracked time from a calendar app. The feed is only reachable from this computer.
+            </p>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Serve Feed</span>
+                <span className="tracking-toggle-description">
+                  {calendarFeed.enabled ? `http://127.0.0.1:${calendarFeed.port}/${calendarFeed.token}/calendar.ics` : "Off"}
+                </span>
+              </div>
+              <button
+                className={`ads-toggle ${calendarFeed.enabled ? "enabled" : ""}`}
+                onClick={() => updateCalendarFeed({ ...calendarFeed, enabled: !calendarFeed.enabled })}
+              >
+                <span className="toggle-track">
+                  <span className="toggle-thumb"></span>
+                </span>
+                <span className="toggle-label">{calendarFeed.enabled ? "Enabled" : "Disabled"}</span>
+              </button>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Covers</span>
+                <span className="tracking-toggle-description">How far back the feed goes</span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={calendarFeed.weeks}
+                onChange={(e) => updateCalendarFeed({ ...calendarFeed, weeks: Number(e.target.value) })}
+              >
+                {[1, 2, 4, 8, 12, 26].map((n) => (
+                  <option key={n} value={n}>{n} {n === 1 ? "week" : "weeks"}</option>
+                ))}
+              </select>
+            </div>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Port</span>
+                <span className="tracking-toggle-description">Change it if another app already uses it</span>
+              </div>
+              <input
+                type="number"
+                min="1024"
+                max="65535"
+                className="manual-entry-time"
+                defaultValue={calendarFeed.port}
+                key={calendarFeed.port}
+                onBlur={(e) => {
+                  const port = Number(e.target.value);
+                  if (port !== calendarFeed.port) updateCalendarFeed({ ...calendarFeed, port });
+                }}
+              />
+            </div>
+            {calendarFeedMessage && <p className="settings-description">{calendarFeedMessage}</p>}
+          </div>
+          <div className="settings-section">
+            <h2>Time Zone</h2>
+            <p className="settings-description">
+              Activity by hour and by day is counted in this time zone. Sessions that cross an hour or midnight are
+              split between them.
+            </p>
+            <div className="tracking-toggle-container">
+              <div className="tracking-toggle-info">
+                <span className="tracking-toggle-label">Stats Time Zone</span>
+                <span className="tracking-toggle-description">
+                  System is {Intl.DateTimeFormat().resolvedOptions().timeZone}
+                </span>
+              </div>
+              <select
+                className="manual-entry-select"
+                value={statsTimezone ?? ""}
+                onChange={async (e) => {
+                  const timezone = e.target.value || null;
+                  setStatsTimezone(await invoke<string | null>("set_stats_timezone", { timezone }));
+                  await loadDatabaseData();
+                }}
+              >
+                <option value="">System</option>
+                {statsTimezone && !TIME_ZONES.includes(statsTimezone) && (
+                  <option value={statsTimezone}>{statsTimezone}</option>
+                )}
+                {TIME_ZONES.map((zone) => (
+                  <option key={zone} value={zone}>{zone}</option>
+                ))}
+              </select>
+            </div>
+          </div>
           <div className="settings-section">
             <h2>Database</h2>
             <p className="settings-description">
-              Manage your database. Warning: These actions cannot be undone.
+              Manage your database. A backup is taken before a reset, which can be restored above.
             </p>
             <div className="database-actions">
               <button
@@ -1486,6 +2533,7 @@ function App() {
                         setProjects(newProjects);
                         setActiveTracking([]);
                         setElapsedTimes({});
+                        loadBackups().catch(console.error);
                         setCurrentView("main");
                       } catch (e) {
                         console.error("Reset database error:", e);
@@ -1505,7 +2553,7 @@ function App() {
                     setCurrentView("main");
                   } catch (e) {
                     console.error("Add mock data error:", e);
-                    alert("Failed to add mock data: " + e);
+                    alert("Failed to add mock data: " + errorMessage(e));
                   }
                 }}
               >
@@ -1533,6 +2581,15 @@ function App() {
                   </>
                 )}
               </button>
+              <button className="export-btn" onClick={() => exportEntries("csv")} disabled={exporting}>
+                CSV
+              </button>
+              <button className="export-btn" onClick={() => exportEntries("json")} disabled={exporting}>
+                JSON
+              </button>
+              <button className="export-btn" onClick={exportCalendar} disabled={exporting}>
+                ICS
+              </button>
               {exportMessage && <span className="export-message">{exportMessage}</span>}
             </div>
           </div>
@@ -1622,6 +2679,28 @@ function App() {
             )}
           </div>
 
+          <div className="db-section">
+            <h3>Context Switches (7 days)</h3>
+            {contextSwitches.length === 0 ? (
+              <div className="no-data">No rotations yet</div>
+            ) : (
+              <ul className="switch-list">
+                {contextSwitches.map(day => (
+                  <li key={day.date}>
+                    <span>{day.date}</span>
+                    <span>{day.project_switches} project · {day.task_switches} task</span>
+                  </li>
+                ))}
+                {dwellTimes.map(dwell => (
+                  <li key={dwell.project_id} className="switch-dwell">
+                    <span>{allProjectsWithStatus.find(p => p.id === dwell.project_id)?.name ?? "Deleted project"}</span>
+                    <span>avg {formatTime(dwell.average_seconds)} over {dwell.visits} visits</span>
+                  </li>
+                ))}
+              </ul>
+            )}
+          </div>
+
           <div className="db-section">
             <h3>Year Activity</h3>
             <div className="activity-months">
@@ -1815,6 +2894,149 @@ function App() {
         )}
       </footer>
 
+      {orphanedSessions.length > 0 && (() => {
+        const orphan = orphanedSessions[0];
+        const project = projects.find(p => p.id === orphan.project_id);
+        const task = project?.tasks.find(t => t.id === orphan.task_id);
+        return (
+          <div className="modal-overlay">
+            <div className="modal-content">
+              <h3 className="modal-title">Timer left running</h3>
+              <p className="modal-message">
+                {project?.name} / {task?.name} was still running when Rotator last closed.
+                It was last seen {formatTime(orphan.last_heartbeat - orphan.started_at)} after it started,
+                {" "}{formatTime(Math.floor(Date.now() / 1000) - orphan.last_heartbeat)} ago.
+              </p>
+              <div className="modal-actions">
+                <button className="modal-btn modal-btn-cancel" onClick={() => resolveOrphanedSession(orphan.task_id, "discard")}>
+                  Discard
+                </button>
+                <button className="modal-btn modal-btn-cancel" onClick={() => resolveOrphanedSession(orphan.task_id, "keep_running")}>
+                  Keep running
+                </button>
+                <button className="modal-btn modal-btn-confirm" onClick={() => resolveOrphanedSession(orphan.task_id, "close_at_last_heartbeat")}>
+                  Stop then
+                </button>
+              </div>
+            </div>
+          </div>
+        );
+      })()}
+
+      {idlePrompt && (
+        <div className="modal-overlay">
+          <div className="modal-content">
+            <h3 className="modal-title">You were away</h3>
+            <p className="modal-message">
+              Idle for {formatTime((idlePrompt.returned_at ?? Math.floor(Date.now() / 1000)) - idlePrompt.started_at)}
+              {idlePrompt.action === "stop_at_last_active" ? " — the timer was stopped." : "."} What should happen to that time?
+            </p>
+            <select
+              className="manual-entry-select"
+              value={idleReassignTarget}
+              onChange={(e) => setIdleReassignTarget(e.target.value)}
+            >
+              <option value="">Reassign to…</option>
+              {projects.flatMap(p => p.tasks.map(t => (
+                <option key={t.id} value={`${p.id}:${t.id}`}>{p.name} / {t.name}</option>
+              )))}
+            </select>
+            <div className="modal-actions">
+              <button className="modal-btn modal-btn-cancel" onClick={() => resolveIdle({ kind: "discard" })}>
+                Discard
+              </button>
+              {idleReassignTarget ? (
+                <button
+                  className="modal-btn modal-btn-confirm"
+                  onClick={() => {
+                    const [projectId, taskId] = idleReassignTarget.split(":").map(Number);
+                    resolveIdle({ kind: "reassign", project_id: projectId, task_id: taskId });
+                  }}
+                >
+                  Reassign
+                </button>
+              ) : (
+                <button className="modal-btn modal-btn-confirm" onClick={() => resolveIdle({ kind: "keep" })}>
+                  Keep
+                </button>
+              )}
+            </div>
+          </div>
+        </div>
+      )}
+
+      {budgetAlert && (() => {
+        const project = projects.find(p => p.id === budgetAlert.budget.project_id);
+        const task = project?.tasks.find(t => t.id === budgetAlert.budget.task_id);
+        return (
+          <div className="modal-overlay" onClick={() => setBudgetAlert(null)}>
+            <div className="modal-content" onClick={(e) => e.stopPropagation()}>
+              <h3 className="modal-title">Budget reached</h3>
+              <p className="modal-message">
+                {task ? `${project?.name} / ${task.name}` : project?.name} has had its
+                {" "}{formatTime(budgetAlert.budget.limit_seconds)} {BUDGET_PERIOD_LABELS[budgetAlert.budget.period]}.
+              </p>
+              <div className="modal-actions">
+                <button className="modal-btn modal-btn-cancel" onClick={() => setBudgetAlert(null)}>
+                  Keep going
+                </button>
+                <button
+                  className="modal-btn modal-btn-confirm"
+                  onClick={() => {
+                    setBudgetAlert(null);
+                    if (task) rotateTask(); else rotateManually();
+                  }}
+                >
+                  Move on
+                </button>
+              </div>
+            </div>
+          </div>
+        );
+      })()}
+
+      {budgetEditor && (
+        <div className="modal-overlay" onClick={() => setBudgetEditor(null)}>
+          <div className="modal-content" onClick={(e) => e.stopPropagation()}>
+            <h3 className="modal-title">Budget</h3>
+            <p className="modal-message">Leave the hours empty to remove the budget.</p>
+            <div className="budget-editor">
+              <input
+                type="number"
+                min="0"
+                step="0.5"
+                className="inline-edit-input"
+                placeholder="Hours"
+                value={budgetEditor.hours}
+                onChange={(e) => setBudgetEditor({ ...budgetEditor, hours: e.target.value })}
+                onKeyDown={(e) => {
+                  if (e.key === "Enter") saveBudget();
+                  if (e.key === "Escape") setBudgetEditor(null);
+                }}
+                autoFocus
+              />
+              <select
+                className="manual-entry-select"
+                value={budgetEditor.period}
+                onChange={(e) => setBudgetEditor({ ...budgetEditor, period: e.target.value as BudgetPeriod })}
+              >
+                {Object.entries(BUDGET_PERIOD_LABELS).map(([value, label]) => (
+                  <option key={value} value={value}>{label}</option>
+                ))}
+              </select>
+            </div>
+            <div className="modal-actions">
+              <button className="modal-btn modal-btn-cancel" onClick={() => setBudgetEditor(null)}>
+                Cancel
+              </button>
+              <button className="modal-btn modal-btn-confirm" onClick={saveBudget}>
+                Save
+              </button>
+            </div>
+          </div>
+        </div>
+      )}
+
       {confirmModal && (
         <div className="modal-overlay" onClick={() => setConfirmModal(null)}>
           <div className="modal-content" onClick={(e) => e.stopPropagation()}>